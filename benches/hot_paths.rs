@@ -0,0 +1,67 @@
+//! Baselines for the per-snapshot hot paths: parsing, free RAM scan, compression
+//!
+//! Run with `cargo bench`. The snapshot is synthetic, with code-like data,
+//! a filled screen and cleared RAM, roughly what a cleared-RAM snapshot of a
+//! small program looks like.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use vice_snapshot_to_prg_converter::config::{Config, DEFAULT_MIN_MATCH_SIZE};
+use vice_snapshot_to_prg_converter::find_ram::FindRam;
+use vice_snapshot_to_prg_converter::parse_vsf::{compress_lzsa1, CompressionQuality, ParseVSF};
+
+#[allow(dead_code)]
+#[path = "../src/test_vsf.rs"]
+mod test_vsf;
+
+use test_vsf::TestVsf;
+
+/// Snapshot with 14 KB of code-like bytes at $0800, a text screen and cleared RAM elsewhere
+fn representative_vsf() -> TestVsf {
+    let mut vsf = TestVsf::new();
+    let mut seed = 0x2545_F491u32;
+    for byte in vsf.ram[0x0800..0x4000].iter_mut() {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        // Few distinct values, like opcodes and small operands
+        *byte = (seed % 24) as u8 * 8;
+    }
+    for (i, byte) in vsf.ram[0x0400..0x07E8].iter_mut().enumerate() {
+        *byte = if i % 40 < 20 { 0x20 } else { (i % 26) as u8 + 1 };
+    }
+    vsf
+}
+
+fn hot_paths(c: &mut Criterion) {
+    let vsf = representative_vsf();
+    let (dir, path) = vsf.write("bench");
+    let config = Config::new(&dir);
+
+    let parser = ParseVSF::import(&path, &config).unwrap();
+    c.bench_function("parse_import", |b| b.iter(|| black_box(parser.parse_import().unwrap())));
+
+    let snap = parser.parse_import().unwrap();
+    let ram: &[u8; 65536] = &snap.mem.ram;
+    c.bench_function("find_ram_64k", |b| b.iter(|| black_box(FindRam::new(black_box(ram)))));
+
+    let mut group = c.benchmark_group("compress_ram");
+    group.sample_size(10);
+    for (name, quality) in [("ratio", CompressionQuality::Ratio), ("fast", CompressionQuality::Fast)] {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(compress_lzsa1(&ram[0x0200..], quality, DEFAULT_MIN_MATCH_SIZE).unwrap()))
+        });
+    }
+    group.finish();
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, hot_paths);
+criterion_main!(benches);
@@ -7,11 +7,85 @@
 
 #![allow(dead_code)]
 
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use asm6502::{Assembler6502, AsmError as Asm6502Error};
 
+/// Branch mnemonics and the branch taken on the opposite condition
+const BRANCHES: [(&str, &str); 8] = [
+    ("BCC", "BCS"),
+    ("BCS", "BCC"),
+    ("BEQ", "BNE"),
+    ("BNE", "BEQ"),
+    ("BMI", "BPL"),
+    ("BPL", "BMI"),
+    ("BVC", "BVS"),
+    ("BVS", "BVC"),
+];
+
+thread_local! {
+    /// Directory and file counter of the active `AsmDump`
+    static DUMP: RefCell<Option<(PathBuf, usize)>> = const { RefCell::new(None) };
+}
+
+/// Writes every source assembled on this thread to a directory until dropped
+///
+/// Files are named `NN_<routine>.asm` in assembly order, after the first label
+/// of the source. Each source is written before it is assembled, so after a
+/// failure the last file is the one that failed.
+pub struct AsmDump {
+    previous: Option<(PathBuf, usize)>,
+}
+
+impl AsmDump {
+    /// Start dumping to `dir`, creating it if needed; `None` dumps nothing
+    pub fn start(dir: Option<&Path>) -> Result<Self, String> {
+        let dump = match dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create assembler dump directory {}: {}", dir.display(), e))?;
+                Some((dir.to_path_buf(), 0))
+            }
+            None => None,
+        };
+        let previous = DUMP.with(|active| active.replace(dump));
+        Ok(Self { previous })
+    }
+}
+
+impl Drop for AsmDump {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DUMP.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+/// Write `src` to the active `AsmDump` directory, if any
+fn dump_source(src: &str) -> Result<(), String> {
+    DUMP.with(|active| {
+        let mut active = active.borrow_mut();
+        let Some((dir, count)) = active.as_mut() else {
+            return Ok(());
+        };
+        let name = routine_name(src).trim_matches(|c| c == '<' || c == '>');
+        let path = dir.join(format!("{:02}_{}.asm", count, name));
+        *count += 1;
+        fs::write(&path, src).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    })
+}
+
+/// Why assembling generated code failed
+///
+/// The assembler is built in, so there is no setup failure to report: `Asm`
+/// is a code generation bug, `Io` a problem with the `AsmDump` directory.
 #[derive(Debug)]
 pub enum AsmError {
+    /// The assembler rejected the source; the message quotes the failing lines
     Asm(String),
+    /// The source could not be written to the `AsmDump` directory
+    Io(String),
 }
 
 impl From<Asm6502Error> for AsmError {
@@ -33,18 +107,76 @@ impl Assembler6502Wrapper {
     }
 
     /// Assemble source into raw bytes with enhanced error reporting
+    ///
+    /// Generated code that grew past a branch's range is retried with only
+    /// the out-of-range branches widened to a JMP (see `widen_branches`).
+    /// Widening can push other branches out of range, so this repeats until
+    /// the source assembles or no branch to a label is left to widen.
     pub fn assemble_bytes(&mut self, src: &str) -> Result<Vec<u8>, AsmError> {
-        self.assembler.reset();
+        dump_source(src).map_err(AsmError::Io)?;
 
-        match self.assembler.assemble_bytes(src) {
-            Ok(bytes) => Ok(bytes),
-            Err(e) => {
-                let error_msg = self.format_assembly_error(&e, src);
-                Err(AsmError::Asm(error_msg))
+        let mut source = src.to_string();
+        let mut widened = 0;
+        loop {
+            let error = match self.assemble_raw(&source) {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) => e,
+            };
+
+            if is_long_branch_error(&format!("{:?}", error)) {
+                let lines = self.out_of_range_branches(&source);
+                if !lines.is_empty() {
+                    source = widen_branches(&source, &lines, widened);
+                    widened += lines.len();
+                    continue;
+                }
             }
+
+            let error_msg = self.format_assembly_error(&error, &source);
+            return Err(AsmError::Asm(error_msg));
         }
     }
 
+    /// Lines of `src` holding a branch to a label that is out of range
+    ///
+    /// The assembler doesn't say which branch failed, so each branch to a label
+    /// is assembled on its own, with all others replaced by a 2-byte
+    /// placeholder that keeps the layout. Only runs after a long-branch error.
+    fn out_of_range_branches(&mut self, src: &str) -> Vec<usize> {
+        let lines: Vec<&str> = src.lines().collect();
+        let branches: Vec<usize> = (0..lines.len()).filter(|&i| widen_branch(lines[i], 0).is_some()).collect();
+
+        let probe = |keep: Option<usize>| {
+            let mut probe = String::with_capacity(src.len());
+            for (i, line) in lines.iter().enumerate() {
+                if Some(i) != keep && branches.contains(&i) {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    probe.push_str(indent);
+                    probe.push_str("LDA #$00");
+                } else {
+                    probe.push_str(line);
+                }
+                probe.push('\n');
+            }
+            probe
+        };
+        let out_of_range = |result: Result<Vec<u8>, Asm6502Error>| match result {
+            Ok(_) => false,
+            Err(e) => is_long_branch_error(&format!("{:?}", e)),
+        };
+
+        // A branch to an address or expression is out of range; nothing to widen
+        if out_of_range(self.assemble_raw(&probe(None))) {
+            return Vec::new();
+        }
+        branches.iter().copied().filter(|&i| out_of_range(self.assemble_raw(&probe(Some(i))))).collect()
+    }
+
+    fn assemble_raw(&mut self, src: &str) -> Result<Vec<u8>, Asm6502Error> {
+        self.assembler.reset();
+        self.assembler.assemble_bytes(src)
+    }
+
     /// Assemble source into a C64 PRG file (with $0801 load address)
     pub fn assemble_prg(&mut self, src: &str) -> Result<Vec<u8>, AsmError> {
         let binary = self.assemble_bytes(src)?;
@@ -76,8 +208,14 @@ impl Assembler6502Wrapper {
             return self.find_error_context(source, &error_string, "undefined");
         }
 
-        if error_string.contains("Long-branch") {
-            return format!("Assembly error: {}\n\nNote: This may be caused by branch instructions that are out of range.\nTry using absolute JMP instructions for long distances.", error_string);
+        if is_long_branch_error(&error_string) {
+            return format!(
+                "Assembly error in generated routine '{}': {}\n\n\
+                 A branch is out of range even with long branches rewritten to JMP.\n\
+                 The generated code has grown too large; please report this bug.",
+                routine_name(source),
+                error_string
+            );
         }
 
         // Default: return the error with source line count
@@ -158,8 +296,117 @@ impl Default for Assembler6502Wrapper {
     }
 }
 
+fn is_long_branch_error(error: &str) -> bool {
+    error.contains("Long-branch")
+}
+
+/// First label in the source, which names the generated routine
+fn routine_name(source: &str) -> &str {
+    source
+        .lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_suffix(':').filter(|label| is_label(label)))
+        .unwrap_or("<unnamed>")
+}
+
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Rewrite the `Bxx label` on each of `lines` as an inverted branch over a `JMP label`
+///
+/// The skip labels are numbered from `first_index`, so repeated passes over the
+/// same source don't reuse them. Branches to addresses or expressions are left alone.
+pub fn widen_branches(src: &str, lines: &[usize], first_index: usize) -> String {
+    let mut widened = String::with_capacity(src.len());
+    let mut index = first_index;
+
+    for (line_index, line) in src.lines().enumerate() {
+        match widen_branch(line, index).filter(|_| lines.contains(&line_index)) {
+            Some(rewritten) => {
+                widened.push_str(&rewritten);
+                index += 1;
+            }
+            None => {
+                widened.push_str(line);
+                widened.push('\n');
+            }
+        }
+    }
+
+    widened
+}
+
+fn widen_branch(line: &str, index: usize) -> Option<String> {
+    let code = line.split(';').next().unwrap_or("");
+    let mut tokens = code.split_whitespace();
+    let mnemonic = tokens.next()?.to_ascii_uppercase();
+    let target = tokens.next()?;
+    if tokens.next().is_some() || !is_label(target) {
+        return None;
+    }
+
+    let inverse = BRANCHES.iter().find(|(branch, _)| *branch == mnemonic)?.1;
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let skip = format!("long_branch_{}", index);
+    Some(format!("{indent}{inverse} {skip}\n{indent}JMP {target}\n{skip}:\n"))
+}
+
 /// Convenience function to assemble source code to bytes
 pub fn assemble_to_bytes(src: &str) -> Result<Vec<u8>, String> {
     let mut assembler = Assembler6502Wrapper::new();
     assembler.assemble_bytes(src).map_err(|e| format!("{:?}", e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BEQ over `distance` bytes of NOPs to `far`
+    fn branch_over(distance: usize) -> String {
+        format!("*=$1000\nstart:\n    LDA #$00\n    BEQ far\n{}far:\n    RTS\n", "    NOP\n".repeat(distance))
+    }
+
+    #[test]
+    fn test_widen_branches_inverts_and_jumps() {
+        let src = "loop:\n    BNE loop ; again\n    BCC $1000\n    BCS loop\n";
+        let widened = widen_branches(src, &[1, 2], 3);
+        assert_eq!(widened, "loop:\n    BEQ long_branch_3\n    JMP loop\nlong_branch_3:\n    BCC $1000\n    BCS loop\n");
+    }
+
+    #[test]
+    fn test_out_of_range_branch_is_rewritten_to_jmp() {
+        let bytes = assemble_to_bytes(&branch_over(200)).unwrap();
+        // LDA #$00; BNE +3; JMP far ($1007 + 200 NOPs = $10CF)
+        assert_eq!(&bytes[..7], &[0xA9, 0x00, 0xD0, 0x03, 0x4C, 0xCF, 0x10]);
+        assert_eq!(bytes.len(), 7 + 200 + 1);
+    }
+
+    #[test]
+    fn test_only_the_out_of_range_branch_is_widened() {
+        let src = format!("*=$1000\nstart:\n    LDA #$00\n    BEQ near\n    BNE far\nnear:\n{}far:\n    RTS\n", "    NOP\n".repeat(200));
+        let bytes = assemble_to_bytes(&src).unwrap();
+        // LDA #$00; BEQ near (now 5 bytes ahead); BEQ +3; JMP far ($1009 + 200 NOPs = $10D1)
+        assert_eq!(&bytes[..9], &[0xA9, 0x00, 0xF0, 0x05, 0xF0, 0x03, 0x4C, 0xD1, 0x10]);
+        assert_eq!(bytes.len(), 9 + 200 + 1);
+    }
+
+    #[test]
+    fn test_error_variants_separate_assembly_from_dump_failures() {
+        let mut assembler = Assembler6502Wrapper::new();
+        assert!(matches!(assembler.assemble_bytes("*=$1000\n    LDA missing\n"), Err(AsmError::Asm(_))));
+
+        // Dump directory removed after the dump started
+        let dir = std::env::temp_dir().join(format!("ViceSnapshotTest.{}.asm_io", std::process::id()));
+        let _dump = AsmDump::start(Some(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(assembler.assemble_bytes("*=$1000\n    RTS\n"), Err(AsmError::Io(_))));
+    }
+
+    #[test]
+    fn test_branch_in_range_is_kept() {
+        let bytes = assemble_to_bytes(&branch_over(100)).unwrap();
+        assert_eq!(&bytes[..4], &[0xA9, 0x00, 0xF0, 100]);
+    }
+}
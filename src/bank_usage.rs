@@ -0,0 +1,186 @@
+//! Bank usage statistics for CRT output
+//!
+//! Reports how many bytes of each 8K ROML bank a built cartridge uses and
+//! what the bank holds: boot code, restore code, compressed RAM or embedded
+//! files. Used bytes are found by scanning each bank for trailing fill bytes,
+//! so the numbers show the space left for files and where a bank is wasted.
+//! ROMH is not counted: it ends in the CPU vectors and is always full.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use crate::crt_builder::{CRTBuilder, BANK_SIZE_8K};
+use crate::file_system_manager::{c64_name, FileAllocation};
+
+/// What a region of the ROML banks holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BankContent {
+    /// Magic Desk CBM80 boot code
+    Boot,
+    /// Restore code and relocated decompressor
+    RestoreCode,
+    /// Compressed RAM (RAM.lzsa)
+    RamData,
+    /// EasyFlash loading screen image
+    LoadingScreen,
+}
+
+impl BankContent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BankContent::Boot => "boot code",
+            BankContent::RestoreCode => "restore code",
+            BankContent::RamData => "RAM data",
+            BankContent::LoadingScreen => "loading screen",
+        }
+    }
+}
+
+/// Bytes written contiguously across the ROML banks
+///
+/// `start` counts from bank 0 offset 0 (bank * 8K + offset), so a region
+/// continues at offset 0 of the following banks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankRegion {
+    pub content: BankContent,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// What a CRT conversion wrote: per-bank usage and the embedded files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrtUsage {
+    pub banks: Vec<BankUsage>,
+    /// C64 names of the include-dir files the cartridge serves, in directory order
+    pub files: Vec<String>,
+}
+
+/// Usage of one bank
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankUsage {
+    pub bank: usize,
+    /// ROML bytes up to the last non-fill byte
+    pub used: usize,
+    pub contents: Vec<BankContent>,
+    /// C64 names of the embedded files stored (partly) in this bank
+    pub files: Vec<String>,
+}
+
+impl BankUsage {
+    /// Unused space at the end of the bank
+    pub fn wasted(&self) -> usize {
+        BANK_SIZE_8K - self.used
+    }
+}
+
+/// Collect usage for every bank of `crt`
+pub fn bank_usage(
+    crt: &CRTBuilder,
+    regions: &[BankRegion],
+    allocations: &[FileAllocation],
+) -> Result<Vec<BankUsage>, String> {
+    (0..crt.bank_count())
+        .map(|bank| {
+            let start = bank * BANK_SIZE_8K;
+            let end = start + BANK_SIZE_8K;
+            let contents = regions
+                .iter()
+                .filter(|region| region.len > 0 && region.start < end && region.start + region.len > start)
+                .map(|region| region.content)
+                .collect();
+            let files = allocations
+                .iter()
+                .filter(|allocation| allocation.banks.contains(&bank))
+                .map(|allocation| c64_name(&allocation.file.filename))
+                .collect();
+
+            Ok(BankUsage {
+                bank,
+                used: crt.used_bytes(bank)?,
+                contents,
+                files,
+            })
+        })
+        .collect()
+}
+
+/// Format a usage table with a total line
+pub fn format_bank_usage(usage: &[BankUsage]) -> Vec<String> {
+    let mut lines = vec![format!("  {:>4}  {:>5}  {:>6}  CONTENTS", "BANK", "USED", "WASTED")];
+    for bank in usage {
+        let mut contents: Vec<String> = bank.contents.iter().map(|content| content.name().to_string()).collect();
+        if !bank.files.is_empty() {
+            contents.push(format!("files: {}", bank.files.join(", ")));
+        }
+        if contents.is_empty() && bank.used == 0 {
+            contents.push("empty".to_string());
+        }
+        lines.push(format!(
+            "  {:>4}  {:>5}  {:>6}  {}",
+            bank.bank,
+            bank.used,
+            bank.wasted(),
+            contents.join(", ")
+        ));
+    }
+
+    let used: usize = usage.iter().map(|bank| bank.used).sum();
+    lines.push(format!(
+        "  {} of {} ROML bytes used in {} bank(s), {} wasted",
+        used,
+        usage.len() * BANK_SIZE_8K,
+        usage.len(),
+        usage.len() * BANK_SIZE_8K - used
+    ));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crt_builder::CartridgeType;
+    use crate::file_system_manager::{FileSystemManager, PRGFile};
+
+    #[test]
+    fn test_used_bytes_match_filled_data() {
+        let mut crt = CRTBuilder::new(CartridgeType::EasyFlash, 3, "USAGE").unwrap();
+        let restore = vec![0xEA; 300];
+        // Never $00, so no data is mistaken for fill
+        let ram: Vec<u8> = (0..BANK_SIZE_8K).map(|i| (i % 255 + 1) as u8).collect();
+        crt.fill_bank(0, &restore, 0).unwrap();
+        crt.fill_spanning(0, restore.len(), &ram).unwrap();
+
+        let data = vec![0x42; 100];
+        let allocations = vec![FileAllocation {
+            file: PRGFile {
+                filename: "intro.prg".to_string(),
+                load_address: 0x0801,
+                total_size: data.len() + 2,
+                data,
+                force_address: false,
+                hidden: false,
+                autostart: false,
+            },
+            banks: vec![2],
+            start_offset: 0,
+            filename_offset: 0,
+        }];
+        FileSystemManager::new("").write_files_to_banks(&mut crt, &allocations).unwrap();
+
+        let regions = [
+            BankRegion { content: BankContent::RestoreCode, start: 0, len: restore.len() },
+            BankRegion { content: BankContent::RamData, start: restore.len(), len: ram.len() },
+        ];
+        let usage = bank_usage(&crt, &regions, &allocations).unwrap();
+
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].used, BANK_SIZE_8K);
+        assert_eq!(usage[0].contents, vec![BankContent::RestoreCode, BankContent::RamData]);
+        assert_eq!(usage[1].used, 300);
+        assert_eq!(usage[1].wasted(), BANK_SIZE_8K - 300);
+        assert_eq!(usage[1].contents, vec![BankContent::RamData]);
+        assert_eq!(usage[2].used, 100);
+        assert!(usage[2].contents.is_empty());
+        assert_eq!(usage[2].files, vec!["INTRO".to_string()]);
+    }
+}
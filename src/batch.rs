@@ -0,0 +1,105 @@
+//! Batch conversion worker pool
+//!
+//! Runs independent conversions across a fixed number of threads and returns
+//! the results in input order.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// One input/output pair of a batch
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub input_path: String,
+    pub output_path: String,
+}
+
+/// Outcome of one batch job
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub job: BatchJob,
+    pub result: Result<(), String>,
+}
+
+/// Run `convert` for every job on up to `workers` threads
+///
+/// Each call must use its own work directory (e.g. `Config::auto()`), since
+/// jobs run concurrently. Results come back in the same order as `jobs`.
+pub fn run_batch<F>(jobs: &[BatchJob], workers: usize, convert: F) -> Vec<BatchResult>
+where
+    F: Fn(&BatchJob) -> Result<(), String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<(), String>>>> = Mutex::new(vec![None; jobs.len()]);
+    let workers = workers.clamp(1, jobs.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(index) else { break };
+
+                let result = convert(job);
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    jobs.iter()
+        .zip(results)
+        .map(|(job, result)| BatchResult {
+            job: job.clone(),
+            result: result.unwrap_or_else(|| Err("Worker did not finish".to_string())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::convert_snapshot::ConvertSnapshot;
+    use crate::test_vsf::{temp_dir, TestVsf};
+    use std::fs;
+
+    #[test]
+    fn test_parallel_batch_converts_all_snapshots() {
+        let out = temp_dir("batch_out");
+        let mut jobs = Vec::new();
+        let mut dirs = Vec::new();
+        for i in 0..6u8 {
+            let mut vsf = TestVsf::new();
+            vsf.vic_regs[0x20] = i;
+            let (dir, path) = vsf.write(&format!("batch{}", i));
+            jobs.push(BatchJob {
+                input_path: path,
+                output_path: out.join(format!("batch{}.prg", i)).to_str().unwrap().to_string(),
+            });
+            dirs.push(dir);
+        }
+
+        let results = run_batch(&jobs, 4, |job| {
+            let config = Config::auto().map_err(|e| e.to_string())?;
+            let work_path = config.work_path.clone();
+            let result = ConvertSnapshot::new(config).convert(&job.input_path, &job.output_path);
+            let _ = fs::remove_dir_all(work_path);
+            result
+        });
+
+        assert_eq!(results.len(), jobs.len());
+        for (result, job) in results.iter().zip(&jobs) {
+            assert_eq!(result.job.input_path, job.input_path);
+            assert!(result.result.is_ok(), "{}: {:?}", job.input_path, result.result);
+            assert!(fs::metadata(&job.output_path).unwrap().len() > 0);
+        }
+
+        let _ = fs::remove_dir_all(&out);
+        for dir in dirs {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
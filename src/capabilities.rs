@@ -0,0 +1,67 @@
+//! Version and capability summary for tools wrapping the CLI
+//!
+//! Lists the output formats, cartridge types and optional features compiled
+//! into this build, so wrappers don't have to parse the help text.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use crate::config::VERSION;
+use crate::crt_builder::CartridgeType;
+
+/// Output formats by their CLI flag names
+pub const OUTPUT_FORMATS: [&str; 3] = ["prg", "crt", "magic-desk"];
+
+/// Compressors the restore code can decompress
+pub const COMPRESSORS: [&str; 1] = ["lzsa1"];
+
+/// Cargo features enabled in this build
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "vice-monitor") {
+        features.push("vice-monitor");
+    }
+    if cfg!(feature = "sha256") {
+        features.push("sha256");
+    }
+    features
+}
+
+/// Version and capabilities as JSON (`--version --json`)
+pub fn capabilities_json() -> String {
+    let quoted = |names: &[&str]| -> String {
+        names.iter().map(|name| format!("\"{}\"", name)).collect::<Vec<_>>().join(", ")
+    };
+    let cartridges: Vec<String> = CartridgeType::ALL
+        .iter()
+        .map(|cart| format!(
+            "    {{ \"name\": \"{}\", \"hardware_type\": {} }}",
+            cart.name(),
+            cart.hardware_type()
+        ))
+        .collect();
+
+    format!(
+        "{{\n  \"version\": \"{}\",\n  \"formats\": [{}],\n  \"cartridge_types\": [\n{}\n  ],\n  \"compressors\": [{}],\n  \"features\": [{}]\n}}\n",
+        VERSION,
+        quoted(&OUTPUT_FORMATS),
+        cartridges.join(",\n"),
+        quoted(&COMPRESSORS),
+        quoted(&enabled_features())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_json_lists_formats_and_version() {
+        let json = capabilities_json();
+        assert!(json.contains(&format!("\"version\": \"{}\"", VERSION)));
+        assert!(json.contains("\"prg\""));
+        assert!(json.contains("\"crt\""));
+        assert!(json.contains("{ \"name\": \"easyflash\", \"hardware_type\": 32 }"));
+        assert_eq!(json.contains("\"vice-monitor\""), cfg!(feature = "vice-monitor"));
+    }
+}
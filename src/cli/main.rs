@@ -5,14 +5,60 @@
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
-use vice_snapshot_to_prg_converter::config::{Config, CrtConfig, VERSION};
+use vice_snapshot_to_prg_converter::bank_usage::{format_bank_usage, BankUsage};
+use vice_snapshot_to_prg_converter::batch::{run_batch, BatchJob};
+use vice_snapshot_to_prg_converter::capabilities::capabilities_json;
+use vice_snapshot_to_prg_converter::conversion_cache::ConversionCache;
+use vice_snapshot_to_prg_converter::config::{Config, CrtConfig, DEFAULT_MIN_MATCH_SIZE, VERSION};
 use vice_snapshot_to_prg_converter::convert_snapshot::ConvertSnapshot;
 use vice_snapshot_to_prg_converter::convert_snapshot_crt::ConvertSnapshotCRT;
 use vice_snapshot_to_prg_converter::convert_snapshot_magic_desk_crt::ConvertSnapshotMagicDeskCRT;
+use vice_snapshot_to_prg_converter::crt_builder::{
+    cartridge_name_from_path, cartridge_name_or_default, output_stem, DEFAULT_CARTRIDGE_NAME,
+};
+use vice_snapshot_to_prg_converter::file_system_manager::{c64_name, FileSystemManager};
+use vice_snapshot_to_prg_converter::make_prg_asm::PRG_LOAD_ADDRESS;
+use vice_snapshot_to_prg_converter::make_romh_asm::ef_name_warning;
+use vice_snapshot_to_prg_converter::output_hash::{hash_file, write_sidecar, HashAlgorithm, OutputHashes};
+use vice_snapshot_to_prg_converter::parse_vsf::{
+    is_sid2_address, ColorRamSource, CompressionQuality, ParseVSF, ParserConfig, VicRestoreOrder,
+    VideoStandard, MIN_MATCH_SIZES, REQUIRED_MODULES,
+};
+use vice_snapshot_to_prg_converter::patch_mem::BlockLayout;
+use vice_snapshot_to_prg_converter::report::ConversionReport;
+use vice_snapshot_to_prg_converter::snapshot_checks::{check_hook_address, check_prg, check_snapshot};
+use vice_snapshot_to_prg_converter::snapshot_diff::{diff_ram, restore_scratch_regions};
+#[cfg(feature = "vice-monitor")]
+use vice_snapshot_to_prg_converter::vice_monitor;
+use vice_snapshot_to_prg_converter::vsf_export::roundtrip_differences;
+
+/// Set by `--quiet`: only errors are printed
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// `println!` unless `--quiet`
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `eprintln!` for warnings, unless `--quiet`
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
 
 #[derive(Debug, PartialEq)]
 enum OutputFormat {
@@ -26,8 +72,59 @@ struct CliArgs {
     output_path: String,
     format: OutputFormat,
     cartridge_name: Option<String>,
+    name_from_filename: bool,
     include_dir: Option<String>,
     hook_addr: Option<u16>,
+    loader_origin: Option<u16>,
+    pad_prg: Option<u16>,
+    both: bool,
+    extract_dir: Option<String>,
+    list_dir: Option<String>,
+    diff: bool,
+    validate: bool,
+    batch_dir: Option<String>,
+    batch_inputs: Vec<String>,
+    jobs: usize,
+    cache: Option<String>,
+    cache_settings: String,
+    easyflash_hardware: bool,
+    ef3: bool,
+    vic_restore_order: VicRestoreOrder,
+    retime: Option<VideoStandard>,
+    retime_timer_b: bool,
+    verbose: bool,
+    quiet: bool,
+    irq_off: bool,
+    no_sid: bool,
+    raw_small: bool,
+    sanitize_zp: bool,
+    blank_on_restore: bool,
+    force_display_on: bool,
+    defer_timers: bool,
+    rearm_raster_irq: bool,
+    save_iec: bool,
+    autostart: Option<String>,
+    loading_screen: Option<String>,
+    report: Option<String>,
+    strict: bool,
+    force: bool,
+    magic_desk_load: bool,
+    split_bins: Option<String>,
+    dump_asm: Option<String>,
+    support_bundle: Option<String>,
+    bundle_snapshot: bool,
+    roundtrip_check: bool,
+    hash: Option<HashAlgorithm>,
+    hash_sidecar: bool,
+    raw: bool,
+    compression: CompressionQuality,
+    min_match_size: u8,
+    verify: Option<(u16, u8)>,
+    prepend: Option<String>,
+    block_layout: BlockLayout,
+    color_ram_source: ColorRamSource,
+    sid2_addr: Option<u16>,
+    from_monitor: Option<String>,
 }
 
 fn main() {
@@ -39,7 +136,17 @@ fn main() {
         process::exit(0);
     }
 
-    let cli_args = match parse_args(&args) {
+    // Version query; --json adds the capabilities for wrapping tools
+    if args.contains(&"--version".to_string()) {
+        if args.contains(&"--json".to_string()) {
+            print!("{}", capabilities_json());
+        } else {
+            println!("VICE Snapshot to PRG/CRT Converter v{}", VERSION);
+        }
+        process::exit(0);
+    }
+
+    let mut cli_args = match parse_args(&args) {
         Ok(args) => args,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -48,6 +155,75 @@ fn main() {
             process::exit(1);
         }
     };
+    QUIET.store(cli_args.quiet, Ordering::Relaxed);
+
+    if let Some(target) = cli_args.retime {
+        warn!("Warning: --retime scales CIA1 timers for {} if the snapshot was taken on another standard.", target.name());
+        warn!("         This is a heuristic: raster timing, VIC-II line counts and music pitch are not adjusted.");
+        warn!();
+    }
+
+    // Preview mode: show the include-dir allocation plan, build nothing
+    if let Some(ref dir) = cli_args.list_dir {
+        info!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
+        info!();
+        match list_files(dir) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Diff mode: compare the RAM of two snapshots, convert nothing (exit 1 if they differ)
+    if cli_args.diff {
+        info!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
+        info!();
+        match diff_snapshot_files(&cli_args.input_path, &cli_args.output_path) {
+            Ok(0) => process::exit(0),
+            Ok(_) => process::exit(1),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
+    // Validate mode: check the snapshot parses, convert nothing (exit 1 if it doesn't)
+    if cli_args.validate {
+        match validate_snapshot(&cli_args.input_path) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                println!("Invalid: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Batch mode: convert every input into the output directory
+    if let Some(ref dir) = cli_args.batch_dir {
+        info!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
+        info!();
+        let failed = convert_batch(&cli_args, dir);
+        process::exit(if failed == 0 { 0 } else { 1 });
+    }
+
+    // Monitor mode: read the running machine into a temporary VSF and convert that
+    let monitor_dir = match cli_args.from_monitor.clone() {
+        Some(addr) => match fetch_from_monitor(&addr) {
+            Ok((dir, vsf)) => {
+                info!("Read snapshot from VICE monitor at {}", addr);
+                cli_args.input_path = vsf;
+                Some(dir)
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
 
     // Validate input file
     if !Path::new(&cli_args.input_path).exists() {
@@ -56,52 +232,45 @@ fn main() {
     }
 
     if !cli_args.input_path.to_lowercase().ends_with(".vsf") {
-        eprintln!("Warning: Input file does not have .vsf extension");
-        eprintln!();
+        emit_warnings(&["Input file does not have .vsf extension".to_string()], cli_args.strict);
     }
 
-    // Validate output extension matches format
-    let output_lower = cli_args.output_path.to_lowercase();
-    match cli_args.format {
-        OutputFormat::Prg if !output_lower.ends_with(".prg") => {
-            eprintln!("Warning: Output file does not have .prg extension");
-            eprintln!();
-        }
-        OutputFormat::Crt | OutputFormat::MagicDeskCrt if !output_lower.ends_with(".crt") => {
-            eprintln!("Warning: Output file does not have .crt extension");
-            eprintln!();
+    // Components-only mode: stop after compression, skip PRG/CRT generation
+    if let Some(ref dir) = cli_args.extract_dir {
+        info!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
+        info!();
+        info!("Input:   {}", cli_args.input_path);
+        info!("Extract: {}", dir);
+        if cli_args.verbose {
+            print_snapshot_info(&cli_args.input_path);
         }
-        _ => {}
-    }
+        info!();
 
-    // Warn if CRT-only options used with PRG
-    if cli_args.format == OutputFormat::Prg {
-        if cli_args.include_dir.is_some() {
-            eprintln!("Warning: --include-dir is only used with EasyFlash CRT format, ignoring");
-            eprintln!();
-        }
-        if cli_args.hook_addr.is_some() {
-            eprintln!("Warning: --hook-addr is only used with EasyFlash CRT format, ignoring");
-            eprintln!();
+        match extract_components(&cli_args, dir) {
+            Ok(()) => {
+                info!("Success!");
+                info!("  Components written to: {}", dir);
+                info!();
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Extraction failed:");
+                eprintln!("  {}", e);
+                eprintln!();
+                process::exit(1);
+            }
         }
     }
 
-    // Warn if LOAD/SAVE options used with Magic Desk
-    if cli_args.format == OutputFormat::MagicDeskCrt {
-        if cli_args.include_dir.is_some() {
-            eprintln!("Warning: --include-dir is not supported with Magic Desk format, ignoring");
-            eprintln!();
-        }
-        if cli_args.hook_addr.is_some() {
-            eprintln!("Warning: --hook-addr is not supported with Magic Desk format, ignoring");
-            eprintln!();
-        }
-    }
+    emit_warnings(&option_warnings(&cli_args), cli_args.strict);
 
-    // Warn if hook-addr used without include-dir
-    if cli_args.hook_addr.is_some() && cli_args.include_dir.is_none() {
-        eprintln!("Warning: --hook-addr requires --include-dir, ignoring");
-        eprintln!();
+    // --force: convert a header the parser would reject, but say so
+    let forced = if cli_args.force { header_error(&cli_args.input_path) } else { None };
+    if let Some(ref e) = forced {
+        emit_warnings(
+            &[format!("--force: {}; converting anyway, the output may not work", e)],
+            cli_args.strict,
+        );
     }
 
     // Validate include directory exists
@@ -117,12 +286,28 @@ fn main() {
         }
     }
 
-    // Handle existing output file
-    if Path::new(&cli_args.output_path).exists() {
-        println!("Output file exists, overwriting: {}", cli_args.output_path);
-        if let Err(e) = std::fs::remove_file(&cli_args.output_path) {
-            eprintln!("Error: Failed to delete existing output file: {}", e);
-            process::exit(1);
+    let job = BatchJob {
+        input_path: cli_args.input_path.clone(),
+        output_path: cli_args.output_path.clone(),
+    };
+    let name = cartridge_name(&cli_args, &job).ok().flatten();
+
+    // Checked before an existing output is deleted, so --strict leaves it alone
+    let pre_warnings = snapshot_warnings(&cli_args, &cli_args.input_path, name.as_deref());
+    if cli_args.strict {
+        emit_warnings(&pre_warnings, true);
+    }
+
+    // Handle existing output files
+    let prg_output = prg_output_path(&cli_args);
+    let outputs = std::iter::once(&cli_args.output_path).chain(prg_output.iter().filter(|_| cli_args.both));
+    for path in outputs {
+        if Path::new(path).exists() {
+            info!("Output file exists, overwriting: {}", path);
+            if let Err(e) = std::fs::remove_file(path) {
+                eprintln!("Error: Failed to delete existing output file: {}", e);
+                process::exit(1);
+            }
         }
     }
 
@@ -132,34 +317,106 @@ fn main() {
         OutputFormat::MagicDeskCrt => "Magic Desk CRT",
     };
 
-    println!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
-    println!();
-    println!("Input:  {}", cli_args.input_path);
-    println!("Output: {} ({})", cli_args.output_path, format_str);
-    if let Some(ref name) = cli_args.cartridge_name {
-        println!("Name:   {}", name);
+    info!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION);
+    info!();
+    match cli_args.from_monitor {
+        Some(ref addr) => info!("Input:  VICE monitor at {}", addr),
+        None => info!("Input:  {}", cli_args.input_path),
+    }
+    info!("Output: {} ({})", cli_args.output_path, format_str);
+    if let (true, Some(path)) = (cli_args.both, &prg_output) {
+        info!("        {} (PRG)", path);
+    }
+    if let Some(ref name) = name {
+        info!("Name:   {}", name);
     }
     if let Some(ref dir) = cli_args.include_dir {
-        println!("Include: {}", dir);
+        info!("Include: {}", dir);
     }
     if let Some(addr) = cli_args.hook_addr {
-        println!("Hook:    ${:04X}", addr);
+        info!("Hook:    ${:04X}", addr);
     }
-    println!();
-    println!("Converting...");
+    if let Some(ref path) = cli_args.prepend {
+        info!("Prepend: {}", path);
+    }
+    if cli_args.easyflash_hardware && cli_args.format == OutputFormat::Crt {
+        info!("Layout:  EasyFlash hardware (EAPI stub)");
+    }
+    if cli_args.verbose {
+        print_snapshot_info(&cli_args.input_path);
+    }
+    info!();
+    if let (Some(dir), OutputFormat::Crt) = (&cli_args.include_dir, &cli_args.format) {
+        if let Ok((_, skipped)) = FileSystemManager::new(dir).read_prg_files_reporting() {
+            print_skipped_files(&skipped);
+        }
+    }
+    emit_warnings(&pre_warnings, cli_args.strict);
+    info!("Converting...");
 
-    let result = match cli_args.format {
-        OutputFormat::Prg => convert_prg(&cli_args),
-        OutputFormat::Crt => convert_crt(&cli_args),
-        OutputFormat::MagicDeskCrt => convert_magic_desk_crt(&cli_args),
+    let hashes = OutputHashes::start(cli_args.hash);
+    let result = match prg_output {
+        // PRG first, then the CRT from the same snapshot
+        Some(ref path) if cli_args.both => {
+            let prg_job = BatchJob { input_path: job.input_path.clone(), output_path: path.clone() };
+            convert_prg(&cli_args, &prg_job).and_then(|()| convert_one(&cli_args, &job))
+        }
+        _ => convert_one(&cli_args, &job),
     };
+    match write_support_bundle(&cli_args, &args, &result) {
+        Ok(()) => {
+            if let Some(ref dir) = cli_args.support_bundle {
+                info!("Support bundle written to: {}", dir);
+            }
+        }
+        Err(e) => warn!("Warning: {}", e),
+    }
+    // Before the monitor cleanup, which removes the temporary input
+    if cli_args.roundtrip_check && result.is_ok() {
+        roundtrip_check(&cli_args);
+    }
+    if let Some(ref dir) = monitor_dir {
+        let _ = cleanup_work_dir(dir);
+    }
 
     match result {
         Ok(()) => {
-            println!();
-            println!("Success!");
-            println!("  Snapshot converted to: {}", cli_args.output_path);
-            println!();
+            if let Some(ref path) = prg_output {
+                let warnings = check_prg(path);
+                if cli_args.strict && !warnings.is_empty() {
+                    // Don't leave behind outputs that --strict rejects
+                    discard_prg(&cli_args, path);
+                    if cli_args.both {
+                        let _ = std::fs::remove_file(&cli_args.output_path);
+                    }
+                    emit_warnings(&warnings, true);
+                }
+                for warning in warnings {
+                    warn!();
+                    warn!("Warning: {}", warning);
+                }
+            }
+            info!();
+            info!("Success!");
+            if let (true, Some(path)) = (cli_args.both, &prg_output) {
+                info!("  Snapshot converted to: {}", path);
+            }
+            info!("  Snapshot converted to: {}", cli_args.output_path);
+            if forced.is_some() {
+                info!("  Forced past an unsupported snapshot header: the output may be unreliable");
+            }
+            if let (true, Some(path)) = (cli_args.raw, &prg_output) {
+                info!(
+                    "  Raw binary:            {} (load at ${:04X})",
+                    ConvertSnapshot::raw_output_path(path),
+                    cli_args.loader_origin.unwrap_or(PRG_LOAD_ADDRESS)
+                );
+            }
+            if let (Some(origin), Some(_)) = (cli_args.loader_origin, &prg_output) {
+                info!("  Entry:                 ${:04X} (SYS {}, no BASIC stub)", origin, origin);
+            }
+            info!();
+            print_hashes(&cli_args, &hashes.take());
             process::exit(0);
         }
         Err(e) => {
@@ -175,9 +432,59 @@ fn main() {
 fn parse_args(args: &[String]) -> Result<CliArgs, String> {
     let mut format: Option<OutputFormat> = None;
     let mut cartridge_name: Option<String> = None;
+    let mut name_from_filename = false;
     let mut include_dir: Option<String> = None;
     let mut hook_addr: Option<u16> = None;
+    let mut loader_origin: Option<u16> = None;
+    let mut pad_prg: Option<u16> = None;
+    let mut both = false;
+    let mut extract_dir: Option<String> = None;
+    let mut list_dir: Option<String> = None;
+    let mut diff = false;
+    let mut validate = false;
+    let mut batch_dir: Option<String> = None;
+    let mut jobs: usize = 1;
+    let mut cache: Option<String> = None;
+    let mut easyflash_hardware = false;
+    let mut ef3 = false;
+    let mut vic_restore_order = VicRestoreOrder::Naive;
+    let mut retime: Option<VideoStandard> = None;
+    let mut retime_timer_b = false;
+    let mut verbose = false;
+    let mut quiet = false;
+    let mut irq_off = false;
+    let mut no_sid = false;
+    let mut raw_small = false;
+    let mut sanitize_zp = false;
+    let mut blank_on_restore = false;
+    let mut force_display_on = false;
+    let mut defer_timers = false;
+    let mut rearm_raster_irq = false;
+    let mut save_iec = false;
+    let mut autostart: Option<String> = None;
+    let mut loading_screen: Option<String> = None;
+    let mut report: Option<String> = None;
+    let mut strict = false;
+    let mut force = false;
+    let mut magic_desk_load = false;
+    let mut split_bins: Option<String> = None;
+    let mut dump_asm: Option<String> = None;
+    let mut support_bundle: Option<String> = None;
+    let mut bundle_snapshot = false;
+    let mut roundtrip_check = false;
+    let mut hash: Option<HashAlgorithm> = None;
+    let mut hash_sidecar = false;
+    let mut raw = false;
+    let mut compression = CompressionQuality::default();
+    let mut min_match_size = DEFAULT_MIN_MATCH_SIZE;
+    let mut verify = None;
+    let mut prepend: Option<String> = None;
+    let mut block_layout = BlockLayout::default();
+    let mut color_ram_source = ColorRamSource::default();
+    let mut sid2_addr: Option<u16> = None;
+    let mut from_monitor: Option<String> = None;
     let mut positional: Vec<String> = Vec::new();
+    let mut positional_at: Vec<usize> = Vec::new();
 
     let mut i = 1;
     while i < args.len() {
@@ -202,6 +509,9 @@ fn parse_args(args: &[String]) -> Result<CliArgs, String> {
                 }
                 format = Some(OutputFormat::MagicDeskCrt);
             }
+            "--both" => {
+                both = true;
+            }
             "--name" => {
                 i += 1;
                 if i >= args.len() {
@@ -213,6 +523,9 @@ fn parse_args(args: &[String]) -> Result<CliArgs, String> {
                 }
                 cartridge_name = Some(name.clone());
             }
+            "--name-from-filename" => {
+                name_from_filename = true;
+            }
             "--include-dir" => {
                 i += 1;
                 if i >= args.len() {
@@ -220,6 +533,48 @@ fn parse_args(args: &[String]) -> Result<CliArgs, String> {
                 }
                 include_dir = Some(args[i].clone());
             }
+            "--split-bins" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--split-bins requires a directory".to_string());
+                }
+                split_bins = Some(args[i].clone());
+            }
+            "--dump-asm" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--dump-asm requires a directory".to_string());
+                }
+                dump_asm = Some(args[i].clone());
+            }
+            "--support-bundle" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--support-bundle requires a directory".to_string());
+                }
+                support_bundle = Some(args[i].clone());
+            }
+            "--support-bundle-snapshot" => {
+                bundle_snapshot = true;
+            }
+            "--roundtrip-check" => {
+                roundtrip_check = true;
+            }
+            "--hash" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--hash requires crc32 or sha256".to_string());
+                }
+                let algorithm = HashAlgorithm::from_name(&args[i])
+                    .ok_or_else(|| format!("Unknown hash: {} (expected crc32 or sha256)", args[i]))?;
+                if !algorithm.is_available() {
+                    return Err("--hash sha256 needs a build with --features sha256; use --hash crc32".to_string());
+                }
+                hash = Some(algorithm);
+            }
+            "--hash-sidecar" => {
+                hash_sidecar = true;
+            }
             "--hook-addr" => {
                 i += 1;
                 if i >= args.len() {
@@ -230,22 +585,377 @@ fn parse_args(args: &[String]) -> Result<CliArgs, String> {
                     .map_err(|_| format!("Invalid hex address: {}", args[i]))?;
                 hook_addr = Some(addr);
             }
+            "--origin" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--origin requires a hex address".to_string());
+                }
+                let addr_str = args[i].trim_start_matches('$').trim_start_matches("0x");
+                let addr = u16::from_str_radix(addr_str, 16)
+                    .map_err(|_| format!("Invalid hex address: {}", args[i]))?;
+                loader_origin = Some(addr);
+            }
+            "--pad-prg" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--pad-prg requires a block size (e.g. 254)".to_string());
+                }
+                pad_prg = Some(args[i].parse::<u16>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid padding block size: {} (use 1-65535)", args[i]))?);
+            }
+            "--verify" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--verify requires $ADDR=$VAL".to_string());
+                }
+                let hex = |s: &str| s.trim_start_matches('$').trim_start_matches("0x").to_string();
+                let (addr_str, value_str) = args[i].split_once('=')
+                    .ok_or_else(|| format!("Invalid --verify check: {} (use $ADDR=$VAL)", args[i]))?;
+                let addr = u16::from_str_radix(&hex(addr_str), 16)
+                    .map_err(|_| format!("Invalid hex address: {}", addr_str))?;
+                let value = u8::from_str_radix(&hex(value_str), 16)
+                    .map_err(|_| format!("Invalid hex value: {}", value_str))?;
+                verify = Some((addr, value));
+            }
+            "--prepend" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--prepend requires a PRG file".to_string());
+                }
+                prepend = Some(args[i].clone());
+            }
+            "--extract" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--extract requires an output directory".to_string());
+                }
+                extract_dir = Some(args[i].clone());
+            }
+            "--list-files" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--list-files requires a directory".to_string());
+                }
+                list_dir = Some(args[i].clone());
+            }
+            "--diff" => {
+                diff = true;
+            }
+            "--validate" => {
+                validate = true;
+            }
+            "--batch" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--batch requires an output directory".to_string());
+                }
+                batch_dir = Some(args[i].clone());
+            }
+            "--cache" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--cache requires a directory".to_string());
+                }
+                cache = Some(args[i].clone());
+            }
+            "--from-monitor" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--from-monitor requires <host:port>".to_string());
+                }
+                from_monitor = Some(args[i].clone());
+            }
+            "--jobs" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--jobs requires a number".to_string());
+                }
+                jobs = args[i].parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid job count: {}", args[i]))?;
+            }
+            "--compress-fast" => {
+                compression = CompressionQuality::Fast;
+            }
+            "--min-match" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--min-match requires a size (3-5)".to_string());
+                }
+                min_match_size = args[i].parse::<u8>()
+                    .ok()
+                    .filter(|n| MIN_MATCH_SIZES.contains(n))
+                    .ok_or_else(|| format!("Invalid minimum match size: {} (use 3-5)", args[i]))?;
+            }
+            "--easyflash-hardware" => {
+                easyflash_hardware = true;
+            }
+            "--ef3" => {
+                ef3 = true;
+            }
+            "--safe-vic-order" => {
+                vic_restore_order = VicRestoreOrder::Safe;
+            }
+            "--retime" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--retime requires pal or ntsc".to_string());
+                }
+                retime = match args[i].to_lowercase().as_str() {
+                    "pal" => Some(VideoStandard::Pal),
+                    "ntsc" => Some(VideoStandard::Ntsc),
+                    _ => return Err(format!("Invalid video standard: {} (expected pal or ntsc)", args[i])),
+                };
+            }
+            "--block-layout" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--block-layout requires auto, standard, compact or split".to_string());
+                }
+                block_layout = match args[i].to_lowercase().as_str() {
+                    "auto" => BlockLayout::Auto,
+                    "standard" => BlockLayout::Standard,
+                    "compact" => BlockLayout::Compact,
+                    "split" => BlockLayout::Split,
+                    _ => {
+                        return Err(format!(
+                            "Invalid block layout: {} (expected auto, standard, compact or split)",
+                            args[i]
+                        ))
+                    }
+                };
+            }
+            "--sid2-addr" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--sid2-addr requires a hex address".to_string());
+                }
+                let addr_str = args[i].trim_start_matches('$').trim_start_matches("0x");
+                let addr = u16::from_str_radix(addr_str, 16)
+                    .map_err(|_| format!("Invalid hex address: {}", args[i]))?;
+                if !is_sid2_address(addr) {
+                    return Err(format!(
+                        "--sid2-addr {} is not a second SID address ($D420-$D7E0 or $DE00-$DFE0, in steps of $20)",
+                        args[i]
+                    ));
+                }
+                sid2_addr = Some(addr);
+            }
+            "--color-ram" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--color-ram requires auto, vic or ram".to_string());
+                }
+                color_ram_source = match args[i].to_lowercase().as_str() {
+                    "auto" => ColorRamSource::Auto,
+                    "vic" => ColorRamSource::VicModule,
+                    "ram" => ColorRamSource::MainRam,
+                    _ => {
+                        return Err(format!(
+                            "Invalid color RAM source: {} (expected auto, vic or ram)",
+                            args[i]
+                        ))
+                    }
+                };
+            }
+            "--retime-timer-b" => {
+                retime_timer_b = true;
+            }
+            "--autostart" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--autostart requires a file name".to_string());
+                }
+                autostart = Some(args[i].clone());
+            }
+            "--loading-screen" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--loading-screen requires a file".to_string());
+                }
+                loading_screen = Some(args[i].clone());
+            }
+            "--save-iec" => {
+                save_iec = true;
+            }
+            "--report" => {
+                i += 1;
+                if i >= args.len() {
+                    return Err("--report requires a file name (or - for stderr)".to_string());
+                }
+                report = Some(args[i].clone());
+            }
+            "--irq-off" => {
+                irq_off = true;
+            }
+            "--no-sid" => {
+                no_sid = true;
+            }
+            "--raw-small" => {
+                raw_small = true;
+            }
+            "--sanitize-zp" => {
+                sanitize_zp = true;
+            }
+            "--blank-on-restore" => {
+                blank_on_restore = true;
+            }
+            "--force-display-on" => {
+                force_display_on = true;
+            }
+            "--defer-timers" => {
+                defer_timers = true;
+            }
+            "--rearm-raster-irq" => {
+                rearm_raster_irq = true;
+            }
+            "--verbose" | "-v" => {
+                verbose = true;
+            }
+            "--quiet" | "-q" => {
+                quiet = true;
+            }
+            "--strict" => {
+                strict = true;
+            }
+            "--force" => {
+                force = true;
+            }
+            "--magic-desk-load" => {
+                magic_desk_load = true;
+            }
+            "--raw" => {
+                raw = true;
+            }
             _ if arg.starts_with('-') => {
                 return Err(format!("Unknown option: {}", arg));
             }
             _ => {
                 positional.push(arg.clone());
+                positional_at.push(i);
             }
         }
         i += 1;
     }
 
-    if positional.len() != 2 {
+    if retime_timer_b && retime.is_none() {
+        return Err("--retime-timer-b requires --retime".to_string());
+    }
+
+    if blank_on_restore && force_display_on {
+        return Err("--blank-on-restore and --force-display-on cannot be combined".to_string());
+    }
+
+    if quiet && verbose {
+        return Err("Cannot combine --quiet and --verbose".to_string());
+    }
+
+    if validate && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff || from_monitor.is_some()) {
+        return Err("--validate only checks a single snapshot file".to_string());
+    }
+
+    if report.is_some() && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff || validate || both) {
+        return Err("--report only applies to a single conversion".to_string());
+    }
+
+    if support_bundle.is_some() && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff || validate || both) {
+        return Err("--support-bundle only applies to a single conversion".to_string());
+    }
+
+    if support_bundle.is_some() && dump_asm.is_some() {
+        return Err("--support-bundle already includes the assembler sources; drop --dump-asm".to_string());
+    }
+
+    if bundle_snapshot && support_bundle.is_none() {
+        return Err("--support-bundle-snapshot requires --support-bundle".to_string());
+    }
+
+    if hash_sidecar && hash.is_none() {
+        return Err("--hash-sidecar requires --hash".to_string());
+    }
+
+    if roundtrip_check && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff || validate) {
+        return Err("--roundtrip-check only applies to a single conversion".to_string());
+    }
+
+    if cache.is_some() && batch_dir.is_none() {
+        return Err("--cache only applies to --batch".to_string());
+    }
+
+    if both && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff || validate) {
+        return Err("--both only applies to a single conversion".to_string());
+    }
+
+    if both && format == Some(OutputFormat::Prg) {
+        return Err("--both already writes a PRG; choose the CRT with --crt or --magic-desk".to_string());
+    }
+
+    if name_from_filename && cartridge_name.is_some() {
+        return Err("--name and --name-from-filename cannot be combined".to_string());
+    }
+
+    if name_from_filename && from_monitor.is_some() {
+        return Err("--name-from-filename needs an input file, not --from-monitor".to_string());
+    }
+
+    if from_monitor.is_some() && (list_dir.is_some() || batch_dir.is_some() || extract_dir.is_some() || diff) {
+        return Err("--from-monitor only applies to a single conversion".to_string());
+    }
+
+    if list_dir.is_some() {
+        if !positional.is_empty() {
+            return Err("--list-files takes no other arguments".to_string());
+        }
+    } else if batch_dir.is_some() {
+        if positional.is_empty() {
+            return Err("--batch expects at least 1 input: <input.vsf>...".to_string());
+        }
+    } else if extract_dir.is_some() {
+        if positional.len() != 1 {
+            return Err("--extract expects exactly 1 argument: <input.vsf>".to_string());
+        }
+    } else if validate {
+        if positional.len() != 1 {
+            return Err("--validate expects exactly 1 argument: <input.vsf>".to_string());
+        }
+    } else if diff {
+        if positional.len() != 2 {
+            return Err("--diff expects exactly 2 arguments: <expected.vsf> <actual.vsf>".to_string());
+        }
+    } else if from_monitor.is_some() {
+        if positional.len() != 1 {
+            return Err("--from-monitor expects exactly 1 argument: <output>".to_string());
+        }
+    } else if positional.len() != 2 {
         return Err("Expected exactly 2 arguments: <input.vsf> <output>".to_string());
     }
 
-    let input_path = positional[0].clone();
-    let output_path = positional[1].clone();
+    // With --from-monitor the input is fetched later; the only argument is the output
+    let (input_path, output_path) = if from_monitor.is_some() {
+        (String::new(), positional[0].clone())
+    } else {
+        (
+            positional.first().cloned().unwrap_or_default(),
+            positional.get(1).cloned().unwrap_or_default(),
+        )
+    };
+    let cache_settings = cache_settings(args, &positional_at);
+    let batch_inputs = if batch_dir.is_some() { positional } else { Vec::new() };
+
+    // The bundle collects the assembler sources through --dump-asm
+    if let Some(ref dir) = support_bundle {
+        dump_asm = Some(Path::new(dir).join("asm").to_string_lossy().into_owned());
+    }
+
+    // With --both the output is a base name: the CRT goes to <base>.crt, the PRG next to it
+    let output_path = if both {
+        Path::new(&output_path).with_extension("crt").to_string_lossy().into_owned()
+    } else {
+        output_path
+    };
 
     // Auto-detect format from output extension if not specified
     let format = format.unwrap_or_else(|| {
@@ -261,29 +971,383 @@ fn parse_args(args: &[String]) -> Result<CliArgs, String> {
         output_path,
         format,
         cartridge_name,
+        name_from_filename,
         include_dir,
         hook_addr,
+        loader_origin,
+        pad_prg,
+        both,
+        extract_dir,
+        list_dir,
+        diff,
+        validate,
+        batch_dir,
+        batch_inputs,
+        jobs,
+        cache,
+        cache_settings,
+        easyflash_hardware,
+        ef3,
+        vic_restore_order,
+        retime,
+        retime_timer_b,
+        verbose,
+        quiet,
+        irq_off,
+        no_sid,
+        raw_small,
+        sanitize_zp,
+        blank_on_restore,
+        force_display_on,
+        defer_timers,
+        rearm_raster_irq,
+        save_iec,
+        autostart,
+        loading_screen,
+        report,
+        strict,
+        force,
+        magic_desk_load,
+        split_bins,
+        dump_asm,
+        support_bundle,
+        bundle_snapshot,
+        roundtrip_check,
+        hash,
+        hash_sidecar,
+        raw,
+        compression,
+        min_match_size,
+        verify,
+        prepend,
+        block_layout,
+        color_ram_source,
+        sid2_addr,
+        from_monitor,
     })
 }
 
-fn convert_prg(cli_args: &CliArgs) -> Result<(), String> {
+/// Arguments that change what a conversion writes, for the `--cache` key
+///
+/// Everything except the inputs and the options that only change how the
+/// batch runs or what is printed.
+///
+/// `positional_at` are the indices of the inputs in `args`, so an option value
+/// that happens to equal an input path is still part of the settings.
+fn cache_settings(args: &[String], positional_at: &[usize]) -> String {
+    let mut settings = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            _ if positional_at.contains(&i) => {}
+            "--cache" | "--batch" | "--jobs" | "--hash" => i += 1,
+            "--quiet" | "-q" | "--verbose" | "-v" | "--hash-sidecar" => {}
+            arg => settings.push(arg),
+        }
+        i += 1;
+    }
+    settings.join("\n")
+}
+
+/// Every file a conversion of `job` writes, the output first, for the `--cache` entry
+fn cache_outputs(cli_args: &CliArgs, job: &BatchJob) -> Vec<PathBuf> {
+    let output = PathBuf::from(&job.output_path);
+    let prg = match cli_args.format {
+        OutputFormat::Prg => Some(output.clone()),
+        _ if cli_args.both => Some(output.with_extension("prg")),
+        _ => None,
+    };
+    let mut files = vec![output.clone()];
+    if let (true, Some(prg)) = (cli_args.both, &prg) {
+        files.push(prg.clone());
+    }
+    if let (true, Some(prg)) = (cli_args.raw, &prg) {
+        files.push(PathBuf::from(ConvertSnapshot::raw_output_path(&prg.to_string_lossy())));
+    }
+    if let (Some(dir), false) = (&cli_args.split_bins, cli_args.format == OutputFormat::Prg) {
+        let stem = output_stem(&job.output_path);
+        files.push(Path::new(dir).join(format!("{}_roml.bin", stem)));
+        if cli_args.format == OutputFormat::Crt {
+            files.push(Path::new(dir).join(format!("{}_romh.bin", stem)));
+        }
+    }
+    if let (Some(algorithm), true) = (cli_args.hash, cli_args.hash_sidecar) {
+        let mut sidecar = output.into_os_string();
+        sidecar.push(format!(".{}", algorithm.name()));
+        files.push(PathBuf::from(sidecar));
+    }
+    files
+}
+
+/// Files besides the snapshot that a conversion reads, for the `--cache` key
+fn cache_extra_files(cli_args: &CliArgs) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = cli_args.loading_screen.iter().chain(cli_args.prepend.iter()).map(PathBuf::from).collect();
+    if let Some(ref dir) = cli_args.include_dir {
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            let mut included: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).filter(|path| path.is_file()).collect();
+            included.sort();
+            files.extend(included);
+        }
+    }
+    files
+}
+
+/// Where the PRG goes: the output itself, or `<base>.prg` next to the CRT with `--both`
+fn prg_output_path(cli_args: &CliArgs) -> Option<String> {
+    if cli_args.both {
+        Some(Path::new(&cli_args.output_path).with_extension("prg").to_string_lossy().into_owned())
+    } else if cli_args.format == OutputFormat::Prg {
+        Some(cli_args.output_path.clone())
+    } else {
+        None
+    }
+}
+
+fn convert_one(cli_args: &CliArgs, job: &BatchJob) -> Result<(), String> {
+    match cli_args.format {
+        OutputFormat::Prg => convert_prg(cli_args, job),
+        OutputFormat::Crt => convert_crt(cli_args, job),
+        OutputFormat::MagicDeskCrt => convert_magic_desk_crt(cli_args, job),
+    }
+}
+
+/// Convert all batch inputs on `--jobs` threads; returns the number of failures
+fn convert_batch(cli_args: &CliArgs, out_dir: &str) -> usize {
+    let extension = match cli_args.format {
+        OutputFormat::Prg => "prg",
+        OutputFormat::Crt | OutputFormat::MagicDeskCrt => "crt",
+    };
+
+    if let Err(e) = std::fs::create_dir_all(out_dir) {
+        eprintln!("Error: Failed to create output directory {}: {}", out_dir, e);
+        return cli_args.batch_inputs.len();
+    }
+
+    let jobs: Vec<BatchJob> = cli_args.batch_inputs
+        .iter()
+        .map(|input| {
+            let stem = Path::new(input)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("snapshot");
+            BatchJob {
+                input_path: input.clone(),
+                output_path: Path::new(out_dir)
+                    .join(format!("{}.{}", stem, extension))
+                    .to_string_lossy()
+                    .into_owned(),
+            }
+        })
+        .collect();
+
+    // Inputs with the same name from different directories would race for one output
+    let mut outputs = HashSet::new();
+    if let Some(job) = jobs.iter().find(|job| !outputs.insert(job.output_path.as_str())) {
+        eprintln!("Error: More than one input would be written to {}; rename one of them", job.output_path);
+        return jobs.len();
+    }
+
+    let cache = match cli_args.cache {
+        Some(ref dir) => match ConversionCache::open(dir) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return cli_args.batch_inputs.len();
+            }
+        },
+        None => None,
+    };
+
+    // Cache key of each job, and whether its outputs were already built from
+    // it; a missing input gets no key and fails in the conversion as usual
+    let extra_files = if cache.is_some() { cache_extra_files(cli_args) } else { Vec::new() };
+    let keys: Vec<Option<String>> = jobs
+        .iter()
+        .map(|job| {
+            cache.as_ref()?;
+            ConversionCache::key(Path::new(&job.input_path), &extra_files, &cli_args.cache_settings).ok()
+        })
+        .collect();
+    let fresh: Vec<bool> = jobs
+        .iter()
+        .zip(&keys)
+        .map(|(job, key)| match (&cache, key) {
+            (Some(cache), Some(key)) => {
+                let outputs = cache_outputs(cli_args, job);
+                cache.is_fresh(&outputs.iter().map(PathBuf::as_path).collect::<Vec<_>>(), key)
+            }
+            _ => false,
+        })
+        .collect();
+    let stale: Vec<BatchJob> = jobs.iter().zip(&fresh).filter(|(_, fresh)| !**fresh).map(|(job, _)| job.clone()).collect();
+
+    info!("Batch:  {} file(s) -> {} ({} job(s))", jobs.len(), out_dir, cli_args.jobs);
+    info!();
+
+    // Hashes of what each job wrote, by output path
+    let written: Mutex<HashMap<String, Vec<(PathBuf, String)>>> = Mutex::new(HashMap::new());
+    let results = run_batch(&stale, cli_args.jobs, |job| {
+        let hashes = OutputHashes::start(cli_args.hash);
+        if !Path::new(&job.input_path).exists() {
+            return Err(format!("Input file not found: {}", job.input_path));
+        }
+        if cli_args.strict {
+            let name = cartridge_name(cli_args, job).ok().flatten();
+            strict_check(&snapshot_warnings(cli_args, &job.input_path, name.as_deref()))?;
+        }
+        if Path::new(&job.output_path).exists() {
+            std::fs::remove_file(&job.output_path)
+                .map_err(|e| format!("Failed to delete existing output file: {}", e))?;
+        }
+        convert_one(cli_args, job)?;
+        if cli_args.strict && cli_args.format == OutputFormat::Prg {
+            strict_check(&check_prg(&job.output_path)).inspect_err(|_| discard_prg(cli_args, &job.output_path))?;
+        }
+        written.lock().unwrap_or_else(|e| e.into_inner()).insert(job.output_path.clone(), hashes.take());
+        Ok(())
+    });
+    let mut written = written.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    // Report in input order: cached jobs in place, the converted ones in between
+    let mut results = results.into_iter();
+    let mut failed = 0;
+    let mut skipped = 0;
+    for ((job, key), fresh) in jobs.iter().zip(&keys).zip(&fresh) {
+        if *fresh {
+            skipped += 1;
+            info!("  CACHED  {} -> {}", job.input_path, job.output_path);
+            // Nothing was written this run, so hash the file on disk; the
+            // cache entry has just checked it is the one it recorded
+            if let Some(algorithm) = cli_args.hash {
+                match hash_file(algorithm, Path::new(&job.output_path)) {
+                    Ok(hex) => print_hashes(cli_args, &[(PathBuf::from(&job.output_path), hex)]),
+                    Err(e) => warn!("Warning: {}", e),
+                }
+            }
+            continue;
+        }
+        let Some(result) = results.next() else { break };
+        match &result.result {
+            Ok(()) => {
+                info!("  OK      {} -> {}", job.input_path, job.output_path);
+                print_hashes(cli_args, &written.remove(&job.output_path).unwrap_or_default());
+                // After the sidecar is written, so the entry covers it
+                if let (Some(cache), Some(key)) = (&cache, key) {
+                    let outputs = cache_outputs(cli_args, job);
+                    if let Err(e) = cache.record(&outputs.iter().map(PathBuf::as_path).collect::<Vec<_>>(), key) {
+                        warn!("Warning: {}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                if QUIET.load(Ordering::Relaxed) {
+                    eprintln!("Error: {}: {}", job.input_path, e);
+                } else {
+                    println!("  FAILED  {}: {}", job.input_path, e);
+                }
+            }
+        }
+    }
+    info!();
+    let converted = jobs.len() - failed - skipped;
+    if cache.is_some() {
+        info!("{} converted, {} cached, {} failed", converted, skipped, failed);
+    } else {
+        info!("{} converted, {} failed", converted, failed);
+    }
+    info!();
+    failed
+}
+
+/// Apply the options shared by every output format
+fn base_options(cli_args: &CliArgs, mut config: Config) -> Config {
+    config = config
+        .with_vic_restore_order(cli_args.vic_restore_order)
+        .with_irq_off(cli_args.irq_off)
+        .with_no_sid(cli_args.no_sid)
+        .with_raw_small(cli_args.raw_small)
+        .with_sanitize_zp(cli_args.sanitize_zp)
+        .with_blank_on_restore(cli_args.blank_on_restore)
+        .with_force_display_on(cli_args.force_display_on)
+        .with_defer_timers(cli_args.defer_timers)
+        .with_rearm_raster_irq(cli_args.rearm_raster_irq)
+        .with_compression(cli_args.compression)
+        .with_min_match_size(cli_args.min_match_size)
+        .with_block_layout(cli_args.block_layout)
+        .with_parser_config(ParserConfig {
+            color_ram_source: cli_args.color_ram_source,
+            force: cli_args.force,
+            sid2_addr: cli_args.sid2_addr,
+            ..ParserConfig::default_vice_like()
+        });
+    if let Some(target) = cli_args.retime {
+        config = config.with_retime(target, cli_args.retime_timer_b);
+    }
+    if let Some((addr, value)) = cli_args.verify {
+        config = config.with_verify(addr, value);
+    }
+    if let Some(ref path) = cli_args.prepend {
+        config = config.with_prepend(path);
+    }
+    if let Some(origin) = cli_args.loader_origin {
+        config = config.with_loader_origin(origin);
+    }
+    if let Some(block) = cli_args.pad_prg {
+        config = config.with_pad_prg(block);
+    }
+    config
+}
+
+/// `--dump-asm` directory for `job`; batch jobs get a subdirectory named after their output
+fn dump_asm_options(cli_args: &CliArgs, job: &BatchJob, config: Config) -> Config {
+    let Some(ref dir) = cli_args.dump_asm else {
+        return config;
+    };
+    let dir = Path::new(dir);
+    if cli_args.batch_dir.is_some() {
+        let stem = Path::new(&job.output_path).file_stem().unwrap_or_default();
+        config.with_dump_asm(dir.join(stem))
+    } else {
+        config.with_dump_asm(dir)
+    }
+}
+
+/// `--name`, or the input's file stem with `--name-from-filename`
+fn cartridge_name(cli_args: &CliArgs, job: &BatchJob) -> Result<Option<String>, String> {
+    if cli_args.name_from_filename {
+        return cartridge_name_from_path(Path::new(&job.input_path)).map(Some);
+    }
+    Ok(cli_args.cartridge_name.clone())
+}
+
+fn convert_prg(cli_args: &CliArgs, job: &BatchJob) -> Result<(), String> {
     let config = Config::auto()
         .map_err(|e| format!("Failed to initialize: {}", e))?;
+    let config = base_options(cli_args, config);
+    let config = dump_asm_options(cli_args, job, config);
 
     let work_path = config.work_path.clone();
-    let converter = ConvertSnapshot::new(config);
-    let result = converter.convert(&cli_args.input_path, &cli_args.output_path);
+    let converter = ConvertSnapshot::new(config.clone()).with_raw_output(cli_args.raw);
+    let result = converter.convert(&job.input_path, &job.output_path);
+    if result.is_ok() {
+        write_report(cli_args, job, "PRG", &config, &[]);
+    }
 
     let _ = cleanup_work_dir(&work_path);
     result
 }
 
-fn convert_crt(cli_args: &CliArgs) -> Result<(), String> {
+fn convert_crt(cli_args: &CliArgs, job: &BatchJob) -> Result<(), String> {
     let mut config = CrtConfig::auto()
         .map_err(|e| format!("Failed to initialize: {}", e))?;
 
-    if let Some(ref name) = cli_args.cartridge_name {
-        config = config.with_cartridge_name(name);
+    if let Some(name) = cartridge_name(cli_args, job)? {
+        config = config.with_cartridge_name(&name);
+    }
+
+    if let Some(ref dir) = cli_args.split_bins {
+        config = config.with_split_bins(dir);
     }
 
     if let Some(ref dir) = cli_args.include_dir {
@@ -294,30 +1358,559 @@ fn convert_crt(cli_args: &CliArgs) -> Result<(), String> {
         config = config.with_trampoline_address(addr);
     }
 
+    if let Some(ref name) = cli_args.autostart {
+        config = config.with_autostart(name);
+    }
+
+    if let Some(ref path) = cli_args.loading_screen {
+        config = config.with_loading_screen(path);
+    }
+
+    config = config
+        .with_easyflash_hardware(cli_args.easyflash_hardware)
+        .with_ef3(cli_args.ef3)
+        .with_save_passthrough(cli_args.save_iec);
+    config.base_config = base_options(cli_args, config.base_config);
+    config.base_config = dump_asm_options(cli_args, job, config.base_config);
+
     let work_path = config.base_config.work_path.clone();
+    let base_config = config.base_config.clone();
     let converter = ConvertSnapshotCRT::new(config);
-    let result = converter.convert(&cli_args.input_path, &cli_args.output_path);
+    let result = converter.convert_with_usage(&job.input_path, &job.output_path)
+        .map(|usage| {
+            print_bank_usage(cli_args, &usage.banks);
+            write_report(cli_args, job, "EasyFlash CRT", &base_config, &usage.files);
+        });
 
     let _ = cleanup_work_dir(&work_path);
     result
 }
 
-fn convert_magic_desk_crt(cli_args: &CliArgs) -> Result<(), String> {
+fn convert_magic_desk_crt(cli_args: &CliArgs, job: &BatchJob) -> Result<(), String> {
     let mut config = CrtConfig::auto()
         .map_err(|e| format!("Failed to initialize: {}", e))?;
 
-    if let Some(ref name) = cli_args.cartridge_name {
-        config = config.with_cartridge_name(name);
+    if let Some(name) = cartridge_name(cli_args, job)? {
+        config = config.with_cartridge_name(&name);
     }
 
+    if let Some(ref dir) = cli_args.split_bins {
+        config = config.with_split_bins(dir);
+    }
+
+    let ram_load_dir = match cli_args.include_dir {
+        Some(ref dir) if cli_args.magic_desk_load => Some(dir.as_str()),
+        _ => None,
+    };
+    if let Some(dir) = ram_load_dir {
+        config = config.with_include_dir(dir).with_magic_desk_ram_load(true);
+    }
+
+    config.base_config = base_options(cli_args, config.base_config);
+    config.base_config = dump_asm_options(cli_args, job, config.base_config);
+
     let work_path = config.base_config.work_path.clone();
+    let base_config = config.base_config.clone();
     let converter = ConvertSnapshotMagicDeskCRT::new(config);
-    let result = converter.convert(&cli_args.input_path, &cli_args.output_path);
+    let result = converter.convert_with_usage(&job.input_path, &job.output_path)
+        .map(|usage| {
+            print_bank_usage(cli_args, &usage.banks);
+            write_report(cli_args, job, "Magic Desk CRT", &base_config, &usage.files);
+        });
 
     let _ = cleanup_work_dir(&work_path);
     result
 }
 
+/// Write the `--report` JSON for a finished conversion, to a file or stderr (`-`)
+///
+/// The output is already written, so a report that fails is a warning.
+fn write_report(cli_args: &CliArgs, job: &BatchJob, format: &str, config: &Config, files: &[String]) {
+    if cli_args.report.is_none() && cli_args.support_bundle.is_none() {
+        return;
+    }
+    if let Err(e) = try_write_report(cli_args, job, format, config, files) {
+        warn!("Warning: {}", e);
+        warn!();
+    }
+}
+
+fn try_write_report(cli_args: &CliArgs, job: &BatchJob, format: &str, config: &Config, files: &[String]) -> Result<(), String> {
+    let json = ConversionReport::collect(&job.input_path, &job.output_path, format, config, files)?
+        .to_json();
+    if let Some(ref dir) = cli_args.support_bundle {
+        let path = Path::new(dir).join("report.json");
+        std::fs::write(&path, &json)
+            .map_err(|e| format!("Failed to write report {}: {}", path.display(), e))?;
+    }
+    match cli_args.report {
+        Some(ref target) if target == "-" => {
+            eprint!("{}", json);
+            Ok(())
+        }
+        Some(ref target) => {
+            std::fs::write(target, json).map_err(|e| format!("Failed to write report {}: {}", target, e))
+        }
+        None => Ok(()),
+    }
+}
+
+/// `--hash`: print "<hex>  <path>" for each output, also with `--quiet`
+///
+/// `hashes` come from `OutputHashes`: the bytes the writer wrote, so the line
+/// matches what `sha256sum` or `crc32` report for the file. With
+/// `--hash-sidecar`, the line is also written to `<output>.crc32` or
+/// `<output>.sha256`.
+fn print_hashes(cli_args: &CliArgs, hashes: &[(PathBuf, String)]) {
+    let Some(algorithm) = cli_args.hash else {
+        return;
+    };
+    for (path, hex) in hashes {
+        println!("{}  {}", hex, path.display());
+        if cli_args.hash_sidecar {
+            if let Err(e) = write_sidecar(algorithm, path, hex) {
+                warn!("Warning: {}", e);
+            }
+        }
+    }
+}
+
+/// `--roundtrip-check`: export the parsed input and report modeled module bytes that didn't come back
+///
+/// A self-test of the parser, not of the restore: the snapshot is parsed with
+/// the conversion's parser options, written out again with `export` and
+/// compared with the input field by field.
+fn roundtrip_check(cli_args: &CliArgs) {
+    let config = base_options(cli_args, Config::new(std::env::temp_dir()));
+    let differences = ParseVSF::import_streaming(&cli_args.input_path, &config)
+        .map_err(|e| format!("Failed to read {}: {}", cli_args.input_path, e))
+        .and_then(|parser| roundtrip_differences(&parser, &config.parser));
+    match differences {
+        Ok(differences) if differences.is_empty() => {
+            info!();
+            info!("Round trip: all modeled module bytes reproduced");
+        }
+        Ok(differences) => {
+            info!();
+            let warnings: Vec<String> = differences.iter().map(|d| format!("Round trip: {}", d)).collect();
+            emit_warnings(&warnings, cli_args.strict);
+        }
+        Err(e) => warn!("Warning: round trip check failed: {}", e),
+    }
+}
+
+/// Write the `--support-bundle` files that don't come from the conversion itself
+///
+/// The assembler sources (`asm/`) and `report.json` are written while converting;
+/// this adds `version.json`, `log.txt` with the command line, warnings and result,
+/// and with `--support-bundle-snapshot` a copy of the snapshot. Paths in the log
+/// are reduced to file names so the bundle can be attached to a public issue.
+fn write_support_bundle(cli_args: &CliArgs, args: &[String], result: &Result<(), String>) -> Result<(), String> {
+    let Some(ref dir) = cli_args.support_bundle else {
+        return Ok(());
+    };
+    let dir = Path::new(dir);
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create support bundle {}: {}", dir.display(), e))?;
+
+    let anonymize = |text: &str| -> String {
+        let mut text = text.to_string();
+        for path in [&cli_args.input_path, &cli_args.output_path] {
+            if let Some(name) = Path::new(path).file_name() {
+                text = text.replace(path.as_str(), &name.to_string_lossy());
+            }
+        }
+        text.replace(&*std::env::temp_dir().to_string_lossy(), "<tmp>")
+    };
+    let command: Vec<String> = args
+        .iter()
+        .skip(1)
+        .map(|arg| match Path::new(arg).file_name() {
+            Some(name) if arg.contains(['/', '\\']) => name.to_string_lossy().into_owned(),
+            _ => arg.clone(),
+        })
+        .collect();
+
+    let mut log = vec![
+        format!("VICE Snapshot to PRG/CRT Converter v{} (CLI)", VERSION),
+        format!("Command: {}", command.join(" ")),
+    ];
+    if let Ok(parser) = ParseVSF::import_streaming(&cli_args.input_path, &Config::new(std::env::temp_dir())) {
+        log.push(match parser.metadata() {
+            Some(meta) => format!("VICE:    {} (r{})", meta.vice_version, meta.svn_revision),
+            None => "VICE:    unknown (no version block)".to_string(),
+        });
+    }
+    let warnings = option_warnings(cli_args)
+        .into_iter()
+        .chain(check_snapshot(&cli_args.input_path, &Config::new(std::env::temp_dir())));
+    for warning in warnings {
+        log.push(format!("Warning: {}", anonymize(&warning)));
+    }
+    match result {
+        Ok(()) => log.push("Result:  success".to_string()),
+        Err(e) => log.push(format!("Result:  failed\n{}", anonymize(e))),
+    }
+
+    let write = |name: &str, contents: &[u8]| -> Result<(), String> {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    };
+    write("log.txt", format!("{}\n", log.join("\n")).as_bytes())?;
+    write("version.json", capabilities_json().as_bytes())?;
+    if cli_args.bundle_snapshot {
+        let snapshot = std::fs::read(&cli_args.input_path)
+            .map_err(|e| format!("Failed to read {}: {}", cli_args.input_path, e))?;
+        write("snapshot.vsf", &snapshot)?;
+    }
+    Ok(())
+}
+
+fn extract_components(cli_args: &CliArgs, output_dir: &str) -> Result<(), String> {
+    let config = Config::auto()
+        .map_err(|e| format!("Failed to initialize: {}", e))?;
+    let config = base_options(cli_args, config);
+
+    let work_path = config.work_path.clone();
+    let converter = ConvertSnapshot::new(config);
+    let result = converter.extract_components(&cli_args.input_path, output_dir);
+
+    let _ = cleanup_work_dir(&work_path);
+    result
+}
+
+/// Why the snapshot header would be rejected without `--force`, if it would
+fn header_error(input_path: &str) -> Option<String> {
+    let raw = std::fs::read(input_path).ok()?;
+    ParseVSF::is_supported(&raw).err()
+}
+
+/// Print the header and module summary of a snapshot and parse it
+///
+/// Runs the parser only: no work directory, compression or assembler.
+fn validate_snapshot(input_path: &str) -> Result<(), String> {
+    println!("Input:   {}", input_path);
+    let raw = std::fs::read(input_path).map_err(|e| format!("Failed to read {}: {}", input_path, e))?;
+    ParseVSF::is_supported(&raw)?;
+
+    let parser = ParseVSF::import(input_path, &Config::new(std::env::temp_dir()))
+        .map_err(|e| format!("Failed to read {}: {}", input_path, e))?;
+    if let Some(machine) = parser.machine_name() {
+        println!("Machine: {}", machine);
+    }
+    if let Some((major, minor)) = parser.format_version() {
+        println!("Format:  {}.{}", major, minor);
+    }
+    match parser.metadata() {
+        Some(meta) => println!("VICE:    {} (r{})", meta.vice_version, meta.svn_revision),
+        None => println!("VICE:    unknown (no version block)"),
+    }
+
+    let names = parser.module_names();
+    println!("Modules:");
+    for module in REQUIRED_MODULES {
+        let status = if names.iter().any(|name| name == module) { "present" } else { "missing" };
+        println!("  {:<8} {}", module, status);
+    }
+    println!();
+
+    parser.parse_import()?;
+    println!("Valid: the snapshot can be converted");
+    Ok(())
+}
+
+/// Print the RAM ranges that differ between two snapshots; returns the range count
+fn diff_snapshot_files(expected_path: &str, actual_path: &str) -> Result<usize, String> {
+    let config = Config::new(std::env::temp_dir());
+    let load = |path: &str| {
+        ParseVSF::import_streaming(path, &config)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?
+            .parse_import()
+            .map_err(|e| format!("Failed to parse {}: {}", path, e))
+    };
+    let expected = load(expected_path)?;
+    let actual = load(actual_path)?;
+
+    let scratch = restore_scratch_regions(&expected);
+    let ranges = diff_ram(&expected.mem.ram, &actual.mem.ram, &scratch);
+
+    let ignored: Vec<String> = scratch
+        .iter()
+        .map(|&(start, size)| format!("${:04X}-${:04X}", start, start as usize + size as usize - 1))
+        .collect();
+    println!("Expected: {}", expected_path);
+    println!("Actual:   {}", actual_path);
+    println!("Ignored:  {} (restore scratch)", ignored.join(", "));
+    println!();
+
+    if ranges.is_empty() {
+        println!("RAM identical outside the restore scratch regions");
+        return Ok(0);
+    }
+
+    for range in &ranges {
+        println!("  ${:04X}-${:04X}  {:>5} byte(s)", range.start, range.end, range.count);
+    }
+    let total: usize = ranges.iter().map(|range| range.count).sum();
+    println!();
+    println!("{} range(s), {} byte(s) differ", ranges.len(), total);
+    Ok(ranges.len())
+}
+
+/// Warnings for options that don't fit the chosen format or each other
+fn option_warnings(args: &CliArgs) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    // Output extension should match the format
+    let output_lower = args.output_path.to_lowercase();
+    match args.format {
+        OutputFormat::Prg if !output_lower.ends_with(".prg") => {
+            warnings.push("Output file does not have .prg extension".to_string());
+        }
+        OutputFormat::Crt | OutputFormat::MagicDeskCrt if !output_lower.ends_with(".crt") => {
+            warnings.push("Output file does not have .crt extension".to_string());
+        }
+        _ => {}
+    }
+
+    // Warn if CRT-only options used with PRG
+    if args.format == OutputFormat::Prg {
+        if args.include_dir.is_some() {
+            warnings.push("--include-dir is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.hook_addr.is_some() {
+            warnings.push("--hook-addr is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.easyflash_hardware {
+            warnings.push("--easyflash-hardware is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.ef3 {
+            warnings.push("--ef3 is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.save_iec {
+            warnings.push("--save-iec is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.autostart.is_some() {
+            warnings.push("--autostart is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+        if args.loading_screen.is_some() {
+            warnings.push("--loading-screen is only used with EasyFlash CRT format, ignoring".to_string());
+        }
+    }
+
+    // A blank header name shows as an empty title in cartridge menus
+    if args.cartridge_name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        warnings.push(format!("--name is blank, using the default name \"{}\"", DEFAULT_CARTRIDGE_NAME));
+    }
+
+    if let Some((addr @ 0xD000..=0xDFFF, _)) = args.verify {
+        warnings.push(format!("--verify ${:04X} is in the I/O area and reads I/O, not RAM", addr));
+    }
+
+    if args.raw && args.format != OutputFormat::Prg && !args.both {
+        warnings.push("--raw is only used with PRG format, ignoring".to_string());
+    }
+
+    if args.loader_origin.is_some() && args.format != OutputFormat::Prg && !args.both {
+        warnings.push("--origin is only used with PRG format, ignoring".to_string());
+    }
+
+    if args.pad_prg.is_some() && args.format != OutputFormat::Prg && !args.both {
+        warnings.push("--pad-prg is only used with PRG format, ignoring".to_string());
+    }
+
+    if args.split_bins.is_some() && args.format == OutputFormat::Prg {
+        warnings.push("--split-bins is only used with CRT formats, ignoring".to_string());
+    }
+
+    if args.magic_desk_load && args.format != OutputFormat::MagicDeskCrt {
+        warnings.push("--magic-desk-load is only used with Magic Desk format, ignoring".to_string());
+    }
+
+    // Warn if LOAD/SAVE options used with Magic Desk
+    if args.format == OutputFormat::MagicDeskCrt {
+        if args.include_dir.is_some() && !args.magic_desk_load {
+            warnings.push("--include-dir is not supported with Magic Desk format (see --magic-desk-load), ignoring".to_string());
+        }
+        if args.magic_desk_load && args.include_dir.is_none() {
+            warnings.push("--magic-desk-load requires --include-dir, ignoring".to_string());
+        }
+        if args.hook_addr.is_some() {
+            warnings.push("--hook-addr is not supported with Magic Desk format, ignoring".to_string());
+        }
+        if args.easyflash_hardware {
+            warnings.push("--easyflash-hardware is not supported with Magic Desk format, ignoring".to_string());
+        }
+        if args.ef3 {
+            warnings.push("--ef3 is not supported with Magic Desk format, ignoring".to_string());
+        }
+        if args.save_iec {
+            warnings.push("--save-iec is not supported with Magic Desk format, ignoring".to_string());
+        }
+        if args.autostart.is_some() {
+            warnings.push("--autostart is not supported with Magic Desk format, ignoring".to_string());
+        }
+        if args.loading_screen.is_some() {
+            warnings.push("--loading-screen is not supported with Magic Desk format, ignoring".to_string());
+        }
+    }
+
+    // Warn if hook-addr used without include-dir
+    if args.hook_addr.is_some() && args.include_dir.is_none() {
+        warnings.push("--hook-addr requires --include-dir, ignoring".to_string());
+    }
+
+    if args.autostart.is_some() && args.include_dir.is_none() {
+        warnings.push("--autostart requires --include-dir, ignoring".to_string());
+    }
+
+    // Without include-dir SAVE is never hooked, so it already reaches the IEC bus
+    if args.save_iec && args.include_dir.is_none() {
+        warnings.push("--save-iec requires --include-dir (SAVE is not hooked without it), ignoring".to_string());
+    }
+
+    warnings
+}
+
+/// Snapshot, hook-address and menu-name warnings, known before anything is written
+fn snapshot_warnings(cli_args: &CliArgs, input_path: &str, name: Option<&str>) -> Vec<String> {
+    let config = Config::new(std::env::temp_dir());
+    let mut warnings = check_snapshot(input_path, &config);
+    if let (Some(addr), Some(_), OutputFormat::Crt) = (cli_args.hook_addr, &cli_args.include_dir, &cli_args.format) {
+        warnings.extend(check_hook_address(input_path, &config, addr));
+    }
+    if cli_args.easyflash_hardware && cli_args.format == OutputFormat::Crt {
+        warnings.extend(ef_name_warning(cartridge_name_or_default(name)));
+    }
+    warnings
+}
+
+/// `--strict` in batch mode: warnings fail the job instead of ending the run
+fn strict_check(warnings: &[String]) -> Result<(), String> {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    Err(format!("{} (warnings are errors with --strict)", warnings.join("; ")))
+}
+
+/// Delete a PRG that `--strict` rejected after it was built, with its `--raw` binary
+fn discard_prg(cli_args: &CliArgs, path: &str) {
+    let _ = std::fs::remove_file(path);
+    if cli_args.raw {
+        let _ = std::fs::remove_file(ConvertSnapshot::raw_output_path(path));
+    }
+}
+
+/// Print warnings; with `--strict` they are reported as errors and the run stops
+fn emit_warnings(warnings: &[String], strict: bool) {
+    if strict && !warnings.is_empty() {
+        for warning in warnings {
+            eprintln!("Error: {}", warning);
+        }
+        eprintln!("Stopping: warnings are errors with --strict");
+        process::exit(1);
+    }
+    for warning in warnings {
+        warn!("Warning: {}", warning);
+        warn!();
+    }
+}
+
+fn list_files(dir: &str) -> Result<(), String> {
+    let fs_manager = FileSystemManager::new(dir);
+    let allocations = fs_manager.preview_files()?;
+    let (_, skipped) = fs_manager.read_prg_files_reporting()?;
+
+    println!("Include: {}", dir);
+    println!();
+    print_skipped_files(&skipped);
+    if allocations.is_empty() {
+        println!("No PRG files found.");
+        return Ok(());
+    }
+
+    println!("  {:<16}  {:<5}  {:>6}  BANKS", "NAME", "LOAD", "SIZE");
+    let mut names: Vec<String> = Vec::new();
+    for allocation in &allocations {
+        let name = c64_name(&allocation.file.filename);
+        let banks: Vec<String> = allocation.banks.iter().map(|b| b.to_string()).collect();
+        let collision = if names.contains(&name) { "  (name collision!)" } else { "" };
+        println!(
+            "  {:<16}  ${:04X}  {:>6}  {}{}",
+            name,
+            allocation.file.load_address,
+            allocation.file.data.len(),
+            banks.join(","),
+            collision
+        );
+        names.push(name);
+    }
+
+    let used_banks = allocations
+        .iter()
+        .flat_map(|a| a.banks.iter().copied())
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    println!();
+    println!("{} file(s) in {} bank(s)", allocations.len(), used_banks);
+    println!("Bank numbers assume the restore data fits in bank 0; a larger snapshot shifts them up.");
+    println!();
+    Ok(())
+}
+
+/// Print which VICE build wrote the snapshot (--verbose)
+fn print_snapshot_info(input_path: &str) {
+    let parser = match ParseVSF::import_streaming(input_path, &Config::new(std::env::temp_dir())) {
+        Ok(parser) => parser,
+        Err(_) => return,
+    };
+    match parser.metadata() {
+        Some(meta) => println!("VICE:    {} (r{})", meta.vice_version, meta.svn_revision),
+        None => println!("VICE:    unknown (no version block)"),
+    }
+}
+
+/// Print per-bank usage of a written CRT (--verbose, single conversions only)
+fn print_bank_usage(cli_args: &CliArgs, usage: &[BankUsage]) {
+    if !cli_args.verbose || cli_args.batch_dir.is_some() {
+        return;
+    }
+    println!("Bank usage:");
+    for line in format_bank_usage(usage) {
+        println!("{}", line);
+    }
+    println!();
+}
+
+fn print_skipped_files(skipped: &[String]) {
+    if !skipped.is_empty() {
+        info!("Skipped {} non-PRG file(s): {}", skipped.len(), skipped.join(", "));
+        info!();
+    }
+}
+
+/// Read a snapshot over VICE's binary monitor into a VSF in a new work directory
+///
+/// Returns (work directory, VSF path); the caller removes the directory.
+#[cfg(feature = "vice-monitor")]
+fn fetch_from_monitor(addr: &str) -> Result<(PathBuf, String), String> {
+    let snap = vice_monitor::snapshot_from_monitor(addr)?;
+    let dir = Config::auto()
+        .map_err(|e| format!("Failed to initialize: {}", e))?
+        .work_path;
+    let vsf = dir.join("vice-monitor.vsf");
+    if let Err(e) = vice_monitor::write_vsf(&snap, &vsf) {
+        let _ = cleanup_work_dir(&dir);
+        return Err(e);
+    }
+    Ok((dir, vsf.to_string_lossy().into_owned()))
+}
+
+#[cfg(not(feature = "vice-monitor"))]
+fn fetch_from_monitor(_addr: &str) -> Result<(PathBuf, String), String> {
+    Err("--from-monitor needs a build with the vice-monitor feature (cargo build --features vice-monitor)".to_string())
+}
+
 fn cleanup_work_dir(work_path: &Path) -> Result<(), String> {
     if work_path.exists() {
         std::fs::remove_dir_all(work_path)
@@ -336,6 +1929,11 @@ fn print_usage(program_name: &str) {
     println!();
     println!("USAGE:");
     println!("  {} [OPTIONS] <input.vsf> <output>", name);
+    println!("  {} --extract <outdir> <input.vsf>", name);
+    println!("  {} --list-files <dir>", name);
+    println!("  {} --validate <input.vsf>", name);
+    println!("  {} [OPTIONS] --batch <outdir> [--jobs N] <input.vsf>...", name);
+    println!("  {} [OPTIONS] --from-monitor <host:port> <output>", name);
     println!();
     println!("DESCRIPTION:");
     println!("  Converts VICE snapshot files (.vsf) to:");
@@ -354,9 +1952,59 @@ fn print_usage(program_name: &str) {
     println!("  --prg                Force PRG format output");
     println!("  --crt                Force EasyFlash CRT format output");
     println!("  --magic-desk         Force Magic Desk CRT format output");
+    println!("  --both               Write <output>.prg and <output>.crt (EasyFlash, or --magic-desk)");
+    println!("  --raw                Also write <output>.bin without the load address (PRG only, loads at $0801 or --origin)");
     println!("  --name <name>        Cartridge name (CRT only, max 32 chars)");
+    println!("  --dump-asm <dir>     Write every generated assembler source to <dir> (for bug reports)");
+    println!("  --support-bundle <dir> Write log, assembler sources, report and versions to <dir> for bug reports");
+    println!("  --support-bundle-snapshot Also copy the snapshot (all of its RAM) into the bundle");
+    println!("  --hash <alg>         Print the crc32 or sha256 (feature sha256) of each output file");
+    println!("  --hash-sidecar       With --hash, also write <output>.crc32 or <output>.sha256");
+    println!("  --roundtrip-check    Check the parser: write the snapshot back out and report bytes it didn't keep");
+    println!("  --split-bins <dir>   Also write raw ROML/ROMH images for EPROM burning (CRT only)");
+    println!("  --name-from-filename Name each cartridge after its input file (CRT only, e.g. with --batch)");
     println!("  --include-dir <dir>  Include PRG files from directory (EasyFlash only)");
     println!("  --hook-addr <hex>    LOAD/SAVE hook address (EasyFlash only, overrides auto)");
+    println!("  --easyflash-hardware Add EAPI stub + EF-Name for real EasyFlash 1/3 (uses ROMH space)");
+    println!("  --ef3                Pad to 64 banks for EasyFlash 3 USB tools (EasyFlash)");
+    println!("  --autostart <name>   LOAD and RUN an embedded file after restore (EasyFlash, BASIC prompt)");
+    println!("  --loading-screen <file> Show a 2000 byte screen + color image while restoring (EasyFlash)");
+    println!("  --save-iec           Pass SAVE to the IEC bus instead of ignoring it (EasyFlash, e.g. Kung Fu Flash)");
+    println!("  --magic-desk-load    Experimental: copy --include-dir files to free RAM and LOAD from there (Magic Desk)");
+    println!("  --safe-vic-order     Keep sprites/display off until memory is restored");
+    println!("  --blank-on-restore   Leave display and sprites off after restore, for the program to enable");
+    println!("  --force-display-on   Turn the display on after restore even if the snapshot had it off");
+    println!("  --defer-timers       Leave all CIA timers stopped after restore (diagnostic, may hang)");
+    println!("  --rearm-raster-irq   Re-trigger a raster IRQ that was pending in the snapshot");
+    println!("  --irq-off            End restore with IRQs disabled and CIA interrupts masked");
+    println!("  --no-sid             Don't restore the SID (music may stay silent until the next SID write)");
+    println!("  --raw-small          Store VIC/SID registers uncompressed when a copy loop is smaller");
+    println!("  --origin <hex>       Load the PRG at <hex> and start it with SYS/JMP there (no BASIC stub)");
+    println!("  --pad-prg <n>        Pad the PRG with $00 to a multiple of <n> bytes (254 = disk block)");
+    println!("  --sanitize-zp        Restore a damaged CHRGET ($0073) or BASIC warm-start vector ($0302)");
+    println!("  --block-layout <l>   Restore block layout: auto (default), standard, compact or split");
+    println!("  --color-ram <s>      Color RAM source: auto (default), vic (VIC-II module) or ram ($D800)");
+    println!("  --sid2-addr <hex>    Second SID address for stereo snapshots that don't store it (e.g. $D420)");
+    println!("  --verify <a>=<v>     Halt with a red border if hex address <a> isn't <v> after restore");
+    println!("  --prepend <file>     Run a PRG (ending in RTS) after restore, before the program resumes");
+    println!("  --retime <pal|ntsc>  Rescale CIA1 Timer A for the target standard (heuristic)");
+    println!("  --retime-timer-b     Also rescale CIA1 Timer B (requires --retime)");
+    println!("  --extract <outdir>   Write compressed components + manifest.json, no PRG/CRT");
+    println!("  --list-files <dir>   Preview PRGs an include dir would embed (names, sizes, banks)");
+    println!("  --diff <a> <b>       List RAM ranges differing between two snapshots (exit 1 if any)");
+    println!("  --validate <vsf>     Check a snapshot can be converted, write nothing (exit 1 if not)");
+    println!("  --batch <outdir>     Convert several snapshots into <outdir> (format from flags, default PRG)");
+    println!("  --jobs <n>           Worker threads for --batch (default 1)");
+    println!("  --cache <dir>        Skip batch inputs whose output is up to date with the same input and options");
+    println!("  --compress-fast      Faster LZSA compression, somewhat larger output");
+    println!("  --min-match <3-5>    LZSA minimum match size (default 3, higher = faster, larger)");
+    println!("  -v, --verbose        Show the VICE version that wrote the snapshot and CRT bank usage");
+    println!("  -q, --quiet          Print errors only (nothing on success)");
+    println!("  --force              Convert snapshots with an unsupported format version or machine type (may not work)");
+    println!("  --strict             Treat warnings as errors (exit 1 before converting)");
+    println!("  --from-monitor <h:p> Read the snapshot from a running VICE (-binarymonitor), no .vsf needed");
+    println!("  --report <file|->    Write a JSON conversion report to <file> or stderr (-)");
+    println!("  --version [--json]   Print the version; --json adds formats and enabled features");
     println!("  -h, --help           Show this help message");
     println!();
     println!("EXAMPLES:");
@@ -365,7 +2013,14 @@ fn print_usage(program_name: &str) {
     println!("  {} --crt --name \"My Game\" snapshot.vsf game.crt", name);
     println!("  {} --crt --include-dir ./files snapshot.vsf game.crt", name);
     println!("  {} --crt --include-dir ./files --hook-addr $0334 snapshot.vsf game.crt", name);
+    println!("  {} --crt --include-dir ./files --autostart game snapshot.vsf game.crt", name);
+    println!("  {} --crt --easyflash-hardware --name \"My Game\" snapshot.vsf game.crt", name);
     println!("  {} --magic-desk --name \"My Game\" snapshot.vsf game.crt", name);
+    println!("  {} --retime ntsc snapshot.vsf output.prg", name);
+    println!("  {} --extract ./parts snapshot.vsf", name);
+    println!("  {} --list-files ./files", name);
+    println!("  {} --diff original.vsf restored.vsf", name);
+    println!("  {} --batch ./out --jobs 4 a.vsf b.vsf c.vsf", name);
     println!();
     println!("IMPORTANT:");
     println!("  - Memory MUST be initialized before snapshot (f 0000 ffff 00)");
@@ -6,19 +6,223 @@
 // Licensed under the MIT License.
 
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::make_crt_asm::DisableMode;
+use crate::parse_vsf::{CompressionQuality, ParserConfig, VicRestoreOrder, VideoStandard};
+use crate::patch_mem::{BlockLayout, RestoreOptions};
+
 pub const VERSION: &str = "2.1";
 
 #[derive(Clone)]
 pub struct Config {
     pub work_path: PathBuf,
+    /// VIC-II restore order used by the generated restore code
+    pub vic_restore_order: VicRestoreOrder,
+    /// Rescale CIA1 timers for this video standard (heuristic)
+    pub retime: Option<VideoStandard>,
+    /// Also rescale CIA1 Timer B when retiming
+    pub retime_timer_b: bool,
+    /// End the restore with IRQs disabled and CIA interrupt masks off
+    pub irq_off: bool,
+    /// LZSA1 compressor preference: smallest output or faster compression
+    pub compression: CompressionQuality,
+    /// LZSA1 minimum match size (3-5); larger values compress faster but worse
+    pub min_match_size: u8,
+    /// Byte checked after restore (address, value); mismatch halts with a red border
+    pub verify: Option<(u16, u8)>,
+    /// PRG run after the restore, before the snapshot program (see `prepend_stub`)
+    pub prepend: Option<PathBuf>,
+    /// Layout of the restore blocks; `Auto` retries other layouts when free RAM is fragmented
+    pub block_layout: BlockLayout,
+    /// Skip the SID restore; music may stay silent until the program next writes the SID
+    pub no_sid: bool,
+    /// Write every generated assembler source to this directory (see `asm_wrapper::AsmDump`)
+    pub dump_asm: Option<PathBuf>,
+    /// Store VIC and SID registers uncompressed when that is smaller (see `make_prg_asm::store_raw`)
+    pub raw_small: bool,
+    /// VSF parser options used by `ParseVSF::parse_import`, e.g. the color RAM source
+    pub parser: ParserConfig,
+    /// Repair a damaged CHRGET or BASIC warm-start vector (see `sanitize_zp`)
+    pub sanitize_zp: bool,
+    /// Load the PRG loader here and start it with a JMP instead of SYS 2061 at $0801
+    pub loader_origin: Option<u16>,
+    /// Restore with the display and sprites off, for the program to turn back on
+    pub blank_on_restore: bool,
+    /// Pad the PRG file to a multiple of this many bytes (see `make_prg_asm::pad_prg`)
+    pub pad_prg: Option<u16>,
+    /// Restore with the display on even if the snapshot had it off
+    pub force_display_on: bool,
+    /// Leave every CIA timer stopped at the end of the restore
+    pub defer_timers: bool,
+    /// Re-create a raster IRQ that was pending when the snapshot was taken
+    pub rearm_raster_irq: bool,
 }
 
+/// Default LZSA1 minimum match size
+pub const DEFAULT_MIN_MATCH_SIZE: u8 = 3;
+
 impl Config {
+    /// Start building a Config; see [`ConfigBuilder`]
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
     pub fn new(work_path: impl AsRef<Path>) -> Self {
         Self {
             work_path: work_path.as_ref().to_path_buf(),
+            vic_restore_order: VicRestoreOrder::default(),
+            retime: None,
+            retime_timer_b: false,
+            irq_off: false,
+            compression: CompressionQuality::default(),
+            min_match_size: DEFAULT_MIN_MATCH_SIZE,
+            verify: None,
+            prepend: None,
+            block_layout: BlockLayout::default(),
+            no_sid: false,
+            dump_asm: None,
+            raw_small: false,
+            parser: ParserConfig::default_vice_like(),
+            sanitize_zp: false,
+            loader_origin: None,
+            blank_on_restore: false,
+            pad_prg: None,
+            force_display_on: false,
+            defer_timers: false,
+            rearm_raster_irq: false,
+        }
+    }
+
+    /// Set the VIC-II restore order
+    pub fn with_vic_restore_order(mut self, order: VicRestoreOrder) -> Self {
+        self.vic_restore_order = order;
+        self
+    }
+
+    /// Retime CIA1 Timer A (and optionally Timer B) for the target standard
+    pub fn with_retime(mut self, target: VideoStandard, timer_b: bool) -> Self {
+        self.retime = Some(target);
+        self.retime_timer_b = timer_b;
+        self
+    }
+
+    /// Keep interrupts disabled after restore (I flag set, CIA masks off)
+    pub fn with_irq_off(mut self, enabled: bool) -> Self {
+        self.irq_off = enabled;
+        self
+    }
+
+    /// Set the LZSA1 compressor preference
+    pub fn with_compression(mut self, quality: CompressionQuality) -> Self {
+        self.compression = quality;
+        self
+    }
+
+    /// Set the LZSA1 minimum match size (3-5, checked when compressing)
+    pub fn with_min_match_size(mut self, size: u8) -> Self {
+        self.min_match_size = size;
+        self
+    }
+
+    /// Check `addr` for `value` at the end of the restore (debugging on real hardware)
+    pub fn with_verify(mut self, addr: u16, value: u8) -> Self {
+        self.verify = Some((addr, value));
+        self
+    }
+
+    /// Run the PRG at `path` after the restore, before the snapshot program
+    pub fn with_prepend(mut self, path: impl AsRef<Path>) -> Self {
+        self.prepend = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the restore block layout
+    pub fn with_block_layout(mut self, layout: BlockLayout) -> Self {
+        self.block_layout = layout;
+        self
+    }
+
+    /// Leave the SID registers alone instead of restoring them
+    pub fn with_no_sid(mut self, enabled: bool) -> Self {
+        self.no_sid = enabled;
+        self
+    }
+
+    /// Write the generated assembler sources to `dir` before assembling them
+    pub fn with_dump_asm(mut self, dir: impl AsRef<Path>) -> Self {
+        self.dump_asm = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Copy small register dumps with a loop instead of decompressing them when that saves bytes
+    pub fn with_raw_small(mut self, enabled: bool) -> Self {
+        self.raw_small = enabled;
+        self
+    }
+
+    /// Parse snapshots with these parser options instead of the VICE-like defaults
+    pub fn with_parser_config(mut self, parser: ParserConfig) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Write the ROM values over a damaged CHRGET or BASIC warm-start vector
+    pub fn with_sanitize_zp(mut self, enabled: bool) -> Self {
+        self.sanitize_zp = enabled;
+        self
+    }
+
+    /// Assemble the PRG loader at `origin` without the BASIC stub (see `make_prg_asm::check_loader_origin`)
+    pub fn with_loader_origin(mut self, origin: u16) -> Self {
+        self.loader_origin = Some(origin);
+        self
+    }
+
+    /// Restore with the display and sprites off (see `VicII::blank`)
+    pub fn with_blank_on_restore(mut self, enabled: bool) -> Self {
+        self.blank_on_restore = enabled;
+        self
+    }
+
+    /// Pad the PRG file with `$00` to a multiple of `block` bytes, e.g. 254 for disk blocks
+    pub fn with_pad_prg(mut self, block: u16) -> Self {
+        self.pad_prg = Some(block);
+        self
+    }
+
+    /// Restore with the display on (see `VicII::show_display`)
+    pub fn with_force_display_on(mut self, enabled: bool) -> Self {
+        self.force_display_on = enabled;
+        self
+    }
+
+    /// Write CIA CRA/CRB with the start bit cleared; the program must start its timers
+    pub fn with_defer_timers(mut self, enabled: bool) -> Self {
+        self.defer_timers = enabled;
+        self
+    }
+
+    /// Latch a pending raster IRQ again at the end of the restore (see `PatchMem`)
+    pub fn with_rearm_raster_irq(mut self, enabled: bool) -> Self {
+        self.rearm_raster_irq = enabled;
+        self
+    }
+
+    /// Options for the final restore code generated by `PatchMem`
+    pub fn restore_options(&self) -> RestoreOptions {
+        RestoreOptions {
+            vic_order: self.vic_restore_order,
+            irq_off: self.irq_off,
+            defer_timers: self.defer_timers,
+            rearm_raster_irq: self.rearm_raster_irq,
+            verify: self.verify,
+            // Set by `prepend_stub::install_configured` once the stub is placed
+            entry: None,
+            layout: self.block_layout,
+            // Library callers pin block 9 with `PatchMem::with_preferred_block9`
+            block9_addr: None,
         }
     }
 
@@ -33,14 +237,23 @@ impl Config {
     }
 
     /// Create a unique temporary work directory
+    /// Process id and a counter keep parallel conversions in the same millisecond apart
     fn create_temp_work_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| format!("Failed to get system time: {}", e))?
             .as_millis();
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
 
         let temp_base = std::env::temp_dir();
-        let work_dir = temp_base.join(format!("ViceSnapshotConvert.{}", timestamp));
+        let work_dir = temp_base.join(format!(
+            "ViceSnapshotConvert.{}.{}.{}",
+            timestamp,
+            std::process::id(),
+            n
+        ));
 
         std::fs::create_dir_all(&work_dir)
             .map_err(|e| format!("Failed to create work directory {:?}: {}", work_dir, e))?;
@@ -58,6 +271,200 @@ impl Default for Config {
     }
 }
 
+/// Builder for [`Config`]
+///
+/// Without `with_work_dir`, `build()` creates a unique temporary work
+/// directory like [`Config::auto`].
+#[derive(Clone, Default)]
+pub struct ConfigBuilder {
+    work_path: Option<PathBuf>,
+    vic_restore_order: VicRestoreOrder,
+    retime: Option<VideoStandard>,
+    retime_timer_b: bool,
+    irq_off: bool,
+    compression: CompressionQuality,
+    min_match_size: Option<u8>,
+    verify: Option<(u16, u8)>,
+    prepend: Option<PathBuf>,
+    block_layout: BlockLayout,
+    no_sid: bool,
+    dump_asm: Option<PathBuf>,
+    raw_small: bool,
+    parser: ParserConfig,
+    sanitize_zp: bool,
+    loader_origin: Option<u16>,
+    blank_on_restore: bool,
+    pad_prg: Option<u16>,
+    force_display_on: bool,
+    defer_timers: bool,
+    rearm_raster_irq: bool,
+}
+
+impl ConfigBuilder {
+    /// Create a builder with default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use an existing work directory instead of a temporary one
+    pub fn with_work_dir(mut self, work_path: impl AsRef<Path>) -> Self {
+        self.work_path = Some(work_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the VIC-II restore order
+    pub fn with_vic_restore_order(mut self, order: VicRestoreOrder) -> Self {
+        self.vic_restore_order = order;
+        self
+    }
+
+    /// Retime CIA1 Timer A for the target standard
+    pub fn with_retime(mut self, target: VideoStandard) -> Self {
+        self.retime = Some(target);
+        self
+    }
+
+    /// Also retime CIA1 Timer B (only used with `with_retime`)
+    pub fn with_retime_timer_b(mut self, enabled: bool) -> Self {
+        self.retime_timer_b = enabled;
+        self
+    }
+
+    /// Keep interrupts disabled after restore
+    pub fn with_irq_off(mut self, enabled: bool) -> Self {
+        self.irq_off = enabled;
+        self
+    }
+
+    /// Set the LZSA1 compressor preference
+    pub fn with_compression(mut self, quality: CompressionQuality) -> Self {
+        self.compression = quality;
+        self
+    }
+
+    /// Set the LZSA1 minimum match size (3-5)
+    pub fn with_min_match_size(mut self, size: u8) -> Self {
+        self.min_match_size = Some(size);
+        self
+    }
+
+    /// Check `addr` for `value` at the end of the restore
+    pub fn with_verify(mut self, addr: u16, value: u8) -> Self {
+        self.verify = Some((addr, value));
+        self
+    }
+
+    /// Run a PRG after the restore, before the snapshot program
+    pub fn with_prepend(mut self, path: impl AsRef<Path>) -> Self {
+        self.prepend = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the restore block layout
+    pub fn with_block_layout(mut self, layout: BlockLayout) -> Self {
+        self.block_layout = layout;
+        self
+    }
+
+    /// Skip the SID restore
+    pub fn with_no_sid(mut self, enabled: bool) -> Self {
+        self.no_sid = enabled;
+        self
+    }
+
+    /// Write the generated assembler sources to a directory
+    pub fn with_dump_asm(mut self, dir: impl AsRef<Path>) -> Self {
+        self.dump_asm = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Store small register dumps uncompressed when that is smaller
+    pub fn with_raw_small(mut self, enabled: bool) -> Self {
+        self.raw_small = enabled;
+        self
+    }
+
+    /// Parse snapshots with these parser options instead of the VICE-like defaults
+    pub fn with_parser_config(mut self, parser: ParserConfig) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Write the ROM values over a damaged CHRGET or BASIC warm-start vector
+    pub fn with_sanitize_zp(mut self, enabled: bool) -> Self {
+        self.sanitize_zp = enabled;
+        self
+    }
+
+    /// Assemble the PRG loader at `origin` without the BASIC stub (see `make_prg_asm::check_loader_origin`)
+    pub fn with_loader_origin(mut self, origin: u16) -> Self {
+        self.loader_origin = Some(origin);
+        self
+    }
+
+    /// Restore with the display and sprites off (see `VicII::blank`)
+    pub fn with_blank_on_restore(mut self, enabled: bool) -> Self {
+        self.blank_on_restore = enabled;
+        self
+    }
+
+    /// Pad the PRG file with `$00` to a multiple of `block` bytes, e.g. 254 for disk blocks
+    pub fn with_pad_prg(mut self, block: u16) -> Self {
+        self.pad_prg = Some(block);
+        self
+    }
+
+    /// Restore with the display on (see `VicII::show_display`)
+    pub fn with_force_display_on(mut self, enabled: bool) -> Self {
+        self.force_display_on = enabled;
+        self
+    }
+
+    /// Write CIA CRA/CRB with the start bit cleared; the program must start its timers
+    pub fn with_defer_timers(mut self, enabled: bool) -> Self {
+        self.defer_timers = enabled;
+        self
+    }
+
+    /// Latch a pending raster IRQ again at the end of the restore (see `PatchMem`)
+    pub fn with_rearm_raster_irq(mut self, enabled: bool) -> Self {
+        self.rearm_raster_irq = enabled;
+        self
+    }
+
+    /// Produce the Config, creating a temporary work directory if none was set
+    pub fn build(self) -> Result<Config, Box<dyn std::error::Error>> {
+        let work_path = match self.work_path {
+            Some(path) => path,
+            None => Config::create_temp_work_dir()?,
+        };
+
+        Ok(Config {
+            work_path,
+            vic_restore_order: self.vic_restore_order,
+            retime: self.retime,
+            retime_timer_b: self.retime_timer_b,
+            irq_off: self.irq_off,
+            compression: self.compression,
+            min_match_size: self.min_match_size.unwrap_or(DEFAULT_MIN_MATCH_SIZE),
+            verify: self.verify,
+            prepend: self.prepend,
+            block_layout: self.block_layout,
+            no_sid: self.no_sid,
+            dump_asm: self.dump_asm,
+            raw_small: self.raw_small,
+            parser: self.parser,
+            sanitize_zp: self.sanitize_zp,
+            loader_origin: self.loader_origin,
+            blank_on_restore: self.blank_on_restore,
+            pad_prg: self.pad_prg,
+            force_display_on: self.force_display_on,
+            defer_timers: self.defer_timers,
+            rearm_raster_irq: self.rearm_raster_irq,
+        })
+    }
+}
+
 /// Configuration for CRT (EasyFlash / Magic Desk cartridge) conversion
 #[derive(Clone)]
 pub struct CrtConfig {
@@ -73,6 +480,22 @@ pub struct CrtConfig {
     pub cartridge_name: Option<String>,
     /// Enable LOAD/SAVE hooking
     pub patch_load_save: bool,
+    /// Add stub EAPI and EF-Name for physical EasyFlash hardware
+    pub easyflash_hardware: bool,
+    /// Pass SAVE through to the IEC bus instead of ignoring it (Kung Fu Flash)
+    pub save_passthrough: bool,
+    /// Embedded file to LOAD and RUN after restore
+    pub autostart: Option<String>,
+    /// Magic Desk: copy the include-dir files to free RAM and serve LOAD from there (experimental)
+    pub magic_desk_ram_load: bool,
+    /// Also write the ROML/ROMH banks as raw EPROM images to this directory
+    pub split_bins: Option<PathBuf>,
+    /// Pad to 64 banks with ROMH on bank 0 only, as EasyFlash 3 USB tools expect
+    pub ef3: bool,
+    /// What the EasyFlash restore code writes to `$DE02` when it switches the cart off
+    pub disable_mode: DisableMode,
+    /// Screen + color RAM image shown while the EasyFlash restore runs
+    pub loading_screen: Option<PathBuf>,
 }
 
 impl CrtConfig {
@@ -85,6 +508,14 @@ impl CrtConfig {
             auto_location: true,
             cartridge_name: None,
             patch_load_save: false,
+            easyflash_hardware: false,
+            save_passthrough: false,
+            autostart: None,
+            magic_desk_ram_load: false,
+            split_bins: None,
+            ef3: false,
+            disable_mode: DisableMode::default(),
+            loading_screen: None,
         }
     }
 
@@ -119,6 +550,54 @@ impl CrtConfig {
         self.patch_load_save = enabled;
         self
     }
+
+    /// Enable/disable the physical EasyFlash ROMH layout (EAPI + EF-Name)
+    pub fn with_easyflash_hardware(mut self, enabled: bool) -> Self {
+        self.easyflash_hardware = enabled;
+        self
+    }
+
+    /// Enable/disable SAVE pass-through to the IEC bus (device 8)
+    pub fn with_save_passthrough(mut self, enabled: bool) -> Self {
+        self.save_passthrough = enabled;
+        self
+    }
+
+    /// Load and run the named embedded file after restore
+    pub fn with_autostart(mut self, name: &str) -> Self {
+        self.autostart = Some(name.to_string());
+        self
+    }
+
+    /// Enable/disable the experimental Magic Desk LOAD from RAM (needs an include dir)
+    pub fn with_magic_desk_ram_load(mut self, enabled: bool) -> Self {
+        self.magic_desk_ram_load = enabled;
+        self
+    }
+
+    /// Emit a 64-bank EasyFlash 3 image (see `CRTBuilder::check_ef3`)
+    pub fn with_ef3(mut self, enabled: bool) -> Self {
+        self.ef3 = enabled;
+        self
+    }
+
+    /// Write raw ROML/ROMH images next to the CRT (see `CRTBuilder::write_split_bins`)
+    pub fn with_split_bins(mut self, dir: impl AsRef<Path>) -> Self {
+        self.split_bins = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the EasyFlash `$DE02` disable value (`$04` permanent, `$03` re-enableable)
+    pub fn with_disable_mode(mut self, mode: DisableMode) -> Self {
+        self.disable_mode = mode;
+        self
+    }
+
+    /// Show a loading screen during the restore (see `make_romh_asm::read_loading_screen`)
+    pub fn with_loading_screen(mut self, path: impl AsRef<Path>) -> Self {
+        self.loading_screen = Some(path.as_ref().to_path_buf());
+        self
+    }
 }
 
 impl Default for CrtConfig {
@@ -126,3 +605,37 @@ impl Default for CrtConfig {
         Self::auto().unwrap_or_else(|_| Self::new(Config::default()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let config = Config::builder()
+            .with_work_dir("/tmp/vsf-work")
+            .with_vic_restore_order(VicRestoreOrder::Safe)
+            .with_retime(VideoStandard::Ntsc)
+            .with_retime_timer_b(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.work_path, PathBuf::from("/tmp/vsf-work"));
+        assert_eq!(config.vic_restore_order, VicRestoreOrder::Safe);
+        assert_eq!(config.retime, Some(VideoStandard::Ntsc));
+        assert!(config.retime_timer_b);
+    }
+
+    #[test]
+    fn test_builder_defaults_match_new() {
+        let config = Config::builder().build().unwrap();
+        let _ = std::fs::remove_dir_all(&config.work_path);
+
+        let plain = Config::new(&config.work_path);
+        assert_eq!(config.vic_restore_order, plain.vic_restore_order);
+        assert_eq!(config.retime, plain.retime);
+        assert_eq!(config.retime_timer_b, plain.retime_timer_b);
+        assert_eq!(config.compression, plain.compression);
+        assert_eq!(config.min_match_size, plain.min_match_size);
+    }
+}
@@ -0,0 +1,138 @@
+//! Conversion cache for repeated batch runs (CLI `--cache`)
+//!
+//! Each conversion gets a small entry file in the cache directory with the key
+//! it was built from (the snapshot, other files the conversion reads, the
+//! settings and the converter version) and the CRC32 of every file it wrote.
+//! A later run with the same key can skip the conversion as long as all of
+//! those files are still on disk unchanged.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::VERSION;
+use crate::output_hash::{hash_bytes, hash_file, HashAlgorithm, OutputHasher};
+
+/// Cache directory holding one entry per output
+pub struct ConversionCache {
+    dir: PathBuf,
+}
+
+impl ConversionCache {
+    /// Use `dir` as the cache, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create cache directory {}: {}", dir.display(), e))?;
+        Ok(Self { dir })
+    }
+
+    /// Key for converting `input` with `settings`
+    ///
+    /// `extra_files` are other files the conversion reads (include directory,
+    /// loading screen, ...); their names and contents are part of the key.
+    pub fn key(input: &Path, extra_files: &[PathBuf], settings: &str) -> Result<String, String> {
+        let input_len = fs::metadata(input)
+            .map_err(|e| format!("Failed to read {}: {}", input.display(), e))?
+            .len();
+        let input_crc = hash_file(HashAlgorithm::Crc32, input)?;
+
+        let mut extras = OutputHasher::new(HashAlgorithm::Crc32)?;
+        for path in extra_files {
+            let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            extras.update(path.to_string_lossy().as_bytes());
+            extras.update(&(data.len() as u64).to_le_bytes());
+            extras.update(&data);
+        }
+
+        let settings_crc = hash_bytes(HashAlgorithm::Crc32, format!("{}\n{}", VERSION, settings).as_bytes())?;
+        Ok(format!("{:x}-{}-{}-{}", input_len, input_crc, extras.finish(), settings_crc))
+    }
+
+    /// Whether `outputs` were recorded with `key` and none changed since
+    ///
+    /// `outputs` are all files the conversion writes, the main output first;
+    /// one that is missing or wasn't recorded means converting again.
+    pub fn is_fresh(&self, outputs: &[&Path], key: &str) -> bool {
+        let Some(main) = outputs.first() else {
+            return false;
+        };
+        let Ok(entry) = fs::read_to_string(self.entry_path(main)) else {
+            return false;
+        };
+        let mut lines = entry.lines();
+        if lines.next().and_then(|line| line.strip_prefix("key ")) != Some(key) {
+            return false;
+        }
+        let recorded: Vec<&str> = lines.filter_map(|line| line.strip_prefix("output ")).collect();
+        recorded.len() == outputs.len()
+            && outputs.iter().all(|output| match hash_file(HashAlgorithm::Crc32, output) {
+                Ok(crc) => recorded.contains(&format!("{} {}", crc, output.display()).as_str()),
+                Err(_) => false,
+            })
+    }
+
+    /// Record that `outputs` (main output first) were just written from `key`
+    pub fn record(&self, outputs: &[&Path], key: &str) -> Result<(), String> {
+        let Some(main) = outputs.first() else {
+            return Ok(());
+        };
+        let mut entry = format!("key {}\n", key);
+        for output in outputs {
+            entry.push_str(&format!("output {} {}\n", hash_file(HashAlgorithm::Crc32, output)?, output.display()));
+        }
+        let path = self.entry_path(main);
+        fs::write(&path, entry).map_err(|e| format!("Failed to write cache entry {}: {}", path.display(), e))
+    }
+
+    /// `<file name>-<CRC32 of the full path>.cache`, so equal names in different directories don't collide
+    fn entry_path(&self, output: &Path) -> PathBuf {
+        let name = output.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let path_crc = hash_bytes(HashAlgorithm::Crc32, output.to_string_lossy().as_bytes()).unwrap_or_default();
+        self.dir.join(format!("{}-{}.cache", name, path_crc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+
+    #[test]
+    fn test_entry_is_fresh_until_input_settings_or_outputs_change() {
+        let dir = temp_dir("conversion_cache");
+        let cache = ConversionCache::open(dir.join("cache")).unwrap();
+        let input = dir.join("game.vsf");
+        let output = dir.join("game.prg");
+        fs::write(&input, b"snapshot").unwrap();
+        fs::write(&output, b"converted").unwrap();
+
+        let sidecar = dir.join("game.prg.crc32");
+        fs::write(&sidecar, b"sum").unwrap();
+        let outputs = [output.as_path(), sidecar.as_path()];
+
+        let key = ConversionCache::key(&input, &[], "--crt").unwrap();
+        assert!(!cache.is_fresh(&outputs, &key));
+        cache.record(&outputs, &key).unwrap();
+        assert!(cache.is_fresh(&outputs, &key));
+
+        // Every file written is part of the entry
+        assert!(!cache.is_fresh(&outputs[..1], &key));
+        fs::remove_file(&sidecar).unwrap();
+        assert!(!cache.is_fresh(&outputs, &key));
+        fs::write(&sidecar, b"sum").unwrap();
+
+        assert_ne!(ConversionCache::key(&input, &[], "--magic-desk").unwrap(), key);
+        fs::write(&input, b"snapshot 2").unwrap();
+        let changed = ConversionCache::key(&input, &[], "--crt").unwrap();
+        assert_ne!(changed, key);
+        assert!(!cache.is_fresh(&outputs, &changed));
+
+        fs::write(&output, b"edited").unwrap();
+        assert!(!cache.is_fresh(&outputs, &key));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
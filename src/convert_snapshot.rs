@@ -5,15 +5,40 @@
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::asm_wrapper::AsmDump;
 use crate::config::Config;
-use crate::parse_vsf::{ParseVSF, C64Snapshot};
-use crate::find_ram::FindRam;
+use crate::parse_vsf::{ParseVSF, C64Snapshot, Cpu6510, SID_REGISTER_COUNT, VIC_REGISTER_COUNT};
+use crate::find_ram::{FindRam, RamBlock};
 use crate::patch_mem::PatchMem;
-use crate::make_prg_asm::MakePRGAsm;
+use crate::prepend_stub::install_configured;
+use crate::sanitize_zp::repair_basic_routines;
+use crate::make_prg_asm::{ram_decompression_overlap, MakePRGAsm};
+
+/// Name of the manifest written next to the extracted components
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Compressed components produced by the shared conversion pipeline
+struct PreparedComponents {
+    ram_lzsa: String,
+    color_lzsa: String,
+    zp_lzsa: String,
+    vic_lzsa: String,
+    sid_lzsa: String,
+    cia1_bin: String,
+    cia2_bin: String,
+    block9_addr: u16,
+    f8_ff_data: [u8; 8],
+    raster_regs: [u8; 2],
+    cpu: Cpu6510,
+}
 
 pub struct ConvertSnapshot {
     config: Config,
     extra_ram_blocks: Vec<(u16, u16)>,
+    raw_output: bool,
 }
 
 impl ConvertSnapshot {
@@ -25,7 +50,21 @@ impl ConvertSnapshot {
     /// Create a new converter with extra RAM blocks
     /// Each block is (address, count)
     pub fn with_extra_blocks(config: Config, extra_ram_blocks: Vec<(u16, u16)>) -> Self {
-        Self { config, extra_ram_blocks }
+        Self { config, extra_ram_blocks, raw_output: false }
+    }
+
+    /// Also write a headerless binary next to the PRG (see `raw_output_path`)
+    ///
+    /// It holds the same bytes without the load address and must be placed
+    /// at `PRG_LOAD_ADDRESS`, or at `Config::loader_origin` if set.
+    pub fn with_raw_output(mut self, enabled: bool) -> Self {
+        self.raw_output = enabled;
+        self
+    }
+
+    /// Path of the headerless binary for `output_path`: same name, `.bin` extension
+    pub fn raw_output_path(output_path: &str) -> String {
+        Path::new(output_path).with_extension("bin").to_string_lossy().into_owned()
     }
 
     /// Convert a VSF snapshot to a PRG file
@@ -42,41 +81,195 @@ impl ConvertSnapshot {
             return Err(format!("Output file already exists:\n{}\n\nPlease choose a different filename or delete the existing file first.", output_path));
         }
 
-        let parser = ParseVSF::import(input_path, &self.config)
-            .map_err(|e| format!("Failed to read VSF file: {}", e))?;
+        let _dump = AsmDump::start(self.config.dump_asm.as_deref())?;
+        let parts = self.prepare_components(input_path)?;
+
+        let ram_lzsa = fs::read(&parts.ram_lzsa)
+            .map_err(|e| format!("Failed to read compressed RAM: {}", e))?;
+        let overlap = ram_decompression_overlap(&ram_lzsa)?;
+        if overlap > 0 {
+            return Err(format!(
+                "Compressed RAM would overwrite itself by {} bytes while the PRG decompresses it \
+                 (the end of RAM compresses worse than the rest). Clear unused memory before \
+                 saving the snapshot, or use a CRT format.",
+                overlap
+            ));
+        }
+
+        let prg_maker = MakePRGAsm::new(
+            &parts.color_lzsa,
+            &parts.vic_lzsa,
+            &parts.sid_lzsa,
+            &parts.cia1_bin,
+            &parts.cia2_bin,
+            &parts.zp_lzsa,
+            &parts.ram_lzsa,
+            parts.block9_addr,
+            parts.f8_ff_data,
+            parts.raster_regs,
+            &self.config,
+        ).map_err(|e| format!("Failed to initialize PRG maker: {}", e))?;
+
+        let generated = if self.raw_output {
+            let raw_path = Self::raw_output_path(output_path);
+            if raw_path == output_path {
+                return Err(format!("Raw binary would overwrite the PRG: {}", output_path));
+            }
+            prg_maker.generate_prg_and_raw(output_path, &raw_path)
+        } else {
+            prg_maker.generate_prg(output_path)
+        };
+        generated.map_err(|e| format!("Failed to generate PRG: {}", e))?;
+        write_reu_sidecar(input_path, output_path)?;
+
+        Ok(())
+    }
+
+    /// Convert an in-memory VSF image and return the PRG bytes
+    ///
+    /// The snapshot and PRG pass through the work directory, which is left
+    /// for the caller to clean up like with `convert`.
+    pub fn convert_bytes(&self, vsf: &[u8]) -> Result<Vec<u8>, String> {
+        let input_path = self.config.work_path.join("input.vsf");
+        let output_path = self.config.work_path.join("output.prg");
+        fs::write(&input_path, vsf)
+            .map_err(|e| format!("Failed to write snapshot to work directory: {}", e))?;
+        let _ = fs::remove_file(&output_path);
+
+        self.convert(&input_path.to_string_lossy(), &output_path.to_string_lossy())?;
+
+        fs::read(&output_path).map_err(|e| format!("Failed to read generated PRG: {}", e))
+    }
+
+    /// Extract the compressed restore components without building a PRG
+    ///
+    /// Runs the same parse/patch/compress pipeline as `convert`, then copies the
+    /// seven components into `output_dir` together with a JSON manifest
+    /// (`manifest.json`) describing sizes, target addresses, block 9 and CPU state.
+    pub fn extract_components(&self, input_path: &str, output_dir: &str) -> Result<(), String> {
+        let out = Path::new(output_dir);
+        fs::create_dir_all(out)
+            .map_err(|e| format!("Failed to create output directory {}: {}", output_dir, e))?;
+
+        let parts = self.prepare_components(input_path)?;
+
+        // (name, source file, output file, target address, raw size)
+        let components: [(&str, &str, &str, u16, usize); 7] = [
+            ("ram", &parts.ram_lzsa, "ram.lzsa", 0x0200, 0xFFF0 - 0x0200),
+            ("color", &parts.color_lzsa, "color.lzsa", 0xD800, 1024),
+            ("zp", &parts.zp_lzsa, "zp.lzsa", 0x0002, 0xF8 - 0x02),
+            ("vic", &parts.vic_lzsa, "vic.lzsa", 0xD000, VIC_REGISTER_COUNT),
+            ("sid", &parts.sid_lzsa, "sid.lzsa", 0xD400, SID_REGISTER_COUNT),
+            ("cia1", &parts.cia1_bin, "cia1.bin", 0xDC00, 20),
+            ("cia2", &parts.cia2_bin, "cia2.bin", 0xDD00, 20),
+        ];
+
+        let mut entries = Vec::new();
+        for &(name, src, file, address, raw_size) in &components {
+            let size = fs::copy(src, out.join(file))
+                .map_err(|e| format!("Failed to write {}: {}", file, e))?;
+            entries.push(format!(
+                "    {{ \"name\": \"{}\", \"file\": \"{}\", \"address\": {}, \"raw_size\": {}, \"size\": {}, \"compressed\": {} }}",
+                name, file, address, raw_size, size, file.ends_with(".lzsa")
+            ));
+        }
+
+        let source = Path::new(input_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        let cpu = &parts.cpu;
+        let manifest = format!(
+            "{{\n  \"source\": \"{}\",\n  \"block9_address\": {},\n  \"cpu\": {{ \"pc\": {}, \"a\": {}, \"x\": {}, \"y\": {}, \"sp\": {}, \"p\": {} }},\n  \"components\": [\n{}\n  ]\n}}\n",
+            json_escape(source),
+            parts.block9_addr,
+            cpu.pc, cpu.a, cpu.x, cpu.y, cpu.sp, cpu.p,
+            entries.join(",\n")
+        );
+
+        fs::write(out.join(MANIFEST_FILE), manifest)
+            .map_err(|e| format!("Failed to write manifest: {}", e))?;
 
+        Ok(())
+    }
+
+    /// Free RAM blocks of a snapshot, as the conversion would find them
+    ///
+    /// Parses and scans the VSF without converting or writing anything.
+    /// Extra blocks given to `with_extra_blocks` are included.
+    pub fn free_ram(&self, input_path: &str) -> Result<Vec<RamBlock>, String> {
+        let parser = ParseVSF::import_streaming(input_path, &self.config)
+            .map_err(|e| format!("Failed to read VSF file: {}", e))?;
         let snap = parser.parse_import()
             .map_err(|e| format!("Failed to parse VSF: {}", e))?;
 
+        let mut ram = snap.mem.ram.clone();
+        self.clear_extra_blocks(&mut ram);
+        Ok(FindRam::with_extra_blocks(&ram, &self.extra_ram_blocks).blocks().to_vec())
+    }
+
+    /// Zero the manually specified extra blocks
+    fn clear_extra_blocks(&self, ram: &mut [u8; 65536]) {
+        for &(address, count) in &self.extra_ram_blocks {
+            let start = address as usize;
+            let end = (start + count as usize).min(ram.len());
+            ram[start..end].fill(0);
+        }
+    }
+
+    /// Parse, patch and compress the snapshot into the work directory
+    fn prepare_components(&self, input_path: &str) -> Result<PreparedComponents, String> {
+        let parser = ParseVSF::import_streaming(input_path, &self.config)
+            .map_err(|e| format!("Failed to read VSF file: {}", e))?;
+
+        let mut snap = parser.parse_import()
+            .map_err(|e| format!("Failed to parse VSF: {}", e))?;
+
+        if let Some(target) = self.config.retime {
+            snap.retime(target, self.config.retime_timer_b);
+        }
+        if self.config.sanitize_zp {
+            repair_basic_routines(&mut snap);
+        }
+        if self.config.blank_on_restore {
+            snap.vic.blank();
+        }
+        if self.config.force_display_on {
+            snap.vic.show_display();
+        }
+
         // Preserve $F8-$FF before any patching (critical for LZSA decompressor)
         let mut f8_ff_data = [0u8; 8];
         f8_ff_data.copy_from_slice(&snap.mem.ram[0xF8..=0xFF]);
 
         // Zero out manually specified extra blocks before compression
         let mut ram = snap.mem.ram.clone();
-        for &(address, count) in &self.extra_ram_blocks {
-            let start = address as usize;
-            let end = (start + count as usize).min(ram.len());
-            for i in start..end {
-                ram[i] = 0;
-            }
-        }
+        self.clear_extra_blocks(&mut ram);
+
+        // A --prepend stub takes free RAM before the restore blocks are placed
+        let (restore_options, stub) = install_configured(&self.config, &snap, &mut ram)?;
 
         let mut ram_finder = FindRam::with_extra_blocks(&ram, &self.extra_ram_blocks);
-        let patch_mem = PatchMem::new(&snap, &mut *ram, &mut ram_finder)
+        let patch_mem = PatchMem::with_options(&snap, &mut *ram, &mut ram_finder, restore_options)
             .map_err(|e| format!("Memory patching failed: {}", e))?;
+        if let Some(ref stub) = stub {
+            stub.check_blocks(&patch_mem.block_ranges())?;
+        }
 
         let patched_snap = C64Snapshot {
             cpu: snap.cpu.clone(),
             mem: crate::parse_vsf::C64Mem {
                 cpu_port_data: snap.mem.cpu_port_data,
                 cpu_port_dir: snap.mem.cpu_port_dir,
+                exrom: snap.mem.exrom,
+                game: snap.mem.game,
                 ram,
             },
             vic: snap.vic.clone(),
             cia1: snap.cia1.clone(),
             cia2: snap.cia2.clone(),
             sid: snap.sid.clone(),
+            sid2_addr: snap.sid2_addr,
         };
 
         let (ram_path, color_path, zp_path, vic_path, sid_path, cia1_path, cia2_path) =
@@ -95,22 +288,511 @@ impl ConvertSnapshot {
         parser.compress_lzsa(&sid_path, &format!("{}.lzsa", sid_path))
             .map_err(|e| format!("Failed to compress SID: {}", e))?;
 
-        let prg_maker = MakePRGAsm::new(
-            &format!("{}.lzsa", color_path),
-            &format!("{}.lzsa", vic_path),
-            &format!("{}.lzsa", sid_path),
-            &cia1_path,
-            &cia2_path,
-            &format!("{}.lzsa", zp_path),
-            &format!("{}.lzsa", ram_path),
-            patch_mem.get_block9_addr(),
+        Ok(PreparedComponents {
+            ram_lzsa: format!("{}.lzsa", ram_path),
+            color_lzsa: format!("{}.lzsa", color_path),
+            zp_lzsa: format!("{}.lzsa", zp_path),
+            vic_lzsa: format!("{}.lzsa", vic_path),
+            sid_lzsa: format!("{}.lzsa", sid_path),
+            cia1_bin: cia1_path,
+            cia2_bin: cia2_path,
+            block9_addr: patch_mem.get_block9_addr(),
             f8_ff_data,
-            &self.config,
-        ).map_err(|e| format!("Failed to initialize PRG maker: {}", e))?;
+            raster_regs: snap.vic.raster_regs(),
+            cpu: snap.cpu,
+        })
+    }
+}
 
-        prg_maker.generate_prg(output_path)
-            .map_err(|e| format!("Failed to generate PRG: {}", e))?;
+/// Escape a string for embedding in a JSON string literal
+pub(crate) fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
 
-        Ok(())
+/// Write the snapshot's REU contents next to the output, as `<output stem>.reu`
+///
+/// The REU can't be restored into C64 RAM, so the image is shipped for the
+/// user to attach. Returns the sidecar path, or `None` without a REU.
+pub fn write_reu_sidecar(input_path: &str, output_path: &str) -> Result<Option<PathBuf>, String> {
+    let reu = match ParseVSF::read_reu(input_path)? {
+        Some(reu) => reu,
+        None => return Ok(None),
+    };
+    let path = Path::new(output_path).with_extension("reu");
+    fs::write(&path, &reu.ram)
+        .map_err(|e| format!("Failed to write REU image {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::make_prg_asm::PRG_LOAD_ADDRESS;
+    use crate::parse_vsf::{ColorRamSource, ParserConfig};
+    use crate::test_vsf::{decompress_lzsa1, TestVsf};
+
+    #[test]
+    fn test_free_ram_matches_ram_layout() {
+        let mut vsf = TestVsf::new();
+        // No two neighbouring bytes are equal outside the runs below
+        for (i, byte) in vsf.ram.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+        vsf.ram[0x1000..0x1100].fill(0xAA);
+        vsf.ram[0xC000..0xC020].fill(0x00);
+        vsf.ram[0x5000..0x5010].fill(0x11);
+        let (dir, path) = vsf.write("free_ram");
+
+        let blocks = ConvertSnapshot::new(Config::new(&dir)).free_ram(&path).unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                RamBlock { address: 0x1000, value: 0xAA, count: 0x100 },
+                RamBlock { address: 0xC000, value: 0x00, count: 0x20 },
+            ]
+        );
+
+        let converter = ConvertSnapshot::with_extra_blocks(Config::new(&dir), vec![(0x8000, 0x40)]);
+        let blocks = converter.free_ram(&path).unwrap();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[3], RamBlock { address: 0x8000, value: 0x00, count: 0x40 });
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_extract_components_writes_all_files() {
+        let (dir, vsf) = TestVsf::new().write("extract");
+        let work = dir.join("work");
+        let out = dir.join("out");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work));
+        converter.extract_components(&vsf, out.to_str().unwrap()).unwrap();
+
+        let manifest = fs::read_to_string(out.join(MANIFEST_FILE)).unwrap();
+        let expected = [
+            ("ram.lzsa", 65008),
+            ("color.lzsa", 1024),
+            ("zp.lzsa", 246),
+            ("vic.lzsa", 47),
+            ("sid.lzsa", 25),
+            ("cia1.bin", 20),
+            ("cia2.bin", 20),
+        ];
+        for (file, raw_size) in expected {
+            let size = fs::metadata(out.join(file)).unwrap().len();
+            assert!(size > 0, "{} is empty", file);
+            let entry = format!("\"file\": \"{}\"", file);
+            assert!(manifest.contains(&entry), "{} missing from manifest", file);
+            assert!(manifest.contains(&format!("\"raw_size\": {}, \"size\": {},", raw_size, size)));
+        }
+        assert_eq!(fs::metadata(out.join("cia1.bin")).unwrap().len(), 20);
+        assert!(manifest.contains("\"sp\": 246"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_high_raster_line_keeps_d011_bit7() {
+        // Raster IRQ on line $110: compare bit 8 lives in $D011 bit 7
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x11] = 0x9B;
+        vsf.vic_regs[0x12] = 0x10;
+        let (dir, path) = vsf.write("raster");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work));
+        let parts = converter.prepare_components(&path).unwrap();
+        assert_eq!(parts.raster_regs, [0x9B, 0x10]);
+
+        let raw_vic = fs::read(parts.vic_lzsa.trim_end_matches(".lzsa")).unwrap();
+        assert_eq!(raw_vic[0x11], 0x9B);
+        assert_eq!(raw_vic[0x12], 0x10);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_blank_on_restore_turns_display_and_sprites_off() {
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x11] = 0x9B;
+        vsf.vic_regs[0x15] = 0xFF;
+        let (dir, path) = vsf.write("blank");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work).with_blank_on_restore(true));
+        let parts = converter.prepare_components(&path).unwrap();
+        // DEN off, raster compare bit 8 kept
+        assert_eq!(parts.raster_regs[0], 0x8B);
+
+        let raw_vic = fs::read(parts.vic_lzsa.trim_end_matches(".lzsa")).unwrap();
+        assert_eq!(raw_vic[0x11], 0x8B);
+        assert_eq!(raw_vic[0x15], 0x00);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_safe_vic_order_writes_vic_data_blanked() {
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x11] = 0x9B;
+        vsf.vic_regs[0x15] = 0xFF;
+        let (dir, path) = vsf.write("safe_vic");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let config = Config::new(&work).with_vic_restore_order(crate::parse_vsf::VicRestoreOrder::Safe);
+        let parts = ConvertSnapshot::new(config).prepare_components(&path).unwrap();
+        // The register copy keeps display and sprites off; the final restore
+        // code still gets the snapshot values
+        let raw_vic = fs::read(parts.vic_lzsa.trim_end_matches(".lzsa")).unwrap();
+        assert_eq!(raw_vic[0x11], 0x8B);
+        assert_eq!(raw_vic[0x15], 0x00);
+        assert_eq!(parts.raster_regs[0], 0x9B);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_force_display_on_sets_den() {
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x11] = 0x8B;
+        let (dir, path) = vsf.write("display_on");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work));
+        let parts = converter.prepare_components(&path).unwrap();
+        assert_eq!(parts.raster_regs[0], 0x8B);
+
+        let converter = ConvertSnapshot::new(Config::new(&work).with_force_display_on(true));
+        let parts = converter.prepare_components(&path).unwrap();
+        assert_eq!(parts.raster_regs[0], 0x9B);
+        let raw_vic = fs::read(parts.vic_lzsa.trim_end_matches(".lzsa")).unwrap();
+        assert_eq!(raw_vic[0x11], 0x9B);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_border_background_round_trip() {
+        // Border light blue, background blue: the common "colors wrong" report
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x20] = 0x0E;
+        vsf.vic_regs[0x21] = 0x06;
+        vsf.ram[0xD800..=0xDBFF].fill(0x01);
+        let (dir, path) = vsf.write("border");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work));
+        let parts = converter.prepare_components(&path).unwrap();
+
+        let vic = decompress_lzsa1(&fs::read(&parts.vic_lzsa).unwrap());
+        assert_eq!(vic.len(), 47);
+        assert_eq!(vic[0x20], 0x0E);
+        assert_eq!(vic[0x21], 0x06);
+        assert_eq!(&vic[..], &vsf.vic_regs[..]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sprite_x_bit_8_reaches_vic_component() {
+        // Sprite 2 at X=$158: low byte in $D004, bit 8 in $D010 bit 2
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x04] = 0x58;
+        vsf.vic_regs[0x05] = 0x90;
+        vsf.vic_regs[0x10] = 0x04;
+        vsf.vic_regs[0x15] = 0x04;
+        let (dir, path) = vsf.write("sprite_msb_component");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work));
+        let parts = converter.prepare_components(&path).unwrap();
+
+        // The LZSA1 stream and the raw copy (--raw-small) both write $D000 upward
+        let vic = decompress_lzsa1(&fs::read(&parts.vic_lzsa).unwrap());
+        let raw = fs::read(parts.vic_lzsa.strip_suffix(".lzsa").unwrap()).unwrap();
+        for regs in [&vic, &raw] {
+            assert_eq!((regs[0x04], regs[0x05]), (0x58, 0x90));
+            assert_eq!(regs[0x10], 0x04);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_vic_restore_stops_below_d02f() {
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs.fill(0x5A);
+        let (dir, path) = vsf.write("vic_end");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let converter = ConvertSnapshot::new(Config::new(&work).with_raw_small(true));
+        let parts = converter.prepare_components(&path).unwrap();
+
+        let vic = decompress_lzsa1(&fs::read(&parts.vic_lzsa).unwrap());
+        assert_eq!(vic.len(), VIC_REGISTER_COUNT);
+        assert!(0xD000 + vic.len() <= 0xD02F);
+        let raw = fs::read(parts.vic_lzsa.strip_suffix(".lzsa").unwrap()).unwrap();
+        assert_eq!(raw.len(), VIC_REGISTER_COUNT);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_color_ram_source_reaches_color_file() {
+        // VIC module holds light blue, main RAM holds white
+        let mut vsf = TestVsf::new();
+        vsf.ram[0xD800..=0xDBFF].fill(0x01);
+        let (dir, path) = vsf.write("color_source");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let color_file = |source: ColorRamSource| {
+            let parser = ParserConfig { color_ram_source: source, ..ParserConfig::default() };
+            let converter = ConvertSnapshot::new(Config::new(&work).with_parser_config(parser));
+            let parts = converter.prepare_components(&path).unwrap();
+            fs::read(parts.color_lzsa.trim_end_matches(".lzsa")).unwrap()
+        };
+
+        assert!(color_file(ColorRamSource::Auto).iter().all(|&b| b & 0x0F == 0x01));
+        assert!(color_file(ColorRamSource::VicModule).iter().all(|&b| b & 0x0F == 0x0E));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_convert_bytes_end_to_end() {
+        // Full ParseVSF -> PatchMem -> MakePRGAsm pipeline on a synthetic C64SC 2.0 VSF
+        let mut vsf = TestVsf::new();
+        vsf.ram[0x0801..0x0810].copy_from_slice(b"SYNTHETIC VSF!!");
+        let dir = crate::test_vsf::temp_dir("convert_bytes");
+
+        let converter = ConvertSnapshot::new(Config::new(&dir));
+        let prg = converter.convert_bytes(&vsf.to_bytes()).unwrap();
+
+        // Load address $0801, then the SYS 2061 BASIC stub
+        assert_eq!(&prg[..2], &[0x01, 0x08]);
+        assert_eq!(&prg[2..14], &[0x0B, 0x08, 0x0A, 0x00, 0x9E, 0x32, 0x30, 0x36, 0x31, 0x00, 0x00, 0x00]);
+        assert!(prg.len() > 1024, "PRG is only {} bytes", prg.len());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_snapshot_without_sid_converts_with_no_sid() {
+        // Drop the trailing SID module (22 byte header + 35 byte payload)
+        let mut vsf = TestVsf::new().to_bytes();
+        vsf.truncate(vsf.len() - 57);
+        let dir = crate::test_vsf::temp_dir("convert_no_sid_module");
+
+        let err = ConvertSnapshot::new(Config::new(&dir)).convert_bytes(&vsf).unwrap_err();
+        assert!(err.contains("SID missing"), "{}", err);
+
+        // Without the SID restore the module isn't needed: same PRG as with a silent SID
+        let converter = ConvertSnapshot::new(Config::new(&dir).with_no_sid(true));
+        let prg = converter.convert_bytes(&vsf).unwrap();
+        assert_eq!(prg, converter.convert_bytes(&TestVsf::new().to_bytes()).unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reu_contents_written_to_sidecar() {
+        let mut vsf = TestVsf::new();
+        let reu: Vec<u8> = (0..128 * 1024).map(|i| (i % 251) as u8).collect();
+        vsf.reu = Some(reu.clone());
+        let (dir, path) = vsf.write("reu_sidecar");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+
+        let output = dir.join("game.prg");
+        ConvertSnapshot::new(Config::new(&work)).convert(&path, output.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read(dir.join("game.reu")).unwrap(), reu);
+
+        // Not a REU size
+        vsf.reu = Some(vec![0u8; 100 * 1024]);
+        let (odd_dir, odd_path) = vsf.write("reu_odd");
+        assert!(ParseVSF::read_reu(&odd_path).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&odd_dir);
+    }
+
+    #[test]
+    fn test_parser_config_reaches_conversion() {
+        // VIC module holds light blue, main RAM holds white
+        let mut vsf = TestVsf::new();
+        vsf.ram[0xD800..=0xDBFF].fill(0x01);
+        let dir = crate::test_vsf::temp_dir("convert_parser");
+
+        let convert = |parser: ParserConfig| {
+            ConvertSnapshot::new(Config::new(&dir).with_parser_config(parser))
+                .convert_bytes(&vsf.to_bytes())
+                .unwrap()
+        };
+        let default_prg = convert(ParserConfig::default_vice_like());
+        let plain_prg = ConvertSnapshot::new(Config::new(&dir)).convert_bytes(&vsf.to_bytes()).unwrap();
+        assert_eq!(plain_prg, default_prg);
+
+        let vic_prg = convert(ParserConfig { color_ram_source: ColorRamSource::VicModule, ..ParserConfig::default() });
+        assert_ne!(vic_prg, default_prg);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_low_memory_survives_loader_footprint() {
+        // Program data in $0800-$0FFF, where the PRG loader itself is loaded
+        let mut vsf = TestVsf::new();
+        for (i, byte) in vsf.ram[0x0800..0x1000].iter_mut().enumerate() {
+            *byte = (i * 7 % 253) as u8 ^ 0x5A;
+        }
+        let dir = crate::test_vsf::temp_dir("low_memory");
+
+        let converter = ConvertSnapshot::new(Config::new(&dir));
+        let prg = converter.convert_bytes(&vsf.to_bytes()).unwrap();
+        let ram_lzsa = fs::read(dir.join("ram.lzsa")).unwrap();
+
+        // Memory as the loader leaves it: its own image at $0801 and the
+        // compressed RAM staged at the top, then decompressed in place
+        let mut mem = [0u8; 65536];
+        mem[0x0801..0x0801 + prg.len() - 2].copy_from_slice(&prg[2..]);
+        let staged = 0x10000 - ram_lzsa.len();
+        mem[staged..].copy_from_slice(&ram_lzsa);
+        decompress_in_place(&mut mem, staged, 0x0200);
+
+        assert_eq!(&mem[0x0900..0x0A00], &vsf.ram[0x0900..0x0A00]);
+        assert_eq!(&mem[0x0800..0x1000], &vsf.ram[0x0800..0x1000]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn next(src: &mut usize, mem: &[u8; 65536]) -> usize {
+        let b = mem[*src];
+        *src += 1;
+        b as usize
+    }
+
+    /// LZSA1 decompression within one memory image, like the relocated decompressor
+    fn decompress_in_place(mem: &mut [u8; 65536], mut src: usize, mut dst: usize) {
+        loop {
+            let token = next(&mut src, mem);
+
+            let mut literals = (token >> 4) & 0x07;
+            if literals == 7 {
+                literals = match next(&mut src, mem) {
+                    249 => next(&mut src, mem) | next(&mut src, mem) << 8,
+                    250 => 256 + next(&mut src, mem),
+                    b => 7 + b,
+                };
+            }
+            for _ in 0..literals {
+                let b = next(&mut src, mem);
+                mem[dst] = b as u8;
+                dst += 1;
+            }
+
+            let offset = if token & 0x80 != 0 {
+                next(&mut src, mem) | next(&mut src, mem) << 8
+            } else {
+                next(&mut src, mem) | 0xFF00
+            };
+
+            let mut length = (token & 0x0F) + 3;
+            if length == 18 {
+                length = match next(&mut src, mem) {
+                    238 => match next(&mut src, mem) | next(&mut src, mem) << 8 {
+                        0 => return,
+                        n => n,
+                    },
+                    239 => 256 + next(&mut src, mem),
+                    b => 18 + b,
+                };
+            }
+            // Offsets are negative 16-bit values, so matches read back from output
+            let from = (dst + offset) & 0xFFFF;
+            for i in 0..length {
+                mem[dst] = mem[from + i];
+                dst += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump_asm_writes_generated_sources() {
+        let (dir, vsf) = TestVsf::new().write("dump_asm");
+        let work = dir.join("work");
+        let dump = dir.join("asm");
+        fs::create_dir_all(&work).unwrap();
+        let output = dir.join("dump_asm.prg");
+
+        ConvertSnapshot::new(Config::new(&work).with_dump_asm(&dump))
+            .convert(&vsf, output.to_str().unwrap())
+            .unwrap();
+
+        let mut files: Vec<String> = fs::read_dir(&dump)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["00_DECOMPRESS_LZSA1.asm", "01_start.asm"]);
+
+        let loader = fs::read_to_string(dump.join("01_start.asm")).unwrap();
+        assert!(loader.contains("*=$0801"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_loader_origin_c000() {
+        let dir = crate::test_vsf::temp_dir("origin_c000");
+        let converter = ConvertSnapshot::new(Config::new(&dir).with_loader_origin(0xC000));
+        let prg = converter.convert_bytes(&TestVsf::new().to_bytes()).unwrap();
+
+        // Load address $C000, entry right there: SEI, CLD, LDA #$35, STA $01
+        assert_eq!(&prg[..2], &[0x00, 0xC0]);
+        assert_eq!(&prg[2..8], &[0x78, 0xD8, 0xA9, 0x35, 0x85, 0x01]);
+        assert!(prg.len() - 2 <= 0x1000);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_raw_output_is_prg_without_header() {
+        let (dir, vsf) = TestVsf::new().write("raw_output");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+        let output = dir.join("raw_output.prg");
+        let output = output.to_str().unwrap();
+
+        ConvertSnapshot::new(Config::new(&work))
+            .with_raw_output(true)
+            .convert(&vsf, output)
+            .unwrap();
+
+        let prg = fs::read(output).unwrap();
+        let raw = fs::read(ConvertSnapshot::raw_output_path(output)).unwrap();
+        assert_eq!(raw.len(), prg.len() - 2);
+        assert_eq!(&prg[..2], &PRG_LOAD_ADDRESS.to_le_bytes());
+        assert_eq!(&prg[2..], &raw[..]);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 }
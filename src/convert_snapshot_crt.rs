@@ -6,15 +6,20 @@
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
+use crate::asm_wrapper::AsmDump;
+use crate::bank_usage::{bank_usage, BankContent, BankRegion, CrtUsage};
 use crate::config::CrtConfig;
-use crate::crt_builder::{CRTBuilder, CartridgeType, BANK_SIZE_8K};
-use crate::file_system_manager::FileSystemManager;
+use crate::convert_snapshot::write_reu_sidecar;
+use crate::crt_builder::{cartridge_name_or_default, output_stem, CRTBuilder, CartridgeType, BANK_SIZE_8K, EF3_BANKS};
+use crate::file_system_manager::{c64_name, FileAllocation, FileSystemManager, HARDWARE_FILENAME_START, MAX_BANKS};
 use crate::find_ram::FindRam;
-use crate::load_save_hook::LoadSaveHook;
+use crate::load_save_hook::{check_autostart, check_trampoline_address, LoadSaveHook, MIN_SP_FOR_PAGE1};
 use crate::make_crt_asm::MakeCRTAsm;
-use crate::make_romh_asm::MakeROMHAsm;
+use crate::make_romh_asm::{check_restore_code_size, read_loading_screen, MakeROMHAsm};
 use crate::parse_vsf::{C64Mem, C64Snapshot, ParseVSF};
 use crate::patch_mem::PatchMem;
+use crate::prepend_stub::install_configured;
+use crate::sanitize_zp::repair_basic_routines;
 use std::fs;
 
 pub struct ConvertSnapshotCRT {
@@ -35,21 +40,46 @@ impl ConvertSnapshotCRT {
 
     /// Convert a VSF snapshot to an EasyFlash CRT file
     pub fn convert(&self, input_path: &str, output_path: &str) -> Result<(), String> {
+        self.convert_with_usage(input_path, output_path).map(|_| ())
+    }
+
+    /// Convert like `convert` and report how the banks of the written CRT are used
+    pub fn convert_with_usage(&self, input_path: &str, output_path: &str) -> Result<CrtUsage, String> {
         if std::path::Path::new(output_path).exists() {
             return Err(format!(
                 "Output file already exists:\n{}\n\nPlease choose a different filename.",
                 output_path
             ));
         }
+        let _dump = AsmDump::start(self.config.base_config.dump_asm.as_deref())?;
+        let loading_screen = self
+            .config
+            .loading_screen
+            .as_deref()
+            .map(read_loading_screen)
+            .transpose()?;
 
         // Parse the VSF file
-        let parser = ParseVSF::import(input_path, &self.config.base_config)
+        let parser = ParseVSF::import_streaming(input_path, &self.config.base_config)
             .map_err(|e| format!("Failed to read VSF file: {}", e))?;
 
-        let snap = parser
+        let mut snap = parser
             .parse_import()
             .map_err(|e| format!("Failed to parse VSF: {}", e))?;
 
+        if let Some(target) = self.config.base_config.retime {
+            snap.retime(target, self.config.base_config.retime_timer_b);
+        }
+        if self.config.base_config.sanitize_zp {
+            repair_basic_routines(&mut snap);
+        }
+        if self.config.base_config.blank_on_restore {
+            snap.vic.blank();
+        }
+        if self.config.base_config.force_display_on {
+            snap.vic.show_display();
+        }
+
         // Preserve $F8-$FF before any patching
         let mut f8_ff_data = [0u8; 8];
         f8_ff_data.copy_from_slice(&snap.mem.ram[0xF8..=0xFF]);
@@ -72,20 +102,28 @@ impl ConvertSnapshotCRT {
             // Determine trampoline address
             // Auto location: use $100 if SP >= 242, otherwise $334
             let trampoline_addr = if self.config.auto_location || self.config.trampoline_address.is_none() {
-                if snap.cpu.sp >= 242 {
+                if snap.cpu.sp >= MIN_SP_FOR_PAGE1 {
                     0x0100 // SP is high enough, safe to use $0100
                 } else {
                     0x0334 // SP is low, use $0334 to avoid stack collision
                 }
             } else {
-                self.config.trampoline_address.unwrap_or(0x0100)
+                let addr = self.config.trampoline_address.unwrap_or(0x0100);
+                check_trampoline_address(addr, snap.cpu.sp)?;
+                addr
             };
 
+            if self.config.autostart.is_some() {
+                check_autostart(&ram[..], snap.cpu.pc)?;
+            }
+
             let mut hook = LoadSaveHook::new(
                 snap.cpu.sp,
                 true,
                 Some(trampoline_addr),
-            );
+            )
+            .with_save_passthrough(self.config.save_passthrough)
+            .with_autostart(self.config.autostart.is_some());
 
             // Patch trampoline code and vectors into RAM BEFORE PatchMem!
             hook.hook_load_and_save(&mut ram[..])
@@ -99,12 +137,17 @@ impl ConvertSnapshotCRT {
         // Initialize RAM finder AFTER trampoline is written
         // This ensures FindRam sees the trampoline area as "used" (non-zero bytes)
         // and won't allocate restore code blocks over it
+        // A --prepend stub must avoid the trampoline, so it goes in after it, before the scan
+        let (restore_options, stub) = install_configured(&self.config.base_config, &snap, &mut ram)?;
         let mut ram_finder = FindRam::with_extra_blocks(&ram, &self.extra_ram_blocks);
 
         // Patch memory with restoration code (using PatchMem)
         // This runs AFTER trampoline is written (if include-dir is set)
-        let patch_mem = PatchMem::new(&snap, &mut *ram, &mut ram_finder)
+        let patch_mem = PatchMem::with_options(&snap, &mut *ram, &mut ram_finder, restore_options)
             .map_err(|e| format!("Memory patching failed: {}", e))?;
+        if let Some(ref stub) = stub {
+            stub.check_blocks(&patch_mem.block_ranges())?;
+        }
 
         // Create patched snapshot
         let patched_snap = C64Snapshot {
@@ -112,12 +155,15 @@ impl ConvertSnapshotCRT {
             mem: C64Mem {
                 cpu_port_data: snap.mem.cpu_port_data,
                 cpu_port_dir: snap.mem.cpu_port_dir,
+                exrom: snap.mem.exrom,
+                game: snap.mem.game,
                 ram,
             },
             vic: snap.vic.clone(),
             cia1: snap.cia1.clone(),
             cia2: snap.cia2.clone(),
             sid: snap.sid.clone(),
+            sid2_addr: snap.sid2_addr,
         };
 
         // Extract and compress components
@@ -156,6 +202,7 @@ impl ConvertSnapshotCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             0, // Will be set after first pass
             ram_lzsa_size,
@@ -187,12 +234,14 @@ impl ConvertSnapshotCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             relocated_size,
             ram_lzsa_size,
             0, // First pass
             0, // LOAD/SAVE code is NOT in ROML
-        )?;
+        )?
+        .with_disable_mode(self.config.disable_mode);
 
         let restore_code = crt_asm.generate_restore_code_binary()?;
         let restore_code_size = restore_code.len();
@@ -207,15 +256,19 @@ impl ConvertSnapshotCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             relocated_size,
             ram_lzsa_size,
             restore_code_size,
             0, // LOAD/SAVE code is NOT in ROML
-        )?;
+        )?
+        .with_disable_mode(self.config.disable_mode);
 
         let final_restore_code = crt_asm_final.generate_restore_code_binary()?;
         let final_relocated = crt_asm_final.generate_relocated_decompressor()?;
+        // Written to ROML bank 0, the boot bank below
+        check_restore_code_size(final_restore_code.len(), 0)?;
 
         // Calculate how many banks we need for restore data
         // NOTE: LOAD/SAVE code is NOT in ROML - it's only in ROMH @ $A600
@@ -224,15 +277,25 @@ impl ConvertSnapshotCRT {
             final_restore_code.len() + final_relocated.len() + ram_lzsa_size;
         let restore_banks_needed = (total_restore_data_size + BANK_SIZE_8K - 1) / BANK_SIZE_8K;
 
+        // The loading screen gets the bank after the restore data, files follow it
+        let loading_screen_bank = loading_screen.as_ref().map(|_| restore_banks_needed);
+        let first_file_bank = restore_banks_needed + loading_screen_bank.map_or(0, |_| 1);
+
         // Process files if include directory is set
         let (file_allocations, metadata, filenames) = if let Some(ref include_dir) = self.config.include_dir {
-            let fs_manager = FileSystemManager::new(include_dir);
-            let prg_files = fs_manager.read_prg_files()?;
+            let mut fs_manager = FileSystemManager::new(include_dir);
+            if self.config.easyflash_hardware {
+                fs_manager = fs_manager.with_filename_start(HARDWARE_FILENAME_START);
+            }
 
-            if !prg_files.is_empty() {
-                // Calculate available banks (after restore data)
-                let available_banks: Vec<usize> = (restore_banks_needed..64).collect();
-                let allocations = fs_manager.allocate_files(&prg_files, &available_banks)?;
+            // Calculate available banks (after restore data and loading screen)
+            let available_banks: Vec<usize> = (first_file_bank..MAX_BANKS).collect();
+            let mut allocations = fs_manager.plan_files(&available_banks)?;
+            if let Some(ref name) = self.config.autostart {
+                fs_manager.mark_autostart(&mut allocations, name)?;
+            }
+
+            if !allocations.is_empty() {
                 let meta = fs_manager.generate_metadata(&allocations)?;
                 let names = fs_manager.generate_filenames(&allocations)?;
                 (Some(allocations), Some(meta), Some(names))
@@ -251,14 +314,10 @@ impl ConvertSnapshotCRT {
                 fs_manager.get_allocated_banks(a).into_iter().max().map(|m| m + 1).unwrap_or(0)
             })
             .unwrap_or(0);
-        let total_banks = restore_banks_needed.max(file_banks).max(1);
+        let total_banks = first_file_bank.max(file_banks).max(1);
 
         // Create CRT builder
-        let cartridge_name = self
-            .config
-            .cartridge_name
-            .as_deref()
-            .unwrap_or("VICE SNAPSHOT");
+        let cartridge_name = cartridge_name_or_default(self.config.cartridge_name.as_deref());
         let mut crt = CRTBuilder::new(CartridgeType::EasyFlash, total_banks, cartridge_name)?;
 
         // Fill bank 0 with restore code
@@ -269,35 +328,48 @@ impl ConvertSnapshotCRT {
         offset += final_restore_code.len();
 
         // Add relocated decompressor (no LOAD/SAVE code in ROML!)
-        if offset + final_relocated.len() <= BANK_SIZE_8K {
-            crt.fill_bank(0, &final_relocated, offset)?;
-            offset += final_relocated.len();
+        // The restore code copies it from right after itself, so it must fit in bank 0
+        if offset + final_relocated.len() > BANK_SIZE_8K {
+            return Err(format!(
+                "Restore code and decompressor do not fit in bank 0 ({} + {} bytes)",
+                final_restore_code.len(),
+                final_relocated.len()
+            ));
         }
+        crt.fill_bank(0, &final_relocated, offset)?;
+        offset += final_relocated.len();
+
+        // Add RAM LZSA (may span multiple banks; banks are added before filling)
+        crt.fill_spanning(0, offset, &ram_lzsa)?;
+
+        // The loading screen and file banks were planned from the sizes above;
+        // check against what was actually written before anything lands on top of it
+        check_bank_overlap(
+            offset + ram_lzsa.len(),
+            loading_screen_bank,
+            file_allocations.as_deref().unwrap_or(&[]),
+        )?;
 
-        // Add RAM LZSA (may span multiple banks)
-        let mut ram_offset = 0;
-        let mut current_bank = 0;
-        while ram_offset < ram_lzsa.len() {
-            let space_in_bank = BANK_SIZE_8K - offset;
-            let chunk_size = space_in_bank.min(ram_lzsa.len() - ram_offset);
-            crt.fill_bank(current_bank, &ram_lzsa[ram_offset..ram_offset + chunk_size], offset)?;
-            ram_offset += chunk_size;
-            offset = 0;
-            current_bank += 1;
-            if current_bank >= total_banks && ram_offset < ram_lzsa.len() {
-                crt.add_bank();
-            }
+        if let (Some(screen), Some(bank)) = (&loading_screen, loading_screen_bank) {
+            crt.fill_bank(bank, screen, 0)?;
         }
 
         // Generate ROMH
         // NOTE: LOAD/SAVE trampoline is NOT passed here - it's written to RAM at $0334
         // and gets decompressed back when RAM.lzsa is decompressed
-        let romh_generator = MakeROMHAsm::new(
+        let mut romh_generator = MakeROMHAsm::new(
             final_restore_code.len(),
             load_save_code.clone(),
             metadata.clone(),
             filenames.clone(),
-        );
+        )
+        .with_boot_bank(0, crt.bank_count())?;
+        if self.config.easyflash_hardware {
+            romh_generator = romh_generator.with_easyflash_hardware(cartridge_name);
+        }
+        if let Some(bank) = loading_screen_bank {
+            romh_generator = romh_generator.with_loading_screen(bank);
+        }
         let romh_data = romh_generator.generate_romh()?;
         crt.set_bank_romh(0, &romh_data)?;
 
@@ -307,9 +379,269 @@ impl ConvertSnapshotCRT {
             fs_manager.write_files_to_banks(&mut crt, allocations)?;
         }
 
+        // EF3 USB tools want a full 1 MB image
+        if self.config.ef3 {
+            crt.pad_to(EF3_BANKS)
+                .map_err(|e| format!("Snapshot does not fit an EasyFlash 3 image: {}", e))?;
+            crt.check_ef3()?;
+        }
+
         // Write CRT file
         crt.make_crt(output_path)?;
+        if let Some(ref dir) = self.config.split_bins {
+            crt.write_split_bins(dir, &output_stem(output_path))?;
+        }
+        write_reu_sidecar(input_path, output_path)?;
+
+        let restore_len = final_restore_code.len() + final_relocated.len();
+        let mut regions = vec![
+            BankRegion { content: BankContent::RestoreCode, start: 0, len: restore_len },
+            BankRegion { content: BankContent::RamData, start: restore_len, len: ram_lzsa.len() },
+        ];
+        if let (Some(screen), Some(bank)) = (&loading_screen, loading_screen_bank) {
+            regions.push(BankRegion {
+                content: BankContent::LoadingScreen,
+                start: bank * BANK_SIZE_8K,
+                len: screen.len(),
+            });
+        }
+        let allocations = file_allocations.as_deref().unwrap_or(&[]);
+        Ok(CrtUsage {
+            banks: bank_usage(&crt, &regions, allocations)?,
+            files: allocations.iter().map(|allocation| c64_name(&allocation.file.filename)).collect(),
+        })
+    }
+}
+
+/// Check that no bank holds two of: restore data, loading screen, embedded file
+///
+/// `restore_end` is the length of the ROML data written from bank 0 offset 0
+/// (restore code, relocated decompressor and RAM.lzsa).
+fn check_bank_overlap(
+    restore_end: usize,
+    loading_screen_bank: Option<usize>,
+    allocations: &[FileAllocation],
+) -> Result<(), String> {
+    let restore_banks = restore_end.div_ceil(BANK_SIZE_8K);
+    if let Some(bank) = loading_screen_bank.filter(|&bank| bank < restore_banks) {
+        return Err(format!(
+            "Bank {} holds both compressed RAM and the loading screen (RAM data uses banks 0-{})",
+            bank,
+            restore_banks - 1
+        ));
+    }
+
+    for allocation in allocations {
+        let shared = allocation
+            .banks
+            .iter()
+            .find(|&&bank| bank < restore_banks || Some(bank) == loading_screen_bank);
+        if let Some(&bank) = shared {
+            let other = if bank < restore_banks { "compressed RAM" } else { "the loading screen" };
+            return Err(format!(
+                "Bank {} holds both {} and embedded file '{}'",
+                bank, other, allocation.file.filename
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::crt_builder::{LOAD_ADDRESS_ROMH, LOAD_ADDRESS_ROML};
+    use crate::file_system_manager::PRGFile;
+    use crate::make_romh_asm::LOADING_SCREEN_SIZE;
+    use crate::test_vsf::TestVsf;
+
+    #[test]
+    fn test_ef3_image_has_64_banks_and_romh_on_bank_0() {
+        let (dir, vsf) = TestVsf::new().write("ef3");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+        let output = dir.join("ef3.crt");
+
+        let config = CrtConfig::new(Config::new(&work)).with_ef3(true);
+        let usage = ConvertSnapshotCRT::new(config)
+            .convert_with_usage(&vsf, output.to_str().unwrap())
+            .unwrap();
+        assert_eq!(usage.banks.len(), EF3_BANKS);
+
+        // (bank, load address) of every CHIP packet
+        let crt = fs::read(&output).unwrap();
+        let mut chips = Vec::new();
+        let mut pos = 64;
+        while pos < crt.len() {
+            let packet_len = u32::from_be_bytes(crt[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            chips.push((
+                u16::from_be_bytes([crt[pos + 10], crt[pos + 11]]),
+                u16::from_be_bytes([crt[pos + 12], crt[pos + 13]]),
+            ));
+            pos += packet_len;
+        }
+        assert_eq!(chips.len(), EF3_BANKS + 1);
+        let romh: Vec<u16> = chips.iter().filter(|chip| chip.1 == LOAD_ADDRESS_ROMH).map(|chip| chip.0).collect();
+        assert_eq!(romh, vec![0]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_large_ram_and_embedded_files_use_separate_banks() {
+        // Incompressible RAM at $0800-$BFFF spills RAM.lzsa over several banks
+        let mut vsf = TestVsf::new();
+        let mut seed: u32 = 0x1234_5678;
+        for byte in &mut vsf.ram[0x0800..0xC000] {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            *byte = (seed >> 16) as u8;
+        }
+        let (dir, input) = vsf.write("crt_bank_overlap");
+        let work = dir.join("work");
+        let include = dir.join("files");
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&include).unwrap();
+        fs::write(include.join("level.prg"), [&[0x00, 0x40][..], &[0x42; 12000]].concat()).unwrap();
+        let output = dir.join("overlap.crt");
+
+        let config = CrtConfig::new(Config::new(&work)).with_include_dir(include.to_str().unwrap());
+        let usage = ConvertSnapshotCRT::new(config)
+            .convert_with_usage(&input, output.to_str().unwrap())
+            .unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let ram_banks: Vec<usize> =
+            usage.banks.iter().filter(|bank| bank.contents.contains(&BankContent::RamData)).map(|bank| bank.bank).collect();
+        let file_banks: Vec<usize> = usage.banks.iter().filter(|bank| !bank.files.is_empty()).map(|bank| bank.bank).collect();
+        assert!(ram_banks.len() > 4, "RAM data in banks {:?}", ram_banks);
+        assert_eq!(file_banks.len(), 2);
+        assert!(file_banks.iter().all(|bank| !ram_banks.contains(bank)), "{:?} / {:?}", ram_banks, file_banks);
+    }
+
+    #[test]
+    fn test_preview_matches_files_in_built_crt() {
+        let (dir, input) = TestVsf::new().write("crt_preview");
+        let work = dir.join("work");
+        let include = dir.join("files");
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&include).unwrap();
+        let write_prg = |name: &str, load: u16, fill: u8, size: usize| {
+            fs::write(include.join(name), [&load.to_le_bytes()[..], &vec![fill; size]].concat()).unwrap();
+        };
+        write_prg("b.prg", 0x0801, 0xBB, 0x1000);
+        write_prg("a.prg", 0xC000, 0xAA, 0x0800);
+        write_prg("c.PRG", 0x2000, 0xCC, 0x3000);
+        let output = dir.join("preview.crt");
+
+        let preview = FileSystemManager::new(include.to_str().unwrap()).preview_files().unwrap();
+        let config = CrtConfig::new(Config::new(&work)).with_include_dir(include.to_str().unwrap());
+        ConvertSnapshotCRT::new(config).convert(&input, output.to_str().unwrap()).unwrap();
+
+        // ROML payloads in bank order
+        let crt = fs::read(&output).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let mut roml = Vec::new();
+        let mut pos = 64;
+        while pos < crt.len() {
+            let packet_len = u32::from_be_bytes(crt[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            if u16::from_be_bytes([crt[pos + 12], crt[pos + 13]]) == LOAD_ADDRESS_ROML {
+                roml.extend_from_slice(&crt[pos + 16..pos + packet_len]);
+            }
+            pos += packet_len;
+        }
+
+        let banks: Vec<_> = preview.iter().map(|a| (a.file.filename.as_str(), a.banks.clone())).collect();
+        assert_eq!(banks, vec![("a.prg", vec![1]), ("b.prg", vec![1]), ("c.PRG", vec![1, 2, 3])]);
+        for allocation in &preview {
+            let start = allocation.banks[0] * BANK_SIZE_8K + allocation.start_offset;
+            assert_eq!(
+                &roml[start..start + allocation.file.data.len()],
+                &allocation.file.data[..],
+                "{} not at bank {} offset ${:04X}",
+                allocation.file.filename,
+                allocation.banks[0],
+                allocation.start_offset
+            );
+        }
+    }
+
+    #[test]
+    fn test_bank_overlap_is_detected() {
+        let allocation = |bank: usize| FileAllocation {
+            file: PRGFile {
+                filename: "level.prg".to_string(),
+                load_address: 0x4000,
+                data: vec![0x42; 100],
+                total_size: 102,
+                force_address: false,
+                hidden: false,
+                autostart: false,
+            },
+            banks: vec![bank],
+            start_offset: 0,
+            filename_offset: 0,
+        };
+
+        // Restore data ends one byte into bank 2
+        let restore_end = 2 * BANK_SIZE_8K + 1;
+        assert!(check_bank_overlap(restore_end, Some(3), &[allocation(4)]).is_ok());
+        let err = check_bank_overlap(restore_end, None, &[allocation(2)]).unwrap_err();
+        assert!(err.contains("Bank 2 holds both compressed RAM and embedded file 'level.prg'"), "{}", err);
+        let err = check_bank_overlap(restore_end, Some(3), &[allocation(3)]).unwrap_err();
+        assert!(err.contains("the loading screen"), "{}", err);
+        assert!(check_bank_overlap(restore_end, Some(2), &[]).is_err());
+    }
+
+    #[test]
+    fn test_loading_screen_is_stored_where_the_trampoline_copies_from() {
+        let (dir, vsf) = TestVsf::new().write("loading_screen");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+        let output = dir.join("screen.crt");
+
+        // PRG load address, 1000 screen codes, 1000 colors
+        let image: Vec<u8> = (0..LOADING_SCREEN_SIZE).map(|i| (i % 251) as u8).collect();
+        let screen_path = dir.join("screen.prg");
+        fs::write(&screen_path, [&[0x00, 0x24][..], &image].concat()).unwrap();
+
+        let config = CrtConfig::new(Config::new(&work)).with_loading_screen(&screen_path);
+        let usage = ConvertSnapshotCRT::new(config)
+            .convert_with_usage(&vsf, output.to_str().unwrap())
+            .unwrap();
+        let screen_bank = usage
+            .banks
+            .iter()
+            .find(|bank| bank.contents.contains(&BankContent::LoadingScreen))
+            .map(|bank| bank.bank)
+            .unwrap();
+
+        // (bank, load address, data) of every CHIP packet
+        let crt = fs::read(&output).unwrap();
+        let mut chips = Vec::new();
+        let mut pos = 64;
+        while pos < crt.len() {
+            let packet_len = u32::from_be_bytes(crt[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            chips.push((
+                u16::from_be_bytes([crt[pos + 10], crt[pos + 11]]) as usize,
+                u16::from_be_bytes([crt[pos + 12], crt[pos + 13]]),
+                &crt[pos + 16..pos + packet_len],
+            ));
+            pos += packet_len;
+        }
+        let chip = |bank: usize, load: u16| chips.iter().find(|c| c.0 == bank && c.1 == load).unwrap().2;
+
+        // ROML $8000 of the screen bank holds the image, load address skipped
+        assert_eq!(&chip(screen_bank, 0x8000)[..LOADING_SCREEN_SIZE], &image[..]);
+
+        // The trampoline selects that bank and copies $8000 -> screen, $83E8 -> color RAM
+        let romh = chip(0, LOAD_ADDRESS_ROMH);
+        let find = |pattern: &[u8]| romh.windows(pattern.len()).any(|w| w == pattern);
+        assert!(find(&[0xA9, screen_bank as u8, 0x8D, 0x00, 0xDE]));
+        assert!(find(&[0xBD, 0x00, 0x80, 0x9D, 0x00, 0x24]));
+        assert!(find(&[0xBD, 0xE8, 0x83, 0x9D, 0x00, 0xD8]));
+        assert!(find(&[0xBD, 0xD6, 0x86, 0x9D, 0xEE, 0xDA]));
 
-        Ok(())
+        let _ = fs::remove_dir_all(&dir);
     }
 }
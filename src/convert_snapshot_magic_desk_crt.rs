@@ -10,18 +10,27 @@
 //! Note: Magic Desk has only a permanent kill bit ($DE00 bit 7). Unlike EasyFlash
 //! ($DE02), there is no way to temporarily disable the cartridge. Once data is
 //! copied to RAM, the cart must be killed permanently. LOAD/SAVE hooks are not
-//! supported -- use EasyFlash format for that.
+//! supported -- use EasyFlash format for that. As an experimental alternative,
+//! `CrtConfig::with_magic_desk_ram_load` copies the include-dir files into free
+//! RAM with the snapshot and serves LOAD from there (see `magic_desk_ram_load`).
 //!
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
+use crate::asm_wrapper::AsmDump;
+use crate::bank_usage::{bank_usage, BankContent, BankRegion, CrtUsage};
 use crate::config::CrtConfig;
-use crate::crt_builder::{CRTBuilder, CartridgeType, BANK_SIZE_8K};
+use crate::convert_snapshot::write_reu_sidecar;
+use crate::crt_builder::{cartridge_name_or_default, output_stem, CRTBuilder, CartridgeType, BANK_SIZE_8K};
+use crate::file_system_manager::{c64_name, FileSystemManager};
 use crate::find_ram::FindRam;
+use crate::magic_desk_ram_load::{visible_ram, RamLoadHook};
 use crate::make_magic_desk_boot_asm::MakeMagicDeskBootAsm;
 use crate::make_magic_desk_crt_asm::MakeMagicDeskCRTAsm;
 use crate::parse_vsf::{C64Mem, C64Snapshot, ParseVSF};
 use crate::patch_mem::PatchMem;
+use crate::prepend_stub::install_configured;
+use crate::sanitize_zp::repair_basic_routines;
 use std::fs;
 
 pub struct ConvertSnapshotMagicDeskCRT {
@@ -42,21 +51,40 @@ impl ConvertSnapshotMagicDeskCRT {
 
     /// Convert a VSF snapshot to a Magic Desk CRT file
     pub fn convert(&self, input_path: &str, output_path: &str) -> Result<(), String> {
+        self.convert_with_usage(input_path, output_path).map(|_| ())
+    }
+
+    /// Convert like `convert` and report how the banks of the written CRT are used
+    pub fn convert_with_usage(&self, input_path: &str, output_path: &str) -> Result<CrtUsage, String> {
         if std::path::Path::new(output_path).exists() {
             return Err(format!(
                 "Output file already exists:\n{}\n\nPlease choose a different filename.",
                 output_path
             ));
         }
+        let _dump = AsmDump::start(self.config.base_config.dump_asm.as_deref())?;
 
         // Parse the VSF file
-        let parser = ParseVSF::import(input_path, &self.config.base_config)
+        let parser = ParseVSF::import_streaming(input_path, &self.config.base_config)
             .map_err(|e| format!("Failed to read VSF file: {}", e))?;
 
-        let snap = parser
+        let mut snap = parser
             .parse_import()
             .map_err(|e| format!("Failed to parse VSF: {}", e))?;
 
+        if let Some(target) = self.config.base_config.retime {
+            snap.retime(target, self.config.base_config.retime_timer_b);
+        }
+        if self.config.base_config.sanitize_zp {
+            repair_basic_routines(&mut snap);
+        }
+        if self.config.base_config.blank_on_restore {
+            snap.vic.blank();
+        }
+        if self.config.base_config.force_display_on {
+            snap.vic.show_display();
+        }
+
         // Preserve $F8-$FF before any patching
         let mut f8_ff_data = [0u8; 8];
         f8_ff_data.copy_from_slice(&snap.mem.ram[0xF8..=0xFF]);
@@ -71,12 +99,50 @@ impl ConvertSnapshotMagicDeskCRT {
             }
         }
 
+        // A --prepend stub takes free RAM before the RAM finder scans
+        let (restore_options, stub) = install_configured(&self.config.base_config, &snap, &mut ram)?;
+
         // No LOAD/SAVE hooking for Magic Desk -- initialize RAM finder directly
         let mut ram_finder = FindRam::with_extra_blocks(&ram, &self.extra_ram_blocks);
 
+        // Experimental RAM LOAD: files take a free block before the restore blocks are placed
+        let mut ram_load_files = Vec::new();
+        if let (true, Some(dir)) = (self.config.magic_desk_ram_load, &self.config.include_dir) {
+            let files = FileSystemManager::new(dir).read_prg_files()?;
+            if !files.is_empty() {
+                ram_load_files = files.iter().map(|file| c64_name(&file.filename)).collect();
+                let hook = RamLoadHook::new(files);
+                let size = hook.required_size()?;
+                // Only RAM the snapshot's $01 leaves visible: LOAD enters the handler with it
+                let port = snap.mem.cpu_port_data;
+                let mut visible = ram_finder.clone();
+                visible.restrict_to(&visible_ram(port));
+                let allocation = u16::try_from(size)
+                    .ok()
+                    .and_then(|count| Some((visible.allocate(count)?.0, count)))
+                    .and_then(|(addr, count)| ram_finder.allocate_at(addr, count));
+                let addr = match allocation {
+                    Some((addr, _)) => addr,
+                    None => {
+                        return Err(format!(
+                            "Not enough free RAM for Magic Desk LOAD: the files need {} bytes in one block, \
+                             the largest free block outside ROM and I/O (${:02X} in $01) is {} bytes",
+                            size,
+                            port,
+                            visible.find_max()
+                        ));
+                    }
+                };
+                hook.install(&mut *ram, addr)?;
+            }
+        }
+
         // Patch memory with restoration code (using PatchMem)
-        let patch_mem = PatchMem::new(&snap, &mut *ram, &mut ram_finder)
+        let patch_mem = PatchMem::with_options(&snap, &mut *ram, &mut ram_finder, restore_options)
             .map_err(|e| format!("Memory patching failed: {}", e))?;
+        if let Some(ref stub) = stub {
+            stub.check_blocks(&patch_mem.block_ranges())?;
+        }
 
         // Create patched snapshot
         let patched_snap = C64Snapshot {
@@ -84,12 +150,15 @@ impl ConvertSnapshotMagicDeskCRT {
             mem: C64Mem {
                 cpu_port_data: snap.mem.cpu_port_data,
                 cpu_port_dir: snap.mem.cpu_port_dir,
+                exrom: snap.mem.exrom,
+                game: snap.mem.game,
                 ram,
             },
             vic: snap.vic.clone(),
             cia1: snap.cia1.clone(),
             cia2: snap.cia2.clone(),
             sid: snap.sid.clone(),
+            sid2_addr: snap.sid2_addr,
         };
 
         // Extract and compress components
@@ -133,6 +202,7 @@ impl ConvertSnapshotMagicDeskCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             0,
             ram_lzsa_size,
@@ -153,6 +223,7 @@ impl ConvertSnapshotMagicDeskCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             relocated_size,
             ram_lzsa_size,
@@ -173,6 +244,7 @@ impl ConvertSnapshotMagicDeskCRT {
             &format!("{}.lzsa", zp_path),
             patch_mem.get_block9_addr(),
             f8_ff_data,
+            snap.vic.raster_regs(),
             &self.config.base_config,
             relocated_size,
             ram_lzsa_size,
@@ -230,11 +302,7 @@ impl ConvertSnapshotMagicDeskCRT {
         payload.extend_from_slice(&ram_lzsa);
 
         // Create CRT builder
-        let cartridge_name = self
-            .config
-            .cartridge_name
-            .as_deref()
-            .unwrap_or("VICE SNAPSHOT");
+        let cartridge_name = cartridge_name_or_default(self.config.cartridge_name.as_deref());
         let mut crt = CRTBuilder::new(CartridgeType::MagicDesk, num_banks, cartridge_name)?;
 
         // Fill bank 0: boot code first, then payload
@@ -267,7 +335,92 @@ impl ConvertSnapshotMagicDeskCRT {
 
         // Write CRT file
         crt.make_crt(output_path)?;
+        if let Some(ref dir) = self.config.split_bins {
+            crt.write_split_bins(dir, &output_stem(output_path))?;
+        }
+        write_reu_sidecar(input_path, output_path)?;
+
+        // RAM-load files travel inside the RAM data, so no bank holds them directly
+        let boot_len = boot_code_binary.len();
+        let restore_len = final_restore_code.len() + final_relocated.len();
+        let regions = [
+            BankRegion { content: BankContent::Boot, start: 0, len: boot_len },
+            BankRegion { content: BankContent::RestoreCode, start: boot_len, len: restore_len },
+            BankRegion { content: BankContent::RamData, start: boot_len + restore_len, len: ram_lzsa.len() },
+        ];
+        Ok(CrtUsage {
+            banks: bank_usage(&crt, &regions, &[])?,
+            files: ram_load_files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::load_save_hook::LOAD_VECTOR;
+    use crate::test_vsf::{busy_ram, TestVsf};
+
+    /// Convert with one embedded file and return the uncompressed RAM image ($0200-$FFEF)
+    fn converted_ram(tag: &str, ram_load: bool) -> (Vec<u8>, Vec<u8>) {
+        let (dir, vsf) = TestVsf::new().write(tag);
+        let work = dir.join("work");
+        let files = dir.join("files");
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&files).unwrap();
+        let data: Vec<u8> = (0..200).map(|i| (i * 7 + 3) as u8).collect();
+        let mut prg = vec![0x00, 0xC0];
+        prg.extend_from_slice(&data);
+        fs::write(files.join("hello.prg"), prg).unwrap();
+
+        let config = CrtConfig::new(Config::new(&work))
+            .with_include_dir(files.to_str().unwrap())
+            .with_magic_desk_ram_load(ram_load);
+        let output = dir.join("out.crt");
+        ConvertSnapshotMagicDeskCRT::new(config).convert(&vsf, output.to_str().unwrap()).unwrap();
+
+        let ram = fs::read(work.join(format!("{}-ram.hi", tag))).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        (ram, data)
+    }
+
+    #[test]
+    fn test_ram_load_embeds_files_in_restored_ram() {
+        let (ram, data) = converted_ram("md_ram_load", true);
+        let file_at = ram.windows(data.len()).position(|w| w == data.as_slice());
+        assert!(file_at.is_some(), "file data missing from RAM image");
+
+        // LOAD vector points at the handler below the file
+        let vector = LOAD_VECTOR - 0x0200;
+        let handler = u16::from_le_bytes([ram[vector], ram[vector + 1]]) as usize;
+        assert!(handler - 0x0200 < file_at.unwrap());
+        assert!(ram.windows(5).any(|w| w == b"HELLO"));
+
+        let (plain, data) = converted_ram("md_no_ram_load", false);
+        assert!(!plain.windows(data.len()).any(|w| w == data.as_slice()));
+    }
 
-        Ok(())
+    #[test]
+    fn test_ram_load_handler_stays_out_of_rom() {
+        // The only free RAM is under BASIC ROM, which $37 leaves banked in
+        let mut vsf = TestVsf::new();
+        vsf.ram = busy_ram();
+        vsf.ram[0xA000..0xC000].fill(0x00);
+        let (dir, input) = vsf.write("md_ram_load_under_rom");
+        let work = dir.join("work");
+        let files = dir.join("files");
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&files).unwrap();
+        fs::write(files.join("hello.prg"), [0x00, 0xC0, 0x60]).unwrap();
+
+        let config = CrtConfig::new(Config::new(&work))
+            .with_include_dir(files.to_str().unwrap())
+            .with_magic_desk_ram_load(true);
+        let output = dir.join("out.crt");
+        let err = ConvertSnapshotMagicDeskCRT::new(config).convert(&input, output.to_str().unwrap()).unwrap_err();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(err.contains("outside ROM and I/O ($37 in $01) is 0 bytes"), "{}", err);
     }
 }
@@ -7,6 +7,9 @@
 
 use std::fs::File;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::output_hash::record_output;
 
 /// Supported cartridge types
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,6 +23,17 @@ pub enum CartridgeType {
 }
 
 impl CartridgeType {
+    /// Every cartridge type the converters can build
+    pub const ALL: [CartridgeType; 2] = [CartridgeType::EasyFlash, CartridgeType::MagicDesk];
+
+    /// Name as the CLI format flags spell it
+    pub fn name(&self) -> &'static str {
+        match self {
+            CartridgeType::EasyFlash => "easyflash",
+            CartridgeType::MagicDesk => "magic-desk",
+        }
+    }
+
     pub fn hardware_type(&self) -> u16 {
         match self {
             CartridgeType::EasyFlash => 32,
@@ -50,8 +64,49 @@ impl CartridgeType {
 }
 
 pub const BANK_SIZE_8K: usize = 8192;
+/// Value of bytes in a bank that were never written
+pub const BANK_FILL: u8 = 0x00;
+/// Value of ROMH banks without data in split EPROM images (erased EPROM)
+pub const EPROM_FILL: u8 = 0xFF;
 pub const LOAD_ADDRESS_ROML: u16 = 0x8000;
 pub const LOAD_ADDRESS_ROMH: u16 = 0xE000;
+/// Banks in an EasyFlash 3 image as the EF3 USB tools expect it (1 MB)
+pub const EF3_BANKS: usize = 64;
+/// Name characters stored in the CRT header (32 bytes, null-terminated)
+pub const MAX_NAME_LEN: usize = 31;
+
+/// File stem of a CRT output path, used to name its split EPROM images
+pub fn output_stem(output_path: &str) -> String {
+    Path::new(output_path)
+        .file_stem()
+        .map_or_else(|| "cartridge".to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Cartridge name from a snapshot's file name: the stem, uppercased and truncated
+///
+/// Characters outside printable ASCII are replaced with `_`, since the CRT
+/// header holds plain ASCII.
+pub fn cartridge_name_from_path(path: &Path) -> Result<String, String> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .filter(|stem| !stem.trim().is_empty())
+        .ok_or_else(|| format!("Cannot derive a cartridge name from {}", path.display()))?;
+
+    Ok(stem
+        .chars()
+        .map(|c| if c == ' ' || c.is_ascii_graphic() { c.to_ascii_uppercase() } else { '_' })
+        .take(MAX_NAME_LEN)
+        .collect())
+}
+
+/// Header name used when no name is given, or the given one is blank
+pub const DEFAULT_CARTRIDGE_NAME: &str = "VICE SNAPSHOT";
+
+/// Name for the header: `name` without surrounding spaces, or the default if nothing is left
+pub fn cartridge_name_or_default(name: Option<&str>) -> &str {
+    name.map(str::trim).filter(|name| !name.is_empty()).unwrap_or(DEFAULT_CARTRIDGE_NAME)
+}
 
 /// Builder for C64 cartridge files (.crt)
 pub struct CRTBuilder {
@@ -67,11 +122,13 @@ impl CRTBuilder {
     /// # Arguments
     /// * `cartridge_type` - Type of cartridge (EasyFlash)
     /// * `initial_banks` - Number of banks to create initially
-    /// * `name` - Cartridge name (max 32 characters, will be converted to uppercase)
+    /// * `name` - Cartridge name (max 32 characters, will be trimmed and converted to uppercase;
+    ///   blank names become `DEFAULT_CARTRIDGE_NAME`)
     pub fn new(cartridge_type: CartridgeType, initial_banks: usize, name: &str) -> Result<Self, String> {
         if initial_banks == 0 {
             return Err("Must have at least one bank".to_string());
         }
+        let name = cartridge_name_or_default(Some(name));
         if name.len() > 32 {
             return Err("Name cannot be longer than 32 characters".to_string());
         }
@@ -92,7 +149,7 @@ impl CRTBuilder {
 
     /// Add a new bank and return the bank number
     pub fn add_bank(&mut self) -> usize {
-        self.banks.push(Box::new([0u8; BANK_SIZE_8K]));
+        self.banks.push(Box::new([BANK_FILL; BANK_SIZE_8K]));
         self.banks_romh.push(None);
         self.banks.len() - 1
     }
@@ -102,6 +159,39 @@ impl CRTBuilder {
         self.banks.len()
     }
 
+    /// Add empty banks until there are `bank_count`; fails if there are already more
+    pub fn pad_to(&mut self, bank_count: usize) -> Result<(), String> {
+        if self.banks.len() > bank_count {
+            return Err(format!(
+                "Cartridge uses {} banks, more than the {} it must be padded to",
+                self.banks.len(),
+                bank_count
+            ));
+        }
+        while self.banks.len() < bank_count {
+            self.add_bank();
+        }
+        Ok(())
+    }
+
+    /// Check the layout EF3 USB tools expect: exactly `EF3_BANKS` banks, ROMH on bank 0 only
+    pub fn check_ef3(&self) -> Result<(), String> {
+        if self.banks.len() != EF3_BANKS {
+            return Err(format!(
+                "EasyFlash 3 image must have {} banks, got {}",
+                EF3_BANKS,
+                self.banks.len()
+            ));
+        }
+        if self.banks_romh[0].is_none() {
+            return Err("EasyFlash 3 image has no ROMH in bank 0".to_string());
+        }
+        if let Some(bank) = (1..self.banks_romh.len()).find(|&bank| self.banks_romh[bank].is_some()) {
+            return Err(format!("EasyFlash 3 image has ROMH in bank {}; only bank 0 may have it", bank));
+        }
+        Ok(())
+    }
+
     /// Get a mutable reference to a bank's data
     pub fn get_bank_mut(&mut self, bank_number: usize) -> Result<&mut [u8; BANK_SIZE_8K], String> {
         let max_bank = self.banks.len().saturating_sub(1);
@@ -155,6 +245,43 @@ impl CRTBuilder {
         Ok(())
     }
 
+    /// Fill data starting at `offset` in `start_bank` and continue at offset 0
+    /// of the following banks, adding banks before they are written
+    pub fn fill_spanning(&mut self, start_bank: usize, offset: usize, data: &[u8]) -> Result<(), String> {
+        if offset > BANK_SIZE_8K {
+            return Err(format!("Offset {} is outside the {} byte bank", offset, BANK_SIZE_8K));
+        }
+
+        let mut bank = start_bank;
+        let mut offset = offset;
+        let mut written = 0;
+        while written < data.len() {
+            if offset == BANK_SIZE_8K {
+                bank += 1;
+                offset = 0;
+            }
+            while bank >= self.banks.len() {
+                self.add_bank();
+            }
+            debug_assert!(bank < self.banks.len(), "bank {} filled before it was added", bank);
+
+            let chunk_size = (BANK_SIZE_8K - offset).min(data.len() - written);
+            self.fill_bank(bank, &data[written..written + chunk_size], offset)?;
+            written += chunk_size;
+            offset += chunk_size;
+        }
+
+        Ok(())
+    }
+
+    /// Bytes in use in a bank: everything up to the last byte that isn't `BANK_FILL`
+    ///
+    /// An estimate, since data may itself end in fill bytes.
+    pub fn used_bytes(&self, bank_number: usize) -> Result<usize, String> {
+        let bank = self.get_bank(bank_number)?;
+        Ok(bank.iter().rposition(|&b| b != BANK_FILL).map_or(0, |last| last + 1))
+    }
+
     /// Clear a bank with a specific byte value
     pub fn clear_bank(&mut self, bank_number: usize, value: u8) -> Result<(), String> {
         let bank = self.get_bank_mut(bank_number)?;
@@ -184,13 +311,77 @@ impl CRTBuilder {
     }
 
     /// Write the CRT file to disk
+    ///
+    /// Flushes and syncs the file, then checks its length, so a full disk
+    /// that truncates the write is reported instead of leaving a short CRT.
     pub fn make_crt(&self, output_file: &str) -> Result<(), String> {
         let crt_data = self.generate_crt_data();
         let mut file = File::create(output_file)
             .map_err(|e| format!("Failed to create CRT file: {}", e))?;
         file.write_all(&crt_data)
             .map_err(|e| format!("Failed to write CRT data: {}", e))?;
-        Ok(())
+        file.flush()
+            .map_err(|e| format!("Failed to flush CRT file: {}", e))?;
+        file.sync_all()
+            .map_err(|e| format!("Failed to sync CRT file: {}", e))?;
+
+        let written = file
+            .metadata()
+            .map_err(|e| format!("Failed to check CRT file: {}", e))?
+            .len();
+        if written != crt_data.len() as u64 {
+            return Err(format!(
+                "CRT file is truncated: {} of {} bytes written to {}",
+                written,
+                crt_data.len(),
+                output_file
+            ));
+        }
+        record_output(Path::new(output_file), &crt_data)
+    }
+
+    /// All ROML banks back to back: bank n at offset n * 8K
+    pub fn roml_image(&self) -> Vec<u8> {
+        self.banks.iter().flat_map(|bank| bank.iter().copied()).collect()
+    }
+
+    /// All ROMH banks back to back, or `None` if no bank has ROMH data
+    ///
+    /// Banks without ROMH are filled with `EPROM_FILL` so bank n stays at
+    /// offset n * 8K, matching the ROML image.
+    pub fn romh_image(&self) -> Option<Vec<u8>> {
+        if self.banks_romh.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(
+            self.banks_romh
+                .iter()
+                .flat_map(|romh| romh.as_deref().map_or([EPROM_FILL; BANK_SIZE_8K], |data| *data))
+                .collect(),
+        )
+    }
+
+    /// Write `<stem>_roml.bin` and, if any bank has ROMH data, `<stem>_romh.bin` to `dir`
+    ///
+    /// Raw images for an EPROM programmer, one per chip, without CRT headers
+    /// (see `roml_image`, `romh_image`). Returns the files written.
+    pub fn write_split_bins(&self, dir: &Path, stem: &str) -> Result<Vec<PathBuf>, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let mut images = vec![("roml", self.roml_image())];
+        if let Some(romh) = self.romh_image() {
+            images.push(("romh", romh));
+        }
+
+        let mut written = Vec::new();
+        for (chip, image) in images {
+            let path = dir.join(format!("{}_{}.bin", stem, chip));
+            std::fs::write(&path, &image)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            written.push(path);
+        }
+        Ok(written)
     }
 
     /// Create CRT file header (64 bytes)
@@ -219,7 +410,7 @@ impl CRTBuilder {
 
         // Cartridge name (32 bytes, null-terminated)
         let name_bytes = self.name.as_bytes();
-        let copy_len = name_bytes.len().min(31);
+        let copy_len = name_bytes.len().min(MAX_NAME_LEN);
         header[32..32 + copy_len].copy_from_slice(&name_bytes[..copy_len]);
         // Rest already filled with zeros
 
@@ -266,6 +457,85 @@ mod tests {
         assert_eq!(builder.bank_count(), 8);
     }
 
+    #[test]
+    fn test_cartridge_name_from_path() {
+        assert_eq!(cartridge_name_from_path(Path::new("game.vsf")).unwrap(), "GAME");
+        assert_eq!(cartridge_name_from_path(Path::new("snaps/Last Ninja.vsf")).unwrap(), "LAST NINJA");
+        assert_eq!(cartridge_name_from_path(Path::new("åbc.vsf")).unwrap(), "_BC");
+        let long = cartridge_name_from_path(Path::new(&format!("{}.vsf", "x".repeat(40)))).unwrap();
+        assert_eq!(long.len(), MAX_NAME_LEN);
+        assert!(cartridge_name_from_path(Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn test_blank_name_falls_back_to_default() {
+        assert_eq!(cartridge_name_or_default(Some("  Game  ")), "Game");
+        assert_eq!(cartridge_name_or_default(Some("   ")), DEFAULT_CARTRIDGE_NAME);
+        assert_eq!(cartridge_name_or_default(None), DEFAULT_CARTRIDGE_NAME);
+
+        let header = CRTBuilder::new(CartridgeType::EasyFlash, 1, "").unwrap().create_file_header();
+        assert_eq!(&header[32..32 + DEFAULT_CARTRIDGE_NAME.len()], DEFAULT_CARTRIDGE_NAME.as_bytes());
+    }
+
+    #[test]
+    fn test_roml_image_matches_chip_payloads() {
+        let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 3, "Split").unwrap();
+        builder.fill_bank(0, &[0x11; 100], 0).unwrap();
+        builder.fill_bank(2, &[0x33; 50], 8000).unwrap();
+        builder.set_bank_romh(0, &[0xEE; BANK_SIZE_8K]).unwrap();
+
+        // Collect the ROML CHIP payloads in bank order
+        let crt = builder.generate_crt_data();
+        let mut roml = Vec::new();
+        let mut pos = 64;
+        while pos < crt.len() {
+            let packet_len = u32::from_be_bytes(crt[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            if u16::from_be_bytes([crt[pos + 12], crt[pos + 13]]) == LOAD_ADDRESS_ROML {
+                roml.extend_from_slice(&crt[pos + 16..pos + packet_len]);
+            }
+            pos += packet_len;
+        }
+        assert_eq!(builder.roml_image(), roml);
+
+        let romh = builder.romh_image().unwrap();
+        assert_eq!(romh.len(), 3 * BANK_SIZE_8K);
+        assert!(romh[..BANK_SIZE_8K].iter().all(|&b| b == 0xEE));
+        assert!(romh[BANK_SIZE_8K..].iter().all(|&b| b == EPROM_FILL));
+
+        let magic_desk = CRTBuilder::new(CartridgeType::MagicDesk, 2, "Split").unwrap();
+        assert!(magic_desk.romh_image().is_none());
+    }
+
+    #[test]
+    fn test_make_crt_writes_whole_image() {
+        let dir = crate::test_vsf::temp_dir("make_crt");
+        let path = dir.join("out.crt");
+        let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 2, "Write").unwrap();
+        builder.set_bank_romh(0, &[0xEA; BANK_SIZE_8K]).unwrap();
+
+        builder.make_crt(path.to_str().unwrap()).unwrap();
+        let crt = std::fs::read(&path).unwrap();
+        assert_eq!(crt.len(), builder.generate_crt_data().len());
+        assert_eq!(crt, builder.generate_crt_data());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pad_to_ef3() {
+        let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 3, "EF3").unwrap();
+        builder.set_bank_romh(0, &[0xEA; BANK_SIZE_8K]).unwrap();
+        assert!(builder.check_ef3().is_err());
+
+        builder.pad_to(EF3_BANKS).unwrap();
+        assert_eq!(builder.bank_count(), EF3_BANKS);
+        builder.check_ef3().unwrap();
+        assert!(builder.pad_to(EF3_BANKS - 1).is_err());
+
+        builder.set_bank_romh(5, &[0xEA; BANK_SIZE_8K]).unwrap();
+        assert!(builder.check_ef3().unwrap_err().contains("bank 5"));
+    }
+
     #[test]
     fn test_add_bank() {
         let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 1, "Test").unwrap();
@@ -282,4 +552,42 @@ mod tests {
         let bank = builder.get_bank(0).unwrap();
         assert_eq!(&bank[0..3], &data);
     }
+
+    #[test]
+    fn test_chip_type_follows_cartridge_type() {
+        for (cartridge_type, chip_type) in [(CartridgeType::EasyFlash, 2u16), (CartridgeType::MagicDesk, 0u16)] {
+            let builder = CRTBuilder::new(cartridge_type, 2, "Test").unwrap();
+            let data = builder.generate_crt_data();
+
+            // CHIP packets follow the 64-byte header, one 16-byte header + 8 KB each
+            for bank in 0..2 {
+                let packet = 64 + bank * (16 + BANK_SIZE_8K);
+                assert_eq!(&data[packet..packet + 4], b"CHIP");
+                assert_eq!(&data[packet + 8..packet + 10], &chip_type.to_be_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_spanning_adds_banks_across_boundary() {
+        let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 2, "Test").unwrap();
+
+        // Starts 0x100 bytes before the end of bank 1 and runs two banks past it
+        let data: Vec<u8> = (0..0x100 + 2 * BANK_SIZE_8K + 0x10).map(|i| (i % 251) as u8).collect();
+        builder.fill_spanning(1, BANK_SIZE_8K - 0x100, &data).unwrap();
+
+        assert_eq!(builder.bank_count(), 4);
+        assert_eq!(&builder.get_bank(1).unwrap()[BANK_SIZE_8K - 0x100..], &data[..0x100]);
+        assert_eq!(&builder.get_bank(2).unwrap()[..], &data[0x100..0x100 + BANK_SIZE_8K]);
+        assert_eq!(&builder.get_bank(3).unwrap()[..0x10], &data[0x100 + BANK_SIZE_8K..]);
+        assert!(builder.get_bank(3).unwrap()[0x10..].iter().all(|&b| b == 0));
+        assert!(builder.get_bank(0).unwrap().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_fill_spanning_exact_fit_adds_no_bank() {
+        let mut builder = CRTBuilder::new(CartridgeType::EasyFlash, 1, "Test").unwrap();
+        builder.fill_spanning(0, 0x1000, &[0xAA; BANK_SIZE_8K - 0x1000]).unwrap();
+        assert_eq!(builder.bank_count(), 1);
+    }
 }
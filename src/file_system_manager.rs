@@ -3,9 +3,19 @@
 //! Reads PRG files from a directory and allocates them to unused banks.
 //! Generates metadata for file directory at $B000-$B7FF and filenames at $B800+
 //!
+//! An optional `files.txt` in the include directory overrides load addresses
+//! and sets per-file flags. One file per line, `#` or `;` starts a comment:
+//!
+//! ```text
+//! intro.prg   $C000           ; header address replaced
+//! music.bin   $1000  force    ; headerless data, always loads to $1000
+//! level2.prg  $4000  hidden   ; not matched by wildcards
+//! ```
+//!
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use crate::crt_builder::{CRTBuilder, BANK_SIZE_8K};
@@ -14,9 +24,19 @@ pub const METADATA_START: u16 = 0xB000;
 pub const METADATA_END: u16 = 0xB7FF;
 pub const FILENAME_START: u16 = 0xB800;
 pub const FILENAME_END: u16 = 0xBFFF;
+/// Filename start when ROMH $B800-$BB1F holds the EAPI stub and EF-Name (`--easyflash-hardware`)
+pub const HARDWARE_FILENAME_START: u16 = 0xBB20;
 pub const MAX_BANKS_PER_FILE: usize = 8;
+pub const MAX_BANKS: usize = 64;
+pub const C64_NAME_LENGTH: usize = 16;
 pub const MAX_FILE_SIZE: usize = 64 * 1024; // 64KB
 pub const METADATA_ENTRY_SIZE: usize = 16;
+/// Per-file override sidecar in the include directory
+pub const OVERRIDE_FILE: &str = "files.txt";
+/// Flag bits in the high byte of the metadata start offset (offsets stay below $2000)
+pub const FLAG_FORCE_ADDRESS: u8 = 0x80;
+pub const FLAG_HIDDEN: u8 = 0x40;
+pub const FLAG_AUTOSTART: u8 = 0x20;
 
 /// Represents a PRG file with its metadata
 #[derive(Debug, Clone)]
@@ -25,6 +45,20 @@ pub struct PRGFile {
     pub load_address: u16,
     pub data: Vec<u8>,
     pub total_size: usize,
+    /// Always load to `load_address`, even for LOAD "NAME",8
+    pub force_address: bool,
+    /// Only matched by its exact name, never by `*` or an empty name
+    pub hidden: bool,
+    /// Loaded by `LOAD ""` and run after restore (`--autostart`)
+    pub autostart: bool,
+}
+
+/// One `files.txt` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileOverride {
+    pub load_address: u16,
+    pub force_address: bool,
+    pub hidden: bool,
 }
 
 /// Represents file allocation in banks
@@ -39,6 +73,7 @@ pub struct FileAllocation {
 /// Manages file system in CRT cartridge
 pub struct FileSystemManager {
     include_dir: String,
+    filename_start: u16,
 }
 
 impl FileSystemManager {
@@ -46,11 +81,24 @@ impl FileSystemManager {
     pub fn new(include_dir: &str) -> Self {
         Self {
             include_dir: include_dir.to_string(),
+            filename_start: FILENAME_START,
         }
     }
 
+    /// Place the filename block at a different ROMH address (default $B800)
+    pub fn with_filename_start(mut self, addr: u16) -> Self {
+        self.filename_start = addr;
+        self
+    }
+
     /// Read all PRG files from directory
     pub fn read_prg_files(&self) -> Result<Vec<PRGFile>, String> {
+        self.read_prg_files_reporting().map(|(files, _)| files)
+    }
+
+    /// Read all PRG files from directory, plus the sorted names of skipped
+    /// non-PRG files (other extensions or none at all)
+    pub fn read_prg_files_reporting(&self) -> Result<(Vec<PRGFile>, Vec<String>), String> {
         let dir = Path::new(&self.include_dir);
         if !dir.exists() || !dir.is_dir() {
             return Err(format!("Include directory does not exist: {}", self.include_dir));
@@ -59,29 +107,77 @@ impl FileSystemManager {
         let entries = fs::read_dir(dir)
             .map_err(|e| format!("Failed to read directory: {}", e))?;
 
+        let mut overrides = self.read_overrides()?;
+
         let mut files = Vec::new();
+        let mut skipped = Vec::new();
         for entry in entries {
             let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
             let path = entry.path();
-
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext.to_ascii_lowercase() == "prg" {
-                        files.push(self.parse_prg_file(&path)?);
-                    }
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if path.is_file() && name != OVERRIDE_FILE {
+                let is_prg = path
+                    .extension()
+                    .is_some_and(|ext| ext.to_ascii_lowercase() == "prg");
+                let file_override = overrides.remove(&name);
+                if is_prg || file_override.is_some() {
+                    files.push(self.parse_prg_file(&path, file_override)?);
+                } else {
+                    skipped.push(name);
                 }
             }
         }
 
-        Ok(files)
+        if let Some(name) = overrides.keys().min() {
+            return Err(format!("{}: no such file in include directory: {}", OVERRIDE_FILE, name));
+        }
+
+        // Directory order is filesystem dependent; sort so every run allocates alike
+        files.sort_by(|a, b| a.filename.cmp(&b.filename));
+        skipped.sort();
+
+        Ok((files, skipped))
+    }
+
+    /// Read the PRG files and allocate them to the given free banks
+    /// Used by both the CRT build and the `--list-files` preview
+    pub fn plan_files(&self, available_banks: &[usize]) -> Result<Vec<FileAllocation>, String> {
+        let files = self.read_prg_files()?;
+        self.allocate_files(&files, available_banks)
+    }
+
+    /// `plan_files` for the `--list-files` preview, without a snapshot
+    ///
+    /// Files start at bank 1, where the build puts them when the restore
+    /// data fits bank 0.
+    pub fn preview_files(&self) -> Result<Vec<FileAllocation>, String> {
+        let available_banks: Vec<usize> = (1..MAX_BANKS).collect();
+        self.plan_files(&available_banks)
     }
 
-    /// Parse a PRG file
-    fn parse_prg_file(&self, path: &Path) -> Result<PRGFile, String> {
+    /// Read `files.txt` from the include directory, keyed by filename
+    pub fn read_overrides(&self) -> Result<HashMap<String, FileOverride>, String> {
+        let path = Path::new(&self.include_dir).join(OVERRIDE_FILE);
+        if !path.is_file() {
+            return Ok(HashMap::new());
+        }
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        parse_overrides(&text)
+    }
+
+    /// Parse a PRG file, or a headerless data file when an override gives its address
+    fn parse_prg_file(&self, path: &Path, file_override: Option<FileOverride>) -> Result<PRGFile, String> {
         let bytes = fs::read(path)
             .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
 
-        if bytes.len() < 2 {
+        let is_prg = path
+            .extension()
+            .is_some_and(|ext| ext.to_ascii_lowercase() == "prg");
+
+        if is_prg && bytes.len() < 2 {
             return Err(format!(
                 "PRG file too small: {} ({} bytes)",
                 path.display(),
@@ -89,9 +185,13 @@ impl FileSystemManager {
             ));
         }
 
-        // First 2 bytes are load address (little-endian)
-        let load_address = (bytes[0] as u16) | ((bytes[1] as u16) << 8);
-        let data = bytes[2..].to_vec();
+        // First 2 bytes are load address (little-endian); other files have no header
+        let (load_address, data) = if is_prg {
+            ((bytes[0] as u16) | ((bytes[1] as u16) << 8), bytes[2..].to_vec())
+        } else {
+            (0, bytes.clone())
+        };
+        let load_address = file_override.map_or(load_address, |o| o.load_address);
 
         if data.len() > MAX_FILE_SIZE {
             return Err(format!(
@@ -108,11 +208,18 @@ impl FileSystemManager {
             .unwrap_or("unknown")
             .to_string();
 
+        if file_override.is_some() {
+            check_ram_range(&filename, load_address, data.len())?;
+        }
+
         Ok(PRGFile {
             filename,
             load_address,
             data,
             total_size: bytes.len(),
+            force_address: file_override.is_some_and(|o| o.force_address),
+            hidden: file_override.is_some_and(|o| o.hidden),
+            autostart: false,
         })
     }
 
@@ -219,6 +326,17 @@ impl FileSystemManager {
         })
     }
 
+    /// Flag the file whose C64 name matches `name` as the autostart entry
+    pub fn mark_autostart(&self, allocations: &mut [FileAllocation], name: &str) -> Result<(), String> {
+        let wanted = c64_name(name);
+        let allocation = allocations
+            .iter_mut()
+            .find(|a| c64_name(&a.file.filename) == wanted)
+            .ok_or_else(|| format!("Autostart file not found in include directory: {}", name))?;
+        allocation.file.autostart = true;
+        Ok(())
+    }
+
     /// Get set of all allocated banks
     pub fn get_allocated_banks(&self, allocations: &[FileAllocation]) -> std::collections::HashSet<usize> {
         allocations.iter().flat_map(|a| a.banks.iter().copied()).collect()
@@ -228,7 +346,7 @@ impl FileSystemManager {
     /// Format per entry (16 bytes):
     /// - 2 bytes: pointer to filename
     /// - 8 bytes: bank list (up to 8 banks, $00 = no more banks)
-    /// - 2 bytes: start offset in first bank
+    /// - 2 bytes: start offset in first bank (bit 15 = force address, bit 14 = hidden, bit 13 = autostart)
     /// - 2 bytes: file length
     /// - 2 bytes: load address
     pub fn generate_metadata(&self, allocations: &[FileAllocation]) -> Result<Vec<u8>, String> {
@@ -241,7 +359,7 @@ impl FileSystemManager {
                 return Err("Too many files - metadata area full".to_string());
             }
 
-            let filename_ptr = FILENAME_START + allocation.filename_offset as u16;
+            let filename_ptr = self.filename_start + allocation.filename_offset as u16;
 
             // Pointer to filename (little-endian)
             metadata[offset] = (filename_ptr & 0xFF) as u8;
@@ -258,9 +376,19 @@ impl FileSystemManager {
                 offset += 1;
             }
 
-            // Start offset in first bank (little-endian)
+            // Start offset in first bank (little-endian), flags in the top bits
+            let mut flags = 0;
+            if allocation.file.force_address {
+                flags |= FLAG_FORCE_ADDRESS;
+            }
+            if allocation.file.hidden {
+                flags |= FLAG_HIDDEN;
+            }
+            if allocation.file.autostart {
+                flags |= FLAG_AUTOSTART;
+            }
             metadata[offset] = (allocation.start_offset & 0xFF) as u8;
-            metadata[offset + 1] = ((allocation.start_offset >> 8) & 0xFF) as u8;
+            metadata[offset + 1] = ((allocation.start_offset >> 8) & 0x1F) as u8 | flags;
             offset += 2;
 
             // File length (little-endian)
@@ -278,10 +406,10 @@ impl FileSystemManager {
         Ok(metadata)
     }
 
-    /// Generate filename block for $B800+ area
+    /// Generate filename block for $B800+ area (or the address set by `with_filename_start`)
     /// Filenames are stored as PETSCII, null-terminated, WITHOUT .prg extension
     pub fn generate_filenames(&self, allocations: &[FileAllocation]) -> Result<Vec<u8>, String> {
-        let max_size = (FILENAME_END - FILENAME_START + 1) as usize;
+        let max_size = (FILENAME_END - self.filename_start + 1) as usize;
         let mut filenames = vec![0u8; max_size];
         let mut offset = 0;
 
@@ -334,6 +462,65 @@ impl FileSystemManager {
     }
 }
 
+/// Name as seen by LOAD on the C64: no extension, uppercase, max 16 characters
+pub fn c64_name(filename: &str) -> String {
+    strip_prg_extension(filename)
+        .to_ascii_uppercase()
+        .chars()
+        .take(C64_NAME_LENGTH)
+        .collect()
+}
+
+/// Parse `files.txt`: `<filename> <address> [force] [hidden]` per line
+pub fn parse_overrides(text: &str) -> Result<HashMap<String, FileOverride>, String> {
+    let mut overrides = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split(['#', ';']).next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let name = fields.next().unwrap_or_default();
+        let addr_str = fields
+            .next()
+            .ok_or_else(|| format!("{} line {}: missing load address for {}", OVERRIDE_FILE, index + 1, name))?;
+        let cleaned = addr_str.trim_start_matches('$').trim_start_matches("0x");
+        let load_address = u16::from_str_radix(cleaned, 16)
+            .map_err(|_| format!("{} line {}: invalid address {}", OVERRIDE_FILE, index + 1, addr_str))?;
+
+        let mut file_override = FileOverride { load_address, force_address: false, hidden: false };
+        for flag in fields {
+            match flag.to_ascii_lowercase().as_str() {
+                "force" => file_override.force_address = true,
+                "hidden" => file_override.hidden = true,
+                _ => return Err(format!("{} line {}: unknown flag {}", OVERRIDE_FILE, index + 1, flag)),
+            }
+        }
+
+        if overrides.insert(name.to_string(), file_override).is_some() {
+            return Err(format!("{} line {}: {} listed twice", OVERRIDE_FILE, index + 1, name));
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Overridden files must land in RAM above the stack and below the I/O area or after it
+fn check_ram_range(filename: &str, load_address: u16, len: usize) -> Result<(), String> {
+    let start = load_address as usize;
+    let end = start + len;
+    let in_io = start < 0xE000 && end > 0xD000;
+    if start < 0x0200 || end > 0x10000 || in_io {
+        return Err(format!(
+            "{}: {} loads to ${:04X}-${:04X}, outside RAM ($0200-$CFFF, $E000-$FFFF)",
+            OVERRIDE_FILE, filename, start, end.saturating_sub(1)
+        ));
+    }
+    Ok(())
+}
+
 /// Strip .prg/.PRG extension from filename if present
 fn strip_prg_extension(filename: &str) -> String {
     if filename.len() > 4 && filename[filename.len() - 4..].eq_ignore_ascii_case(".prg") {
@@ -352,3 +539,107 @@ fn ascii_to_petscii(ascii: u8) -> u8 {
         _ => ascii,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+
+    fn write_prg(dir: &Path, name: &str, load: u16, size: usize) {
+        let mut bytes = load.to_le_bytes().to_vec();
+        bytes.extend(std::iter::repeat(0xEA).take(size));
+        fs::write(dir.join(name), bytes).unwrap();
+    }
+
+    #[test]
+    fn test_non_prg_files_are_skipped_and_reported() {
+        let dir = temp_dir("mixed_dir");
+        write_prg(&dir, "game.prg", 0x0801, 0x100);
+        write_prg(&dir, "TOOL.PRG", 0xC000, 0x80);
+        fs::write(dir.join("disk.d64"), [0u8; 16]).unwrap();
+        fs::write(dir.join("readme.txt"), b"hello").unwrap();
+        fs::write(dir.join("noext"), [0u8; 4]).unwrap();
+        fs::create_dir(dir.join("subdir")).unwrap();
+
+        let fs_manager = FileSystemManager::new(dir.to_str().unwrap());
+        let (files, skipped) = fs_manager.read_prg_files_reporting().unwrap();
+
+        let names: Vec<_> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, vec!["TOOL.PRG", "game.prg"]);
+        assert_eq!(skipped, vec!["disk.d64", "noext", "readme.txt"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_c64_name_truncates_to_16_chars() {
+        assert_eq!(c64_name("a-very-long-filename.prg"), "A-VERY-LONG-FILE");
+        assert_eq!(c64_name("game.PRG"), "GAME");
+    }
+
+    #[test]
+    fn test_override_file_replaces_load_address() {
+        let dir = temp_dir("file_overrides");
+        write_prg(&dir, "game.prg", 0x0801, 0x100);
+        fs::write(dir.join("music.bin"), [0x60u8; 0x80]).unwrap();
+        fs::write(
+            dir.join(OVERRIDE_FILE),
+            "# name  address  flags\ngame.prg  $C000\nmusic.bin $1000 force hidden\n",
+        ).unwrap();
+
+        let fs_manager = FileSystemManager::new(dir.to_str().unwrap());
+        let (files, skipped) = fs_manager.read_prg_files_reporting().unwrap();
+        assert!(skipped.is_empty());
+
+        let allocations = fs_manager.allocate_files(&files, &[3, 4]).unwrap();
+        let metadata = fs_manager.generate_metadata(&allocations).unwrap();
+
+        // game.prg: header says $0801, override wins; PRG header still stripped
+        assert_eq!(&metadata[14..16], &[0x00, 0xC0]);
+        assert_eq!(&metadata[12..14], &[0x00, 0x01]);
+        assert_eq!(metadata[11] & (FLAG_FORCE_ADDRESS | FLAG_HIDDEN), 0);
+
+        // music.bin: headerless, whole file embedded, flags set
+        let entry = &metadata[METADATA_ENTRY_SIZE..2 * METADATA_ENTRY_SIZE];
+        assert_eq!(&entry[14..16], &[0x00, 0x10]);
+        assert_eq!(&entry[12..14], &[0x80, 0x00]);
+        assert_eq!(entry[11] & 0xE0, FLAG_FORCE_ADDRESS | FLAG_HIDDEN);
+        assert_eq!(entry[10], 0x00);
+        assert_eq!(entry[11] & 0x1F, 0x01);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_override_outside_ram_is_rejected() {
+        let dir = temp_dir("file_overrides_io");
+        write_prg(&dir, "game.prg", 0x0801, 0x100);
+        fs::write(dir.join(OVERRIDE_FILE), "game.prg $CF80\n").unwrap();
+
+        let fs_manager = FileSystemManager::new(dir.to_str().unwrap());
+        let err = fs_manager.read_prg_files().unwrap_err();
+        assert!(err.contains("outside RAM"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_mark_autostart_flags_one_entry() {
+        let dir = temp_dir("autostart");
+        write_prg(&dir, "game.prg", 0x0801, 0x100);
+        write_prg(&dir, "intro.prg", 0x0801, 0x80);
+
+        let fs_manager = FileSystemManager::new(dir.to_str().unwrap());
+        let mut allocations = fs_manager.plan_files(&[3, 4]).unwrap();
+        fs_manager.mark_autostart(&mut allocations, "intro").unwrap();
+        assert!(fs_manager.mark_autostart(&mut allocations, "missing").is_err());
+
+        let metadata = fs_manager.generate_metadata(&allocations).unwrap();
+        let flagged: Vec<_> = (0..2)
+            .filter(|i| metadata[i * METADATA_ENTRY_SIZE + 11] & FLAG_AUTOSTART != 0)
+            .collect();
+        assert_eq!(flagged, vec![1]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
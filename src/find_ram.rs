@@ -6,18 +6,29 @@
 //! Only tracks sequences of 32 or more consecutive identical bytes in the
 //! $0200-$FFEF range (avoiding zero page, stack, and system vectors).
 //!
+//! The scan (`FindRam::new`, `blocks`, `find_max`, `total_free_bytes`) is
+//! stable public API for analysing a snapshot's free space without a
+//! conversion; `ConvertSnapshot::free_ram` parses a VSF and scans it.
+//!
 // Copyright (c) 2025-2026 Tommy Olsen
 // Licensed under the MIT License.
 
 #![allow(dead_code)]
 
-#[derive(Debug, Clone)]
+use std::ops::RangeInclusive;
+
+/// A run of identical bytes the restore code may use
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RamBlock {
+    /// First address of the run
     pub address: u16,
+    /// Byte the run is filled with, written back when the block is wiped
     pub value: u8,
+    /// Length in bytes (at least 32)
     pub count: u16,
 }
 
+#[derive(Clone)]
 pub struct FindRam {
     blocks: Vec<RamBlock>,
 }
@@ -72,6 +83,35 @@ impl FindRam {
         FindRam { blocks }
     }
 
+    /// Scan RAM like `new`, keeping only the parts of free blocks inside `ranges`
+    ///
+    /// Lets the restore blocks be confined to memory known to be unused, e.g.
+    /// `&[0xC000..=0xCFFF]`, so the rest of RAM is left as the snapshot had it.
+    pub fn within_ranges(ram: &[u8; 65536], ranges: &[RangeInclusive<u16>]) -> Self {
+        let mut finder = Self::new(ram);
+        finder.restrict_to(ranges);
+        finder
+    }
+
+    /// Drop free space outside `ranges`; blocks crossing a range edge are cut at it
+    pub fn restrict_to(&mut self, ranges: &[RangeInclusive<u16>]) {
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by_key(|range| *range.start());
+
+        let mut blocks = Vec::new();
+        for block in &self.blocks {
+            let block_end = block.address as u32 + block.count as u32;
+            for range in &ranges {
+                let start = (block.address as u32).max(*range.start() as u32);
+                let end = block_end.min(*range.end() as u32 + 1);
+                if start < end {
+                    blocks.push(RamBlock { address: start as u16, value: block.value, count: (end - start) as u16 });
+                }
+            }
+        }
+        self.blocks = blocks;
+    }
+
     /// Find the maximum contiguous sequence length available (0 if none)
     pub fn find_max(&self) -> u16 {
         self.blocks
@@ -121,14 +161,48 @@ impl FindRam {
         }
     }
 
+    /// Allocate `requested_count` bytes starting exactly at `address`
+    ///
+    /// Succeeds only if one free block covers the whole range; the parts of
+    /// the block before and after it stay free. Returns Some((address, value)).
+    pub fn allocate_at(&mut self, address: u16, requested_count: u16) -> Option<(u16, u8)> {
+        if requested_count == 0 {
+            return None;
+        }
+
+        let start = address as u32;
+        let end = start + requested_count as u32;
+        let index = self.blocks.iter().position(|block| {
+            block.address as u32 <= start && end <= block.address as u32 + block.count as u32
+        })?;
+
+        let block = self.blocks.remove(index);
+        let block_end = block.address as u32 + block.count as u32;
+        let mut pieces = Vec::new();
+        if start > block.address as u32 {
+            pieces.push(RamBlock { address: block.address, value: block.value, count: (start - block.address as u32) as u16 });
+        }
+        if block_end > end {
+            pieces.push(RamBlock { address: end as u16, value: block.value, count: (block_end - end) as u16 });
+        }
+        for (offset, piece) in pieces.into_iter().enumerate() {
+            self.blocks.insert(index + offset, piece);
+        }
+
+        Some((address, block.value))
+    }
+
+    /// Number of free blocks left
     pub fn block_count(&self) -> usize {
         self.blocks.len()
     }
 
+    /// Sum of all free block lengths
     pub fn total_free_bytes(&self) -> u32 {
         self.blocks.iter().map(|b| b.count as u32).sum()
     }
 
+    /// Free blocks in address order, followed by any extra blocks; shrinks as blocks are allocated
     pub fn blocks(&self) -> &[RamBlock] {
         &self.blocks
     }
@@ -137,6 +211,7 @@ impl FindRam {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_vsf::busy_ram;
 
     #[test]
     fn test_find_sequences() {
@@ -231,6 +306,34 @@ mod tests {
         assert_eq!(finder.block_count(), 2);
     }
 
+    #[test]
+    fn test_allocate_at_splits_the_covering_block() {
+        let mut ram = busy_ram();
+        ram[0x1000..0x1100].fill(0x00);
+        let mut finder = FindRam::new(&ram);
+
+        assert_eq!(finder.allocate_at(0x1040, 0x40), Some((0x1040, 0x00)));
+        let blocks: Vec<(u16, u16)> = finder.blocks().iter().map(|b| (b.address, b.count)).collect();
+        assert_eq!(blocks, vec![(0x1000, 0x40), (0x1080, 0x80)]);
+
+        // Taken, straddling the end of a block, or not free at all
+        assert_eq!(finder.allocate_at(0x1040, 0x10), None);
+        assert_eq!(finder.allocate_at(0x10F0, 0x20), None);
+        assert_eq!(finder.allocate_at(0x2000, 0x10), None);
+    }
+
+    #[test]
+    fn test_within_ranges_cuts_blocks_at_the_range_edges() {
+        let mut ram = busy_ram();
+        ram[0xBF00..0xC100].fill(0x00);
+        ram[0xC800..0xC840].fill(0x11);
+        ram[0xD000..0xD100].fill(0x00);
+        let finder = FindRam::within_ranges(&ram, &[0xC000..=0xCFFF]);
+
+        let blocks: Vec<(u16, u8, u16)> = finder.blocks().iter().map(|b| (b.address, b.value, b.count)).collect();
+        assert_eq!(blocks, vec![(0xC000, 0x00, 0x100), (0xC800, 0x11, 0x40)]);
+    }
+
     #[test]
     fn test_allocate_not_found() {
         let mut ram = [0u8; 65536];
@@ -7,14 +7,28 @@
 // Licensed under the MIT License.
 
 pub mod asm_wrapper;
+pub mod batch;
+pub mod capabilities;
 pub mod config;
+pub mod conversion_cache;
 pub mod convert_snapshot;
 pub mod find_ram;
 pub mod make_prg_asm;
+pub mod opcodes;
+pub mod output_hash;
 pub mod parse_vsf;
 pub mod patch_mem;
+pub mod prepend_stub;
+pub mod report;
+pub mod sanitize_zp;
+pub mod snapshot_checks;
+pub mod snapshot_diff;
+#[cfg(feature = "vice-monitor")]
+pub mod vice_monitor;
+pub mod vsf_export;
 
 // CRT/EasyFlash modules
+pub mod bank_usage;
 pub mod convert_snapshot_crt;
 pub mod crt_builder;
 pub mod file_system_manager;
@@ -24,5 +38,9 @@ pub mod make_romh_asm;
 
 // CRT/Magic Desk modules
 pub mod convert_snapshot_magic_desk_crt;
+pub mod magic_desk_ram_load;
 pub mod make_magic_desk_boot_asm;
 pub mod make_magic_desk_crt_asm;
+
+#[cfg(test)]
+mod test_vsf;
@@ -12,6 +12,9 @@ use crate::asm_wrapper::assemble_to_bytes;
 pub const LOAD_VECTOR: usize = 0x0330;
 pub const SAVE_VECTOR: usize = 0x0332;
 
+/// KERNAL indirect vector table: IRQ, BRK, NMI, I/O, LOAD, SAVE ($0314-$0333)
+pub const KERNAL_VECTORS: std::ops::Range<usize> = 0x0314..0x0334;
+
 // ROMH addresses for LOAD/SAVE code (in bank 0 ROMH @ $A000-$BFFF in 16K mode)
 pub const ROMH_LOAD_SAVE_CODE: u16 = 0xA600;
 
@@ -26,6 +29,84 @@ pub const TRAMPOLINE_PAGE3: u16 = 0x0334;
 /// Default trampoline address
 pub const DEFAULT_TRAMPOLINE_ADDR: u16 = 0x0100;
 
+/// Lowest snapshot SP that leaves room for the trampoline at $0100
+pub const MIN_SP_FOR_PAGE1: u8 = 242;
+
+/// CRT restore code area; a trampoline must not start here
+pub const RESTORE_CODE_AREA: std::ops::RangeInclusive<u16> = 0x0340..=0x03FF;
+
+/// Keyboard buffer ($0277-$0280) and its length ($C6), used to queue autostart
+pub const KEYBOARD_BUFFER: usize = 0x0277;
+pub const KEYBOARD_COUNT: usize = 0x00C6;
+
+/// KERNAL wait-for-key loop ($E5CD-$E5D5), where BASIC waits at `READY.`
+pub const KERNAL_KEY_WAIT: std::ops::RangeInclusive<u16> = 0xE5CD..=0xE5D5;
+
+/// KERNAL default SAVE routine (ISAVE), used when the snapshot vector is unset
+pub const KERNAL_SAVE: u16 = 0xF5ED;
+
+/// Reject a user-chosen trampoline address that would break the restore
+pub fn check_trampoline_address(addr: u16, sp: u8) -> Result<(), String> {
+    if addr == TRAMPOLINE_PAGE1 && sp < MIN_SP_FOR_PAGE1 {
+        return Err(format!(
+            "Hook address $0100 needs SP >= ${:02X}, but the snapshot SP is ${:02X}: \
+             the stack would overwrite the trampoline (use $0334 or auto)",
+            MIN_SP_FOR_PAGE1, sp
+        ));
+    }
+    if RESTORE_CODE_AREA.contains(&addr) {
+        return Err(format!(
+            "Hook address ${:04X} is inside the restore code area ${:04X}-${:04X}",
+            addr, RESTORE_CODE_AREA.start(), RESTORE_CODE_AREA.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Reject `--autostart` for a snapshot that wouldn't read the queued keys as typed
+///
+/// Queuing overwrites the keyboard buffer and its length, so keys still
+/// pending would be lost, and only the KERNAL input loop hands them to BASIC.
+pub fn check_autostart(ram: &[u8], pc: u16) -> Result<(), String> {
+    if ram[KEYBOARD_COUNT] != 0 {
+        return Err(format!(
+            "Autostart needs an empty keyboard buffer, but the snapshot has {} key(s) pending ($C6)",
+            ram[KEYBOARD_COUNT]
+        ));
+    }
+    if !KERNAL_KEY_WAIT.contains(&pc) {
+        return Err(format!(
+            "Autostart needs a snapshot taken at the BASIC READY. prompt (PC ${:04X}-${:04X}), \
+             but the PC is ${:04X}",
+            KERNAL_KEY_WAIT.start(),
+            KERNAL_KEY_WAIT.end(),
+            pc
+        ));
+    }
+    Ok(())
+}
+
+/// Warn when a trampoline of `len` bytes at `addr` reaches into the live stack
+///
+/// The snapshot's stack occupies $0100+SP+1..$01FF; anything the program
+/// pushes after the restore grows down from there.
+pub fn trampoline_stack_warning(addr: u16, sp: u8, len: usize) -> Option<String> {
+    let stack_start = 0x0100 + sp as usize + 1;
+    let end = addr as usize + len;
+    if (addr as usize) > 0x01FF || end <= stack_start {
+        return None;
+    }
+
+    Some(format!(
+        "Hook address ${:04X}-${:04X} overlaps the active stack ${:04X}-$01FF (SP ${:02X}); \
+         deeper stack use by the program will overwrite the LOAD/SAVE trampoline",
+        addr,
+        end - 1,
+        stack_start,
+        sp
+    ))
+}
+
 /// Manages LOAD/SAVE vector hooking for EasyFlash cartridge file system
 pub struct LoadSaveHook {
     #[allow(dead_code)]
@@ -37,6 +118,9 @@ pub struct LoadSaveHook {
     save_trampoline_addr: u16,
     temp_filename_addr: u16,
     trampoline_binary: Vec<u8>,
+    save_passthrough: bool,
+    save_target: u16,
+    autostart: bool,
 }
 
 impl LoadSaveHook {
@@ -60,9 +144,27 @@ impl LoadSaveHook {
             save_trampoline_addr: 0,
             temp_filename_addr: 0,
             trampoline_binary: Vec::new(),
+            save_passthrough: false,
+            save_target: KERNAL_SAVE,
+            autostart: false,
         }
     }
 
+    /// Queue `LOAD` + `RUN` in the keyboard buffer and make `LOAD ""` pick the
+    /// entry flagged as autostart in the metadata
+    pub fn with_autostart(mut self, enabled: bool) -> Self {
+        self.autostart = enabled;
+        self
+    }
+
+    /// Pass SAVE through to the snapshot's SAVE vector (real IEC bus, device 8)
+    /// instead of ignoring it. Meant for Kung Fu Flash and other carts used
+    /// alongside a drive.
+    pub fn with_save_passthrough(mut self, enabled: bool) -> Self {
+        self.save_passthrough = enabled;
+        self
+    }
+
     /// Get the trampoline address
     pub fn get_trampoline_address(&self) -> u16 {
         self.trampoline_address
@@ -113,9 +215,7 @@ no_filename:
     RTS
 
 save_trampoline:
-    CLC
-    RTS
-
+{}
 set_bank:
     STX $DE00
     STY $DE02
@@ -179,10 +279,28 @@ copy_done:
 restore_memmap:
     RTS
 "#,
-            self.trampoline_address, temp_addr
+            self.trampoline_address, temp_addr, self.generate_save_asm()
         )
     }
 
+    /// SAVE routine: ignore (ROM is read-only) or hand over to the original vector
+    fn generate_save_asm(&self) -> String {
+        if self.save_passthrough {
+            // Keep the cartridge hidden so the KERNAL IEC routines run from ROM
+            format!(
+                r#"    PHA
+    LDA #$04
+    STA $DE02
+    PLA
+    JMP ${:04X}
+"#,
+                self.save_target
+            )
+        } else {
+            "    CLC\n    RTS\n".to_string()
+        }
+    }
+
     /// Generate trampoline binary code
     pub fn generate_trampoline_binary(&mut self) -> Result<Vec<u8>, String> {
         if !self.has_files {
@@ -245,10 +363,15 @@ restore_memmap:
 
         self.copy_data_addr = self.set_bank_addr + 7;
 
-        // Find save_trampoline: CLC (18) RTS (60)
+        // Find save_trampoline: CLC (18) RTS (60), or PHA / LDA #$04 / STA $DE02 / PLA / JMP
+        let save_pattern: &[u8] = if self.save_passthrough {
+            &[0x48, 0xA9, 0x04, 0x8D, 0x02, 0xDE, 0x68, 0x4C]
+        } else {
+            &[0x18, 0x60]
+        };
         let set_bank_offset = (self.set_bank_addr - self.trampoline_address) as usize;
         for i in (0..set_bank_offset).rev() {
-            if bytes[i] == 0x18 && i + 1 < bytes.len() && bytes[i + 1] == 0x60 {
+            if bytes[i..].starts_with(save_pattern) {
                 self.save_trampoline_addr = self.trampoline_address + i as u16;
                 break;
             }
@@ -262,11 +385,26 @@ restore_memmap:
     }
 
     /// Hook LOAD and SAVE vectors in RAM
+    ///
+    /// Of the vector table ($0314-$0333) only $0330-$0333 are written: IRQ,
+    /// BRK, NMI and the I/O vectors keep the snapshot's values, so a custom
+    /// IRQ handler keeps running.
     pub fn hook_load_and_save(&mut self, ram: &mut [u8]) -> Result<(), String> {
         if !self.has_files {
             return Ok(());
         }
 
+        // LOAD with no name (device ignored by the hook) serves the autostart entry
+        if self.autostart {
+            let keys = b"LOAD\rRUN\r";
+            ram[KEYBOARD_BUFFER..KEYBOARD_BUFFER + keys.len()].copy_from_slice(keys);
+            ram[KEYBOARD_COUNT] = keys.len() as u8;
+        }
+
+        // Pass-through SAVE continues at whatever the snapshot's vector held
+        let original_save = (ram[SAVE_VECTOR] as u16) | ((ram[SAVE_VECTOR + 1] as u16) << 8);
+        self.save_target = if original_save == 0 { KERNAL_SAVE } else { original_save };
+
         let trampoline_code = self.generate_trampoline_binary()?;
         let addr = self.trampoline_address as usize;
 
@@ -291,6 +429,7 @@ restore_memmap:
     fn generate_romh_handler_asm(&self) -> String {
         let copy_data_addr = format!("{:04X}", self.copy_data_addr);
         let temp_filename = format!("{:04X}", self.temp_filename_addr);
+        let empty_name_target = if self.autostart { "autostart_match" } else { "wildcard_match" };
 
         format!(
             r#"*=$A600
@@ -298,7 +437,7 @@ restore_memmap:
 ; Metadata format @ $B000 (16 bytes per entry):
 ;   +0: Filename pointer (2 bytes)
 ;   +2: Bank list (8 bytes, $00 = end)
-;   +10: Start offset (2 bytes)
+;   +10: Start offset (2 bytes, bit 15 = force address, bit 14 = hidden, bit 13 = autostart)
 ;   +12: File length (2 bytes)
 ;   +14: Load address (2 bytes)
 
@@ -326,7 +465,7 @@ metadata_not_empty:
 
     LDX $B7
     BNE check_filename
-    JMP filename_match
+    JMP {}
 check_filename:
     ; Null-terminate the filename copy
     LDA #$00
@@ -343,7 +482,7 @@ compare_filename_loop:
     LDA ${},Y
 
     CMP #$2A
-    BEQ filename_match
+    BEQ wildcard_match
 
     CMP #$3F
     BEQ wildcard_question
@@ -389,7 +528,7 @@ check_remaining_spaces:
     CMP #$20
     BEQ check_remaining_spaces
     CMP #$2A
-    BEQ filename_match
+    BEQ wildcard_match
     CMP #$3F
     BEQ check_remaining_spaces
     JMP next_entry
@@ -406,12 +545,25 @@ char_matches:
 pattern_exhausted:
     JMP filename_match
 
+wildcard_match:
+    ; Hidden entries only match their exact name
+    LDY #$0B
+    LDA ($A3),Y
+    AND #$40
+    BEQ filename_match
+    JMP next_entry
+
 filename_match:
     LDA $A3
     STA $A7
     LDA $A4
     STA $A8
 
+    ; Forced entries always load to their own address
+    LDY #$0B
+    LDA ($A7),Y
+    BMI use_file_addr
+
     ; SA=0: use file address, SA=1: use $C3/$C4
     LDA $93
     BEQ use_file_addr
@@ -443,6 +595,7 @@ got_dest_addr:
     STA $A5
     INY
     LDA ($A7),Y
+    AND #$1F
     STA $A6
 
     LDY #$02
@@ -539,6 +692,14 @@ load_complete:
     LDY $AF
     RTS
 
+autostart_match:
+    ; LOAD "" picks the entry flagged as autostart
+    LDY #$0B
+    LDA ($A3),Y
+    AND #$20
+    BEQ next_entry
+    JMP filename_match
+
 next_entry:
     LDA $A3
     CLC
@@ -559,7 +720,7 @@ file_not_found:
     LDY #$00
     RTS
 "#,
-            temp_filename, temp_filename, temp_filename, copy_data_addr
+            empty_name_target, temp_filename, temp_filename, temp_filename, copy_data_addr
         )
     }
 
@@ -593,3 +754,107 @@ file_not_found:
         &self.trampoline_binary
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_routine(passthrough: bool) -> Vec<u8> {
+        let mut ram = vec![0u8; 65536];
+        ram[SAVE_VECTOR] = 0xED;
+        ram[SAVE_VECTOR + 1] = 0xF5;
+
+        let mut hook = LoadSaveHook::new(0xF6, true, Some(TRAMPOLINE_PAGE3))
+            .with_save_passthrough(passthrough);
+        hook.hook_load_and_save(&mut ram).unwrap();
+
+        let target = (ram[SAVE_VECTOR] as usize) | ((ram[SAVE_VECTOR + 1] as usize) << 8);
+        ram[target..target + 10].to_vec()
+    }
+
+    #[test]
+    fn test_save_passthrough_jumps_to_original_vector() {
+        let routine = save_routine(true);
+        assert_ne!(&routine[..2], &[0x18, 0x60]);
+        assert_eq!(&routine[..10], &[0x48, 0xA9, 0x04, 0x8D, 0x02, 0xDE, 0x68, 0x4C, 0xED, 0xF5]);
+    }
+
+    #[test]
+    fn test_default_save_is_ignored() {
+        assert_eq!(&save_routine(false)[..2], &[0x18, 0x60]);
+    }
+
+    #[test]
+    fn test_autostart_queues_load_and_run() {
+        let mut ram = vec![0xAAu8; 65536];
+        ram[KEYBOARD_COUNT] = 0;
+        let mut hook = LoadSaveHook::new(0xF6, true, Some(TRAMPOLINE_PAGE3)).with_autostart(true);
+        hook.hook_load_and_save(&mut ram).unwrap();
+
+        assert_eq!(ram[KEYBOARD_COUNT], 9);
+        assert_eq!(&ram[KEYBOARD_BUFFER..KEYBOARD_BUFFER + 10], b"LOAD\rRUN\r\xAA");
+
+        let mut ram = vec![0xAAu8; 65536];
+        ram[KEYBOARD_COUNT] = 0;
+        LoadSaveHook::new(0xF6, true, Some(TRAMPOLINE_PAGE3)).hook_load_and_save(&mut ram).unwrap();
+        assert_eq!(ram[KEYBOARD_COUNT], 0);
+        assert_eq!(&ram[KEYBOARD_BUFFER..KEYBOARD_BUFFER + 10], &[0xAA; 10]);
+    }
+
+    #[test]
+    fn test_autostart_needs_ready_prompt_and_empty_buffer() {
+        let mut ram = vec![0u8; 65536];
+        assert!(check_autostart(&ram, 0xE5D4).is_ok());
+
+        let err = check_autostart(&ram, 0x0810).unwrap_err();
+        assert!(err.contains("$0810"), "{}", err);
+
+        ram[KEYBOARD_COUNT] = 2;
+        let err = check_autostart(&ram, 0xE5D4).unwrap_err();
+        assert!(err.contains("2 key(s)"), "{}", err);
+    }
+
+    fn custom_vector_table() -> Vec<u8> {
+        let mut ram = vec![0u8; 65536];
+        for (i, b) in ram[KERNAL_VECTORS].iter_mut().enumerate() {
+            *b = 0x80 + i as u8;
+        }
+        ram
+    }
+
+    #[test]
+    fn test_hook_only_overwrites_load_save_vectors() {
+        for trampoline in [TRAMPOLINE_PAGE1, TRAMPOLINE_PAGE3] {
+            let mut ram = custom_vector_table();
+            let before = ram.clone();
+            let mut hook = LoadSaveHook::new(0xF6, true, Some(trampoline));
+            hook.hook_load_and_save(&mut ram).unwrap();
+
+            assert_eq!(&ram[KERNAL_VECTORS.start..LOAD_VECTOR], &before[KERNAL_VECTORS.start..LOAD_VECTOR]);
+            assert_eq!(&ram[LOAD_VECTOR..LOAD_VECTOR + 2], &trampoline.to_le_bytes());
+            assert_eq!(&ram[SAVE_VECTOR..SAVE_VECTOR + 2], &hook.save_trampoline_addr.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_page1_hook_rejected_for_low_sp() {
+        let err = check_trampoline_address(TRAMPOLINE_PAGE1, 0xF1).unwrap_err();
+        assert!(err.contains("$F1"), "{}", err);
+        assert!(check_trampoline_address(TRAMPOLINE_PAGE1, MIN_SP_FOR_PAGE1).is_ok());
+    }
+
+    #[test]
+    fn test_hook_in_restore_code_area_rejected() {
+        assert!(check_trampoline_address(0x0340, 0xFF).is_err());
+        assert!(check_trampoline_address(0x03FF, 0xFF).is_err());
+        assert!(check_trampoline_address(TRAMPOLINE_PAGE3, 0x80).is_ok());
+    }
+
+    #[test]
+    fn test_hook_in_active_stack_warns() {
+        let warning = trampoline_stack_warning(0x0180, 0xC0, 0x80).unwrap();
+        assert!(warning.contains("$01C1-$01FF"), "{}", warning);
+        assert!(trampoline_stack_warning(0x0100, 0xF6, 0x80).is_none());
+        assert!(trampoline_stack_warning(TRAMPOLINE_PAGE3, 0x00, 0x80).is_none());
+    }
+}
@@ -0,0 +1,402 @@
+//! RAM-resident LOAD for Magic Desk cartridges (experimental)
+//!
+//! Magic Desk can only be switched off for good, so the cartridge is gone
+//! by the time the snapshot calls LOAD. Instead of reading ROM at LOAD time,
+//! the embedded files, a directory and a small LOAD handler are written into
+//! a free RAM block of the snapshot before compression. The restore copies
+//! them out of the cart together with the rest of RAM, before the kill.
+//!
+//! Constraints:
+//! - Handler, directory and all file data must fit one free RAM block
+//!   (a run of identical bytes in the snapshot) that the CPU sees as RAM
+//!   with the snapshot's $01, since LOAD jumps there before banking
+//! - LOAD only; SAVE keeps the snapshot's vector
+//! - Files are lost if the program overwrites that block
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::ops::RangeInclusive;
+
+use crate::asm_wrapper::assemble_to_bytes;
+use crate::file_system_manager::{c64_name, PRGFile, C64_NAME_LENGTH};
+use crate::load_save_hook::LOAD_VECTOR;
+use crate::prepend_stub::hidden_by;
+
+/// Directory entry: name (16), data address (2), length (2), load address (2), flags (1), pad (1)
+pub const DIR_ENTRY_SIZE: usize = 24;
+const DATA_OFFSET: usize = 16;
+const LENGTH_OFFSET: usize = 18;
+const LOAD_OFFSET: usize = 20;
+const FLAGS_OFFSET: usize = 22;
+
+const FLAG_FORCE_ADDRESS: u8 = 0x80;
+const FLAG_HIDDEN: u8 = 0x40;
+
+/// RAM ($0200-$FFFF) the CPU sees with `port` in $01, in address order
+///
+/// The handler can only go here: the LOAD vector jumps to it with the
+/// program's banking, before it switches to all RAM.
+pub fn visible_ram(port: u8) -> Vec<RangeInclusive<u16>> {
+    let mut ranges: Vec<RangeInclusive<u16>> = Vec::new();
+    for (start, end) in [(0x0200, 0xA000), (0xA000, 0xC000), (0xC000, 0xD000), (0xD000, 0xE000), (0xE000, 0x10000)] {
+        if hidden_by(port, start, end).is_some() {
+            continue;
+        }
+        let last = (end - 1) as u16;
+        match ranges.last_mut() {
+            Some(range) if *range.end() as usize + 1 == start => *range = *range.start()..=last,
+            _ => ranges.push(start as u16..=last),
+        }
+    }
+    ranges
+}
+
+/// LOAD handler and embedded files laid out for one free RAM block
+pub struct RamLoadHook {
+    files: Vec<PRGFile>,
+}
+
+impl RamLoadHook {
+    pub fn new(files: Vec<PRGFile>) -> Self {
+        Self { files }
+    }
+
+    /// Generate the LOAD handler assembly code
+    ///
+    /// Names not in the directory go on to `original_load`, the LOAD vector
+    /// the snapshot had, so disk and tape loads keep working.
+    fn generate_handler_asm(&self, addr: u16, dir_addr: u16, original_load: u16) -> String {
+        format!(
+            r#"*=${:04X}
+
+; Directory @ ${:04X} ({} bytes per entry, name $00 = end):
+;   +0: Name (16 bytes, $00 padded)
+;   +16: Data address (2 bytes)
+;   +18: File length (2 bytes)
+;   +20: Load address (2 bytes)
+;   +22: Flags (bit 7 = force address, bit 6 = hidden)
+
+load_handler:
+    STA $93
+    ; Caller's interrupt flag, restored on the way out
+    PHP
+    PLA
+    STA restore_flags+1
+    SEI
+    LDA $01
+    STA restore_port+1
+    ; All RAM: files may sit under ROM or I/O
+    LDA #$34
+    STA $01
+
+    LDA #${:02X}
+    STA $A3
+    LDA #${:02X}
+    STA $A4
+
+search_loop:
+    LDY #$00
+    LDA ($A3),Y
+    BNE compare_loop
+    JMP file_not_found
+
+compare_loop:
+    CPY $B7
+    BEQ pattern_end
+    LDA ($BB),Y
+
+    ; * matches the rest of the name
+    CMP #$2A
+    BEQ wildcard_match
+    CPY #$10
+    BCS next_entry
+
+    ; ? matches any one character
+    CMP #$3F
+    BEQ any_char
+
+    ; Case-insensitive: convert PETSCII and ASCII lowercase to uppercase
+    CMP #$C1
+    BCC check_ascii_lower
+    CMP #$DB
+    BCS check_ascii_lower
+    SEC
+    SBC #$80
+    JMP compare_char
+
+check_ascii_lower:
+    CMP #$61
+    BCC compare_char
+    CMP #$7B
+    BCS compare_char
+    SEC
+    SBC #$20
+
+compare_char:
+    CMP ($A3),Y
+    BNE next_entry
+    INY
+    JMP compare_loop
+
+any_char:
+    LDA ($A3),Y
+    BEQ next_entry
+    INY
+    JMP compare_loop
+
+pattern_end:
+    ; LOAD "" takes the first visible entry
+    CPY #$00
+    BEQ wildcard_match
+    CPY #$10
+    BEQ found
+    LDA ($A3),Y
+    BNE next_entry
+    JMP found
+
+wildcard_match:
+    ; Hidden entries only match their exact name
+    LDY #${:02X}
+    LDA ($A3),Y
+    AND #${:02X}
+    BEQ found
+
+next_entry:
+    LDA $A3
+    CLC
+    ADC #${:02X}
+    STA $A3
+    BCC search_next
+    INC $A4
+search_next:
+    JMP search_loop
+
+found:
+    LDY #${:02X}
+    LDA ($A3),Y
+    STA $A5
+    INY
+    LDA ($A3),Y
+    STA $A6
+    INY
+    LDA ($A3),Y
+    STA $A7
+    INY
+    LDA ($A3),Y
+    STA $A8
+
+    ; Forced entries and LOAD "NAME",8,1 use the file's own address
+    LDY #${:02X}
+    LDA ($A3),Y
+    BMI use_file_addr
+    LDA $B9
+    BNE use_file_addr
+
+    LDA $C3
+    STA $AE
+    LDA $C4
+    STA $AF
+    JMP copy_loop
+
+use_file_addr:
+    LDY #${:02X}
+    LDA ($A3),Y
+    STA $AE
+    INY
+    LDA ($A3),Y
+    STA $AF
+
+copy_loop:
+    LDA $A7
+    ORA $A8
+    BEQ copy_done
+    LDY #$00
+    LDA ($A5),Y
+    STA ($AE),Y
+    INC $A5
+    BNE no_carry_src
+    INC $A6
+no_carry_src:
+    INC $AE
+    BNE no_carry_dst
+    INC $AF
+no_carry_dst:
+    LDA $A7
+    BNE no_borrow
+    DEC $A8
+no_borrow:
+    DEC $A7
+    JMP copy_loop
+
+copy_done:
+    JSR restore_port
+    LDA #$00
+    STA $90
+    CLC
+    LDX $AE
+    LDY $AF
+    RTS
+
+file_not_found:
+    JSR restore_port
+    LDA $93
+    JMP ${:04X}
+
+restore_port:
+    LDA #$37
+    STA $01
+restore_flags:
+    LDA #$00
+    PHA
+    PLP
+    RTS
+"#,
+            addr,
+            dir_addr,
+            DIR_ENTRY_SIZE,
+            dir_addr & 0xFF,
+            dir_addr >> 8,
+            FLAGS_OFFSET,
+            FLAG_HIDDEN,
+            DIR_ENTRY_SIZE,
+            DATA_OFFSET,
+            FLAGS_OFFSET,
+            LOAD_OFFSET,
+            original_load
+        )
+    }
+
+    /// Assemble the handler for `addr`; its size does not depend on the addresses
+    fn handler_binary(&self, addr: u16, original_load: u16) -> Result<Vec<u8>, String> {
+        let first_pass = assemble_to_bytes(&self.generate_handler_asm(addr, addr, original_load))?;
+        let dir_addr = addr as usize + first_pass.len();
+        assemble_to_bytes(&self.generate_handler_asm(addr, dir_addr as u16, original_load))
+    }
+
+    /// Directory size including the end marker
+    fn directory_size(&self) -> usize {
+        self.files.len() * DIR_ENTRY_SIZE + 1
+    }
+
+    /// Bytes needed in RAM: handler, directory and file data
+    pub fn required_size(&self) -> Result<usize, String> {
+        let handler = self.handler_binary(0x1000, 0x1000)?;
+        let data: usize = self.files.iter().map(|file| file.data.len()).sum();
+        Ok(handler.len() + self.directory_size() + data)
+    }
+
+    /// Write handler, directory and files to `ram` at `addr` and hook the LOAD vector
+    pub fn install(&self, ram: &mut [u8], addr: u16) -> Result<(), String> {
+        let original_load = u16::from_le_bytes([ram[LOAD_VECTOR], ram[LOAD_VECTOR + 1]]);
+        let handler = self.handler_binary(addr, original_load)?;
+        let size = self.required_size()?;
+        let start = addr as usize;
+        if start + size > ram.len() {
+            return Err(format!(
+                "Magic Desk LOAD area ${:04X} + {} bytes exceeds RAM",
+                addr, size
+            ));
+        }
+
+        ram[start..start + handler.len()].copy_from_slice(&handler);
+
+        let dir_start = start + handler.len();
+        let mut data_addr = dir_start + self.directory_size();
+        for (index, file) in self.files.iter().enumerate() {
+            let entry = &mut ram[dir_start + index * DIR_ENTRY_SIZE..dir_start + (index + 1) * DIR_ENTRY_SIZE];
+            entry.fill(0);
+
+            let name = c64_name(&file.filename).into_bytes();
+            let name_len = name.len().min(C64_NAME_LENGTH);
+            entry[..name_len].copy_from_slice(&name[..name_len]);
+            entry[DATA_OFFSET..DATA_OFFSET + 2].copy_from_slice(&(data_addr as u16).to_le_bytes());
+            entry[LENGTH_OFFSET..LENGTH_OFFSET + 2].copy_from_slice(&(file.data.len() as u16).to_le_bytes());
+            entry[LOAD_OFFSET..LOAD_OFFSET + 2].copy_from_slice(&file.load_address.to_le_bytes());
+            let mut flags = 0;
+            if file.force_address {
+                flags |= FLAG_FORCE_ADDRESS;
+            }
+            if file.hidden {
+                flags |= FLAG_HIDDEN;
+            }
+            entry[FLAGS_OFFSET] = flags;
+
+            ram[data_addr..data_addr + file.data.len()].copy_from_slice(&file.data);
+            data_addr += file.data.len();
+        }
+        ram[dir_start + self.files.len() * DIR_ENTRY_SIZE] = 0x00;
+
+        ram[LOAD_VECTOR] = (addr & 0xFF) as u8;
+        ram[LOAD_VECTOR + 1] = (addr >> 8) as u8;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, load_address: u16, data: &[u8]) -> PRGFile {
+        PRGFile {
+            filename: name.to_string(),
+            load_address,
+            data: data.to_vec(),
+            total_size: data.len() + 2,
+            force_address: false,
+            hidden: false,
+            autostart: false,
+        }
+    }
+
+    #[test]
+    fn test_visible_ram_follows_the_cpu_port() {
+        assert_eq!(visible_ram(0x37), vec![0x0200..=0x9FFF, 0xC000..=0xCFFF]);
+        assert_eq!(visible_ram(0x36), vec![0x0200..=0xCFFF]);
+        assert_eq!(visible_ram(0x35), vec![0x0200..=0xCFFF, 0xE000..=0xFFFF]);
+        assert_eq!(visible_ram(0x34), vec![0x0200..=0xFFFF]);
+    }
+
+    #[test]
+    fn test_install_places_directory_and_data() {
+        let hook = RamLoadHook::new(vec![
+            file("intro.prg", 0x0801, &[0x11, 0x22, 0x33]),
+            file("level1.prg", 0xC000, &[0x44; 300]),
+        ]);
+        let mut ram = vec![0u8; 65536];
+        hook.install(&mut ram, 0x6000).unwrap();
+
+        assert_eq!(&ram[LOAD_VECTOR..LOAD_VECTOR + 2], &[0x00, 0x60]);
+        let size = hook.required_size().unwrap();
+        let area = &ram[0x6000..0x6000 + size];
+        // Directory follows the handler; the files follow the end marker
+        let dir = area.windows(5).position(|w| w == b"INTRO").unwrap();
+        let entry = &area[dir..dir + DIR_ENTRY_SIZE];
+        let data = u16::from_le_bytes([entry[DATA_OFFSET], entry[DATA_OFFSET + 1]]) as usize;
+        assert_eq!(&ram[data..data + 3], &[0x11, 0x22, 0x33]);
+        assert_eq!(&entry[LOAD_OFFSET..LOAD_OFFSET + 2], &[0x01, 0x08]);
+        assert_eq!(&area[dir + DIR_ENTRY_SIZE..dir + DIR_ENTRY_SIZE + 6], b"LEVEL1");
+        assert_eq!(area[dir + 2 * DIR_ENTRY_SIZE], 0x00);
+        assert_eq!(&area[size - 300..], &[0x44; 300][..]);
+    }
+
+    #[test]
+    fn test_handler_keeps_interrupt_flag_and_falls_back_to_original_load() {
+        let hook = RamLoadHook::new(vec![file("intro.prg", 0x0801, &[0x11])]);
+        let mut ram = vec![0u8; 65536];
+        ram[LOAD_VECTOR..LOAD_VECTOR + 2].copy_from_slice(&[0xA5, 0xF4]);
+        hook.install(&mut ram, 0x6000).unwrap();
+        let handler = &ram[0x6000..0x6000 + hook.required_size().unwrap()];
+        let contains = |pattern: &[u8]| handler.windows(pattern.len()).any(|w| w == pattern);
+
+        // STA $93 / PHP / PLA / STA restore_flags+1 / SEI
+        assert_eq!(&handler[..5], &[0x85, 0x93, 0x08, 0x68, 0x8D]);
+        assert_eq!(handler[7], 0x78);
+        // Port and flags restored with PHA / PLP instead of CLI
+        assert!(contains(&[0x48, 0x28, 0x60]));
+        assert!(!contains(&[0x58, 0x60]));
+        // Not found: LDA $93 / JMP $F4A5
+        assert!(contains(&[0xA5, 0x93, 0x4C, 0xA5, 0xF4]));
+    }
+}
@@ -23,6 +23,7 @@ use vice_snapshot_to_prg_converter::config::{Config, CrtConfig, VERSION};
 use vice_snapshot_to_prg_converter::convert_snapshot::ConvertSnapshot;
 use vice_snapshot_to_prg_converter::convert_snapshot_crt::ConvertSnapshotCRT;
 use vice_snapshot_to_prg_converter::convert_snapshot_magic_desk_crt::ConvertSnapshotMagicDeskCRT;
+use vice_snapshot_to_prg_converter::snapshot_checks::{check_prg, check_snapshot};
 
 const WINDOW_WIDTH: i32 = 720;
 const WINDOW_HEIGHT: i32 = 720;
@@ -646,21 +647,13 @@ fn main() {
                     return;
                 }
 
-                if Path::new(&output_path).exists() {
-                    let choice = dialog::choice2_default(
-                        &format!("The output file already exists:\n\n{}\n\nDo you want to overwrite it?", output_path),
-                        "Cancel",
-                        "Overwrite",
-                        ""
-                    );
-
-                    if choice != Some(1) {
+                match confirm_overwrite(&output_path) {
+                    Ok(true) => {}
+                    Ok(false) => {
                         status_buffer.borrow_mut().set_text("Conversion cancelled by user.");
                         return;
                     }
-
-                    if let Err(e) = std::fs::remove_file(&output_path) {
-                        let msg = format!("Error: Failed to delete existing file:\n{}", e);
+                    Err(msg) => {
                         status_buffer.borrow_mut().set_text(&msg);
                         return;
                     }
@@ -725,10 +718,11 @@ fn main() {
                         Ok(()) => {
                             // Success - clear extra blocks
                             extra_blocks.borrow_mut().clear();
-                            let success_msg = format!(
+                            let mut success_msg = format!(
                                 "Success!\n\nSnapshot successfully converted to {} CRT:\n{}",
                                 cart_type_name, output_path
                             );
+                            append_snapshot_warnings(&mut success_msg, &input_path);
                             status_buffer.borrow_mut().set_text(&success_msg);
                             break;
                         }
@@ -793,21 +787,13 @@ fn main() {
                     return;
                 }
 
-                if Path::new(&output_path).exists() {
-                    let choice = dialog::choice2_default(
-                        &format!("The output file already exists:\n\n{}\n\nDo you want to overwrite it?", output_path),
-                        "Cancel",
-                        "Overwrite",
-                        ""
-                    );
-
-                    if choice != Some(1) {
+                match confirm_overwrite(&output_path) {
+                    Ok(true) => {}
+                    Ok(false) => {
                         status_buffer.borrow_mut().set_text("Conversion cancelled by user.");
                         return;
                     }
-
-                    if let Err(e) = std::fs::remove_file(&output_path) {
-                        let msg = format!("Error: Failed to delete existing file:\n{}", e);
+                    Err(msg) => {
                         status_buffer.borrow_mut().set_text(&msg);
                         return;
                     }
@@ -857,10 +843,14 @@ fn main() {
                         Ok(()) => {
                             // Success - clear extra blocks
                             extra_blocks.borrow_mut().clear();
-                            let success_msg = format!(
+                            let mut success_msg = format!(
                                 "Success!\n\nSnapshot image successfully converted to:\n{}",
                                 output_path
                             );
+                            append_snapshot_warnings(&mut success_msg, &input_path);
+                            for warning in check_prg(&output_path) {
+                                success_msg.push_str(&format!("\n\nWarning: {}", warning));
+                            }
                             status_buffer.borrow_mut().set_text(&success_msg);
                             break;
                         }
@@ -1060,6 +1050,38 @@ fn show_add_ram_block_dialog() -> Option<(u16, u16)> {
     result.borrow().clone()
 }
 
+/// Append Smart attach and similar warnings to a status message
+fn append_snapshot_warnings(msg: &mut String, input_path: &str) {
+    for warning in check_snapshot(input_path, &Config::new(std::env::temp_dir())) {
+        msg.push_str(&format!("\n\nWarning: {}", warning));
+    }
+}
+
+/// Ask before replacing an existing output file and delete it on consent
+///
+/// The library refuses to overwrite, so a path typed into the output field
+/// (bypassing the save dialog's own prompt) is handled here. Returns false if
+/// the user cancels.
+fn confirm_overwrite(output_path: &str) -> Result<bool, String> {
+    if !Path::new(output_path).exists() {
+        return Ok(true);
+    }
+
+    let choice = dialog::choice2_default(
+        &format!("The output file already exists:\n\n{}\n\nDo you want to overwrite it?", output_path),
+        "Cancel",
+        "Overwrite",
+        ""
+    );
+    if choice != Some(1) {
+        return Ok(false);
+    }
+
+    std::fs::remove_file(output_path)
+        .map_err(|e| format!("Error: Failed to delete existing file:\n{}", e))?;
+    Ok(true)
+}
+
 /// Check if an error message indicates an allocation failure
 fn is_allocation_error(error_msg: &str) -> bool {
     error_msg.contains("Failed to allocate block")
@@ -1160,6 +1182,8 @@ IMPORTANT LIMITATIONS
 
 - Memory MUST be initialized before snapshot (f 0000 ffff 00)
 - Do NOT use "Smart attach..." feature in VICE
+- The bytes just below the stack pointer are used by the restore;
+  programs that keep data there may fail (warned when SP < $80)
 "#, VERSION);
 
     let mut text_buffer = TextBuffer::default();
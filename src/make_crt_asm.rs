@@ -9,23 +9,51 @@
 use std::fs;
 use crate::asm_wrapper::assemble_to_bytes;
 use crate::config::Config;
+use crate::make_prg_asm::{component_restore_asm, sid_raw_component, sid_restore_asm, vic_raw_component};
+use crate::parse_vsf::VicRestoreOrder;
+
+/// Value the restore code writes to `$DE02` to switch the EasyFlash off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisableMode {
+    /// `Reenableable` when LOAD/SAVE code is copied from ROML, else `Permanent`
+    #[default]
+    Auto,
+    /// `$04`: cartridge off, as without embedded files
+    Permanent,
+    /// `$03`: leave the cartridge lines for a later re-enable (LOAD/SAVE, own cart tricks)
+    Reenableable,
+}
+
+impl DisableMode {
+    /// `$DE02` value for this mode; `Auto` picks by `load_save_code_size`
+    pub fn control_value(self, load_save_code_size: usize) -> u8 {
+        match self {
+            DisableMode::Auto if load_save_code_size > 0 => 0x03,
+            DisableMode::Auto | DisableMode::Permanent => 0x04,
+            DisableMode::Reenableable => 0x03,
+        }
+    }
+}
 
 /// CRT restore code generator
 pub struct MakeCRTAsm {
     color_lzsa: Vec<u8>,
     vic_lzsa: Vec<u8>,
     sid_lzsa: Vec<u8>,
+    vic_raw: Option<Vec<u8>>,
+    sid_raw: Option<Vec<u8>>,
     cia1_bin: Vec<u8>,
     cia2_bin: Vec<u8>,
     zp_lzsa: Vec<u8>,
     block9_addr: u16,
     f8_ff_data: [u8; 8],
-    #[allow(dead_code)]
+    raster_regs: [u8; 2],
     config: Config,
     relocated_size: usize,
     ram_lzsa_size: usize,
     restore_code_size: usize,
     load_save_code_size: usize,
+    disable_mode: DisableMode,
 }
 
 impl MakeCRTAsm {
@@ -38,6 +66,7 @@ impl MakeCRTAsm {
         zp_lzsa_path: &str,
         block9_addr: u16,
         f8_ff_data: [u8; 8],
+        raster_regs: [u8; 2],
         config: &Config,
         relocated_size: usize,
         ram_lzsa_size: usize,
@@ -56,27 +85,45 @@ impl MakeCRTAsm {
             return Err(format!("CIA2 file must be 20 bytes, got {}", cia2_bin.len()));
         }
 
+        let vic_lzsa = fs::read(vic_lzsa_path)
+            .map_err(|e| format!("Failed to read VIC LZSA: {}", e))?;
+        let sid_lzsa = fs::read(sid_lzsa_path)
+            .map_err(|e| format!("Failed to read SID LZSA: {}", e))?;
+
         Ok(Self {
             color_lzsa: fs::read(color_lzsa_path)
                 .map_err(|e| format!("Failed to read color LZSA: {}", e))?,
-            vic_lzsa: fs::read(vic_lzsa_path)
-                .map_err(|e| format!("Failed to read VIC LZSA: {}", e))?,
-            sid_lzsa: fs::read(sid_lzsa_path)
-                .map_err(|e| format!("Failed to read SID LZSA: {}", e))?,
+            vic_raw: vic_raw_component(config, vic_lzsa_path, &vic_lzsa),
+            sid_raw: sid_raw_component(config, sid_lzsa_path, &sid_lzsa),
+            vic_lzsa,
+            sid_lzsa,
             cia1_bin,
             cia2_bin,
             zp_lzsa: fs::read(zp_lzsa_path)
                 .map_err(|e| format!("Failed to read ZP LZSA: {}", e))?,
             block9_addr,
             f8_ff_data,
+            raster_regs,
             config: config.clone(),
             relocated_size,
             ram_lzsa_size,
             restore_code_size,
             load_save_code_size,
+            disable_mode: DisableMode::Auto,
         })
     }
 
+    /// Choose what the restore code writes to `$DE02` (see `DisableMode`)
+    pub fn with_disable_mode(mut self, mode: DisableMode) -> Self {
+        self.disable_mode = mode;
+        self
+    }
+
+    /// The `$DE02` value the generated restore code writes
+    pub fn disable_control(&self) -> u8 {
+        self.disable_mode.control_value(self.load_save_code_size)
+    }
+
     /// Generate CRT restore code binary (to be placed at $0340 in RAM)
     pub fn generate_restore_code_binary(&self) -> Result<Vec<u8>, String> {
         let main_asm = self.generate_main_code_asm6502();
@@ -95,10 +142,9 @@ impl MakeCRTAsm {
         let ram_dest_hi = (ram_end_data_start >> 8) & 0xFF;
         let ram_dest_lo = ram_end_data_start & 0xFF;
 
-        let disable_mode = if self.load_save_code_size > 0 {
-            "; Use $03 (allow re-enable later for LOAD/SAVE)\n    LDA #$03"
-        } else {
-            "; Use $04 (full disable - original behavior)\n    LDA #$04"
+        let disable_mode = match self.disable_control() {
+            0x03 => "; Use $03 (allow re-enable later for LOAD/SAVE)\n    LDA #$03",
+            _ => "; Use $04 (full disable - original behavior)\n    LDA #$04",
         };
 
         format!(
@@ -207,10 +253,21 @@ copy_done:
 
         let data_copy_code = self.generate_data_copy_code(end_data_start, ram_data_size);
 
+        // Safe VIC order: the VIC data already has display and sprites off
+        // (`ParseVSF::extract_ram`); the raster compare write keeps DEN off too
+        let raster_d011 = match self.config.vic_restore_order {
+            VicRestoreOrder::Safe => self.raster_regs[0] & !0x10,
+            VicRestoreOrder::Naive => self.raster_regs[0],
+        };
+
         // Generate inline data bytes
         let color_data = self.format_bytes(&self.color_lzsa);
-        let vic_data = self.format_bytes(&self.vic_lzsa);
-        let sid_data = self.format_bytes(&self.sid_lzsa);
+        let vic_data = self.format_bytes(self.vic_raw.as_deref().unwrap_or(&self.vic_lzsa));
+        let sid_data = if self.config.no_sid {
+            String::new()
+        } else {
+            self.format_bytes(self.sid_raw.as_deref().unwrap_or(&self.sid_lzsa))
+        };
         let cia1_data = self.format_bytes(&self.cia1_bin);
         let cia2_data = self.format_bytes(&self.cia2_bin);
         let zp_data = self.format_bytes(&self.zp_lzsa);
@@ -249,11 +306,11 @@ start:
     LDA #$2F
     STA $00
 
-    LDA $DC0D
-    LDA $DD0D
     LDA #$7F
     STA $DC0D
     STA $DD0D
+    LDA $DC0D
+    LDA $DD0D
     LDA #$00
     STA $D01A
     LDA #$FF
@@ -272,19 +329,12 @@ start:
     STA LZSA_DST_HI
     JSR decompress_lzsa1
 
-    LDA #<vic_data
-    STA LZSA_SRC_LO
-    LDA #>vic_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D0
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
-    LDA $D011
+{}
+    ; Immediate snapshot values: reading $D011/$D012 returns the current
+    ; raster line, so a read-back would clobber the compare (incl. bit 8)
+    LDA #${:02X}
     STA $D011
-    LDA $D012
+    LDA #${:02X}
     STA $D012
 
     LDA #$00
@@ -293,16 +343,7 @@ start:
     LDA #$FF
     STA $D019
 
-    LDA #<sid_data
-    STA LZSA_SRC_LO
-    LDA #>sid_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D4
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
+{}
 ; CIA1 Setup
     LDA #$7F
     STA $DC0D
@@ -644,6 +685,10 @@ got_byte:
             end_data_start,
             ram_lzsa_start,
             data_copy_code,
+            component_restore_asm("vic_data", 0xD000, self.vic_raw.as_ref().map(Vec::len)),
+            raster_d011,
+            self.raster_regs[1],
+            sid_restore_asm(&self.config, self.sid_raw.as_deref()),
             color_data,
             vic_data,
             sid_data,
@@ -840,3 +885,99 @@ got_byte:
         lines.join("\n")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+
+    #[test]
+    fn test_cia2_icr_drained_after_cart_disable_and_memory_settle() {
+        let dir = temp_dir("crt_drain");
+        for name in ["color.lzsa", "vic.lzsa", "sid.lzsa", "zp.lzsa"] {
+            fs::write(dir.join(name), [0u8]).unwrap();
+        }
+        fs::write(dir.join("cia1.bin"), [0u8; 20]).unwrap();
+        fs::write(dir.join("cia2.bin"), [0u8; 20]).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let maker = MakeCRTAsm::new(
+            &path("color.lzsa"),
+            &path("vic.lzsa"),
+            &path("sid.lzsa"),
+            &path("cia1.bin"),
+            &path("cia2.bin"),
+            &path("zp.lzsa"),
+            0x1000,
+            [0u8; 8],
+            [0x1B, 0x10],
+            &Config::new(&dir),
+            0x100,
+            0x2000,
+            0x800,
+            0x100,
+        ).unwrap();
+        let asm = maker.generate_main_code_asm6502();
+
+        // The ICR read that follows the cartridge disable
+        let disable = asm.find("    STA $DE02\n").unwrap();
+        let drain = disable + asm[disable..].find("LDA $DD0D").unwrap();
+
+        // At start-up: memory map settled, CIA2 masked, then the ICR read
+        let settle = asm.find("LDA #$2F\n    STA $00").unwrap();
+        assert!(drain < settle);
+        let mask = settle + asm[settle..].find("STA $DD0D").unwrap();
+        let read = settle + asm[settle..].find("LDA $DD0D").unwrap();
+        assert!(mask < read);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_disable_mode_sets_de02_value() {
+        let dir = temp_dir("crt_disable_mode");
+        for name in ["color.lzsa", "vic.lzsa", "sid.lzsa", "zp.lzsa"] {
+            fs::write(dir.join(name), [0u8]).unwrap();
+        }
+        fs::write(dir.join("cia1.bin"), [0u8; 20]).unwrap();
+        fs::write(dir.join("cia2.bin"), [0u8; 20]).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let maker = |load_save_code_size: usize| {
+            MakeCRTAsm::new(
+                &path("color.lzsa"),
+                &path("vic.lzsa"),
+                &path("sid.lzsa"),
+                &path("cia1.bin"),
+                &path("cia2.bin"),
+                &path("zp.lzsa"),
+                0x1000,
+                [0u8; 8],
+                [0x1B, 0x10],
+                &Config::new(&dir),
+                0x100,
+                0x2000,
+                0x800,
+                load_save_code_size,
+            ).unwrap()
+        };
+        let writes = |maker: &MakeCRTAsm, value: u8| {
+            maker.generate_main_code_asm6502().contains(&format!("LDA #${:02X}\n    STA $DE02", value))
+        };
+
+        // Auto keeps the implicit choice
+        assert_eq!(maker(0).disable_control(), 0x04);
+        assert!(writes(&maker(0), 0x04));
+        assert_eq!(maker(0x100).disable_control(), 0x03);
+
+        let reenable = maker(0).with_disable_mode(DisableMode::Reenableable);
+        assert_eq!(reenable.disable_control(), 0x03);
+        assert!(writes(&reenable, 0x03));
+        assert!(!writes(&reenable, 0x04));
+
+        let permanent = maker(0x100).with_disable_mode(DisableMode::Permanent);
+        assert!(writes(&permanent, 0x04));
+        assert!(!writes(&permanent, 0x03));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -10,6 +10,8 @@
 use std::fs;
 use crate::asm_wrapper::assemble_to_bytes;
 use crate::config::Config;
+use crate::make_prg_asm::{component_restore_asm, sid_raw_component, sid_restore_asm, vic_raw_component};
+use crate::parse_vsf::VicRestoreOrder;
 
 /// Magic Desk CRT restore code generator
 /// Generates restore code that starts at $0340 (called from boot trampoline @ $0100)
@@ -18,12 +20,14 @@ pub struct MakeMagicDeskCRTAsm {
     color_lzsa: Vec<u8>,
     vic_lzsa: Vec<u8>,
     sid_lzsa: Vec<u8>,
+    vic_raw: Option<Vec<u8>>,
+    sid_raw: Option<Vec<u8>>,
     cia1_bin: Vec<u8>,
     cia2_bin: Vec<u8>,
     zp_lzsa: Vec<u8>,
     block9_addr: u16,
     f8_ff_data: [u8; 8],
-    #[allow(dead_code)]
+    raster_regs: [u8; 2],
     config: Config,
     relocated_size: usize,
     ram_lzsa_size: usize,
@@ -42,6 +46,7 @@ impl MakeMagicDeskCRTAsm {
         zp_lzsa_path: &str,
         block9_addr: u16,
         f8_ff_data: [u8; 8],
+        raster_regs: [u8; 2],
         config: &Config,
         relocated_size: usize,
         ram_lzsa_size: usize,
@@ -60,19 +65,25 @@ impl MakeMagicDeskCRTAsm {
             return Err(format!("CIA2 file must be 20 bytes, got {}", cia2_bin.len()));
         }
 
+        let vic_lzsa = fs::read(vic_lzsa_path)
+            .map_err(|e| format!("Failed to read VIC LZSA: {}", e))?;
+        let sid_lzsa = fs::read(sid_lzsa_path)
+            .map_err(|e| format!("Failed to read SID LZSA: {}", e))?;
+
         Ok(Self {
             color_lzsa: fs::read(color_lzsa_path)
                 .map_err(|e| format!("Failed to read color LZSA: {}", e))?,
-            vic_lzsa: fs::read(vic_lzsa_path)
-                .map_err(|e| format!("Failed to read VIC LZSA: {}", e))?,
-            sid_lzsa: fs::read(sid_lzsa_path)
-                .map_err(|e| format!("Failed to read SID LZSA: {}", e))?,
+            vic_raw: vic_raw_component(config, vic_lzsa_path, &vic_lzsa),
+            sid_raw: sid_raw_component(config, sid_lzsa_path, &sid_lzsa),
+            vic_lzsa,
+            sid_lzsa,
             cia1_bin,
             cia2_bin,
             zp_lzsa: fs::read(zp_lzsa_path)
                 .map_err(|e| format!("Failed to read ZP LZSA: {}", e))?,
             block9_addr,
             f8_ff_data,
+            raster_regs,
             config: config.clone(),
             relocated_size,
             ram_lzsa_size,
@@ -231,10 +242,21 @@ copy_done:
 
         let data_copy_code = self.generate_data_copy_code(end_data_start, ram_data_size);
 
+        // Safe VIC order: the VIC data already has display and sprites off
+        // (`ParseVSF::extract_ram`); the raster compare write keeps DEN off too
+        let raster_d011 = match self.config.vic_restore_order {
+            VicRestoreOrder::Safe => self.raster_regs[0] & !0x10,
+            VicRestoreOrder::Naive => self.raster_regs[0],
+        };
+
         // Generate inline data bytes
         let color_data = self.format_bytes(&self.color_lzsa);
-        let vic_data = self.format_bytes(&self.vic_lzsa);
-        let sid_data = self.format_bytes(&self.sid_lzsa);
+        let vic_data = self.format_bytes(self.vic_raw.as_deref().unwrap_or(&self.vic_lzsa));
+        let sid_data = if self.config.no_sid {
+            String::new()
+        } else {
+            self.format_bytes(self.sid_raw.as_deref().unwrap_or(&self.sid_lzsa))
+        };
         let cia1_data = self.format_bytes(&self.cia1_bin);
         let cia2_data = self.format_bytes(&self.cia2_bin);
         let zp_data = self.format_bytes(&self.zp_lzsa);
@@ -277,12 +299,13 @@ start:
     LDA #$2F
     STA $00
 
-    ; Clear all pending interrupts
-    LDA $DC0D
-    LDA $DD0D
+    ; Clear all pending interrupts: mask first, then read the ICRs, so a
+    ; CIA2 FLAG event (serial activity) can't leave an NMI pending
     LDA #$7F
     STA $DC0D
     STA $DD0D
+    LDA $DC0D
+    LDA $DD0D
     LDA #$00
     STA $D01A
     LDA #$FF
@@ -302,20 +325,13 @@ start:
     STA LZSA_DST_HI
     JSR decompress_lzsa1
 
-    LDA #<vic_data
-    STA LZSA_SRC_LO
-    LDA #>vic_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D0
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
-    ; Setup VIC raster position early
-    LDA $D011
+{}
+    ; Setup VIC raster compare early
+    ; Immediate snapshot values: reading $D011/$D012 returns the current
+    ; raster line, so a read-back would clobber the compare (incl. bit 8)
+    LDA #${:02X}
     STA $D011
-    LDA $D012
+    LDA #${:02X}
     STA $D012
 
     LDA #$00
@@ -324,16 +340,7 @@ start:
     LDA #$FF
     STA $D019
 
-    LDA #<sid_data
-    STA LZSA_SRC_LO
-    LDA #>sid_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D4
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
+{}
 ; =============================================================================
 ; CIA1 Complete Setup
 ; =============================================================================
@@ -703,6 +710,10 @@ got_byte:
             end_data_start,
             ram_lzsa_start,
             data_copy_code,
+            component_restore_asm("vic_data", 0xD000, self.vic_raw.as_ref().map(Vec::len)),
+            raster_d011,
+            self.raster_regs[1],
+            sid_restore_asm(&self.config, self.sid_raw.as_deref()),
             color_data,
             vic_data,
             sid_data,
@@ -901,3 +912,51 @@ got_byte:
         lines.join("\n")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+
+    #[test]
+    fn test_cia2_icr_drained_after_cart_disable_and_memory_settle() {
+        let dir = temp_dir("md_crt_drain");
+        for name in ["color.lzsa", "vic.lzsa", "sid.lzsa", "zp.lzsa"] {
+            fs::write(dir.join(name), [0u8]).unwrap();
+        }
+        fs::write(dir.join("cia1.bin"), [0u8; 20]).unwrap();
+        fs::write(dir.join("cia2.bin"), [0u8; 20]).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let maker = MakeMagicDeskCRTAsm::new(
+            &path("color.lzsa"),
+            &path("vic.lzsa"),
+            &path("sid.lzsa"),
+            &path("cia1.bin"),
+            &path("cia2.bin"),
+            &path("zp.lzsa"),
+            0x1000,
+            [0u8; 8],
+            [0x1B, 0x10],
+            &Config::new(&dir),
+            0x100,
+            0x2000,
+            0x800,
+            0x100,
+        ).unwrap();
+        let asm = maker.generate_main_code_asm6502();
+
+        // The ICR read that follows the cartridge disable
+        let disable = asm.find("    LDA #$80\n    STA MAGIC_DESK_BANK\n").unwrap();
+        let drain = disable + asm[disable..].find("LDA $DD0D").unwrap();
+
+        // At start-up: memory map settled, CIA2 masked, then the ICR read
+        let settle = asm.find("LDA #$2F\n    STA $00").unwrap();
+        assert!(drain < settle);
+        let mask = settle + asm[settle..].find("STA $DD0D").unwrap();
+        let read = settle + asm[settle..].find("LDA $DD0D").unwrap();
+        assert!(mask < read);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -8,18 +8,303 @@
 #![allow(dead_code)]
 
 use crate::config::Config;
+use crate::output_hash::record_output;
+use crate::prepend_stub::hidden_by;
+use crate::parse_vsf::{VicRestoreOrder, SID_REGISTER_COUNT, VIC_REGISTER_COUNT};
 use std::fs;
+use std::path::Path;
+
+/// Where the PRG loads and the main code is assembled (BASIC start)
+pub const PRG_LOAD_ADDRESS: u16 = 0x0801;
+/// Entry of the default loader: SYS 2061, right after the BASIC stub
+pub const PRG_ENTRY_ADDRESS: u16 = 0x080D;
+
+/// Lowest `Config::loader_origin`: LOAD below it would overwrite the KERNAL's
+/// buffers and vectors, and pages 0-1 hold the decompressor during the restore
+pub const MIN_LOADER_ORIGIN: u16 = 0x0400;
+
+/// Load and entry address of the PRG loader for `config`
+pub fn loader_addresses(config: &Config) -> (u16, u16) {
+    match config.loader_origin {
+        Some(origin) => (origin, origin),
+        None => (PRG_LOAD_ADDRESS, PRG_ENTRY_ADDRESS),
+    }
+}
+
+/// Check that a loader of `len` bytes at `origin` can be loaded and run
+///
+/// It must start at `MIN_LOADER_ORIGIN` or above and stay in RAM the CPU
+/// sees with the default $01 ($37): $0400-$9FFF or $C000-$CFFF. Under the
+/// BASIC or KERNAL ROM a JMP/SYS to the origin runs the ROM, and over the
+/// I/O area LOAD would write to the chips.
+pub fn check_loader_origin(origin: u16, len: usize) -> Result<(), String> {
+    let start = origin as usize;
+    let end = start + len;
+    if origin < MIN_LOADER_ORIGIN {
+        return Err(format!(
+            "Loader origin ${:04X} is below ${:04X}: loading would overwrite the zero page, stack or KERNAL vectors",
+            origin, MIN_LOADER_ORIGIN
+        ));
+    }
+    if let Some(area) = hidden_by(0x37, start, end) {
+        let reason = if area == "I/O" {
+            "LOAD would write to the chips instead of RAM"
+        } else {
+            "with the default $01 ($37) a JMP/SYS there runs the ROM, not the loader"
+        };
+        return Err(format!(
+            "Loader at ${:04X}-${:04X} overlaps {}: {}; use an origin in $0400-$9FFF or $C000-$CFFF",
+            origin,
+            end - 1,
+            area,
+            reason
+        ));
+    }
+    Ok(())
+}
+
+/// Append `$00` to `prg` (load address included) up to a multiple of `block` bytes
+///
+/// The fill loads past the program, where staging and decompression later
+/// overwrite it, so only the LOAD itself is checked: like the loader, the
+/// padded file must not reach the I/O area.
+pub fn pad_prg(prg: &mut Vec<u8>, block: u16) -> Result<(), String> {
+    if block == 0 {
+        return Err("PRG padding block size must be at least 1".to_string());
+    }
+    let padded_len = prg.len().div_ceil(block as usize) * block as usize;
+    if padded_len == prg.len() || prg.len() < 2 {
+        return Ok(());
+    }
+
+    let load_address = u16::from_le_bytes([prg[0], prg[1]]) as usize;
+    let end = load_address + padded_len - 2;
+    if end > 0xD000 {
+        return Err(format!(
+            "Padding to a multiple of {} bytes loads the PRG to ${:04X}-${:04X}, past $CFFF",
+            block,
+            load_address,
+            end - 1
+        ));
+    }
+
+    prg.resize(padded_len, 0x00);
+    Ok(())
+}
+
+/// The compressed RAM is staged so it ends at $FFFF and decompresses
+/// forward into $0200-$FFEF, over the same memory
+///
+/// This also overwrites the loader at $0801, which is safe: LZSA1 matches
+/// only copy from output already written, so nothing the loader left in
+/// $0800-$0FFF can leak into the restored RAM. Only the unread input at the
+/// top of memory needs protecting (`ram_decompression_overlap`).
+const RAM_STAGING_END: isize = 0x10000;
+const RAM_DEST: isize = 0x0200;
+
+/// Bytes by which decompressing `ram_lzsa` in place would overwrite compressed
+/// data it has not read yet; 0 when the loader's staging layout is safe
+///
+/// Output grows by more than input while the stream compresses well, so the
+/// write pointer can catch up with the read pointer when the data after that
+/// point expands. Walks the LZSA1 tokens and checks every literal run and
+/// match against the bytes still unread.
+pub fn ram_decompression_overlap(ram_lzsa: &[u8]) -> Result<usize, String> {
+    let src = RAM_STAGING_END - ram_lzsa.len() as isize;
+    let byte = |at: isize| {
+        ram_lzsa.get(at as usize)
+            .map(|&b| b as isize)
+            .ok_or_else(|| "Compressed RAM ends without an LZSA1 end marker".to_string())
+    };
+
+    let mut read = 0isize;
+    let mut written = 0isize;
+    let mut overlap = 0isize;
+    loop {
+        let token = byte(read)?;
+        read += 1;
+
+        let mut literals = (token >> 4) & 0x07;
+        if literals == 7 {
+            literals = match byte(read)? {
+                249 => {
+                    read += 2;
+                    byte(read - 1)? | byte(read)? << 8
+                }
+                250 => {
+                    read += 1;
+                    256 + byte(read)?
+                }
+                b => 7 + b,
+            };
+            read += 1;
+        }
+        if literals > 0 {
+            // Each literal is read before it is written, so the gap stays constant
+            overlap = overlap.max(RAM_DEST + written - (src + read));
+            read += literals;
+            written += literals;
+        }
+
+        // Offset: one byte, two with token bit 7
+        read += if token & 0x80 != 0 { 2 } else { 1 };
+
+        let mut length = (token & 0x0F) + 3;
+        if length == 18 {
+            length = match byte(read)? {
+                238 => {
+                    read += 2;
+                    match byte(read - 1)? | byte(read)? << 8 {
+                        0 => return Ok(overlap as usize),
+                        n => n,
+                    }
+                }
+                239 => {
+                    read += 1;
+                    256 + byte(read)?
+                }
+                b => 18 + b,
+            };
+            read += 1;
+        }
+        // The last byte of the match is the closest to the unread input
+        overlap = overlap.max(RAM_DEST + written + length - (src + read));
+        written += length;
+    }
+}
+
+/// Loader code setting up the move to the top of memory: LDA #<(RAM_DATA_END-1)
+/// / STA $FE / LDA #>(RAM_DATA_END-1) / STA $FF / LDA #$FF / STA $FC / STA $FD
+const MOVE_SETUP: [Option<u8>; 14] = [
+    Some(0xA9), None, Some(0x85), Some(0xFE), Some(0xA9), None, Some(0x85), Some(0xFF),
+    Some(0xA9), Some(0xFF), Some(0x85), Some(0xFC), Some(0x85), Some(0xFD),
+];
+
+/// Loader code handing the staged stream to the relocated decompressor:
+/// source to $FC/$FD, destination $0200 to $FE/$FF, JMP $0100
+const DECOMPRESS_SETUP: [Option<u8>; 19] = [
+    Some(0xA9), None, Some(0x85), Some(0xFC), Some(0xA9), None, Some(0x85), Some(0xFD),
+    Some(0xA9), Some(0x00), Some(0x85), Some(0xFE), Some(0xA9), Some(0x02), Some(0x85), Some(0xFF),
+    Some(0x4C), Some(0x00), Some(0x01),
+];
+
+/// Operands (the `None` bytes) of the first match of `pattern` in `code`
+fn pattern_operands(code: &[u8], pattern: &[Option<u8>]) -> Option<Vec<u8>> {
+    let window = code
+        .windows(pattern.len())
+        .find(|window| window.iter().zip(pattern).all(|(&b, p)| p.is_none_or(|p| p == b)))?;
+    Some(window.iter().zip(pattern).filter(|(_, p)| p.is_none()).map(|(&b, _)| b).collect())
+}
+
+/// Where the loader in `prg` stages the compressed RAM, and that stream
+///
+/// Read back from the loader's own operands: the end of the block it moves to
+/// the top of memory and the address it hands the relocated decompressor.
+/// `None` if `prg` (load address included) doesn't hold this loader.
+pub fn staged_ram_stream(prg: &[u8]) -> Option<(u16, &[u8])> {
+    let load_address = u16::from_le_bytes([*prg.first()?, *prg.get(1)?]) as usize;
+    let data_end = pattern_operands(prg, &MOVE_SETUP)?;
+    let staged = pattern_operands(prg, &DECOMPRESS_SETUP)?;
+
+    let data_end = u16::from_le_bytes([data_end[0], data_end[1]]) as usize + 1;
+    let staged = u16::from_le_bytes([staged[0], staged[1]]);
+    let len = RAM_STAGING_END as usize - staged as usize;
+    let end = (data_end + 2).checked_sub(load_address)?;
+    Some((staged, prg.get(end.checked_sub(len)?..end)?))
+}
+
+/// Restore code bytes per LZSA1 component: source and destination setup plus the JSR
+const LZSA_CALL_BYTES: usize = 19;
+
+/// Restore code bytes of a copy loop: LDX, LDA abs,X, STA abs,X, INX, CPX, BNE
+const COPY_LOOP_BYTES: usize = 13;
+
+/// Whether a component is smaller stored raw with a copy loop than as an LZSA1 stream
+///
+/// The loop costs 6 bytes less than the decompressor call, so the raw bytes win
+/// unless LZSA1 saves more than that: 47 VIC or 25 SID bytes of mostly distinct
+/// register values rarely compress by 6. The loop copies at most 256 bytes.
+pub fn store_raw(raw_len: usize, lzsa_len: usize) -> bool {
+    raw_len > 0 && raw_len <= 256 && raw_len + COPY_LOOP_BYTES <= lzsa_len + LZSA_CALL_BYTES
+}
+
+/// The uncompressed component next to `lzsa_path`, when `Config::raw_small` is set and it is smaller
+///
+/// A file longer than `max_len` is left to the decompressor, so the copy loop
+/// never writes past the chip's registers (e.g. into $D02F-$D03F).
+fn raw_component(config: &Config, lzsa_path: &str, lzsa: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    if !config.raw_small {
+        return None;
+    }
+    let raw = fs::read(lzsa_path.strip_suffix(".lzsa")?).ok()?;
+    (raw.len() <= max_len && store_raw(raw.len(), lzsa.len())).then_some(raw)
+}
+
+/// Raw VIC-II registers for the copy loop, at most `VIC_REGISTER_COUNT` bytes
+pub(crate) fn vic_raw_component(config: &Config, vic_lzsa_path: &str, vic_lzsa: &[u8]) -> Option<Vec<u8>> {
+    raw_component(config, vic_lzsa_path, vic_lzsa, VIC_REGISTER_COUNT)
+}
+
+/// Raw SID registers for the copy loop, at most `SID_REGISTER_COUNT` bytes
+pub(crate) fn sid_raw_component(config: &Config, sid_lzsa_path: &str, sid_lzsa: &[u8]) -> Option<Vec<u8>> {
+    raw_component(config, sid_lzsa_path, sid_lzsa, SID_REGISTER_COUNT)
+}
+
+/// Restore code for the component at `label`: a copy loop for `raw_len` raw bytes, else an LZSA1 call
+pub(crate) fn component_restore_asm(label: &str, dest: u16, raw_len: Option<usize>) -> String {
+    match raw_len {
+        Some(len) => format!(r#"    LDX #$00
+{label}_copy:
+    LDA {label},X
+    STA ${dest:04X},X
+    INX
+    CPX #${:02X}
+    BNE {label}_copy
+"#, len as u8),
+        None => format!(r#"    LDA #<{label}
+    STA LZSA_SRC_LO
+    LDA #>{label}
+    STA LZSA_SRC_HI
+    LDA #${:02X}
+    STA LZSA_DST_LO
+    LDA #${:02X}
+    STA LZSA_DST_HI
+    JSR decompress_lzsa1
+"#, dest & 0xFF, dest >> 8),
+    }
+}
+
+/// SID register restore for the generated loaders; left out with `Config::no_sid`
+pub(crate) fn sid_restore_asm(config: &Config, sid_raw: Option<&[u8]>) -> String {
+    if config.no_sid {
+        return String::new();
+    }
+    component_restore_asm("sid_data", 0xD400, sid_raw.map(<[u8]>::len))
+}
+
+/// `.byte` lines for data embedded in the source
+pub(crate) fn byte_lines(data: &[u8]) -> String {
+    data.chunks(16)
+        .map(|chunk| {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("${:02X}", b)).collect();
+            format!("    .byte {}\n", bytes.join(","))
+        })
+        .collect()
+}
 
 pub struct MakePRGAsm {
     color_lzsa: Vec<u8>,
     vic_lzsa: Vec<u8>,
     sid_lzsa: Vec<u8>,
+    vic_raw: Option<Vec<u8>>,
+    sid_raw: Option<Vec<u8>>,
     cia1_bin: Vec<u8>,
     cia2_bin: Vec<u8>,
     zp_lzsa: Vec<u8>,
     ram_lzsa: Vec<u8>,
     block9_addr: u16,
     f8_ff_data: [u8; 8],
+    raster_regs: [u8; 2],
     config: Config,
 }
 
@@ -34,6 +319,7 @@ impl MakePRGAsm {
         ram_lzsa_path: &str,
         block9_addr: u16,
         f8_ff_data: [u8; 8],
+        raster_regs: [u8; 2],
         config: &Config,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let cia1_bin = fs::read(cia1_bin_path)?;
@@ -47,21 +333,37 @@ impl MakePRGAsm {
             return Err(format!("CIA2 file must be 20 bytes, got {}", cia2_bin.len()).into());
         }
 
+        let vic_lzsa = fs::read(vic_lzsa_path)?;
+        let sid_lzsa = fs::read(sid_lzsa_path)?;
+
         Ok(Self {
             color_lzsa: fs::read(color_lzsa_path)?,
-            vic_lzsa: fs::read(vic_lzsa_path)?,
-            sid_lzsa: fs::read(sid_lzsa_path)?,
+            vic_raw: vic_raw_component(config, vic_lzsa_path, &vic_lzsa),
+            sid_raw: sid_raw_component(config, sid_lzsa_path, &sid_lzsa),
+            vic_lzsa,
+            sid_lzsa,
             cia1_bin,
             cia2_bin,
             zp_lzsa: fs::read(zp_lzsa_path)?,
             ram_lzsa: fs::read(ram_lzsa_path)?,
             block9_addr,
             f8_ff_data,
+            raster_regs,
             config: config.clone(),
         })
     }
 
     pub fn generate_prg(&self, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.generate(output_path, None)
+    }
+
+    /// Like `generate_prg`, and also write the code without the load address
+    /// header to `raw_path` (for EPROMs or loaders with a fixed address)
+    pub fn generate_prg_and_raw(&self, output_path: &str, raw_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.generate(output_path, Some(raw_path))
+    }
+
+    fn generate(&self, output_path: &str, raw_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
         let relocated_binary = self.assemble_relocated_code()?;
 
         if relocated_binary.len() > 256 {
@@ -75,9 +377,24 @@ impl MakePRGAsm {
         self.write_data_files(&relocated_binary)?;
 
         let main_asm = self.generate_main_code_asm6502();
-        let prg_binary = self.assemble_with_asm6502(&main_asm)?;
+        let binary = self.assemble_with_asm6502(&main_asm)?;
 
+        let (load_address, _) = loader_addresses(&self.config);
+        if let Some(origin) = self.config.loader_origin {
+            check_loader_origin(origin, binary.len())?;
+        }
+
+        let mut prg_binary = load_address.to_le_bytes().to_vec();
+        prg_binary.extend_from_slice(&binary);
+        if let Some(block) = self.config.pad_prg {
+            pad_prg(&mut prg_binary, block)?;
+        }
         fs::write(output_path, &prg_binary)?;
+        record_output(Path::new(output_path), &prg_binary)?;
+
+        if let Some(raw_path) = raw_path {
+            fs::write(raw_path, &binary)?;
+        }
 
         Ok(())
     }
@@ -101,10 +418,10 @@ impl MakePRGAsm {
         use crate::asm_wrapper::Assembler6502Wrapper;
 
         let mut assembler = Assembler6502Wrapper::new();
-        let prg_binary = assembler.assemble_prg(asm_source)
+        let binary = assembler.assemble_bytes(asm_source)
             .map_err(|e| format!("Assembly failed: {:?}", e))?;
 
-        Ok(prg_binary)
+        Ok(binary)
     }
 
     fn assemble_relocated_code(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -125,12 +442,33 @@ impl MakePRGAsm {
         // Convert Windows backslashes to forward slashes for cross-platform compatibility
         let work_path = work.replace('\\', "/");
 
-        format!(r#"; C64 LZSA1 Snapshot Loader - Conservative Optimization
-*=$0801
-
-; BASIC stub: SYS 2061
-.byte $0B,$08,$0A,$00,$9E,$32,$30,$36,$31,$00,$00,$00
+        // Safe VIC order: the VIC data already has display and sprites off
+        // (`ParseVSF::extract_ram`); the raster compare write keeps DEN off too
+        let raster_d011 = match self.config.vic_restore_order {
+            VicRestoreOrder::Safe => self.raster_regs[0] & !0x10,
+            VicRestoreOrder::Naive => self.raster_regs[0],
+        };
+
+        let vic_data = match &self.vic_raw {
+            Some(raw) => byte_lines(raw),
+            None => format!("    .incbin \"{}/vic.lzsa\"\n", work_path),
+        };
+        let sid_data = match &self.sid_raw {
+            _ if self.config.no_sid => String::new(),
+            Some(raw) => byte_lines(raw),
+            None => format!("    .incbin \"{}/sid.lzsa\"\n", work_path),
+        };
+
+        // A custom origin is entered with a JMP, so it needs no BASIC stub
+        let (load_address, _) = loader_addresses(&self.config);
+        let basic_stub = match self.config.loader_origin {
+            Some(_) => "",
+            None => "\n; BASIC stub: SYS 2061\n.byte $0B,$08,$0A,$00,$9E,$32,$30,$36,$31,$00,$00,$00\n",
+        };
 
+        format!(r#"; C64 LZSA1 Snapshot Loader - Conservative Optimization
+*=${:04X}
+{}
 ; LZSA1 zero page variables
 LZSA_SRC_LO = $FC
 LZSA_SRC_HI = $FD
@@ -144,19 +482,21 @@ start:
     SEI
     CLD
 
-    ; Clear all pending interrupts
-    LDA $DC0D
-    LDA $DD0D
+    LDA #$35
+    STA $01
+
+    ; Clear all pending interrupts: mask first, then read the ICRs, so a
+    ; CIA2 FLAG event (serial activity) can't leave an NMI pending
     LDA #$7F
     STA $DC0D
     STA $DD0D
+    LDA $DC0D
+    LDA $DD0D
     LDA #$00
     STA $D01A
     LDA #$FF
     STA $D019
 
-    LDA #$35
-    STA $01
     LDX #$FF
     TXS
 
@@ -170,21 +510,14 @@ start:
     STA LZSA_DST_HI
     JSR decompress_lzsa1
 
-    LDA #<vic_data
-    STA LZSA_SRC_LO
-    LDA #>vic_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D0
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
-    ; OPTIMIZATION: Setup VIC raster position early (moved from $01xx)
+{}
+    ; OPTIMIZATION: Setup VIC raster compare early (moved from $01xx)
     ; This is 100% safe - no interrupts enabled yet
-    LDA $D011
+    ; Immediate snapshot values: reading $D011/$D012 returns the current
+    ; raster line, so a read-back would clobber the compare (incl. bit 8)
+    LDA #${:02X}
     STA $D011
-    LDA $D012
+    LDA #${:02X}
     STA $D012
 
     LDA #$00
@@ -193,16 +526,7 @@ start:
     LDA #$FF
     STA $D019
 
-    LDA #<sid_data
-    STA LZSA_SRC_LO
-    LDA #>sid_data
-    STA LZSA_SRC_HI
-    LDA #$00
-    STA LZSA_DST_LO
-    LDA #$D4
-    STA LZSA_DST_HI
-    JSR decompress_lzsa1
-
+{}
 ; =============================================================================
 ; CIA1 Complete Setup (100% safe - no timers started yet)
 ; =============================================================================
@@ -418,10 +742,8 @@ CPLP:
 color_data:
     .incbin "{}/color.lzsa"
 vic_data:
-    .incbin "{}/vic.lzsa"
-sid_data:
-    .incbin "{}/sid.lzsa"
-cia1_data:
+{}sid_data:
+{}cia1_data:
     .incbin "{}/cia1.bin"
 cia2_data:
     .incbin "{}/cia2.bin"
@@ -597,7 +919,10 @@ get_byte:
     INC LZSA_SRC_HI
 got_byte:
     RTS
-"#, work_path, work_path, work_path, work_path, work_path, work_path, work_path, work_path)
+"#, load_address, basic_stub,
+            component_restore_asm("vic_data", 0xD000, self.vic_raw.as_ref().map(Vec::len)),
+            raster_d011, self.raster_regs[1], sid_restore_asm(&self.config, self.sid_raw.as_deref()),
+            work_path, vic_data, sid_data, work_path, work_path, work_path, work_path, work_path)
     }
 
     fn generate_relocated_decompressor(&self) -> String {
@@ -767,3 +1092,183 @@ got_byte:
 "#, self.block9_addr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+    use std::path::Path;
+
+    /// LZSA1 stream: one literal, a match filling `head` bytes, `tail` tokens of
+    /// 7 literals + 3-byte match (11 bytes in, 10 out), then the end marker
+    fn crafted_ram_stream(head: u16, tail: usize) -> Vec<u8> {
+        let mut stream = vec![0x1F, 0xAA, 0xFF, 238];
+        stream.extend_from_slice(&head.to_le_bytes());
+        for _ in 0..tail {
+            stream.extend_from_slice(&[0xF0, 0x00, 1, 2, 3, 4, 5, 6, 7, 0xF9, 0xFF]);
+        }
+        stream.extend_from_slice(&[0x0F, 0x00, 238, 0x00, 0x00]);
+        stream
+    }
+
+    #[test]
+    fn test_expanding_tail_overlaps_unread_input() {
+        // 65008 bytes out: the head runs 89 bytes into the 1111 compressed bytes
+        let stream = crafted_ram_stream(64007, 100);
+        assert_eq!(ram_decompression_overlap(&stream), Ok(89));
+    }
+
+    #[test]
+    fn test_compressible_tail_decompresses_safely() {
+        let stream = crafted_ram_stream(65007, 0);
+        assert_eq!(ram_decompression_overlap(&stream), Ok(0));
+        assert!(ram_decompression_overlap(&stream[..stream.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_pad_prg_rounds_up_to_block() {
+        let mut prg = vec![0x01, 0x08, 0xA9, 0x00, 0x60];
+        pad_prg(&mut prg, 254).unwrap();
+        assert_eq!(prg.len(), 254);
+        assert_eq!(&prg[..5], &[0x01, 0x08, 0xA9, 0x00, 0x60]);
+        assert!(prg[5..].iter().all(|&b| b == 0x00));
+
+        // Already a multiple: unchanged
+        pad_prg(&mut prg, 127).unwrap();
+        assert_eq!(prg.len(), 254);
+
+        // $C000 + 4095 bytes ends at $CFFE; a 4 KB block would load into $D000
+        let mut prg = vec![0x00, 0xC0];
+        prg.resize(4097, 0xEA);
+        assert!(pad_prg(&mut prg.clone(), 4097).is_ok());
+        assert!(pad_prg(&mut prg, 4096).unwrap_err().contains("$C000-$DFFD"));
+    }
+
+    #[test]
+    fn test_raster_compare_uses_snapshot_values() {
+        let dir = temp_dir("prg_raster");
+        let asm = main_code(&dir, Config::new(&dir));
+
+        assert!(asm.contains("LDA #$9B\n    STA $D011"));
+        assert!(asm.contains("LDA #$10\n    STA $D012"));
+        assert!(!asm.contains("LDA $D011"));
+        assert!(!asm.contains("LDA $D012"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_no_sid_omits_sid_restore() {
+        let dir = temp_dir("prg_no_sid");
+        let asm = main_code(&dir, Config::new(&dir));
+        assert!(asm.contains("LDA #$D4\n    STA LZSA_DST_HI"));
+        assert!(asm.contains("sid.lzsa"));
+
+        let asm = main_code(&dir, Config::new(&dir).with_no_sid(true));
+        assert!(!asm.contains("LDA #$D4"));
+        assert!(!asm.contains("sid.lzsa"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cia2_icr_drained_after_memory_settle() {
+        let dir = temp_dir("prg_drain");
+        let asm = main_code(&dir, Config::new(&dir));
+
+        let settle = asm.find("LDA #$35\n    STA $01").unwrap();
+        let mask = asm.find("STA $DD0D").unwrap();
+        let read = asm.find("LDA $DD0D").unwrap();
+        assert!(settle < mask && mask < read);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_small_components_stored_raw() {
+        // Break-even: raw wins unless LZSA1 saves more than 6 bytes
+        assert!(store_raw(47, 41));
+        assert!(!store_raw(47, 40));
+        assert!(store_raw(25, 30));
+        assert!(!store_raw(300, 400));
+
+        let dir = temp_dir("prg_raw_small");
+        fs::write(dir.join("vic"), [0x11u8; 47]).unwrap();
+        fs::write(dir.join("vic.lzsa"), [0u8; 45]).unwrap();
+        fs::write(dir.join("sid"), [0x22u8; 25]).unwrap();
+        fs::write(dir.join("sid.lzsa"), [0u8; 10]).unwrap();
+
+        let asm = main_code(&dir, Config::new(&dir));
+        assert!(asm.contains("vic.lzsa"));
+        assert!(!asm.contains("vic_data_copy"));
+
+        let asm = main_code(&dir, Config::new(&dir).with_raw_small(true));
+        assert!(!asm.contains("vic.lzsa"));
+        assert!(asm.contains("vic_data_copy:\n    LDA vic_data,X\n    STA $D000,X"));
+        assert!(asm.contains("CPX #$2F"));
+        assert!(asm.contains("vic_data:\n    .byte $11,$11"));
+        // LZSA1 saves 15 SID bytes, more than the decompressor call costs
+        assert!(asm.contains("sid.lzsa"));
+        assert!(asm.contains("LDA #$D4\n    STA LZSA_DST_HI"));
+
+        // A copy loop over 48 bytes would write $D02F: left to the decompressor
+        fs::write(dir.join("vic"), [0x11u8; 48]).unwrap();
+        let asm = main_code(&dir, Config::new(&dir).with_raw_small(true));
+        assert!(asm.contains("vic.lzsa"));
+        assert!(!asm.contains("vic_data_copy"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_loader_origin_replaces_basic_stub() {
+        let dir = temp_dir("prg_origin");
+        let asm = main_code(&dir, Config::new(&dir));
+        assert!(asm.contains("*=$0801\n\n; BASIC stub: SYS 2061"));
+        assert_eq!(loader_addresses(&Config::new(&dir)), (0x0801, 0x080D));
+
+        let config = Config::new(&dir).with_loader_origin(0xC000);
+        let asm = main_code(&dir, config.clone());
+        assert!(asm.contains("*=$C000\n\n; LZSA1 zero page variables"));
+        assert!(!asm.contains("SYS 2061"));
+        assert_eq!(loader_addresses(&config), (0xC000, 0xC000));
+
+        assert!(check_loader_origin(0xC000, 0x0800).is_ok());
+        assert!(check_loader_origin(0x9000, 0x1000).is_ok());
+        assert!(check_loader_origin(0x0300, 0x0800).is_err());
+        assert!(check_loader_origin(0xC000, 0x1001).unwrap_err().contains("I/O"));
+        assert!(check_loader_origin(0x9000, 0x1001).unwrap_err().contains("BASIC ROM"));
+        assert!(check_loader_origin(0xE000, 0x2000).unwrap_err().contains("KERNAL ROM"));
+        assert!(check_loader_origin(0xF000, 0x1001).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Main loader source for dummy data files in `dir`, raster $9B/$10;
+    /// data files a test already wrote are kept
+    fn main_code(dir: &Path, config: Config) -> String {
+        for name in ["color.lzsa", "vic.lzsa", "sid.lzsa", "zp.lzsa", "ram.lzsa"] {
+            if !dir.join(name).exists() {
+                fs::write(dir.join(name), [0u8]).unwrap();
+            }
+        }
+        fs::write(dir.join("cia1.bin"), [0u8; 20]).unwrap();
+        fs::write(dir.join("cia2.bin"), [0u8; 20]).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let maker = MakePRGAsm::new(
+            &path("color.lzsa"),
+            &path("vic.lzsa"),
+            &path("sid.lzsa"),
+            &path("cia1.bin"),
+            &path("cia2.bin"),
+            &path("zp.lzsa"),
+            &path("ram.lzsa"),
+            0x1000,
+            [0u8; 8],
+            [0x9B, 0x10],
+            &config,
+        ).unwrap();
+        maker.generate_main_code_asm6502()
+    }
+}
@@ -9,6 +9,79 @@
 
 use crate::asm_wrapper::assemble_to_bytes;
 use crate::crt_builder::BANK_SIZE_8K;
+use crate::file_system_manager::HARDWARE_FILENAME_START;
+use std::fs;
+use std::path::Path;
+
+/// EAPI location in ROMH bank 0 (@ $B800 in 16K mode)
+pub const EAPI_OFFSET: usize = 0x1800;
+/// EAPI size reserved for flash drivers
+pub const EAPI_SIZE: usize = 0x0300;
+/// EasyFlash 3 menu name location in ROMH bank 0 (@ $BB00 in 16K mode)
+pub const EF_NAME_OFFSET: usize = 0x1B00;
+/// Characters of the cartridge name the EF-Name entry holds
+pub const EF_NAME_MAX_LEN: usize = 16;
+
+/// EAPI signature "EAPI"
+const EAPI_SIGNATURE: [u8; 4] = [0x65, 0x61, 0x70, 0x69];
+/// EasyFlash 3 menu name signature "EF-Name:"
+const EF_NAME_SIGNATURE: [u8; 8] = [0xC5, 0xC6, 0x2D, 0xCE, 0x41, 0x4D, 0x45, 0x3A];
+
+/// ROML address the boot trampoline copies the restore code from (in the boot bank)
+const ROML_RESTORE_CODE_START: usize = 0x8000;
+
+/// Largest boot trampoline the RESET code's 8-bit copy loop can move to $0100
+const MAX_BOOT_TRAMPOLINE_SIZE: usize = 255;
+/// LOAD/SAVE code location in ROMH (@ $A600 in 16K mode) and the space it has
+const LOAD_SAVE_OFFSET: usize = 0x0600;
+const LOAD_SAVE_MAX_SIZE: usize = 0x0A00;
+
+/// Loading screen image: 1000 screen codes followed by 1000 colors
+pub const LOADING_SCREEN_SIZE: usize = 2000;
+/// Screen RAM the boot trampoline shows the loading screen from
+///
+/// Above the highest restore code byte ($0340 + 8K), so copying the restore
+/// code does not overwrite it; RAM decompression does later.
+pub const LOADING_SCREEN_ADDRESS: u16 = 0x2400;
+/// `$D018` for screen RAM at `LOADING_SCREEN_ADDRESS` and the ROM charset
+const LOADING_SCREEN_D018: u8 = 0x94;
+
+/// Read a loading screen image
+///
+/// Accepts the raw 2000 byte image or the same behind a 2 byte PRG load
+/// address, which is skipped.
+pub fn read_loading_screen(path: &Path) -> Result<Vec<u8>, String> {
+    let data = fs::read(path)
+        .map_err(|e| format!("Failed to read loading screen {}: {}", path.display(), e))?;
+    match data.len() {
+        LOADING_SCREEN_SIZE => Ok(data),
+        len if len == LOADING_SCREEN_SIZE + 2 => Ok(data[2..].to_vec()),
+        len => Err(format!(
+            "Loading screen {} is {} bytes, expected {} (1000 screen codes + 1000 colors) \
+             or {} with a PRG load address",
+            path.display(),
+            len,
+            LOADING_SCREEN_SIZE,
+            LOADING_SCREEN_SIZE + 2
+        )),
+    }
+}
+
+/// Check that `len` bytes of restore code fit in ROML bank `boot_bank`
+///
+/// The boot trampoline copies whole pages from $8000 to $0340 without
+/// switching banks, so a longer restore routine would be copied from the
+/// wrong data past $9FFF.
+pub fn check_restore_code_size(len: usize, boot_bank: usize) -> Result<(), String> {
+    if len > BANK_SIZE_8K {
+        return Err(format!(
+            "Restore code is {} bytes, but the boot trampoline copies it from ROML bank {} \
+             ($8000-$9FFF, {} bytes) only",
+            len, boot_bank, BANK_SIZE_8K
+        ));
+    }
+    Ok(())
+}
 
 /// EasyFlash ROMH code generator
 pub struct MakeROMHAsm {
@@ -16,6 +89,9 @@ pub struct MakeROMHAsm {
     load_save_code: Option<Vec<u8>>,
     metadata: Option<Vec<u8>>,
     filenames: Option<Vec<u8>>,
+    hardware_name: Option<String>,
+    loading_screen_bank: Option<usize>,
+    boot_bank: usize,
 }
 
 impl MakeROMHAsm {
@@ -31,19 +107,69 @@ impl MakeROMHAsm {
             load_save_code,
             metadata,
             filenames,
+            hardware_name: None,
+            loading_screen_bank: None,
+            boot_bank: 0,
         }
     }
 
+    /// Lay out ROMH for physical EasyFlash 1/3 hardware
+    ///
+    /// Adds a stub EAPI @ $B800 and the EF-Name menu entry @ $BB00. Costs
+    /// $B800-$BB1F, so embedded filenames move to $BB20.
+    pub fn with_easyflash_hardware(mut self, name: &str) -> Self {
+        self.hardware_name = Some(name.to_string());
+        self
+    }
+
+    /// Show the loading screen stored at the start of ROML `bank` while restoring
+    ///
+    /// The boot trampoline copies it to `LOADING_SCREEN_ADDRESS` and color RAM
+    /// before it copies the restore code.
+    pub fn with_loading_screen(mut self, bank: usize) -> Self {
+        self.loading_screen_bank = Some(bank);
+        self
+    }
+
+    /// Copy the restore code from ROML `bank` instead of bank 0
+    ///
+    /// `total_banks` is the size of the cartridge the ROMH goes into; the bank
+    /// must be one of them.
+    pub fn with_boot_bank(mut self, bank: usize, total_banks: usize) -> Result<Self, String> {
+        if bank >= total_banks {
+            return Err(format!(
+                "Boot bank {} is outside the cartridge (banks 0-{})",
+                bank,
+                total_banks.saturating_sub(1)
+            ));
+        }
+        self.boot_bank = bank;
+        Ok(self)
+    }
+
     /// Generate complete ROMH bank @ $E000 (8KB)
     pub fn generate_romh(&self) -> Result<[u8; BANK_SIZE_8K], String> {
+        check_restore_code_size(self.restore_code_size, self.boot_bank)?;
+        self.check_boot_trampoline_size()?;
         let asm_source = self.generate_romh_asm();
         let assembled = assemble_to_bytes(&asm_source)?;
 
+        // Long branches rewritten to JMP make the code grow, so check it against
+        // the data written after it rather than letting that overwrite it
+        let code_limit = self.code_limit();
+        if assembled.len() > code_limit {
+            return Err(format!(
+                "ROMH boot code is {} bytes, but only {} bytes are free before its data at ${:04X}",
+                assembled.len(),
+                code_limit,
+                0xE000 + code_limit
+            ));
+        }
+
         let mut romh = [0u8; BANK_SIZE_8K];
 
         // Copy assembled code
-        let copy_len = assembled.len().min(BANK_SIZE_8K);
-        romh[..copy_len].copy_from_slice(&assembled[..copy_len]);
+        romh[..assembled.len()].copy_from_slice(&assembled);
 
         // Set interrupt vectors at $FFFA-$FFFF (offsets $1FFA-$1FFF in 8KB bank)
         // NMI vector @ $FFFA/$FFFB -> $E000 (RTI)
@@ -60,9 +186,14 @@ impl MakeROMHAsm {
 
         // Write LOAD/SAVE code at offset $0600 if provided (will be @ $A600 in 16K mode)
         if let Some(ref code) = self.load_save_code {
-            let code_offset = 0x0600;
-            let copy_size = code.len().min(0x0A00); // Max ~2.5KB
-            romh[code_offset..code_offset + copy_size].copy_from_slice(&code[..copy_size]);
+            if code.len() > LOAD_SAVE_MAX_SIZE {
+                return Err(format!(
+                    "LOAD/SAVE code is {} bytes, but ROMH has room for {} bytes",
+                    code.len(),
+                    LOAD_SAVE_MAX_SIZE
+                ));
+            }
+            romh[LOAD_SAVE_OFFSET..LOAD_SAVE_OFFSET + code.len()].copy_from_slice(code);
         }
 
         // Write metadata at offset $1000 if provided (will be @ $B000 in 16K mode)
@@ -72,16 +203,58 @@ impl MakeROMHAsm {
             romh[meta_offset..meta_offset + copy_size].copy_from_slice(&meta[..copy_size]);
         }
 
+        // Write EAPI stub and menu name for physical hardware (@ $B800/$BB00 in 16K mode)
+        if let Some(ref name) = self.hardware_name {
+            let eapi = eapi_stub();
+            romh[EAPI_OFFSET..EAPI_OFFSET + eapi.len()].copy_from_slice(&eapi);
+            let ef_name = ef_name_block(name);
+            romh[EF_NAME_OFFSET..EF_NAME_OFFSET + ef_name.len()].copy_from_slice(&ef_name);
+        }
+
         // Write filenames at offset $1800 if provided (will be @ $B800 in 16K mode)
+        // With EAPI present they start after the EF-Name block instead
         if let Some(ref names) = self.filenames {
-            let names_offset = 0x1800;
-            let copy_size = names.len().min(0x07FC); // Max ~2KB, avoid vectors
+            let names_offset = if self.hardware_name.is_some() {
+                (HARDWARE_FILENAME_START - 0xA000) as usize
+            } else {
+                0x1800
+            };
+            let copy_size = names.len().min(0x1FFC - names_offset); // Avoid vectors
             romh[names_offset..names_offset + copy_size].copy_from_slice(&names[..copy_size]);
         }
 
         Ok(romh)
     }
 
+    /// ROMH offset of the first data block, which the boot code must end before
+    fn code_limit(&self) -> usize {
+        if self.load_save_code.is_some() {
+            LOAD_SAVE_OFFSET
+        } else if self.metadata.is_some() {
+            0x1000
+        } else if self.hardware_name.is_some() || self.filenames.is_some() {
+            EAPI_OFFSET
+        } else {
+            0x1FFA
+        }
+    }
+
+    /// Check that the assembled boot trampoline fits the RESET code's copy loop
+    fn check_boot_trampoline_size(&self) -> Result<(), String> {
+        let trampoline = assemble_to_bytes(&format!(
+            "*=$0100\nEASYFLASH_ROML = $DE00\nEASYFLASH_CONTROL = $DE02\nboot_trampoline:\n{}",
+            self.generate_boot_trampoline_asm()
+        ))?;
+        if trampoline.len() > MAX_BOOT_TRAMPOLINE_SIZE {
+            return Err(format!(
+                "Boot trampoline is {} bytes, but the RESET code copies at most {} bytes to $0100",
+                trampoline.len(),
+                MAX_BOOT_TRAMPOLINE_SIZE
+            ));
+        }
+        Ok(())
+    }
+
     fn generate_romh_asm(&self) -> String {
         let boot_trampoline_asm = self.generate_boot_trampoline_asm();
 
@@ -104,21 +277,35 @@ EASYFLASH_CONTROL = $DE02
     RTI
 
 ; $E001: RESET entry point
+; A warm reset can leave the bank, mode and CIA/VIC-II state of the last run,
+; so everything the boot relies on is set explicitly
 start:
     SEI
     CLD
 
+    ; Bank 0, Ultimax via register instead of the boot jumper
+    LDA #$00
+    STA EASYFLASH_ROML
+    LDA #$05
+    STA EASYFLASH_CONTROL
+
     LDA #$37
     STA $00
     LDA #$37
     STA $01
 
-    LDA $DC0D
-    LDA $DD0D
+    ; Mask, stop and acknowledge the CIA interrupts
     LDA #$7F
     STA $DC0D
     STA $DD0D
     LDA #$00
+    STA $DC0E
+    STA $DC0F
+    STA $DD0E
+    STA $DD0F
+    LDA $DC0D
+    LDA $DD0D
+    LDA #$00
     STA $D01A
     LDA #$FF
     STA $D019
@@ -144,29 +331,28 @@ boot_trampoline_end:
 
 after_trampoline:
 BOOT_TRAMPOLINE_SIZE = boot_trampoline_end - boot_trampoline_code
-
-*=$FFFA
-    .word $E000    ; NMI vector
-    .word $E001    ; RESET vector
-    .word $E000    ; IRQ vector
 "#,
             boot_trampoline_asm
         )
     }
 
     fn generate_boot_trampoline_asm(&self) -> String {
-        let roml_restore_code_start = 0x8000;
+        let roml_restore_code_start = ROML_RESTORE_CODE_START;
         let src_hi = (roml_restore_code_start >> 8) & 0xFF;
         let src_lo = roml_restore_code_start & 0xFF;
         let pages = (self.restore_code_size + 255) / 256;
+        let loading_screen_asm = self
+            .loading_screen_bank
+            .map(loading_screen_asm)
+            .unwrap_or_default();
 
         format!(
             r#"    ; Trampoline @ $0100 (MINIMAL)
 
     LDA #$37
     STA $01
-
-    LDA #$00
+{}
+    LDA #${:02X}
     STA EASYFLASH_ROML
 
     LDA #$06
@@ -206,7 +392,242 @@ copy_restore_byte:
 restore_done:
     JMP $0340
 "#,
-            roml_restore_code_start, src_hi, src_lo, pages
+            loading_screen_asm, self.boot_bank, roml_restore_code_start, src_hi, src_lo, pages
         )
     }
 }
+
+/// Copy the loading screen from ROML `bank` and point the VIC-II at it
+///
+/// Runs in the trampoline with I/O visible; 4 x 250 bytes each for screen
+/// and color RAM.
+fn loading_screen_asm(bank: usize) -> String {
+    let mut copy = String::new();
+    for chunk in 0..4u16 {
+        let offset = chunk * 250;
+        copy.push_str(&format!(
+            "    LDA ${:04X},X\n    STA ${:04X},X\n    LDA ${:04X},X\n    STA ${:04X},X\n",
+            0x8000 + offset,
+            LOADING_SCREEN_ADDRESS + offset,
+            0x8000 + 1000 + offset,
+            0xD800 + offset
+        ));
+    }
+
+    format!(
+        r#"
+    ; Loading screen from ROML bank {}
+    LDA #${:02X}
+    STA EASYFLASH_ROML
+    LDA #$06
+    STA EASYFLASH_CONTROL
+
+    LDX #$00
+copy_loading_screen:
+{}    INX
+    CPX #$FA
+    BNE copy_loading_screen
+
+    ; VIC bank 0, screen ${:04X}, ROM charset, black border and background
+    LDA $DD02
+    ORA #$03
+    STA $DD02
+    LDA $DD00
+    ORA #$03
+    STA $DD00
+    LDA #${:02X}
+    STA $D018
+    LDA #$08
+    STA $D016
+    LDA #$00
+    STA $D015
+    STA $D020
+    STA $D021
+    LDA #$1B
+    STA $D011
+"#,
+        bank, bank, copy, LOADING_SCREEN_ADDRESS, LOADING_SCREEN_D018
+    )
+}
+
+/// Stub EAPI: signature, driver name and a jump table where every call fails
+///
+/// Each slot is SEC/RTS/NOP, so the stub stays valid after a program copies it
+/// to RAM and reports "no flash" (carry set) instead of touching the chips.
+fn eapi_stub() -> Vec<u8> {
+    let mut eapi = vec![0u8; EAPI_SIZE];
+    eapi[..4].copy_from_slice(&EAPI_SIGNATURE);
+
+    // Driver name, zero-terminated; uppercase ASCII is lowercase PETSCII
+    let name = b"STUB - NO FLASH";
+    eapi[4..4 + name.len()].copy_from_slice(name);
+
+    // Jump table @ +$14: Init, WriteFlash, EraseSector, SetBank, GetBank,
+    // SetPtr, SetLen, ReadFlashInc, WriteFlashInc, SetSlot, GetSlot
+    for slot in 0..11 {
+        let offset = 0x14 + slot * 3;
+        eapi[offset..offset + 3].copy_from_slice(&[0x38, 0x60, 0xEA]);
+    }
+
+    eapi
+}
+
+/// Warning for a cartridge name the EF-Name entry cuts short
+///
+/// The CRT header holds 32 characters, the menu entry only `EF_NAME_MAX_LEN`.
+pub fn ef_name_warning(name: &str) -> Option<String> {
+    (name.len() > EF_NAME_MAX_LEN).then(|| {
+        format!(
+            "Cartridge name \"{}\" is {} characters; the EasyFlash menu (EF-Name) shows only \"{}\"",
+            name,
+            name.len(),
+            String::from_utf8_lossy(&name.as_bytes()[..EF_NAME_MAX_LEN])
+        )
+    })
+}
+
+/// "EF-Name:" followed by up to `EF_NAME_MAX_LEN` PETSCII characters, zero padded
+fn ef_name_block(name: &str) -> [u8; 24] {
+    let mut block = [0u8; 24];
+    block[..8].copy_from_slice(&EF_NAME_SIGNATURE);
+    for (i, c) in name.bytes().take(EF_NAME_MAX_LEN).enumerate() {
+        block[8 + i] = match c {
+            b'a'..=b'z' => c - 0x20,
+            b'A'..=b'Z' => c + 0x80,
+            _ => c,
+        };
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hardware_layout_writes_eapi_signature() {
+        let romh = MakeROMHAsm::new(0x100, None, None, None)
+            .with_easyflash_hardware("My Game")
+            .generate_romh()
+            .unwrap();
+
+        assert_eq!(&romh[EAPI_OFFSET..EAPI_OFFSET + 4], &EAPI_SIGNATURE);
+        assert_eq!(&romh[EAPI_OFFSET + 4..EAPI_OFFSET + 20], b"STUB - NO FLASH\0");
+        assert_eq!(&romh[EAPI_OFFSET + 0x14..EAPI_OFFSET + 0x17], &[0x38, 0x60, 0xEA]);
+        assert_eq!(&romh[EF_NAME_OFFSET..EF_NAME_OFFSET + 8], &EF_NAME_SIGNATURE);
+        assert_eq!(&romh[EF_NAME_OFFSET + 8..EF_NAME_OFFSET + 10], &[0xCD, 0x59]);
+    }
+
+    #[test]
+    fn test_long_name_is_cut_with_a_warning() {
+        assert_eq!(ef_name_warning("SIXTEEN CHARS OK"), None);
+
+        let name = "A NAME LONGER THAN SIXTEEN";
+        let warning = ef_name_warning(name).unwrap();
+        assert!(warning.contains("26 characters") && warning.contains("\"A NAME LONGER TH\""), "{}", warning);
+        assert_eq!(&ef_name_block(name)[8..], b"\xC1 \xCE\xC1\xCD\xC5 \xCC\xCF\xCE\xC7\xC5\xD2 \xD4\xC8");
+    }
+
+    #[test]
+    fn test_default_layout_has_no_eapi() {
+        let romh = MakeROMHAsm::new(0x100, None, None, None)
+            .generate_romh()
+            .unwrap();
+
+        assert_eq!(&romh[EAPI_OFFSET..EAPI_OFFSET + 4], &[0u8; 4]);
+    }
+
+    #[test]
+    fn test_restore_code_must_fit_in_roml_bank_0() {
+        assert!(check_restore_code_size(BANK_SIZE_8K, 0).is_ok());
+        assert!(MakeROMHAsm::new(BANK_SIZE_8K, None, None, None).generate_romh().is_ok());
+
+        let err = MakeROMHAsm::new(BANK_SIZE_8K + 1, None, None, None)
+            .generate_romh()
+            .unwrap_err();
+        assert!(err.contains("8193 bytes"), "{}", err);
+        assert!(err.contains("ROML bank 0"), "{}", err);
+
+        let err = MakeROMHAsm::new(BANK_SIZE_8K + 1, None, None, None)
+            .with_boot_bank(2, 4)
+            .unwrap()
+            .generate_romh()
+            .unwrap_err();
+        assert!(err.contains("ROML bank 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_save_code_must_fit_its_space() {
+        let romh = MakeROMHAsm::new(0x100, Some(vec![0xEA; LOAD_SAVE_MAX_SIZE]), None, None)
+            .generate_romh()
+            .unwrap();
+        assert_eq!(romh[LOAD_SAVE_OFFSET + LOAD_SAVE_MAX_SIZE - 1], 0xEA);
+
+        let err = MakeROMHAsm::new(0x100, Some(vec![0xEA; LOAD_SAVE_MAX_SIZE + 1]), None, None)
+            .generate_romh()
+            .unwrap_err();
+        assert!(err.contains("2561 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn test_reset_selects_bank_and_mode_before_copying() {
+        let romh = MakeROMHAsm::new(0x100, None, None, None)
+            .generate_romh()
+            .unwrap();
+        let find = |pattern: &[u8]| romh.windows(pattern.len()).position(|w| w == pattern);
+
+        // RESET vector -> $E001
+        assert_eq!(&romh[0x1FFC..0x1FFE], &[0x01, 0xE0]);
+
+        // LDA #$00 / STA $DE00 / LDA #$05 / STA $DE02 first thing after SEI/CLD
+        assert_eq!(&romh[1..3], &[0x78, 0xD8]);
+        assert_eq!(&romh[3..13], &[0xA9, 0x00, 0x8D, 0x00, 0xDE, 0xA9, 0x05, 0x8D, 0x02, 0xDE]);
+
+        // CIA interrupts masked before the ICRs are read, then the stack is reset
+        let mask = find(&[0xA9, 0x7F, 0x8D, 0x0D, 0xDC]).unwrap();
+        let ack = find(&[0xAD, 0x0D, 0xDC]).unwrap();
+        let txs = find(&[0xA2, 0xFF, 0x9A]).unwrap();
+        // STA $0100,X in the trampoline copy loop
+        let copy = find(&[0x9D, 0x00, 0x01]).unwrap();
+        assert!(mask < ack && ack < txs && txs < copy);
+    }
+
+    #[test]
+    fn test_trampoline_selects_the_boot_bank() {
+        // LDA #bank / STA $DE00 / LDA #$06 / STA $DE02
+        let select = |bank: u8| [0xA9, bank, 0x8D, 0x00, 0xDE, 0xA9, 0x06, 0x8D, 0x02, 0xDE];
+        let contains = |romh: &[u8], pattern: &[u8]| romh.windows(pattern.len()).any(|w| w == pattern);
+
+        let romh = MakeROMHAsm::new(0x100, None, None, None).generate_romh().unwrap();
+        assert!(contains(&romh, &select(0x00)));
+
+        let romh = MakeROMHAsm::new(0x100, None, None, None)
+            .with_boot_bank(3, 4)
+            .unwrap()
+            .generate_romh()
+            .unwrap();
+        assert!(contains(&romh, &select(0x03)));
+        assert!(!contains(&romh, &select(0x00)));
+
+        let err = MakeROMHAsm::new(0x100, None, None, None).with_boot_bank(4, 4).err().unwrap();
+        assert!(err.contains("banks 0-3"), "{}", err);
+    }
+
+    #[test]
+    fn test_loading_screen_size_is_checked() {
+        let dir = crate::test_vsf::temp_dir("loading_screen_size");
+        let path = dir.join("screen.bin");
+
+        fs::write(&path, vec![0x20; LOADING_SCREEN_SIZE]).unwrap();
+        assert_eq!(read_loading_screen(&path).unwrap().len(), LOADING_SCREEN_SIZE);
+
+        fs::write(&path, [&[0x00, 0x04][..], &[0x20; LOADING_SCREEN_SIZE]].concat()).unwrap();
+        assert_eq!(read_loading_screen(&path).unwrap(), vec![0x20; LOADING_SCREEN_SIZE]);
+
+        fs::write(&path, vec![0x20; 1000]).unwrap();
+        let err = read_loading_screen(&path).unwrap_err();
+        assert!(err.contains("1000 bytes"), "{}", err);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
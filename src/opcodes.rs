@@ -0,0 +1,153 @@
+//! 6502 instruction encodings for generated machine code
+//!
+//! `patch_mem` assembles the restore blocks byte by byte, since their sizes
+//! and addresses are only known while RAM is being allocated. Each function
+//! returns the bytes of one instruction, so the code reads like assembler:
+//! `code.extend(op::lda_imm(0x35))`. Absolute operands are little endian.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+fn abs(opcode: u8, addr: u16) -> [u8; 3] {
+    [opcode, addr as u8, (addr >> 8) as u8]
+}
+
+/// LDA #value
+pub fn lda_imm(value: u8) -> [u8; 2] {
+    [0xA9, value]
+}
+
+/// LDX #value
+pub fn ldx_imm(value: u8) -> [u8; 2] {
+    [0xA2, value]
+}
+
+/// LDY #value
+pub fn ldy_imm(value: u8) -> [u8; 2] {
+    [0xA0, value]
+}
+
+/// LDA addr
+pub fn lda_abs(addr: u16) -> [u8; 3] {
+    abs(0xAD, addr)
+}
+
+/// LDA addr,X
+pub fn lda_abs_x(addr: u16) -> [u8; 3] {
+    abs(0xBD, addr)
+}
+
+/// STA addr
+pub fn sta_abs(addr: u16) -> [u8; 3] {
+    abs(0x8D, addr)
+}
+
+/// STA addr,X
+pub fn sta_abs_x(addr: u16) -> [u8; 3] {
+    abs(0x9D, addr)
+}
+
+/// STY addr
+pub fn sty_abs(addr: u16) -> [u8; 3] {
+    abs(0x8C, addr)
+}
+
+/// STA zp
+pub fn sta_zp(addr: u8) -> [u8; 2] {
+    [0x85, addr]
+}
+
+/// STX zp
+pub fn stx_zp(addr: u8) -> [u8; 2] {
+    [0x86, addr]
+}
+
+/// CMP #value
+pub fn cmp_imm(value: u8) -> [u8; 2] {
+    [0xC9, value]
+}
+
+/// CPX #value
+pub fn cpx_imm(value: u8) -> [u8; 2] {
+    [0xE0, value]
+}
+
+/// JMP addr
+pub fn jmp(addr: u16) -> [u8; 3] {
+    abs(0x4C, addr)
+}
+
+/// INX
+pub fn inx() -> u8 {
+    0xE8
+}
+
+/// DEX
+pub fn dex() -> u8 {
+    0xCA
+}
+
+/// PHA
+pub fn pha() -> u8 {
+    0x48
+}
+
+/// PLA
+pub fn pla() -> u8 {
+    0x68
+}
+
+/// TXS
+pub fn txs() -> u8 {
+    0x9A
+}
+
+/// RTI
+pub fn rti() -> u8 {
+    0x40
+}
+
+/// BNE to `target`, for a branch emitted at `code_len`
+pub fn bne(target: usize, code_len: usize) -> [u8; 2] {
+    [0xD0, relative(target, code_len)]
+}
+
+/// BPL to `target`, for a branch emitted at `code_len`
+pub fn bpl(target: usize, code_len: usize) -> [u8; 2] {
+    [0x10, relative(target, code_len)]
+}
+
+/// BEQ to `target`, for a branch emitted at `code_len`
+pub fn beq(target: usize, code_len: usize) -> [u8; 2] {
+    [0xF0, relative(target, code_len)]
+}
+
+/// Branch operand from the instruction at `code_len` to `target`
+///
+/// Counts from the byte after the 2-byte branch; targets must be within
+/// -128..=127 of it, which the generated loops always are.
+fn relative(target: usize, code_len: usize) -> u8 {
+    (target as isize - (code_len as isize + 2)) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_encodings() {
+        assert_eq!(lda_imm(0x35), [0xA9, 0x35]);
+        assert_eq!(sta_abs(0xD01A), [0x8D, 0x1A, 0xD0]);
+        assert_eq!(sta_abs_x(0x4000), [0x9D, 0x00, 0x40]);
+        assert_eq!(lda_abs_x(0x01E0), [0xBD, 0xE0, 0x01]);
+        assert_eq!(jmp(0x0810), [0x4C, 0x10, 0x08]);
+        assert_eq!(txs(), 0x9A);
+        assert_eq!(stx_zp(0x00), [0x86, 0x00]);
+
+        // Back to the start of a 5-byte loop body, and a branch to itself
+        assert_eq!(bne(10, 15), [0xD0, 0xF9]);
+        assert_eq!(bne(20, 20), [0xD0, 0xFE]);
+        // Forward over 7 bytes
+        assert_eq!(beq(9, 0), [0xF0, 0x07]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Checksums of converted files for publishing (CLI `--hash`)
+//!
+//! CRC32 is built in; SHA-256 needs the `sha256` feature. The PRG and CRT
+//! writers hash the bytes they write while an `OutputHashes` is active, so
+//! the hash is of exactly what went to disk without reading it back.
+//! Sidecars use the `sha256sum`/`cksum`-style line "<hex>  <file name>" so
+//! the usual tools can check them.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Read size while hashing a file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reflected CRC-32 polynomial (IEEE 802.3, as zip and PNG use it)
+const CRC32_POLY: u32 = 0xEDB8_8320;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+thread_local! {
+    /// Algorithm and hashes recorded by the active `OutputHashes`
+    static RECORDED: RefCell<Option<(HashAlgorithm, Vec<(PathBuf, String)>)>> = const { RefCell::new(None) };
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Hash algorithms for `--hash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Crc32,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 2] = [Self::Crc32, Self::Sha256];
+
+    /// Name as written on the command line, also the sidecar extension
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Crc32 => "crc32",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|algorithm| algorithm.name() == name.to_lowercase())
+    }
+
+    /// Whether this build can compute it (SHA-256 needs the `sha256` feature)
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Crc32 => true,
+            Self::Sha256 => cfg!(feature = "sha256"),
+        }
+    }
+}
+
+/// Incremental hash of a byte stream
+pub enum OutputHasher {
+    Crc32(u32),
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+}
+
+impl OutputHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Result<Self, String> {
+        match algorithm {
+            HashAlgorithm::Crc32 => Ok(Self::Crc32(0xFFFF_FFFF)),
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => Ok(Self::Sha256(<sha2::Sha256 as sha2::Digest>::new())),
+            #[cfg(not(feature = "sha256"))]
+            HashAlgorithm::Sha256 => Err("SHA-256 needs a build with --features sha256; use --hash crc32".to_string()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc32(crc) => {
+                for &byte in data {
+                    *crc = CRC32_TABLE[((*crc ^ byte as u32) & 0xFF) as usize] ^ (*crc >> 8);
+                }
+            }
+            #[cfg(feature = "sha256")]
+            Self::Sha256(hasher) => sha2::Digest::update(hasher, data),
+        }
+    }
+
+    /// Lowercase hex digest; CRC32 as 8 digits, most significant first
+    pub fn finish(self) -> String {
+        match self {
+            Self::Crc32(crc) => format!("{:08x}", !crc),
+            #[cfg(feature = "sha256")]
+            Self::Sha256(hasher) => sha2::Digest::finalize(hasher).iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+/// Hash of `data` as lowercase hex
+pub fn hash_bytes(algorithm: HashAlgorithm, data: &[u8]) -> Result<String, String> {
+    let mut hasher = OutputHasher::new(algorithm)?;
+    hasher.update(data);
+    Ok(hasher.finish())
+}
+
+/// Records the hash of every PRG/CRT written on this thread until dropped
+pub struct OutputHashes {
+    previous: Option<(HashAlgorithm, Vec<(PathBuf, String)>)>,
+}
+
+impl OutputHashes {
+    /// Start recording with `algorithm`; `None` records nothing
+    pub fn start(algorithm: Option<HashAlgorithm>) -> Self {
+        let previous = RECORDED.with(|active| active.replace(algorithm.map(|algorithm| (algorithm, Vec::new()))));
+        Self { previous }
+    }
+
+    /// (output path, hex) of everything written so far, in write order
+    pub fn take(&self) -> Vec<(PathBuf, String)> {
+        RECORDED.with(|active| match active.borrow_mut().as_mut() {
+            Some((_, hashes)) => std::mem::take(hashes),
+            None => Vec::new(),
+        })
+    }
+}
+
+impl Drop for OutputHashes {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RECORDED.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+/// Hash `data`, just written to `path`, for the active `OutputHashes`, if any
+pub fn record_output(path: &Path, data: &[u8]) -> Result<(), String> {
+    RECORDED.with(|active| {
+        let mut active = active.borrow_mut();
+        let Some((algorithm, hashes)) = active.as_mut() else {
+            return Ok(());
+        };
+        hashes.push((path.to_path_buf(), hash_bytes(*algorithm, data)?));
+        Ok(())
+    })
+}
+
+/// Hash of the file at `path`, read in chunks
+pub fn hash_file(algorithm: HashAlgorithm, path: &Path) -> Result<String, String> {
+    let read_error = |e: std::io::Error| format!("Failed to read {} for hashing: {}", path.display(), e);
+    let mut hasher = OutputHasher::new(algorithm)?;
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf).map_err(read_error)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Write `<path>.<algorithm>` with the line "<hex>  <file name>"; returns the sidecar path
+pub fn write_sidecar(algorithm: HashAlgorithm, path: &Path, hex: &str) -> Result<PathBuf, String> {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(format!(".{}", algorithm.name()));
+    let sidecar = PathBuf::from(sidecar);
+    fs::write(&sidecar, format!("{}  {}\n", hex, name))
+        .map_err(|e| format!("Failed to write {}: {}", sidecar.display(), e))?;
+    Ok(sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::temp_dir;
+
+    #[test]
+    fn test_crc32_check_value() {
+        // The standard check value for "123456789"
+        assert_eq!(hash_bytes(HashAlgorithm::Crc32, b"123456789").unwrap(), "cbf43926");
+        assert_eq!(hash_bytes(HashAlgorithm::Crc32, b"").unwrap(), "00000000");
+    }
+
+    #[test]
+    fn test_file_hash_spans_chunks_and_matches_bytes() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| (i * 31) as u8).collect();
+        let dir = temp_dir("output_hash");
+        let path = dir.join("out.crt");
+        fs::write(&path, &data).unwrap();
+
+        let hex = hash_file(HashAlgorithm::Crc32, &path).unwrap();
+        assert_eq!(hex, hash_bytes(HashAlgorithm::Crc32, &data).unwrap());
+        let sidecar = write_sidecar(HashAlgorithm::Crc32, &path, &hex).unwrap();
+        assert_eq!(sidecar, dir.join("out.crt.crc32"));
+        assert_eq!(fs::read_to_string(&sidecar).unwrap(), format!("{}  out.crt\n", hex));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_written_outputs_are_recorded_until_dropped() {
+        record_output(Path::new("ignored.prg"), b"123456789").unwrap();
+        {
+            let hashes = OutputHashes::start(Some(HashAlgorithm::Crc32));
+            record_output(Path::new("game.prg"), b"123456789").unwrap();
+            record_output(Path::new("game.crt"), b"").unwrap();
+            assert_eq!(hashes.take(), vec![
+                (PathBuf::from("game.prg"), "cbf43926".to_string()),
+                (PathBuf::from("game.crt"), "00000000".to_string()),
+            ]);
+            assert!(hashes.take().is_empty());
+        }
+        record_output(Path::new("ignored.prg"), b"123456789").unwrap();
+        assert!(OutputHashes::start(None).take().is_empty());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_sha256_known_digest() {
+        assert_eq!(
+            hash_bytes(HashAlgorithm::Sha256, b"abc").unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
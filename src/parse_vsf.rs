@@ -8,7 +8,7 @@
 #![allow(dead_code)]
 
 use std::fs;
-use std::io::{Cursor, Read, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use lzsa_sys::{compress_with_options, Options, Version, Mode, Quality};
 use crate::config::Config;
@@ -23,6 +23,9 @@ pub struct C64Snapshot {
     pub cia1: Cia6526,
     pub cia2: Cia6526,
     pub sid: Sid6581,
+    /// Base address of a second SID ($D420, $DE00, ...), `None` without one
+    /// or when neither the snapshot nor `ParserConfig::sid2_addr` gives it
+    pub sid2_addr: Option<u16>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,13 +42,88 @@ pub struct Cpu6510 {
 pub struct C64Mem {
     pub cpu_port_data: u8,
     pub cpu_port_dir: u8,
+    /// Cartridge EXROM/GAME lines as VICE stores them: true = asserted by a cartridge
+    pub exrom: bool,
+    pub game: bool,
     pub ram: Box<[u8; 65536]>,
 }
 
+impl C64Mem {
+    /// Memory mode set by a cartridge that was mapped in, if any
+    pub fn cartridge_mode(&self) -> Option<&'static str> {
+        match (self.exrom, self.game) {
+            (false, false) => None,
+            (true, false) => Some("8K"),
+            (true, true) => Some("16K"),
+            (false, true) => Some("Ultimax"),
+        }
+    }
+}
+
+/// VIC-II registers restored: $D000-$D02E. $D02F-$D03F (unused on the C64,
+/// C128 keyboard/clock registers or turbo control on other machines) are never written.
+///
+/// Every restore path writes them in address order, so the sprite X low bytes
+/// ($D000-$D00E) are in place before their bit 8 ($D010). Those 17 registers
+/// read back as written, so LZSA1 matches copied from them are exact too.
+pub const VIC_REGISTER_COUNT: usize = 47;
+/// SID registers restored: $D400-$D418 (the read-only $D419-$D41C are skipped)
+pub const SID_REGISTER_COUNT: usize = 25;
+
 #[derive(Debug, Clone)]
 pub struct VicII {
-    pub registers: [u8; 47],
+    pub registers: [u8; VIC_REGISTER_COUNT],
     pub color_ram: Box<[u8; 1024]>,
+    /// VICE chip model, only stored by the cycle-accurate VIC-II
+    pub model: Option<u8>,
+}
+
+impl VicII {
+    /// Raster compare registers ($D011, $D012) as written by the program.
+    /// Bit 7 of $D011 is bit 8 of the compare line.
+    pub fn raster_regs(&self) -> [u8; 2] {
+        [self.registers[0x11], self.registers[0x12]]
+    }
+
+    /// Position of `sprite` (0-7): X with bit 8 from $D010, and Y
+    pub fn sprite_position(&self, sprite: usize) -> (u16, u8) {
+        let low_x = self.registers[sprite * 2] as u16;
+        let high_x = (self.registers[0x10] >> sprite) as u16 & 0x01;
+        ((high_x << 8) | low_x, self.registers[sprite * 2 + 1])
+    }
+
+    /// Turn the display ($D011 DEN) and all sprites ($D015) off
+    ///
+    /// For programs that switch them on themselves, so no half-restored
+    /// frame is visible. The raster compare bit 8 in $D011 is kept.
+    pub fn blank(&mut self) {
+        self.registers[0x11] &= !0x10;
+        self.registers[0x15] = 0x00;
+    }
+
+    /// Whether the display was on ($D011 DEN); off shows only the border color
+    pub fn display_enabled(&self) -> bool {
+        self.registers[0x11] & 0x10 != 0
+    }
+
+    /// Turn the display ($D011 DEN) on, whatever the snapshot had
+    ///
+    /// Shows the screen RAM of a snapshot taken while the program had the
+    /// display off, which may not match what was on screen at that moment.
+    pub fn show_display(&mut self) {
+        self.registers[0x11] |= 0x10;
+    }
+
+    /// Video standard implied by the chip model, if the snapshot stores one
+    pub fn video_standard(&self) -> Option<VideoStandard> {
+        match self.model? {
+            // 6567, 8562, 6567R56A, 6573 (PAL-M runs at the NTSC clock)
+            3 | 4 | 5 | 7 => Some(VideoStandard::Ntsc),
+            // 6572 (Drean): PAL colors, but close to the NTSC clock
+            6 => Some(VideoStandard::PalN),
+            _ => Some(VideoStandard::Pal),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -72,13 +150,67 @@ pub struct Sid6581 {
     pub regs_25: [u8; 25],
 }
 
+/// Whether VICE can map a second SID at `addr`: $D420-$D7E0 or $DE00-$DFE0 in $20 steps
+pub fn is_sid2_address(addr: u16) -> bool {
+    addr % 0x20 == 0 && ((0xD420..=0xD7E0).contains(&addr) || (0xDE00..=0xDFE0).contains(&addr))
+}
+
+/// REU sizes VICE emulates, in KB: 1700, 1764, 1750 and the larger clones
+pub const REU_SIZES_KB: [u32; 8] = [128, 256, 512, 1024, 2048, 4096, 8192, 16384];
+
+/// RAM Expansion Unit contents from a "REU1764" module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReuImage {
+    /// REC registers $DF00-$DF0F
+    pub registers: [u8; 16],
+    /// Expansion RAM; its length is one of `REU_SIZES_KB`
+    pub ram: Vec<u8>,
+}
+
 /* ======================= Parser configuration ======================= */
 
+/// Where the restored color RAM is taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorRamSource {
+    /// Main RAM $D800-$DBFF if it looks valid, otherwise the VIC-II module
+    #[default]
+    Auto,
+    /// Always the VIC-II module's color RAM
+    VicModule,
+    /// Always main RAM $D800-$DBFF
+    MainRam,
+}
+
+/// LZSA1 compressor preference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionQuality {
+    /// Smallest output (best for tight CRT bank budgets)
+    #[default]
+    Ratio,
+    /// Faster compression, somewhat larger output (large batch jobs)
+    Fast,
+}
+
+/// LZSA1 accepts minimum match sizes of 3 to 5 bytes
+pub const MIN_MATCH_SIZES: std::ops::RangeInclusive<u8> = 3..=5;
+
 #[derive(Debug, Clone, Default)]
 pub struct ParserConfig {
+    /// VIC-II register offset in the module payload; `None` picks it by module version
     pub vic_regs_off: Option<usize>,
+    /// VIC-II color RAM offset in the module payload; `None` picks it by module version
     pub vic_color_off: Option<usize>,
+    /// SID register offset in the module payload; `None` picks it by module version
     pub sid_regs_off: Option<usize>,
+    pub color_ram_source: ColorRamSource,
+    /// Parse snapshots with an unsupported format version or machine name
+    /// anyway, with the x64sc module layout (CLI `--force`)
+    pub force: bool,
+    /// Second SID base for stereo snapshots that don't store one (CLI `--sid2-addr`)
+    pub sid2_addr: Option<u16>,
+    /// Accept a snapshot without a SID module, parsed as a silent SID (all
+    /// registers zero); `parse_import` sets it with `Config::no_sid`
+    pub optional_sid: bool,
 }
 
 impl ParserConfig {
@@ -87,6 +219,38 @@ impl ParserConfig {
     }
 }
 
+/// Creator information from the optional "VICE Version" header block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VsfMeta {
+    /// e.g. "3.7.1"
+    pub vice_version: String,
+    pub svn_revision: u32,
+}
+
+/// Bytes of one module field the parser reads into `C64Snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeledField {
+    /// Module name ("CIA1", ...)
+    pub module: String,
+    /// Field within the module ("timers", "color RAM", ...)
+    pub field: &'static str,
+    /// File offset of the first byte
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Offset of the "VICE Version" block: magic(19) + version(2) + machine(16)
+const VICE_VERSION_OFFSET: usize = 37;
+/// "VICE Version\x1A"(13) + major, minor, build, patch(4) + SVN revision(4)
+const VICE_VERSION_LEN: usize = 21;
+
+/// Offset of the first module when there is no "VICE Version" block
+const VSF_HEADER_LEN: usize = VICE_VERSION_OFFSET;
+/// Module header: name(16) + major(1) + minor(1) + size(4)
+const MODULE_HEADER_LEN: usize = 22;
+/// Modules `parse_import_with` needs; `import_streaming` skips the rest
+pub const REQUIRED_MODULES: [&str; 6] = ["MAINCPU", "C64MEM", "VIC-II", "CIA1", "CIA2", "SID"];
+
 /// Module header version (major, minor). Used to dispatch per-module layout.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct ModuleVersion {
@@ -112,14 +276,45 @@ impl Machine {
     }
 }
 
-/// Accept VSF file versions 1.1 and 2.0. Per-module layout dispatches further below.
+/// VSF file versions (major, minor) the parser accepts. Per-module layout dispatches further below.
+pub const SUPPORTED_FORMAT_VERSIONS: [(u8, u8); 2] = [(1, 1), (2, 0)];
+/// Machine names from the VSF header the parser accepts (x64 and x64sc)
+pub const SUPPORTED_MACHINES: [&str; 2] = ["C64", "C64SC"];
+
 fn check_file_version(major: u8, minor: u8) -> Result<(), String> {
-    match (major, minor) {
-        (1, 1) | (2, 0) => Ok(()),
-        _ => Err(format!(
-            "Unsupported snapshot format version {}.{}",
-            major, minor
-        )),
+    if SUPPORTED_FORMAT_VERSIONS.contains(&(major, minor)) {
+        Ok(())
+    } else {
+        Err(format!("Unsupported snapshot format version {}.{}", major, minor))
+    }
+}
+
+/// Validate the magic, file version and machine name at the start of `raw`
+///
+/// With `force`, an unsupported version or machine is accepted and parsed as
+/// x64sc; the magic and header length are still required.
+fn check_header(raw: &[u8], force: bool) -> Result<Machine, String> {
+    let magic = raw.get(..19).unwrap_or(raw);
+    if !vsf_magic_ok(magic) {
+        let hint = sniff_compression_prefix(magic)
+            .map(|c| format!(" (looks like {}-compressed; decompress first)", c))
+            .unwrap_or_default();
+        return Err(format!("Not a VSF file{}", hint));
+    }
+
+    let (vmaj, vmin) = match raw.get(19..21) {
+        Some(&[vmaj, vmin]) => (vmaj, vmin),
+        _ => return Err("VSF header truncated".to_string()),
+    };
+    if !force {
+        check_file_version(vmaj, vmin)?;
+    }
+
+    let mach = trim_nul(raw.get(21..VSF_HEADER_LEN).ok_or("VSF header truncated")?);
+    match Machine::from_name(mach) {
+        Some(machine) => Ok(machine),
+        None if force => Ok(Machine::C64Sc),
+        None => Err(format!("Unsupported machine type '{}'", mach)),
     }
 }
 
@@ -141,36 +336,204 @@ impl ParseVSF {
         })
     }
 
-    pub fn parse_import(&self) -> Result<C64Snapshot, String> {
-        self.parse_import_with(&ParserConfig::default_vice_like())
+    /// Like `import`, but only buffers the header and the C64 modules
+    ///
+    /// Other modules (drives, REU, ...) are skipped with a seek, so memory
+    /// stays bounded however large they are. Malformed module headers stop
+    /// the scan and the rest of the file is kept so `parse_import` reports
+    /// the same errors as with `import`.
+    pub fn import_streaming(file_path: &str, config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = fs::File::open(file_path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut raw = Vec::new();
+        (&mut file).take(VSF_HEADER_LEN as u64).read_to_end(&mut raw)?;
+
+        if raw.len() == VSF_HEADER_LEN && vsf_magic_ok(&raw[..19]) {
+            // Optional "VICE Version" block
+            let mut version = Vec::new();
+            (&mut file).take(VICE_VERSION_LEN as u64).read_to_end(&mut version)?;
+            if version.starts_with(b"VICE Version") {
+                raw.extend_from_slice(&version);
+            } else {
+                file.seek(SeekFrom::Start(VSF_HEADER_LEN as u64))?;
+            }
+
+            loop {
+                let pos = file.stream_position()?;
+                if pos >= file_len {
+                    break;
+                }
+
+                let mut header = [0u8; MODULE_HEADER_LEN];
+                let size = match file.read_exact(&mut header) {
+                    Ok(()) => u32::from_le_bytes([header[18], header[19], header[20], header[21]]) as u64,
+                    Err(_) => 0,
+                };
+                if size < MODULE_HEADER_LEN as u64 || pos + size > file_len {
+                    // Keep the remainder for the parser to reject
+                    file.seek(SeekFrom::Start(pos))?;
+                    file.read_to_end(&mut raw)?;
+                    break;
+                }
+
+                let payload_len = size - MODULE_HEADER_LEN as u64;
+                if REQUIRED_MODULES.contains(&trim_nul(&header[..16])) {
+                    raw.extend_from_slice(&header);
+                    (&mut file).take(payload_len).read_to_end(&mut raw)?;
+                } else {
+                    file.seek(SeekFrom::Current(payload_len as i64))?;
+                }
+            }
+        } else {
+            file.read_to_end(&mut raw)?;
+        }
+
+        Ok(Self {
+            raw,
+            file_path: file_path.to_string(),
+            config: config.clone(),
+        })
     }
 
-    pub fn parse_import_with(&self, cfg: &ParserConfig) -> Result<C64Snapshot, String> {
-        let mut cur = Cursor::new(self.raw.as_slice());
+    /// REU contents of the snapshot at `file_path`, or `None` without a REU module
+    ///
+    /// Reads the file directly: `import_streaming` skips the module, and up to
+    /// 16 MB of expansion RAM shouldn't be buffered with the C64 modules.
+    pub fn read_reu(file_path: &str) -> Result<Option<ReuImage>, String> {
+        let read = |e: std::io::Error| format!("Failed to read {}: {}", file_path, e);
+        let mut file = fs::File::open(file_path).map_err(read)?;
+        let file_len = file.metadata().map_err(read)?.len();
+
+        let mut header = [0u8; VSF_HEADER_LEN + 12];
+        if file.read_exact(&mut header).is_err() || !vsf_magic_ok(&header[..19]) {
+            return Err("Not a VSF file".to_string());
+        }
+        let mut pos = VSF_HEADER_LEN as u64;
+        if &header[VSF_HEADER_LEN..] == b"VICE Version" {
+            pos += VICE_VERSION_LEN as u64;
+        }
+
+        while pos + MODULE_HEADER_LEN as u64 <= file_len {
+            let mut module = [0u8; MODULE_HEADER_LEN];
+            file.seek(SeekFrom::Start(pos)).map_err(read)?;
+            file.read_exact(&mut module).map_err(read)?;
+            let size = u32::from_le_bytes([module[18], module[19], module[20], module[21]]) as u64;
+            if size < MODULE_HEADER_LEN as u64 || pos + size > file_len {
+                return Err(format!("Module '{}' size corrupt", trim_nul(&module[..16])));
+            }
 
-        // Read and validate VSF magic header (19 bytes: "VICE Snapshot File\x1A")
-        let magic = read_fixed(&mut cur, 19)?;
-        if !vsf_magic_ok(&magic) {
-            let hint = sniff_compression_prefix(&magic)
-                .map(|c| format!(" (looks like {}-compressed; decompress first)", c))
-                .unwrap_or_default();
-            return Err(format!("Not a VSF file{}", hint));
+            if trim_nul(&module[..16]) == "REU1764" {
+                let mut payload = Vec::new();
+                (&mut file).take(size - MODULE_HEADER_LEN as u64).read_to_end(&mut payload).map_err(read)?;
+                return parse_reu(&payload).map(Some);
+            }
+            pos += size;
         }
+        Ok(None)
+    }
 
-        let vmaj = read_u8(&mut cur)?;
-        let vmin = read_u8(&mut cur)?;
+    /// Number of `SID` modules; a stereo or triple-SID setup has more than one
+    ///
+    /// Only the first is restored (to $D400). The second SID's base comes
+    /// from the primary module when VICE stored it there, otherwise from
+    /// `ParserConfig::sid2_addr`; see `C64Snapshot::sid2_addr`.
+    pub fn sid_module_count(&self) -> usize {
+        self.module_names().iter().filter(|name| *name == "SID").count()
+    }
 
-        check_file_version(vmaj, vmin)?;
+    /// Names of the modules in file order, from their headers only
+    ///
+    /// Stops at the first corrupt module size. After `import_streaming` only
+    /// the `REQUIRED_MODULES` are listed.
+    pub fn module_names(&self) -> Vec<String> {
+        let mut pos = VSF_HEADER_LEN;
+        if self.raw.get(pos..pos + 12) == Some(b"VICE Version") {
+            pos += VICE_VERSION_LEN;
+        }
+
+        let mut names = Vec::new();
+        while let Some(header) = self.raw.get(pos..pos + MODULE_HEADER_LEN) {
+            names.push(trim_nul(&header[..16]).to_string());
+            let size = u32::from_le_bytes([header[18], header[19], header[20], header[21]]) as usize;
+            if size < MODULE_HEADER_LEN {
+                break;
+            }
+            pos += size;
+        }
+        names
+    }
+
+    /// Module bytes `parse_import_with(cfg)` reads, field by field
+    pub fn modeled_fields(&self, cfg: &ParserConfig) -> Vec<ModeledField> {
+        modeled_fields(&self.raw, cfg)
+    }
+
+    /// Snapshot format version (major, minor) from the header
+    pub fn format_version(&self) -> Option<(u8, u8)> {
+        match self.raw.get(19..21)? {
+            &[major, minor] => Some((major, minor)),
+            _ => None,
+        }
+    }
+
+    /// Machine name from the snapshot header ("C64", "C64SC", ...)
+    pub fn machine_name(&self) -> Option<String> {
+        if !vsf_magic_ok(self.raw.get(..19)?) {
+            return None;
+        }
+        Some(trim_nul(self.raw.get(21..VICE_VERSION_OFFSET)?).to_string())
+    }
+
+    /// VICE version and SVN revision that wrote the snapshot
+    ///
+    /// Informational only; `None` for snapshots from VICE builds that predate
+    /// the version block. Conversion never depends on it.
+    pub fn metadata(&self) -> Option<VsfMeta> {
+        let block = self.raw.get(VICE_VERSION_OFFSET..VICE_VERSION_OFFSET + VICE_VERSION_LEN)?;
+        if &block[..12] != b"VICE Version" {
+            return None;
+        }
+
+        let v = &block[13..17];
+        let mut vice_version = format!("{}.{}.{}", v[0], v[1], v[2]);
+        if v[3] != 0 {
+            vice_version.push_str(&format!(".{}", v[3]));
+        }
+        let svn_revision = u32::from_le_bytes([block[17], block[18], block[19], block[20]]);
+
+        Some(VsfMeta { vice_version, svn_revision })
+    }
 
-        let mach = trim_nul(&read_fixed(&mut cur, 16)?).to_string();
+    /// Check only the VSF header (magic, format version, machine) without parsing modules
+    ///
+    /// Returns the same error `parse_import` would give for the header, so a
+    /// GUI or batch run can reject a file from its first bytes.
+    pub fn is_supported(raw: &[u8]) -> Result<(), String> {
+        check_header(raw, false).map(|_| ())
+    }
+
+    /// Parse with the `Config::parser` options the VSF was imported with
+    ///
+    /// With `Config::no_sid` the SID isn't restored, so a snapshot without a
+    /// SID module (no SID configured in VICE) is accepted as well.
+    pub fn parse_import(&self) -> Result<C64Snapshot, String> {
+        if self.config.no_sid {
+            return self.parse_import_with(&ParserConfig { optional_sid: true, ..self.config.parser.clone() });
+        }
+        self.parse_import_with(&self.config.parser)
+    }
 
-        let machine = Machine::from_name(&mach)
-            .ok_or_else(|| format!("Unsupported machine type '{}'", mach))?;
+    pub fn parse_import_with(&self, cfg: &ParserConfig) -> Result<C64Snapshot, String> {
+        // Magic "VICE Snapshot File\x1A"(19) + version(2) + machine(16)
+        let machine = check_header(&self.raw, cfg.force)?;
+        let mut cur = Cursor::new(self.raw.as_slice());
+        cur.set_position(VSF_HEADER_LEN as u64);
 
         // Newer snapshots insert a 21-byte "VICE Version" block here; older ones don't.
         let pos = cur.position() as usize;
         if self.raw.get(pos..pos + 12) == Some(b"VICE Version") {
-            let _ = read_fixed(&mut cur, 21)?;
+            let _ = read_fixed(&mut cur, VICE_VERSION_LEN)?;
         }
 
         let mut cpu: Option<Cpu6510> = None;
@@ -179,6 +542,8 @@ impl ParseVSF {
         let mut cia1: Option<Cia6526> = None;
         let mut cia2: Option<Cia6526> = None;
         let mut sid: Option<Sid6581> = None;
+        let mut sid_modules = 0;
+        let mut sid2_addr: Option<u16> = None;
 
         // Parse all modules (each has: name(16), major(1), minor(1), size(4), payload(size-22))
         while (cur.position() as usize) < self.raw.len() {
@@ -210,9 +575,17 @@ impl ParseVSF {
                 "MAINCPU" => cpu = Some(parse_cpu(payload, mver)?),
                 "C64MEM" => mem = Some(parse_memory(payload, mver)?),
                 "VIC-II" => vic = Some(parse_vic(payload, cfg, machine, mver)?),
-                "CIA1" => cia1 = Some(parse_cia(payload)?),
+                "CIA1" => cia1 = Some(release_keyboard_rows(parse_cia(payload)?)),
                 "CIA2" => cia2 = Some(parse_cia(payload)?),
-                "SID" => sid = Some(parse_sid(payload, cfg, mver)?),
+                "SID" => {
+                    sid_modules += 1;
+                    // Stereo setups: keep the first SID (see `sid_module_count`)
+                    if sid.is_none() {
+                        sid = Some(parse_sid(payload, cfg, mver)?);
+                        sid_modules = sid_modules.max(declared_sids(payload, mver));
+                        sid2_addr = parse_sid2_addr(payload, cfg, mver);
+                    }
+                }
                 _ => {}  // Ignore unknown modules (e.g. DRIVE, PRINTER)
             }
         }
@@ -224,19 +597,32 @@ impl ParseVSF {
         let mut vic = vic.ok_or_else(|| "VIC-II missing".to_string())?;
         let cia1 = cia1.ok_or_else(|| "CIA1 missing".to_string())?;
         let cia2 = cia2.ok_or_else(|| "CIA2 missing".to_string())?;
-        let sid = sid.ok_or_else(|| "SID missing".to_string())?;
+        let sid = match sid {
+            Some(sid) => sid,
+            None if cfg.optional_sid => Sid6581 { regs_25: [0u8; SID_REGISTER_COUNT] },
+            None => return Err("SID missing; convert without the SID restore (--no-sid) to accept it".to_string()),
+        };
+        let sid2_addr = sid2_addr.or(cfg.sid2_addr).filter(|_| sid_modules > 1);
 
         // Extract Color RAM from main memory ($D800-$DBFF) instead of VIC module
         // The VIC module's color RAM is often unreliable, but main RAM $D800-$DBFF
         // contains the actual color RAM values that were active during snapshot
         let color_slice = &mem.ram[0xD800..=0xDBFF];
 
-        // Validate color RAM data quality (should be 4-bit values in low nibble)
-        let all_low_nibble = color_slice.iter().all(|&b| (b & 0xF0) == 0);
-        let count_0 = color_slice.iter().filter(|&&b| b == 0x00).count();
+        let use_main_ram = match cfg.color_ram_source {
+            ColorRamSource::VicModule => false,
+            ColorRamSource::MainRam => true,
+            ColorRamSource::Auto => {
+                // Validate color RAM data quality (should be 4-bit values in low nibble)
+                let all_low_nibble = color_slice.iter().all(|&b| (b & 0xF0) == 0);
+                let count_0 = color_slice.iter().filter(|&&b| b == 0x00).count();
 
-        // Only use main memory color RAM if it looks valid (mostly non-zero, low nibble only)
-        if all_low_nibble && count_0 < 900 {
+                // Only use main memory color RAM if it looks valid (mostly non-zero, low nibble only)
+                all_low_nibble && count_0 < 900
+            }
+        };
+
+        if use_main_ram {
             vic.color_ram = Box::new(
                 color_slice.try_into()
                     .map_err(|_| "Color RAM slice conversion error".to_string())?
@@ -250,6 +636,7 @@ impl ParseVSF {
             cia1,
             cia2,
             sid,
+            sid2_addr,
         })
     }
     
@@ -276,11 +663,19 @@ impl ParseVSF {
         let mut color_file = fs::File::create(&color_path)?;
         color_file.write_all(&snap.vic.color_ram[..])?;
 
+        // $00/$01 are the CPU port, set by the restore code; the RAM beneath
+        // them can't be written by the CPU and is left out
         let mut zp_file = fs::File::create(&zp_path)?;
         zp_file.write_all(&snap.mem.ram[0x02..=0xF7])?;
 
+        // Safe order: the loaders write these registers before RAM is back, so
+        // display and sprites stay off until the final restore code enables them
+        let mut vic = snap.vic.clone();
+        if self.config.vic_restore_order == VicRestoreOrder::Safe {
+            vic.blank();
+        }
         let mut vic_file = fs::File::create(&vic_path)?;
-        vic_file.write_all(&snap.vic.registers)?;
+        vic_file.write_all(&vic.registers)?;
 
         let mut sid_file = fs::File::create(&sid_path)?;
         sid_file.write_all(&snap.sid.regs_25)?;
@@ -338,22 +733,104 @@ impl ParseVSF {
 
     pub fn compress_lzsa(&self, in_path: &str, out_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let input_data = fs::read(in_path)?;
+        let compressed = compress_lzsa1(&input_data, self.config.compression, self.config.min_match_size)?;
+        fs::write(out_path, &compressed)?;
 
-        // Configure LZSA1 with raw mode (no frame header)
-        let options = Options {
-            version: Version::V1,
-            mode: Mode::RawForward,
-            quality: Quality::Ratio,
-            min_match_size: 3,
-        };
+        Ok(())
+    }
+}
 
-        let compressed = compress_with_options(&input_data, &options)
-            .map_err(|e| format!("LZSA compression failed: {}", e))?;
+/// Compress `data` as raw LZSA1 (no frame header)
+pub fn compress_lzsa1(data: &[u8], quality: CompressionQuality, min_match_size: u8) -> Result<Vec<u8>, String> {
+    if !MIN_MATCH_SIZES.contains(&min_match_size) {
+        return Err(format!(
+            "LZSA1 minimum match size must be {}-{}, got {}",
+            MIN_MATCH_SIZES.start(),
+            MIN_MATCH_SIZES.end(),
+            min_match_size
+        ));
+    }
 
-        fs::write(out_path, &compressed)?;
+    let options = Options {
+        version: Version::V1,
+        mode: Mode::RawForward,
+        quality: match quality {
+            CompressionQuality::Ratio => Quality::Ratio,
+            CompressionQuality::Fast => Quality::Speed,
+        },
+        min_match_size: min_match_size as _,
+    };
 
-        Ok(())
+    compress_with_options(data, &options).map_err(|e| format!("LZSA compression failed: {}", e))
+}
+
+/// Module bytes the parser reads from the VSF image `raw`, field by field
+///
+/// Covers the `REQUIRED_MODULES` (the first SID only) at the offsets
+/// `parse_import_with(cfg)` uses. Fields past the end of a short module are
+/// left out; the parser reports those modules as errors.
+pub fn modeled_fields(raw: &[u8], cfg: &ParserConfig) -> Vec<ModeledField> {
+    let machine = check_header(raw, true).unwrap_or(Machine::C64Sc);
+    let mut pos = VSF_HEADER_LEN;
+    if raw.get(pos..pos + 12) == Some(b"VICE Version") {
+        pos += VICE_VERSION_LEN;
+    }
+
+    let mut fields = Vec::new();
+    let mut sid_seen = false;
+    while let Some(header) = raw.get(pos..pos + MODULE_HEADER_LEN) {
+        let name = trim_nul(&header[..16]).to_string();
+        let mver = ModuleVersion { major: header[16], minor: header[17] };
+        let size = u32::from_le_bytes([header[18], header[19], header[20], header[21]]) as usize;
+        if size < MODULE_HEADER_LEN {
+            break;
+        }
+        let start = pos + MODULE_HEADER_LEN;
+        let payload = &raw[start..(pos + size).min(raw.len())];
+        pos += size;
+
+        let ranges: Vec<(&'static str, usize, usize)> = match name.as_str() {
+            "MAINCPU" => {
+                let clock_size = if mver.minor >= 3 { 8 } else { 4 };
+                vec![("registers", clock_size, 7)]
+            }
+            "C64MEM" => vec![("CPU port", 0, 2), ("EXROM/GAME", 2, 2), ("RAM", 4, 65536)],
+            "VIC-II" => {
+                let (regs_off, color_off) = vic_layout(cfg, machine, mver);
+                let mut ranges = vec![("registers", regs_off, VIC_REGISTER_COUNT), ("color RAM", color_off, 1024)];
+                if machine == Machine::C64Sc {
+                    ranges.insert(0, ("model", 0, 1));
+                }
+                ranges
+            }
+            "CIA1" | "CIA2" => vec![
+                ("ports", 0, 4),
+                ("timers", 4, 4),
+                ("TOD", 8, 4),
+                ("interrupt mask", 13, 1),
+                ("control", 14, 2),
+                ("latches", 16, 4),
+            ],
+            "SID" if !sid_seen => {
+                sid_seen = true;
+                match sid_regs_offset(cfg, mver, payload.len()) {
+                    Ok(Some(offset)) if declared_sids(payload, mver) >= 2 => {
+                        vec![("registers", offset, SID_REGISTER_COUNT), ("second SID address", offset + 32, 2)]
+                    }
+                    Ok(Some(offset)) => vec![("registers", offset, SID_REGISTER_COUNT)],
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        for (field, offset, len) in ranges {
+            if let Some(bytes) = payload.get(offset..offset + len) {
+                fields.push(ModeledField { module: name.clone(), field, offset: start + offset, bytes: bytes.to_vec() });
+            }
+        }
     }
+    fields
 }
 
 /* ======================= Module parsers ======================= */
@@ -376,39 +853,65 @@ fn parse_cpu(payload: &[u8], mver: ModuleVersion) -> Result<Cpu6510, String> {
     Ok(Cpu6510 { a, x, y, sp, pc, p })
 }
 
-fn parse_memory(payload: &[u8], _mver: ModuleVersion) -> Result<C64Mem, String> {
+fn parse_memory(payload: &[u8], mver: ModuleVersion) -> Result<C64Mem, String> {
     // C64MEM prefix (shared across 0.0 and 0.1):
     //   cpu_port_data(1), cpu_port_dir(1), exrom(1), game(1), ram(65536)
     // The 0.1 tail (pport bit6/bit7 decay) is skipped.
+    // VICE has no compressed C64MEM layout (it compresses whole files), so a
+    // short payload is named rather than guessed at.
     if payload.len() < 4 + 65536 {
-        return Err("C64MEM too short".to_string());
+        let hint = sniff_compression_prefix(payload.get(4..).unwrap_or_default())
+            .map(|c| format!("; the RAM looks {}-compressed, which is not supported", c))
+            .unwrap_or_default();
+        return Err(format!(
+            "C64MEM {}.{} too short: {} bytes, expected {} (flat 64K RAM){}",
+            mver.major,
+            mver.minor,
+            payload.len(),
+            4 + 65536,
+            hint
+        ));
     }
 
     let mut c = Cursor::new(payload);
     let cpu_port_data = read_u8(&mut c)?;
     let cpu_port_dir = read_u8(&mut c)?;
-    let _exrom = read_u8(&mut c)?;
-    let _game = read_u8(&mut c)?;
+    let exrom = read_u8(&mut c)? != 0;
+    let game = read_u8(&mut c)? != 0;
 
     let ram_vec = read_fixed(&mut c, 65536)?;
     let ram_array: [u8; 65536] = ram_vec.try_into()
         .map_err(|_| "RAM size mismatch".to_string())?;
     let ram = Box::new(ram_array);
 
-    Ok(C64Mem { cpu_port_data, cpu_port_dir, ram })
+    Ok(C64Mem { cpu_port_data, cpu_port_dir, exrom, game, ram })
 }
 
-fn parse_vic(payload: &[u8], _cfg: &ParserConfig, machine: Machine, mver: ModuleVersion) -> Result<VicII, String> {
-    let (regs_off, color_off) = match machine {
+/// Register and color RAM offsets in a VIC-II payload, by machine and module version
+fn vic_offsets(machine: Machine, mver: ModuleVersion) -> (usize, usize) {
+    match machine {
         // Cycle-accurate VIC-II: model(1), regs(0x40), ..., cram(0x400).
-        // cram offset depends on trigger_cycle width (32-bit vs CLOCK).
-        Machine::C64Sc => (1usize, if mver.minor >= 3 { 761 } else { 757 }),
+        // cram offset depends on trigger_cycle width:
+        //   1.0-1.2: 32-bit trigger cycle                          cram@757
+        //   1.3+:    CLOCK (64-bit) trigger cycle                  cram@761
+        Machine::C64Sc => (1, if mver.minor >= 3 { 761 } else { 757 }),
 
         // Non-cycle-accurate VIC-II: flags+cbuf first, then cram, then regs.
-        Machine::C64 => (1119usize, 43usize),
-    };
+        Machine::C64 => (1119, 43),
+    }
+}
+
+/// VIC-II offsets `parse_vic` uses: `vic_offsets` with the `ParserConfig` overrides applied
+fn vic_layout(cfg: &ParserConfig, machine: Machine, mver: ModuleVersion) -> (usize, usize) {
+    // ParserConfig overrides win over the version table, for layouts it doesn't know
+    let (regs_off, color_off) = vic_offsets(machine, mver);
+    (cfg.vic_regs_off.unwrap_or(regs_off), cfg.vic_color_off.unwrap_or(color_off))
+}
 
-    if payload.len() < regs_off + 47 {
+fn parse_vic(payload: &[u8], cfg: &ParserConfig, machine: Machine, mver: ModuleVersion) -> Result<VicII, String> {
+    let (regs_off, color_off) = vic_layout(cfg, machine, mver);
+
+    if payload.len() < regs_off + VIC_REGISTER_COUNT {
         return Err(format!(
             "VIC-II {}.{} too small for registers ({} bytes)",
             mver.major, mver.minor, payload.len()
@@ -425,13 +928,19 @@ fn parse_vic(payload: &[u8], _cfg: &ParserConfig, machine: Machine, mver: Module
         .try_into()
         .map_err(|_| "Color RAM slice error".to_string())?;
 
-    let registers: [u8; 47] = payload[regs_off..regs_off + 47]
+    let registers: [u8; VIC_REGISTER_COUNT] = payload[regs_off..regs_off + VIC_REGISTER_COUNT]
         .try_into()
         .map_err(|_| "VIC regs slice error".to_string())?;
 
+    let model = match machine {
+        Machine::C64Sc => Some(payload[0]),
+        Machine::C64 => None,
+    };
+
     Ok(VicII {
         registers,
         color_ram: Box::new(color_ram_array),
+        model,
     })
 }
 
@@ -455,14 +964,11 @@ fn parse_cia(payload: &[u8]) -> Result<Cia6526, String> {
     let tal = read_u16(&mut c)?;
     let tbl = read_u16(&mut c)?;
 
-    // Fix PRB if zero (key pressed during snapshot)
-    let orb_fixed = if orb == 0x00 { 0xFF } else { orb };
-
     Ok(Cia6526 {
         ddra,
         ddrb,
         ora,
-        orb: orb_fixed,
+        orb,
         tac,
         tbc,
         tal,
@@ -477,13 +983,64 @@ fn parse_cia(payload: &[u8]) -> Result<Cia6526, String> {
     })
 }
 
-fn parse_sid(payload: &[u8], _cfg: &ParserConfig, mver: ModuleVersion) -> Result<Sid6581, String> {
+/// Release keyboard rows that CIA1 port B would hold low after the restore
+///
+/// Port B only reaches the keyboard matrix through bits set in DDRB. With
+/// port B as input (the usual scan: columns out on port A, rows in on B) the
+/// latch drives nothing and is kept as saved, mid-scan or not. Only when both
+/// ports drive the matrix do zero output bits on B look like a held key to
+/// the program; those bits are set high.
+pub(crate) fn release_keyboard_rows(mut cia: Cia6526) -> Cia6526 {
+    let rows_held_low = cia.ddrb & !cia.orb;
+    if cia.ddra != 0x00 && rows_held_low != 0 {
+        cia.orb |= rows_held_low;
+    }
+    cia
+}
+
+fn parse_reu(payload: &[u8]) -> Result<ReuImage, String> {
+    // REU1764 0.0: size in KB(4), registers(16), RAM(size)
+    if payload.len() < 20 {
+        return Err(format!("REU payload too short (len {})", payload.len()));
+    }
+    let size_kb = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    if !REU_SIZES_KB.contains(&size_kb) {
+        return Err(format!("Unsupported REU size {} KB (expected 128 KB to 16 MB)", size_kb));
+    }
+
+    let ram_len = size_kb as usize * 1024;
+    let ram = payload.get(20..20 + ram_len)
+        .ok_or_else(|| format!("REU payload too short for {} KB of RAM (len {})", size_kb, payload.len()))?;
+
+    let mut registers = [0u8; 16];
+    registers.copy_from_slice(&payload[4..20]);
+    Ok(ReuImage { registers, ram: ram.to_vec() })
+}
+
+/// Register offset in a primary SID payload of `len` bytes; `None` for a
+/// 1.0 "sound off" module without register data
+fn sid_regs_offset(cfg: &ParserConfig, mver: ModuleVersion, len: usize) -> Result<Option<usize>, String> {
     // Primary SID module layout by minor version:
     //   1.0:    sound(1), engine(1), siddata(32)                       regs@2
     //           (or payload_len==1 for "sound off", or bare 32)
     //   1.1-3:  sids(1), sound(1), engine(1), siddata(32)              regs@3
     //   1.4+:   sids(1), sound(1), engine(1), model(1), siddata(32)    regs@4
 
+    // A ParserConfig override wins over the version table, like for the VIC-II
+    Ok(match (cfg.sid_regs_off, mver.major, mver.minor) {
+        (Some(offset), _, _) => Some(offset),
+        (None, 1, 0) => match len {
+            1 => None,               // sound off, no register data
+            n if n >= 34 => Some(2), // sound, engine, siddata
+            n if n >= 32 => Some(0), // bare siddata fallback
+            _ => return Err(format!("SID 1.0 payload too short (len {})", len)),
+        },
+        (None, 1, 1) | (None, 1, 2) | (None, 1, 3) => Some(3),
+        _ => Some(4),
+    })
+}
+
+fn parse_sid(payload: &[u8], cfg: &ParserConfig, mver: ModuleVersion) -> Result<Sid6581, String> {
     let slice_regs = |offset: usize| -> Result<[u8; 25], String> {
         if payload.len() < offset + 25 {
             return Err(format!(
@@ -496,20 +1053,37 @@ fn parse_sid(payload: &[u8], _cfg: &ParserConfig, mver: ModuleVersion) -> Result
             .map_err(|_| "SID regs slice error".to_string())
     };
 
-    let regs_25: [u8; 25] = match (mver.major, mver.minor) {
-        (1, 0) => match payload.len() {
-            1 => [0u8; 25],                 // sound off, no register data
-            n if n >= 34 => slice_regs(2)?, // sound, engine, siddata
-            n if n >= 32 => slice_regs(0)?, // bare siddata fallback
-            _ => return Err(format!("SID 1.0 payload too short (len {})", payload.len())),
-        },
-        (1, 1) | (1, 2) | (1, 3) => slice_regs(3)?,
-        _ => slice_regs(4)?,
+    let regs_25: [u8; 25] = match sid_regs_offset(cfg, mver, payload.len())? {
+        Some(offset) => slice_regs(offset)?,
+        None => [0u8; 25],
     };
 
     Ok(Sid6581 { regs_25 })
 }
 
+/// SIDs the primary module says are configured; its `sids` byte exists from module 1.1 on
+fn declared_sids(payload: &[u8], mver: ModuleVersion) -> usize {
+    match (mver.major, mver.minor) {
+        (1, 0) => 1,
+        _ => payload.first().map_or(1, |&sids| sids as usize),
+    }
+}
+
+/// Second SID base from the primary SID payload, if it stores a usable one
+///
+/// With two or more SIDs configured, VICE writes SidStereoAddressStart as a
+/// little-endian word after the primary SID's 32-byte register file.
+/// Addresses a second SID can't be mapped at are ignored.
+fn parse_sid2_addr(payload: &[u8], cfg: &ParserConfig, mver: ModuleVersion) -> Option<u16> {
+    if declared_sids(payload, mver) < 2 {
+        return None;
+    }
+    let regs_off = sid_regs_offset(cfg, mver, payload.len()).ok()??;
+    let word = payload.get(regs_off + 32..regs_off + 34)?;
+    let addr = u16::from_le_bytes([word[0], word[1]]);
+    is_sid2_address(addr).then_some(addr)
+}
+
 /* ======================= Validation ======================= */
 
 fn validate_cpu(_c: &Cpu6510) -> Result<(), String> {
@@ -533,6 +1107,76 @@ pub trait CpuControl {
 const CIA1_BASE: u16 = 0xDC00;
 const CIA2_BASE: u16 = 0xDD00;
 
+/// Order in which the VIC-II is brought back on restore
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VicRestoreOrder {
+    /// All registers in index order before memory (original behavior)
+    #[default]
+    Naive,
+    /// Display and sprites stay off until memory and color RAM are restored
+    Safe,
+}
+
+/// Video standard, used to retime CIA timers between PAL and NTSC machines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoStandard {
+    Pal,
+    Ntsc,
+    /// PAL-N (6572, Argentinian Drean C64); only detected, not a `--retime` target
+    PalN,
+}
+
+impl VideoStandard {
+    /// CPU clock in Hz
+    pub fn clock_hz(self) -> u64 {
+        match self {
+            VideoStandard::Pal => 985_248,
+            VideoStandard::Ntsc => 1_022_727,
+            VideoStandard::PalN => 1_023_440,
+        }
+    }
+
+    /// Display name ("PAL", "NTSC", "PAL-N")
+    pub fn name(self) -> &'static str {
+        match self {
+            VideoStandard::Pal => "PAL",
+            VideoStandard::Ntsc => "NTSC",
+            VideoStandard::PalN => "PAL-N",
+        }
+    }
+}
+
+/// Scale a timer value so it keeps the same period in seconds on another clock
+fn retime_timer(value: u16, from: VideoStandard, to: VideoStandard) -> u16 {
+    let scaled = (value as u64 * to.clock_hz() + from.clock_hz() / 2) / from.clock_hz();
+    scaled.min(0xFFFF) as u16
+}
+
+/// Rescale Timer A (and optionally Timer B) latch and counter for another clock
+pub fn retime_cia(cia: &mut Cia6526, from: VideoStandard, to: VideoStandard, timer_b: bool) {
+    cia.tal = retime_timer(cia.tal, from, to);
+    cia.tac = retime_timer(cia.tac, from, to);
+    if timer_b {
+        cia.tbl = retime_timer(cia.tbl, from, to);
+        cia.tbc = retime_timer(cia.tbc, from, to);
+    }
+}
+
+impl C64Snapshot {
+    /// Retime CIA1 for the target standard
+    ///
+    /// Snapshots without a VIC-II model are assumed to be PAL. Returns the
+    /// detected standard when timers were changed, `None` if it already matched.
+    pub fn retime(&mut self, target: VideoStandard, timer_b: bool) -> Option<VideoStandard> {
+        let from = self.vic.video_standard().unwrap_or(VideoStandard::Pal);
+        if from == target {
+            return None;
+        }
+        retime_cia(&mut self.cia1, from, target, timer_b);
+        Some(from)
+    }
+}
+
 pub fn restore_cia(b: &mut impl Bus, base: u16, s: &Cia6526) {
     b.write8(base + 0x0E, 0x00);
     b.write8(base + 0x0F, 0x00);
@@ -568,6 +1212,19 @@ pub fn restore_vic(b: &mut impl Bus, v: &VicII) {
     }
 }
 
+/// Like `restore_vic`, but with the display ($D011 DEN) and sprites ($D015) off
+pub fn restore_vic_blanked(b: &mut impl Bus, v: &VicII) {
+    let mut blanked = v.clone();
+    blanked.blank();
+    restore_vic(b, &blanked);
+}
+
+/// Enable sprites and display with the snapshot values (after `restore_vic_blanked`)
+pub fn restore_vic_display(b: &mut impl Bus, v: &VicII) {
+    b.write8(0xD015, v.registers[0x15]);
+    b.write8(0xD011, v.registers[0x11]);
+}
+
 pub fn restore_sid(b: &mut impl Bus, sid: &Sid6581) {
     let base = 0xD400u16;
     for (i, &v) in sid.regs_25.iter().enumerate() {
@@ -575,11 +1232,15 @@ pub fn restore_sid(b: &mut impl Bus, sid: &Sid6581) {
     }
 }
 
+/// Write RAM with all ROMs banked out, then set the CPU port
+///
+/// `$00/$01` on the bus are the CPU port, not RAM: the bytes VICE saved under
+/// the port are only visible to the VIC-II and are not written.
 pub fn restore_ram(b: &mut impl Bus, m: &C64Mem) {
-    b.write8(0x0001, 0x07);
+    b.write8(0x0000, 0x07);
     b.write8(0x0001, 0x00);
 
-    for (addr, &val) in m.ram.iter().enumerate() {
+    for (addr, &val) in m.ram.iter().enumerate().skip(2) {
         let a = addr as u16;
         if (0xD800..=0xDBFF).contains(&a) {
             continue;
@@ -587,7 +1248,7 @@ pub fn restore_ram(b: &mut impl Bus, m: &C64Mem) {
         b.write8(a, val);
     }
 
-    b.write8(0x0001, m.cpu_port_dir);
+    b.write8(0x0000, m.cpu_port_dir);
     b.write8(0x0001, m.cpu_port_data);
 }
 
@@ -596,11 +1257,21 @@ pub fn restore_cpu(ctrl: &mut impl CpuControl, c: &Cpu6510) {
 }
 
 pub fn restore_all<B: Bus, C: CpuControl>(bus: &mut B, cpu: &mut C, snap: &C64Snapshot) {
+    restore_all_with(bus, cpu, snap, VicRestoreOrder::Naive);
+}
+
+pub fn restore_all_with<B: Bus, C: CpuControl>(bus: &mut B, cpu: &mut C, snap: &C64Snapshot, order: VicRestoreOrder) {
     restore_cia(bus, CIA1_BASE, &snap.cia1);
     restore_cia(bus, CIA2_BASE, &snap.cia2);
-    restore_vic(bus, &snap.vic);
+    match order {
+        VicRestoreOrder::Naive => restore_vic(bus, &snap.vic),
+        VicRestoreOrder::Safe => restore_vic_blanked(bus, &snap.vic),
+    }
     restore_sid(bus, &snap.sid);
     restore_ram(bus, &snap.mem);
+    if order == VicRestoreOrder::Safe {
+        restore_vic_display(bus, &snap.vic);
+    }
     restore_cpu(cpu, &snap.cpu);
 }
 
@@ -682,3 +1353,384 @@ fn sniff_compression_prefix(head: &[u8]) -> Option<&'static str> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::TestVsf;
+
+    #[derive(Default)]
+    struct RecordingBus {
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl Bus for RecordingBus {
+        fn write8(&mut self, addr: u16, val: u8) {
+            self.writes.push((addr, val));
+        }
+    }
+
+    impl CpuControl for RecordingBus {
+        fn set_cpu(&mut self, _a: u8, _x: u8, _y: u8, _sp: u8, _p: u8, _pc: u16) {}
+    }
+
+    fn color_ram_with(vsf: &TestVsf, source: ColorRamSource) -> Box<[u8; 1024]> {
+        let cfg = ParserConfig { color_ram_source: source, ..ParserConfig::default() };
+        vsf.import("color_ram").parse_import_with(&cfg).unwrap().vic.color_ram
+    }
+
+    #[test]
+    fn test_restore_ram_sets_cpu_port_last() {
+        let mut vsf = TestVsf::new();
+        vsf.cpu_port_dir = 0x2F;
+        vsf.cpu_port_data = 0x35;
+        // RAM under the port differs from the port values
+        vsf.ram[0x00] = 0xAA;
+        vsf.ram[0x01] = 0x55;
+        vsf.ram[0x02] = 0x42;
+        let snap = vsf.parse("cpu_port");
+
+        let mut bus = RecordingBus::default();
+        restore_ram(&mut bus, &snap.mem);
+
+        // Port writes: all RAM first, snapshot DDR/data at the end, never the RAM beneath
+        let port: Vec<(u16, u8)> = bus.writes.iter().copied().filter(|&(a, _)| a < 2).collect();
+        assert_eq!(port, vec![(0x0000, 0x07), (0x0001, 0x00), (0x0000, 0x2F), (0x0001, 0x35)]);
+        assert_eq!(&bus.writes[bus.writes.len() - 2..], &[(0x0000, 0x2F), (0x0001, 0x35)]);
+        assert!(bus.writes.contains(&(0x0002, 0x42)));
+    }
+
+    #[test]
+    fn test_mid_scan_keyboard_ports_are_kept() {
+        // KERNAL scan selecting column 2: port A out, port B in, row latch 0
+        let mut vsf = TestVsf::new();
+        vsf.cia1[..4].copy_from_slice(&[0xFB, 0x00, 0xFF, 0x00]);
+        vsf.cia2[1] = 0x00;
+        let snap = vsf.parse("mid_scan");
+        let (cia1, cia2) = (snap.cia1, snap.cia2);
+        assert_eq!((cia1.ora, cia1.orb, cia1.ddra, cia1.ddrb), (0xFB, 0x00, 0xFF, 0x00));
+        // CIA2 port B is the user port, never the keyboard
+        assert_eq!(cia2.orb, 0x00);
+
+        // Port B driving rows low while port A drives columns: rows released
+        vsf.cia1[..4].copy_from_slice(&[0x00, 0xF0, 0xFF, 0x0F]);
+        let cia1 = vsf.parse("rows_driven").cia1;
+        assert_eq!((cia1.ora, cia1.orb, cia1.ddrb), (0x00, 0xFF, 0x0F));
+    }
+
+    #[test]
+    fn test_ratio_quality_is_no_larger_than_fast() {
+        // Code-like data with repeats at varying distances
+        let data: Vec<u8> = (0..16384u32)
+            .map(|i| match i % 97 {
+                0..=40 => (i % 13) as u8,
+                41..=70 => b"LDA #$00 STA $D020 "[(i % 19) as usize],
+                _ => (i.wrapping_mul(2_654_435_761) >> 24) as u8,
+            })
+            .collect();
+
+        let ratio = compress_lzsa1(&data, CompressionQuality::Ratio, 3).unwrap();
+        let fast = compress_lzsa1(&data, CompressionQuality::Fast, 3).unwrap();
+        assert!(ratio.len() <= fast.len(), "ratio {} > fast {}", ratio.len(), fast.len());
+        assert_eq!(crate::test_vsf::decompress_lzsa1(&fast), data);
+
+        assert!(compress_lzsa1(&data, CompressionQuality::Ratio, 2).is_err());
+    }
+
+    #[test]
+    fn test_color_ram_source_selection() {
+        // VIC module holds light blue, main RAM holds white
+        let mut vsf = TestVsf::new();
+        vsf.ram[0xD800..=0xDBFF].fill(0x01);
+
+        assert!(color_ram_with(&vsf, ColorRamSource::Auto).iter().all(|&b| b == 0x01));
+        assert!(color_ram_with(&vsf, ColorRamSource::MainRam).iter().all(|&b| b == 0x01));
+        assert!(color_ram_with(&vsf, ColorRamSource::VicModule).iter().all(|&b| b == 0x0E));
+
+        // Deliberately black color RAM fails the Auto heuristic
+        vsf.ram[0xD800..=0xDBFF].fill(0x00);
+
+        assert!(color_ram_with(&vsf, ColorRamSource::Auto).iter().all(|&b| b == 0x0E));
+        assert!(color_ram_with(&vsf, ColorRamSource::MainRam).iter().all(|&b| b == 0x00));
+        assert!(color_ram_with(&vsf, ColorRamSource::VicModule).iter().all(|&b| b == 0x0E));
+    }
+
+    fn vic_writes(order: VicRestoreOrder) -> Vec<(u16, u8)> {
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x15] = 0xFF;
+        let snap = vsf.parse("vic_order");
+
+        let mut bus = RecordingBus::default();
+        let mut cpu = RecordingBus::default();
+        restore_all_with(&mut bus, &mut cpu, &snap, order);
+        bus.writes
+    }
+
+    fn last_write(writes: &[(u16, u8)], addr: u16) -> usize {
+        writes.iter().rposition(|&(a, _)| a == addr).unwrap()
+    }
+
+    #[test]
+    fn test_safe_vic_order_enables_sprites_last() {
+        let writes = vic_writes(VicRestoreOrder::Safe);
+        assert!(last_write(&writes, 0xD015) > last_write(&writes, 0xD018));
+        assert!(last_write(&writes, 0xD015) > last_write(&writes, 0x0400));
+        assert_eq!(writes[last_write(&writes, 0xD015)].1, 0xFF);
+        assert_eq!(writes[last_write(&writes, 0xD011)].1, 0x1B);
+
+        // Display and sprites start out blanked
+        assert!(writes.contains(&(0xD015, 0x00)));
+        assert!(writes.contains(&(0xD011, 0x0B)));
+    }
+
+    #[test]
+    fn test_sprite_x_bit_8_restores_with_the_low_byte() {
+        // Sprite 0 at X=$140 (bit 8 in $D010), sprite 7 at X=$F0
+        let mut vsf = TestVsf::new();
+        vsf.vic_regs[0x00] = 0x40;
+        vsf.vic_regs[0x01] = 0x80;
+        vsf.vic_regs[0x0E] = 0xF0;
+        vsf.vic_regs[0x0F] = 0x60;
+        vsf.vic_regs[0x10] = 0x01;
+        vsf.vic_regs[0x15] = 0x81;
+        let snap = vsf.parse("sprite_msb");
+
+        assert_eq!(snap.vic.sprite_position(0), (0x140, 0x80));
+        assert_eq!(snap.vic.sprite_position(7), (0xF0, 0x60));
+
+        for order in [VicRestoreOrder::Naive, VicRestoreOrder::Safe] {
+            let mut bus = RecordingBus::default();
+            let mut cpu = RecordingBus::default();
+            restore_all_with(&mut bus, &mut cpu, &snap, order);
+            let writes = bus.writes;
+
+            assert_eq!(writes[last_write(&writes, 0xD000)].1, 0x40);
+            assert_eq!(writes[last_write(&writes, 0xD00E)].1, 0xF0);
+            assert_eq!(writes[last_write(&writes, 0xD010)].1, 0x01);
+            let msb = last_write(&writes, 0xD010);
+            assert!((0xD000..0xD010).all(|addr| last_write(&writes, addr) < msb));
+        }
+    }
+
+    #[test]
+    fn test_naive_vic_order_unchanged() {
+        let writes = vic_writes(VicRestoreOrder::Naive);
+        assert!(last_write(&writes, 0xD015) < last_write(&writes, 0xD018));
+        assert_eq!(writes.iter().filter(|&&(a, _)| a == 0xD015).count(), 1);
+    }
+
+    #[test]
+    fn test_retime_pal_to_ntsc_scales_timer_a() {
+        // KERNAL PAL jiffy timer: $4025 cycles at 985248 Hz
+        let mut vsf = TestVsf::new();
+        vsf.cia1[4..6].copy_from_slice(&0x4025u16.to_le_bytes());
+        vsf.cia1[16..18].copy_from_slice(&0x4025u16.to_le_bytes());
+        vsf.cia1[18..20].copy_from_slice(&0x1234u16.to_le_bytes());
+        let mut snap = vsf.parse("retime");
+
+        assert_eq!(snap.vic.video_standard(), Some(VideoStandard::Pal));
+        assert_eq!(snap.retime(VideoStandard::Pal, false), None);
+        assert_eq!(snap.cia1.tal, 0x4025);
+
+        assert_eq!(snap.retime(VideoStandard::Ntsc, false), Some(VideoStandard::Pal));
+        assert_eq!(snap.cia1.tal, 0x4296);
+        assert_eq!(snap.cia1.tac, 0x4296);
+        assert_eq!(snap.cia1.tbl, 0x1234);
+
+        // A Drean snapshot is retimed from its own clock, not the PAL one
+        snap.vic.model = Some(6);
+        snap.cia1.tal = 0x4025;
+        assert_eq!(snap.retime(VideoStandard::Ntsc, false), Some(VideoStandard::PalN));
+        assert_eq!(snap.cia1.tal, 0x401A);
+        snap.cia1.tal = 0x4025;
+        assert_eq!(snap.retime(VideoStandard::Pal, false), Some(VideoStandard::PalN));
+        assert_eq!(snap.cia1.tal, 0x3DC0);
+    }
+
+    #[test]
+    fn test_is_supported_checks_header_only() {
+        let mut raw = TestVsf::new().to_bytes();
+        assert_eq!(ParseVSF::is_supported(&raw), Ok(()));
+        // The header alone is enough
+        assert_eq!(ParseVSF::is_supported(&raw[..VSF_HEADER_LEN]), Ok(()));
+
+        raw[19] = 1;
+        raw[20] = 0;
+        assert_eq!(
+            ParseVSF::is_supported(&raw),
+            Err("Unsupported snapshot format version 1.0".to_string())
+        );
+
+        assert!(SUPPORTED_MACHINES.iter().all(|name| Machine::from_name(name).is_some()));
+        let mut vic20 = TestVsf::new().to_bytes();
+        vic20[21..26].copy_from_slice(b"VIC20");
+        assert_eq!(ParseVSF::is_supported(&vic20), Err("Unsupported machine type 'VIC20'".to_string()));
+    }
+
+    #[test]
+    fn test_force_parses_unsupported_header() {
+        let mut raw = TestVsf::new().to_bytes();
+        raw[20] = 9;
+        raw[21..26].copy_from_slice(b"C64XX");
+        let dir = crate::test_vsf::temp_dir("force_header");
+        let path = dir.join("force.vsf");
+        std::fs::write(&path, &raw).unwrap();
+
+        let parser = ParseVSF::import(path.to_str().unwrap(), &Config::new(&dir)).unwrap();
+        assert_eq!(parser.parse_import().unwrap_err(), "Unsupported snapshot format version 2.9");
+        let forced = ParserConfig { force: true, ..ParserConfig::default() };
+        let snap = parser.parse_import_with(&forced).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(snap.cpu.pc, 0x0810);
+        assert_eq!(snap.vic.registers[0x18], 0x15);
+    }
+
+    #[test]
+    fn test_second_sid_address_is_read_from_the_sid_module() {
+        // The SID module comes last: sids(1), sound(1), engine(1), siddata(32)
+        let with_sids = |sids: u8, tail: &[u8]| {
+            let mut raw = TestVsf::new().to_bytes();
+            let payload = raw.len() - 35;
+            raw[payload] = sids;
+            raw.extend_from_slice(tail);
+            raw[payload - 4..payload].copy_from_slice(&((35 + tail.len() + 22) as u32).to_le_bytes());
+            raw
+        };
+        let dir = crate::test_vsf::temp_dir("sid2_addr");
+        let parse = |raw: &[u8], cfg: &ParserConfig| {
+            let path = dir.join("stereo.vsf");
+            std::fs::write(&path, raw).unwrap();
+            ParseVSF::import(path.to_str().unwrap(), &Config::new(&dir)).unwrap().parse_import_with(cfg).unwrap()
+        };
+        let fallback = ParserConfig { sid2_addr: Some(0xD500), ..ParserConfig::default() };
+
+        let snap = parse(&with_sids(2, &[0x00, 0xDE]), &ParserConfig::default());
+        assert_eq!(snap.sid2_addr, Some(0xDE00));
+        assert_eq!(parse(&with_sids(2, &[0x20, 0xD4]), &fallback).sid2_addr, Some(0xD420));
+
+        // Not stored, or not a second SID address: the configured fallback
+        assert_eq!(parse(&with_sids(2, &[]), &ParserConfig::default()).sid2_addr, None);
+        assert_eq!(parse(&with_sids(2, &[]), &fallback).sid2_addr, Some(0xD500));
+        assert_eq!(parse(&with_sids(2, &[0x00, 0xD4]), &fallback).sid2_addr, Some(0xD500));
+
+        // One SID: no second address, whatever follows the registers
+        assert_eq!(parse(&with_sids(1, &[0x00, 0xDE]), &fallback).sid2_addr, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_metadata_reads_vice_version() {
+        let mut vsf = TestVsf::new();
+        vsf.vice_version = Some(([3, 7, 1, 0], 43776));
+        let parser = vsf.import("vsf_meta");
+
+        let meta = parser.metadata().unwrap();
+        assert_eq!(meta.vice_version, "3.7.1");
+        assert_eq!(meta.svn_revision, 43776);
+        assert!(parser.parse_import().is_ok());
+    }
+
+    #[test]
+    fn test_metadata_absent_without_version_block() {
+        let parser = TestVsf::new().import("vsf_no_meta");
+
+        assert_eq!(parser.metadata(), None);
+    }
+
+    #[test]
+    fn test_streaming_import_skips_large_modules() {
+        // 4 MB REU module between the CIAs; only the C64 modules are buffered
+        let mut bytes = TestVsf::new().to_bytes();
+        let reu_len = 4 * 1024 * 1024;
+        let mut reu = Vec::with_capacity(reu_len + MODULE_HEADER_LEN);
+        reu.extend_from_slice(b"REU1764\0\0\0\0\0\0\0\0\0");
+        reu.extend_from_slice(&[0, 0]);
+        reu.extend_from_slice(&((reu_len + MODULE_HEADER_LEN) as u32).to_le_bytes());
+        reu.resize(reu_len + MODULE_HEADER_LEN, 0xA5);
+        bytes.extend_from_slice(&reu);
+
+        let dir = crate::test_vsf::temp_dir("streaming");
+        let path = dir.join("reu.vsf");
+        std::fs::write(&path, &bytes).unwrap();
+        let path = path.to_str().unwrap();
+
+        let parser = ParseVSF::import_streaming(path, &Config::new(&dir)).unwrap();
+        assert!(parser.raw.len() < 128 * 1024, "buffered {} bytes", parser.raw.len());
+        let snap = parser.parse_import().unwrap();
+
+        let full = ParseVSF::import(path, &Config::new(&dir)).unwrap().parse_import().unwrap();
+        assert_eq!(snap.mem.ram[..], full.mem.ram[..]);
+        assert_eq!(snap.vic.registers, full.vic.registers);
+        assert_eq!(snap.cpu.pc, full.cpu.pc);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_vic_color_ram_offset_follows_module_version() {
+        // Same payload read as 1.2 and 1.3: color RAM moves by 4 bytes
+        let mut payload = vec![0u8; 761 + 1024];
+        payload[757..757 + 1024].fill(0x01);
+        payload[761..761 + 1024].fill(0x02);
+        let cfg = ParserConfig::default();
+
+        let old = parse_vic(&payload, &cfg, Machine::C64Sc, ModuleVersion { major: 1, minor: 2 }).unwrap();
+        assert_eq!(old.color_ram[0], 0x01);
+
+        let new = parse_vic(&payload, &cfg, Machine::C64Sc, ModuleVersion { major: 1, minor: 3 }).unwrap();
+        assert!(new.color_ram.iter().all(|&b| b == 0x02));
+
+        let forced = ParserConfig { vic_color_off: Some(757), ..ParserConfig::default() };
+        let overridden = parse_vic(&payload, &forced, Machine::C64Sc, ModuleVersion { major: 1, minor: 3 }).unwrap();
+        assert_eq!(overridden.color_ram[0], 0x01);
+    }
+
+    #[test]
+    fn test_short_memory_module_is_reported() {
+        let mver = ModuleVersion { major: 0, minor: 1 };
+        let mut payload = vec![0x37, 0x2F, 0x00, 0x00];
+        let err = parse_memory(&payload, mver).unwrap_err();
+        assert!(err.contains("C64MEM 0.1 too short: 4 bytes"), "{}", err);
+        assert!(!err.contains("compressed"), "{}", err);
+
+        payload.extend_from_slice(&[0x1F, 0x8B, 0x08, 0x00]);
+        let err = parse_memory(&payload, mver).unwrap_err();
+        assert!(err.contains("gzip-compressed"), "{}", err);
+
+        payload.resize(4 + 65536, 0);
+        assert_eq!(parse_memory(&payload, mver).unwrap().ram[0], 0x1F);
+    }
+
+    #[test]
+    fn test_vic_register_offset_override() {
+        // Register bytes numbered by payload position
+        let mut payload = vec![0u8; 761 + 1024];
+        for (i, b) in payload[..64].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let mver = ModuleVersion { major: 1, minor: 3 };
+
+        let default = parse_vic(&payload, &ParserConfig::default(), Machine::C64Sc, mver).unwrap();
+        let forced = ParserConfig { vic_regs_off: Some(8), ..ParserConfig::default() };
+        let overridden = parse_vic(&payload, &forced, Machine::C64Sc, mver).unwrap();
+        assert_eq!(overridden.registers[0], 8);
+        assert_ne!(overridden.registers[0], default.registers[0]);
+    }
+
+    #[test]
+    fn test_sid_register_offset_override() {
+        let payload: Vec<u8> = (0..40).collect();
+        let mver = ModuleVersion { major: 1, minor: 4 };
+
+        let default = parse_sid(&payload, &ParserConfig::default(), mver).unwrap();
+        assert_eq!(default.regs_25[0], 4);
+
+        let forced = ParserConfig { sid_regs_off: Some(7), ..ParserConfig::default() };
+        let overridden = parse_sid(&payload, &forced, mver).unwrap();
+        assert_eq!(overridden.regs_25[0], 7);
+        assert_eq!(overridden.regs_25[24], 31);
+
+        let past_end = ParserConfig { sid_regs_off: Some(20), ..ParserConfig::default() };
+        assert!(parse_sid(&payload, &past_end, mver).is_err());
+    }
+}
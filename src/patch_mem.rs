@@ -10,14 +10,18 @@
 
 #![allow(dead_code)]
 
+use std::ops::RangeInclusive;
+
 use crate::find_ram::FindRam;
-use crate::parse_vsf::C64Snapshot;
+use crate::opcodes as op;
+use crate::parse_vsf::{C64Snapshot, VicRestoreOrder};
 
 #[derive(Debug)]
 pub enum PatchError {
     AllocationFailed(String),
     StackTooLow(String),
     CodeTooLarge(String),
+    Overlap(String),
 }
 
 impl std::fmt::Display for PatchError {
@@ -26,18 +30,105 @@ impl std::fmt::Display for PatchError {
             PatchError::AllocationFailed(s) => write!(f, "Allocation failed: {}", s),
             PatchError::StackTooLow(s) => write!(f, "Stack too low: {}", s),
             PatchError::CodeTooLarge(s) => write!(f, "Code too large: {}", s),
+            PatchError::Overlap(s) => write!(f, "Overlapping restore regions: {}", s),
         }
     }
 }
 
 impl std::error::Error for PatchError {}
 
+/// Lowest stack pointer the restore can work with. The `$01xx` restore code
+/// must sit below the live stack in page 1, which the relocated decompressor
+/// also occupies while unpacking, so a lower SP leaves no room for it.
+pub const MIN_STACK_POINTER: u8 = 16;
+
 struct BlockAllocation {
     address: u16,
     original_value: u8,
     size: u16,
 }
 
+/// Options for the generated final restore code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    pub vic_order: VicRestoreOrder,
+    /// Leave the I flag set and CIA interrupt masks off; the program must re-enable them
+    pub irq_off: bool,
+    /// Write CRA/CRB with bit 0 (start) cleared, so every CIA timer is stopped
+    /// when the program resumes
+    pub defer_timers: bool,
+    /// Re-create a raster IRQ that was pending in the snapshot (see `rearm_raster_irq_code`)
+    pub rearm_raster_irq: bool,
+    /// Check one restored byte (address, expected value); on mismatch the
+    /// border turns red and the restore halts instead of starting the program
+    pub verify: Option<(u16, u8)>,
+    /// Start at this address (a `--prepend` stub) instead of the snapshot PC,
+    /// with interrupts disabled; the stub resumes with `PatchMem::resume_code`
+    pub entry: Option<u16>,
+    /// How the saved stack page and $FFF0-$FFFF are split into blocks
+    pub layout: BlockLayout,
+    /// Put block 9 here if a free run still covers it after blocks 1-8;
+    /// best fit otherwise (see `PatchMem::with_preferred_block9`)
+    pub block9_addr: Option<u16>,
+}
+
+/// Layout of the blocks holding $0100-$01FF and $FFF0-$FFFF during the restore
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockLayout {
+    /// Standard, then Compact and Split if free RAM doesn't fit it
+    #[default]
+    Auto,
+    /// Eight blocks: 48, 40 and 6 x 32 bytes
+    Standard,
+    /// One 272-byte block; much shorter block 9, but needs one large free run
+    Compact,
+    /// Nine blocks of at most 32 bytes, for RAM with only small free runs
+    Split,
+}
+
+/// Standard blocks 1-8 as (address, length) pieces; block 2 also keeps $F8-$FF
+const STANDARD_PIECES: [&[(u16, u16)]; 8] = [
+    &[(0x0100, 32), (0xFFF0, 16)],
+    &[(0x0120, 32), (0x00F8, 8)],
+    &[(0x0140, 32)],
+    &[(0x0160, 32)],
+    &[(0x0180, 32)],
+    &[(0x01A0, 32)],
+    &[(0x01C0, 32)],
+    &[(0x01E0, 32)],
+];
+
+const COMPACT_PIECES: [&[(u16, u16)]; 1] = [&[(0x0100, 256), (0xFFF0, 16)]];
+
+const SPLIT_PIECES: [&[(u16, u16)]; 9] = [
+    &[(0x0100, 32)],
+    &[(0x0120, 32)],
+    &[(0x0140, 32)],
+    &[(0x0160, 32)],
+    &[(0x0180, 32)],
+    &[(0x01A0, 32)],
+    &[(0x01C0, 32)],
+    &[(0x01E0, 32)],
+    &[(0xFFF0, 16)],
+];
+
+impl BlockLayout {
+    /// Memory saved in each block, in order, as (address, length) pieces
+    fn pieces(self) -> &'static [&'static [(u16, u16)]] {
+        match self {
+            BlockLayout::Auto | BlockLayout::Standard => &STANDARD_PIECES,
+            BlockLayout::Compact => &COMPACT_PIECES,
+            BlockLayout::Split => &SPLIT_PIECES,
+        }
+    }
+}
+
+/// Border color shown when the `verify` byte does not match (red)
+pub const VERIFY_FAIL_COLOR: u8 = 0x02;
+
+/// Status pushed for a prepended stub: I set, bit 5 set, B clear
+const ENTRY_STATUS: u8 = 0x24;
+
 pub struct PatchMem {
     blocks: Vec<BlockAllocation>,
     block9_addr: u16,
@@ -47,11 +138,128 @@ pub struct PatchMem {
 impl PatchMem {
     /// Patch RAM with restoration code and allocate blocks
     pub fn new(snap: &C64Snapshot, ram: &mut [u8; 65536], ram_finder: &mut FindRam) -> Result<Self, PatchError> {
+        Self::with_vic_order(snap, ram, ram_finder, VicRestoreOrder::Naive)
+    }
+
+    /// Patch RAM with restoration code using the given VIC-II restore order
+    /// With `Safe`, the final restore code enables sprites and display
+    pub fn with_vic_order(
+        snap: &C64Snapshot,
+        ram: &mut [u8; 65536],
+        ram_finder: &mut FindRam,
+        vic_order: VicRestoreOrder,
+    ) -> Result<Self, PatchError> {
+        Self::with_options(snap, ram, ram_finder, RestoreOptions { vic_order, ..RestoreOptions::default() })
+    }
+
+    /// Patch RAM like `with_options`, with block 9 pinned to `addr` if it fits
+    ///
+    /// The generators jump to block 9 after RAM is decompressed, so a fixed
+    /// address makes that jump predictable when debugging a restore. If no
+    /// free run covers `addr` and block 9's size once blocks 1-8 are placed,
+    /// block 9 goes wherever best fit puts it; `get_block9_addr` tells which.
+    pub fn with_preferred_block9(
+        snap: &C64Snapshot,
+        ram: &mut [u8; 65536],
+        ram_finder: &mut FindRam,
+        options: RestoreOptions,
+        addr: u16,
+    ) -> Result<Self, PatchError> {
+        Self::with_options(snap, ram, ram_finder, RestoreOptions { block9_addr: Some(addr), ..options })
+    }
+
+    /// Patch RAM like `with_options`, taking the restore blocks only from `ranges`
+    ///
+    /// `ram_finder` is cut down to the free space inside `ranges` (see
+    /// `FindRam::restrict_to`) and stays that way, so RAM outside them keeps
+    /// the snapshot's contents for inspection after the restore. Fails with
+    /// the ranges named if they can't hold every block.
+    pub fn with_allowed_ranges(
+        snap: &C64Snapshot,
+        ram: &mut [u8; 65536],
+        ram_finder: &mut FindRam,
+        options: RestoreOptions,
+        ranges: &[RangeInclusive<u16>],
+    ) -> Result<Self, PatchError> {
+        ram_finder.restrict_to(ranges);
+        Self::with_options(snap, ram, ram_finder, options).map_err(|e| match e {
+            PatchError::AllocationFailed(msg) => {
+                let allowed: Vec<String> = ranges
+                    .iter()
+                    .map(|range| format!("${:04X}-${:04X}", range.start(), range.end()))
+                    .collect();
+                PatchError::AllocationFailed(format!("restore blocks limited to {}: {}", allowed.join(", "), msg))
+            }
+            e => e,
+        })
+    }
+
+    /// Patch RAM with restoration code using the given restore options
+    ///
+    /// With `BlockLayout::Auto`, a failed allocation is retried with the
+    /// Compact and Split layouts; the Standard layout's error is reported
+    /// if none fits.
+    pub fn with_options(
+        snap: &C64Snapshot,
+        ram: &mut [u8; 65536],
+        ram_finder: &mut FindRam,
+        options: RestoreOptions,
+    ) -> Result<Self, PatchError> {
         let sp = snap.cpu.sp;
+        if sp < MIN_STACK_POINTER {
+            return Err(PatchError::StackTooLow(format!(
+                "SP=${:02X} leaves only {} bytes of page 1 below the live stack ($0100-${:04X}), too few for the restore code; \
+                 the rest of $0100-$01FF holds the snapshot's stack and the relocated decompressor (minimum SP is ${:02X})",
+                sp, sp, 0x0100 + sp as u16, MIN_STACK_POINTER
+            )));
+        }
+
+        // Fail early with both numbers when no layout can fit the free RAM
+        let free = ram_finder.total_free_bytes();
+        let needed = Self::required_free_bytes(options.layout);
+        if free < needed {
+            return Err(PatchError::AllocationFailed(format!(
+                "Failed to allocate blocks: the snapshot has {} bytes of free RAM, the restore blocks need at least {}. \
+                 Try with a cleaner snapshot (run 'f 0000 ffff 00' in VICE monitor before taking snapshot)",
+                free, needed
+            )));
+        }
+
+        let layouts = match options.layout {
+            BlockLayout::Auto => vec![BlockLayout::Standard, BlockLayout::Compact, BlockLayout::Split],
+            layout => vec![layout],
+        };
+
+        let mut first_error = None;
+        for layout in layouts {
+            // Allocation fails before RAM is touched, so only the finder needs a fresh copy
+            let mut finder = ram_finder.clone();
+            match Self::with_layout(snap, ram, &mut finder, options, layout) {
+                Ok(patch) => {
+                    *ram_finder = finder;
+                    return Ok(patch);
+                }
+                Err(e @ PatchError::AllocationFailed(_)) => {
+                    first_error.get_or_insert(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(first_error.expect("at least one layout is tried"))
+    }
 
-        // Allocate blocks 1-8 for preserving stack area
+    fn with_layout(
+        snap: &C64Snapshot,
+        ram: &mut [u8; 65536],
+        ram_finder: &mut FindRam,
+        options: RestoreOptions,
+        layout: BlockLayout,
+    ) -> Result<Self, PatchError> {
+        let sp = snap.cpu.sp;
+
+        // Allocate the blocks preserving the stack area
         let mut blocks = Vec::new();
-        let sizes = [48u16, 40, 32, 32, 32, 32, 32, 32];
+        let sizes: Vec<u16> = layout.pieces().iter().map(|pieces| pieces.iter().map(|&(_, len)| len).sum()).collect();
 
         for (i, &size) in sizes.iter().enumerate() {
             match ram_finder.allocate(size) {
@@ -71,7 +279,7 @@ impl PatchMem {
         f8_ff.copy_from_slice(&snap.mem.ram[0xF8..=0xFF]);
 
         // Generate block 9 with placeholder JMP
-        let mut block9_code = Self::generate_block9_final(&blocks, snap)?;
+        let mut block9_code = Self::generate_block9_final(&blocks, layout, sp)?;
         let exact_block9_size = block9_code.len() as u16;
 
         if exact_block9_size > 255 {
@@ -80,8 +288,9 @@ impl PatchMem {
             ));
         }
 
-        // Allocate block 9 with exact size
-        let (block9_addr, block9_fill) = match ram_finder.allocate(exact_block9_size) {
+        // Allocate block 9 with exact size, at the preferred address if it is free
+        let preferred = options.block9_addr.and_then(|addr| ram_finder.allocate_at(addr, exact_block9_size));
+        let (block9_addr, block9_fill) = match preferred.or_else(|| ram_finder.allocate(exact_block9_size)) {
             Some((addr, value)) => (addr, value),
             None => {
                 return Err(PatchError::AllocationFailed(
@@ -91,7 +300,7 @@ impl PatchMem {
         };
 
         // Generate block 10 (needs block 9 info for wiping)
-        let mut block10_code = Self::generate_block10(&f8_ff, snap, block9_addr, exact_block9_size, block9_fill)?;
+        let mut block10_code = Self::generate_block10(&f8_ff, snap.mem.cpu_port_dir, snap.cpu.a, block9_addr, exact_block9_size, block9_fill)?;
         let exact_block10_size = block10_code.len() as u16;
 
         if exact_block10_size > 255 {
@@ -111,7 +320,7 @@ impl PatchMem {
         };
 
         // Generate restore code
-        let restore_code = Self::generate_restore_code(snap, block10_addr, exact_block10_size, block10_fill)?;
+        let restore_code = Self::generate_restore_code(snap, block10_addr, exact_block10_size, block10_fill, options)?;
         let code_len = restore_code.len() as u16;
 
         // Calculate placement for restore code
@@ -135,6 +344,8 @@ impl PatchMem {
             ideal_start
         };
 
+        Self::check_overlaps(&blocks, (block9_addr, exact_block9_size), (block10_addr, exact_block10_size), (code_start, code_len))?;
+
         // Patch the JMP address in block 9 to jump to block 10
         let jmp_offset = block9_code.len() - 3;
         block9_code[jmp_offset + 1] = (block10_addr & 0xFF) as u8;
@@ -150,36 +361,14 @@ impl PatchMem {
         let code_end_usize = code_start_usize + restore_code.len();
         ram[code_start_usize..code_end_usize].copy_from_slice(&restore_code);
 
-        // Copy $0100-$01FF to allocated blocks
-        // Block 1: $0100-$011F + $FFF0-$FFFF (48 bytes)
-        let mut temp = [0u8; 48];
-        temp[0..32].copy_from_slice(&ram[0x0100..0x0120]);
-        temp[32..48].copy_from_slice(&ram[0xFFF0..0x10000]);
-        let addr = blocks[0].address as usize;
-        ram[addr..addr + 48].copy_from_slice(&temp);
-
-        // Block 2: $0120-$013F + $F8-$FF (40 bytes)
-        let mut temp = [0u8; 40];
-        temp[0..32].copy_from_slice(&ram[0x0120..0x0140]);
-        temp[32..40].copy_from_slice(&ram[0x00F8..0x0100]);
-        let addr = blocks[1].address as usize;
-        ram[addr..addr + 40].copy_from_slice(&temp);
-
-        // Blocks 3-8: 32 bytes each
-        let ranges = [
-            (0x0140, 0x0160, 2),
-            (0x0160, 0x0180, 3),
-            (0x0180, 0x01A0, 4),
-            (0x01A0, 0x01C0, 5),
-            (0x01C0, 0x01E0, 6),
-            (0x01E0, 0x0200, 7),
-        ];
-
-        for &(start, end, idx) in &ranges {
-            let mut temp = [0u8; 32];
-            temp.copy_from_slice(&ram[start..end]);
-            let addr = blocks[idx].address as usize;
-            ram[addr..addr + 32].copy_from_slice(&temp);
+        // Copy $0100-$01FF (and $FFF0-$FFFF, $F8-$FF) to the allocated blocks
+        for (block, pieces) in blocks.iter().zip(layout.pieces()) {
+            let saved: Vec<u8> = pieces
+                .iter()
+                .flat_map(|&(start, len)| ram[start as usize..start as usize + len as usize].to_vec())
+                .collect();
+            let addr = block.address as usize;
+            ram[addr..addr + saved.len()].copy_from_slice(&saved);
         }
 
         // Write block 9 complete code (with patched JMP to block 10)
@@ -211,6 +400,124 @@ impl PatchMem {
         })
     }
 
+    /// Verify blocks 1-10 and the $01xx restore code are mutually disjoint and
+    /// that no block lands in a region saved by blocks 1/2 ($F8-$FF, $0100-$01FF, $FFF0-$FFFF)
+    fn check_overlaps(
+        blocks: &[BlockAllocation],
+        block9: (u16, u16),
+        block10: (u16, u16),
+        restore_code: (u16, u16),
+    ) -> Result<(), PatchError> {
+        // (name, start, end exclusive)
+        let mut ranges: Vec<(String, u32, u32)> = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (format!("block {}", i + 1), b.address as u32, b.address as u32 + b.size as u32))
+            .collect();
+        ranges.push(("block 9".to_string(), block9.0 as u32, block9.0 as u32 + block9.1 as u32));
+        ranges.push(("block 10".to_string(), block10.0 as u32, block10.0 as u32 + block10.1 as u32));
+
+        let saved = [
+            ("saved $F8-$FF", 0x00F8u32, 0x0100u32),
+            ("saved page $01xx", 0x0100, 0x0200),
+            ("saved $FFF0-$FFFF", 0xFFF0, 0x10000),
+        ];
+
+        for (i, (name, start, end)) in ranges.iter().enumerate() {
+            let code = ("$01xx restore code", restore_code.0 as u32, restore_code.0 as u32 + restore_code.1 as u32);
+            let others = ranges[i + 1..]
+                .iter()
+                .map(|(n, s, e)| (n.as_str(), *s, *e))
+                .chain(saved.iter().copied())
+                .chain(std::iter::once(code));
+
+            for (other, o_start, o_end) in others {
+                if *start < o_end && o_start < *end {
+                    return Err(PatchError::Overlap(format!(
+                        "{} (${:04X}-${:04X}) overlaps {} (${:04X}-${:04X})",
+                        name, start, end - 1, other, o_start, o_end - 1
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Latch the raster IRQ again by setting the compare to the current line
+    ///
+    /// The VIC-II raises the raster IRQ when the compare register is written
+    /// with the line it is on, so copying the current line into $D011/$D012
+    /// sets `$D019` bit 0; the snapshot's compare line is then written back.
+    /// With IRQs enabled in the restored P the program takes the IRQ right
+    /// after the RTI, instead of a frame later at its compare line. Missed if
+    /// the raster moves to the next line between the reads and writes.
+    fn rearm_raster_irq_code(snap: &C64Snapshot) -> Vec<u8> {
+        let [d011, d012] = snap.vic.raster_regs();
+        let mut code = Vec::new();
+        // Bit 7 of $D011 reads as bit 8 of the current line
+        code.extend(op::lda_abs(0xD011));
+        code.extend(op::sta_abs(0xD011));
+        code.extend(op::lda_abs(0xD012));
+        code.extend(op::sta_abs(0xD012));
+        code.extend(op::lda_imm(d011));
+        code.extend(op::sta_abs(0xD011));
+        code.extend(op::lda_imm(d012));
+        code.extend(op::sta_abs(0xD012));
+        code
+    }
+
+    /// Fill `size` (1-256) bytes at `addr` with `fill`: LDA/LDX #0/STA,X/INX/CPX/BNE
+    fn wipe_loop(code: &mut Vec<u8>, addr: u16, size: u16, fill: u8) {
+        code.extend(op::lda_imm(fill));
+        code.extend(op::ldx_imm(0x00));
+        let wipe_loop = code.len();
+        code.extend(op::sta_abs_x(addr));
+        code.push(op::inx());
+        code.extend(op::cpx_imm(size as u8));
+        code.extend(op::bne(wipe_loop, code.len()));
+    }
+
+    /// Free RAM the restore blocks of `layout` take: its saved-data blocks, block 9 and block 10
+    ///
+    /// A lower bound to compare with `FindRam::total_free_bytes`, since each
+    /// block also needs its own run. SP doesn't change it: the `$01xx` code
+    /// lives in page 1 (see `MIN_STACK_POINTER`). `Auto` gives the smallest
+    /// layout's requirement.
+    pub fn required_free_bytes(layout: BlockLayout) -> u32 {
+        if layout == BlockLayout::Auto {
+            return [BlockLayout::Standard, BlockLayout::Compact, BlockLayout::Split]
+                .into_iter()
+                .map(Self::required_free_bytes)
+                .min()
+                .unwrap_or(0);
+        }
+
+        // Code sizes don't depend on the addresses or values, so dummy blocks do
+        let blocks: Vec<BlockAllocation> = layout
+            .pieces()
+            .iter()
+            .map(|pieces| BlockAllocation {
+                address: 0x1000,
+                original_value: 0,
+                size: pieces.iter().map(|&(_, len)| len).sum(),
+            })
+            .collect();
+        let block9_len = Self::generate_block9_final(&blocks, layout, 0xFF)
+            .expect("fixed layouts fit block 9")
+            .len();
+        let block10_len = Self::generate_block10(&[0; 8], 0, 0, 0x1000, block9_len as u16, 0)
+            .expect("block 10 has a fixed size")
+            .len();
+
+        blocks.iter().map(|block| block.size as u32).sum::<u32>() + block9_len as u32 + block10_len as u32
+    }
+
+    /// Blocks 1-10 as (address, size)
+    pub fn block_ranges(&self) -> Vec<(u16, u16)> {
+        self.blocks.iter().map(|block| (block.address, block.size)).collect()
+    }
+
     pub fn get_block9_addr(&self) -> u16 {
         self.block9_addr
     }
@@ -222,83 +529,99 @@ impl PatchMem {
     /// Generate block 9 final code without $F8-$FF restore
     fn generate_block9_final(
         blocks: &[BlockAllocation],
-        snap: &C64Snapshot,
+        layout: BlockLayout,
+        sp: u8,
     ) -> Result<Vec<u8>, PatchError> {
-        let mut code = Self::generate_block9_core(blocks)?;
+        let mut code = Self::generate_block9_core(blocks, layout)?;
 
         // Restore stack pointer here (Action Replay style!)
-        code.extend_from_slice(&[0xA2, snap.cpu.sp]); // LDX #SP
-        code.push(0x9A); // TXS
+        code.extend(op::ldx_imm(sp));
+        code.push(op::txs());
 
         // Jump to block 10 (block 10 will set up A/X/Y)
-        code.extend_from_slice(&[0x4C, 0x00, 0x00]); // JMP $0000
+        code.extend(op::jmp(0x0000));
 
         Ok(code)
     }
 
     /// Generate block 9 core
-    fn generate_block9_core(blocks: &[BlockAllocation]) -> Result<Vec<u8>, PatchError> {
+    fn generate_block9_core(blocks: &[BlockAllocation], layout: BlockLayout) -> Result<Vec<u8>, PatchError> {
         let mut code = Vec::new();
 
-        // Copy blocks 1-8 back to $0100-$01FF
-        for i in 0..8 {
-            let dst = 0x0100u16 + ((i as u16) * 32);
-            code.extend_from_slice(&[0xA2, 31]);
-            let loop_start = code.len();
-            code.extend_from_slice(&[
-                0xBD, blocks[i].address as u8, (blocks[i].address >> 8) as u8
-            ]);
-            code.extend_from_slice(&[
-                0x9D, (dst & 0xFF) as u8, (dst >> 8) as u8
-            ]);
-            code.push(0xCA);
-            let offset = ((loop_start as isize) - (code.len() as isize + 2)) as u8;
-            code.extend_from_slice(&[0x10, offset]);
+        // (block address, piece) for every saved piece; $F8-$FF is restored by block 10
+        let pieces: Vec<(u16, u16, u16)> = blocks
+            .iter()
+            .zip(layout.pieces())
+            .flat_map(|(block, pieces)| {
+                pieces.iter().scan(block.address, |src, &(dst, len)| {
+                    let piece = (*src, dst, len);
+                    *src += len;
+                    Some(piece)
+                })
+            })
+            .filter(|&(_, dst, _)| dst != 0x00F8)
+            .collect();
+
+        // Copy the blocks back to $0100-$01FF, then $FFF0-$FFFF
+        let (stack, vectors): (Vec<_>, Vec<_>) = pieces.into_iter().partition(|&(_, dst, _)| dst < 0x0200);
+        for &(src, dst, len) in stack.iter().chain(vectors.iter()) {
+            Self::copy_loop(&mut code, src, dst, len)?;
         }
 
-        // Restore $FFF0-$FFFF from block 1 offset +32
-        code.extend_from_slice(&[0xA2, 0x0F]);
-        let loop2 = code.len();
-        let addr = blocks[0].address + 32;
-        code.extend_from_slice(&[
-            0xBD, addr as u8, (addr >> 8) as u8
-        ]);
-        code.extend_from_slice(&[0x9D, 0xF0, 0xFF]);
-        code.push(0xCA);
-        let offset = ((loop2 as isize) - (code.len() as isize + 2)) as u8;
-        code.extend_from_slice(&[0x10, offset]);
-
-        // Clean blocks 1-8
-        for i in 0..8 {
-            let addr = blocks[i].address;
-            let size = blocks[i].size;
-            let value = blocks[i].original_value;
+        // Clean the blocks
+        for (i, block) in blocks.iter().enumerate() {
+            let addr = block.address;
+            let size = block.size;
+            let value = block.original_value;
 
-            if size > 256 {
+            code.extend(op::lda_imm(value));
+            code.extend(op::ldx_imm(0x00));
+            let fill = code.len();
+            code.extend(op::sta_abs_x(addr));
+            if size <= 256 {
+                code.push(op::inx());
+                code.extend(op::cpx_imm(size as u8));
+            } else if size <= 512 {
+                // Two overlapping 256-byte passes cover the whole block
+                code.extend(op::sta_abs_x(addr + size - 256));
+                code.push(op::inx());
+            } else {
                 return Err(PatchError::CodeTooLarge(
-                    format!("Block {} size {} exceeds 256 bytes", i+1, size)
+                    format!("Block {} size {} exceeds 512 bytes", i + 1, size)
                 ));
             }
-
-            code.extend_from_slice(&[0xA9, value]);
-            code.extend_from_slice(&[0xA2, 0x00]);
-            let fill = code.len();
-            code.extend_from_slice(&[
-                0x9D, addr as u8, (addr >> 8) as u8
-            ]);
-            code.push(0xE8);
-            code.extend_from_slice(&[0xE0, size as u8]);
-            let offset = ((fill as isize) - (code.len() as isize + 2)) as u8;
-            code.extend_from_slice(&[0xD0, offset]);
+            code.extend(op::bne(fill, code.len()));
         }
 
         Ok(code)
     }
 
+    /// Copy `len` bytes from `src` to `dst` (up to 128, or exactly 256)
+    fn copy_loop(code: &mut Vec<u8>, src: u16, dst: u16, len: u16) -> Result<(), PatchError> {
+        let (start_x, step, branch): (u8, u8, fn(usize, usize) -> [u8; 2]) = match len {
+            1..=128 => ((len - 1) as u8, op::dex(), op::bpl),
+            256 => (0x00, op::inx(), op::bne),
+            _ => {
+                return Err(PatchError::CodeTooLarge(
+                    format!("Cannot copy {} bytes to ${:04X} in one loop", len, dst)
+                ));
+            }
+        };
+
+        code.extend(op::ldx_imm(start_x));
+        let loop_start = code.len();
+        code.extend(op::lda_abs_x(src));
+        code.extend(op::sta_abs_x(dst));
+        code.push(step);
+        code.extend(branch(loop_start, code.len()));
+        Ok(())
+    }
+
     /// Generate block 10: Wipe block 9 and restore $F8-$FF
     fn generate_block10(
         f8_ff: &[u8; 8],
-        snap: &C64Snapshot,
+        cpu_port_dir: u8,
+        a: u8,
         block9_addr: u16,
         exact_block9_size: u16,
         block9_fill: u8,
@@ -307,31 +630,22 @@ impl PatchMem {
 
         // Wipe block 9 first
         if exact_block9_size > 0 && exact_block9_size <= 256 {
-            code.extend_from_slice(&[0xA9, block9_fill]);
-            code.extend_from_slice(&[0xA2, 0x00]);
-            let wipe_loop = code.len();
-            code.extend_from_slice(&[
-                0x9D, (block9_addr & 0xFF) as u8, (block9_addr >> 8) as u8
-            ]);
-            code.push(0xE8);
-            code.extend_from_slice(&[0xE0, exact_block9_size as u8]);
-            let offset = ((wipe_loop as isize) - (code.len() as isize + 2)) as u8;
-            code.extend_from_slice(&[0xD0, offset]);
+            Self::wipe_loop(&mut code, block9_addr, exact_block9_size, block9_fill);
         }
 
         // Restore $F8-$FF
         for i in 0..8 {
-            code.extend_from_slice(&[0xA9, f8_ff[i]]);
-            code.extend_from_slice(&[0x85, 0xF8 + i as u8]);
+            code.extend(op::lda_imm(f8_ff[i]));
+            code.extend(op::sta_zp(0xF8 + i as u8));
         }
 
         // Restore A/X/Y registers (block 9 set them up for us)
-        code.extend_from_slice(&[0xA2, snap.mem.cpu_port_dir]);
-        code.extend_from_slice(&[0xA0, 0xFF]);
-        code.extend_from_slice(&[0xA9, snap.cpu.a]);
+        code.extend(op::ldx_imm(cpu_port_dir));
+        code.extend(op::ldy_imm(0xFF));
+        code.extend(op::lda_imm(a));
 
         // Jump to restore code (placeholder - will be patched in new())
-        code.extend_from_slice(&[0x4C, 0x00, 0x00]);
+        code.extend(op::jmp(0x0000));
 
         Ok(code)
     }
@@ -342,6 +656,7 @@ impl PatchMem {
         block10_addr: u16,
         exact_block10_size: u16,
         block10_fill: u8,
+        options: RestoreOptions,
     ) -> Result<Vec<u8>, PatchError> {
         let mut code = Vec::new();
 
@@ -355,79 +670,449 @@ impl PatchMem {
 
         // Wipe block 10
         if exact_block10_size > 0 && exact_block10_size <= 256 {
-            code.push(0x48);
-
-            code.extend_from_slice(&[0xA9, block10_fill]);
-            code.extend_from_slice(&[0xA2, 0x00]);
-            let wipe_loop = code.len();
-            code.extend_from_slice(&[
-                0x9D, (block10_addr & 0xFF) as u8, (block10_addr >> 8) as u8
-            ]);
-            code.push(0xE8);
-            code.extend_from_slice(&[0xE0, exact_block10_size as u8]);
-            let offset = ((wipe_loop as isize) - (code.len() as isize + 2)) as u8;
-            code.extend_from_slice(&[0xD0, offset]);
-
-            code.extend_from_slice(&[0xA2, snap.mem.cpu_port_dir]);
-            code.extend_from_slice(&[0xA0, 0xFF]);
-            code.push(0x68);
+            code.push(op::pha());
+            Self::wipe_loop(&mut code, block10_addr, exact_block10_size, block10_fill);
+            code.extend(op::ldx_imm(snap.mem.cpu_port_dir));
+            code.extend(op::ldy_imm(0xFF));
+            code.push(op::pla());
         }
 
-        code.extend_from_slice(&[0x86, 0x00]);
+        // CPU port DDR from X; writes to $00/$01 never reach the RAM beneath,
+        // so the snapshot's RAM bytes there are not restored (VIC-II only)
+        code.extend(op::stx_zp(0x00));
 
-        code.extend_from_slice(&[0xA9, 0x35]);
-        code.extend_from_slice(&[0x85, 0x01]);
+        code.extend(op::lda_imm(0x35));
+        code.extend(op::sta_zp(0x01));
 
-        code.extend_from_slice(&[0xA9, 0x00]);
-        code.extend_from_slice(&[0x8D, 0x1A, 0xD0]);
+        code.extend(op::lda_imm(0x00));
+        code.extend(op::sta_abs(0xD01A));
 
-        code.extend_from_slice(&[0x8C, 0x19, 0xD0]);
+        code.extend(op::sty_abs(0xD019));
 
-        code.extend_from_slice(&[0xAD, 0x0D, 0xDC]);
-        code.extend_from_slice(&[0xAD, 0x0D, 0xDD]);
+        code.extend(op::lda_abs(0xDC0D));
+        code.extend(op::lda_abs(0xDD0D));
 
-        code.extend_from_slice(&[0xA9, 0xFF]);
-        code.extend_from_slice(&[0x8D, 0x19, 0xD0]);
+        code.extend(op::lda_imm(0xFF));
+        code.extend(op::sta_abs(0xD019));
 
-        code.extend_from_slice(&[0xA9, snap.vic.registers[0x1A]]);
-        code.extend_from_slice(&[0x8D, 0x1A, 0xD0]);
+        code.extend(op::lda_imm(snap.vic.registers[0x1A]));
+        code.extend(op::sta_abs(0xD01A));
 
-        code.extend_from_slice(&[0xAD, 0x0D, 0xDC]);
-        code.extend_from_slice(&[0xAD, 0x0D, 0xDD]);
-
-        if snap.cia1.ier != 0 {
-            code.extend_from_slice(&[0xA9, snap.cia1.ier | 0x80]);
-            code.extend_from_slice(&[0x8D, 0x0D, 0xDC]);
+        // The latch acknowledged above loses a pending IRQ unless it is re-armed
+        if options.rearm_raster_irq && raster_irq_pending(snap) {
+            code.extend(Self::rearm_raster_irq_code(snap));
         }
-        if snap.cia2.ier != 0 {
-            code.extend_from_slice(&[0xA9, snap.cia2.ier | 0x80]);
-            code.extend_from_slice(&[0x8D, 0x0D, 0xDD]);
+
+        // Safe order: sprites and display were blanked, enable them now RAM is back
+        if options.vic_order == VicRestoreOrder::Safe {
+            code.extend(op::lda_imm(snap.vic.registers[0x15]));
+            code.extend(op::sta_abs(0xD015));
+            code.extend(op::lda_imm(snap.vic.registers[0x11]));
+            code.extend(op::sta_abs(0xD011));
         }
 
-        code.extend_from_slice(&[0xA9, snap.cia1.cra]);
-        code.extend_from_slice(&[0x8D, 0x0E, 0xDC]);
-        code.extend_from_slice(&[0xA9, snap.cia1.crb]);
-        code.extend_from_slice(&[0x8D, 0x0F, 0xDC]);
-        code.extend_from_slice(&[0xA9, snap.cia2.cra]);
-        code.extend_from_slice(&[0x8D, 0x0E, 0xDD]);
-        code.extend_from_slice(&[0xA9, snap.cia2.crb]);
-        code.extend_from_slice(&[0x8D, 0x0F, 0xDD]);
+        code.extend(op::lda_abs(0xDC0D));
+        code.extend(op::lda_abs(0xDD0D));
 
-        code.extend_from_slice(&[0xA9, snap.mem.cpu_port_data]);
-        code.extend_from_slice(&[0x85, 0x01]);
+        // IRQ off: CIA interrupt masks stay cleared for the program to set
+        if snap.cia1.ier != 0 && !options.irq_off {
+            code.extend(op::lda_imm(snap.cia1.ier | 0x80));
+            code.extend(op::sta_abs(0xDC0D));
+        }
+        if snap.cia2.ier != 0 && !options.irq_off {
+            code.extend(op::lda_imm(snap.cia2.ier | 0x80));
+            code.extend(op::sta_abs(0xDD0D));
+        }
 
-        code.extend_from_slice(&[0xA9, (snap.cpu.pc >> 8) as u8]);
-        code.push(0x48);
-        code.extend_from_slice(&[0xA9, (snap.cpu.pc & 0xFF) as u8]);
-        code.push(0x48);
-        code.extend_from_slice(&[0xA9, snap.cpu.p]);
-        code.push(0x48);
+        // Deferred timers: the program starts them itself
+        let start_mask = if options.defer_timers { 0xFE } else { 0xFF };
+        code.extend(op::lda_imm(snap.cia1.cra & start_mask));
+        code.extend(op::sta_abs(0xDC0E));
+        code.extend(op::lda_imm(snap.cia1.crb & start_mask));
+        code.extend(op::sta_abs(0xDC0F));
+        code.extend(op::lda_imm(snap.cia2.cra & start_mask));
+        code.extend(op::sta_abs(0xDD0E));
+        code.extend(op::lda_imm(snap.cia2.crb & start_mask));
+        code.extend(op::sta_abs(0xDD0F));
+
+        // Verify while $01 is still $35: RAM everywhere except $D000-$DFFF (I/O)
+        if let Some((addr, value)) = options.verify {
+            code.extend(op::lda_abs(addr));
+            code.extend(op::cmp_imm(value));
+            // Skip the 7-byte failure path below
+            code.extend(op::beq(code.len() + 2 + 7, code.len()));
+            code.extend(op::lda_imm(VERIFY_FAIL_COLOR));
+            code.extend(op::sta_abs(0xD020));
+            // Z is clear after loading a non-zero color: branch to itself
+            code.extend(op::bne(code.len(), code.len()));
+        }
 
-        code.extend_from_slice(&[0xA2, snap.cpu.x]);
-        code.extend_from_slice(&[0xA0, snap.cpu.y]);
+        code.extend(op::lda_imm(snap.mem.cpu_port_data));
+        code.extend(op::sta_zp(0x01));
 
-        code.push(0x40);
+        match options.entry {
+            Some(entry) => code.extend(Self::rti_frame(entry, ENTRY_STATUS, snap.cpu.x, snap.cpu.y)),
+            None => code.extend(Self::resume_code(snap, options.irq_off)),
+        }
 
         Ok(code)
     }
+
+    /// Code that starts the snapshot program: RTI to its PC with P, X and Y restored
+    ///
+    /// Ends the restore code, or a prepended stub when `RestoreOptions::entry` is set.
+    pub fn resume_code(snap: &C64Snapshot, irq_off: bool) -> Vec<u8> {
+        Self::rti_frame(snap.cpu.pc, Self::rti_status(snap.cpu.p, irq_off), snap.cpu.x, snap.cpu.y)
+    }
+
+    /// Push an RTI frame for `pc` and `status`, load X and Y, then RTI
+    fn rti_frame(pc: u16, status: u8, x: u8, y: u8) -> Vec<u8> {
+        let mut code = Vec::new();
+        code.extend(op::lda_imm((pc >> 8) as u8));
+        code.push(op::pha());
+        code.extend(op::lda_imm((pc & 0xFF) as u8));
+        code.push(op::pha());
+        code.extend(op::lda_imm(status));
+        code.push(op::pha());
+
+        code.extend(op::ldx_imm(x));
+        code.extend(op::ldy_imm(y));
+
+        code.push(op::rti());
+        code
+    }
+
+    /// Status byte for the final RTI frame, in the form an IRQ would push it
+    ///
+    /// RTI ignores bit 4 (B) and bit 5, so N, V, D, I, Z and C come back
+    /// exactly as in the snapshot. The byte stays on the stack below SP,
+    /// though, so it gets bit 5 set and B clear like a hardware interrupt frame.
+    fn rti_status(p: u8, irq_off: bool) -> u8 {
+        let status = (p | 0x20) & !0x10;
+        if irq_off { status | 0x04 } else { status }
+    }
+}
+
+/// Whether the snapshot holds an enabled raster IRQ that hadn't been acknowledged
+pub fn raster_irq_pending(snap: &C64Snapshot) -> bool {
+    snap.vic.registers[0x19] & snap.vic.registers[0x1A] & 0x01 != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::{busy_ram, TestVsf};
+
+    #[test]
+    fn test_aliased_free_blocks_are_rejected() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = busy_ram();
+
+        // Two free runs at the same address: block 1 fills the exact-fit one,
+        // block 2 is handed the same addresses again from the other
+        let extra = [(0x4000, 0x0030), (0x4000, 0x0400)];
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+
+        match PatchMem::new(&snap, &mut ram, &mut finder) {
+            Err(PatchError::Overlap(msg)) => assert!(msg.contains("overlaps"), "{}", msg),
+            Err(e) => panic!("expected overlap error, got {}", e),
+            Ok(_) => panic!("expected overlap error"),
+        }
+    }
+
+    #[test]
+    fn test_block_in_stack_page_is_rejected() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = busy_ram();
+
+        let extra = [(0x0100, 0x0040), (0x4000, 0x0400)];
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+
+        assert!(matches!(
+            PatchMem::new(&snap, &mut ram, &mut finder),
+            Err(PatchError::Overlap(_))
+        ));
+    }
+
+    #[test]
+    fn test_required_free_bytes_matches_allocated_blocks() {
+        // Blocks 1-8, block 9 and block 10 of a real patch add up to the requirement
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = snap.mem.ram.clone();
+        let mut finder = FindRam::new(&ram);
+        let standard = RestoreOptions { layout: BlockLayout::Standard, ..RestoreOptions::default() };
+        let patch = PatchMem::with_options(&snap, &mut ram, &mut finder, standard).unwrap();
+        let allocated: u32 = patch.block_ranges().iter().map(|&(_, size)| size as u32).sum();
+        assert_eq!(PatchMem::required_free_bytes(BlockLayout::Standard), allocated);
+
+        let pieces: u32 = STANDARD_PIECES.iter().flat_map(|p| p.iter()).map(|&(_, len)| len as u32).sum();
+        assert!(allocated > pieces);
+
+        let compact = PatchMem::required_free_bytes(BlockLayout::Compact);
+        assert!(compact < PatchMem::required_free_bytes(BlockLayout::Standard));
+        assert_eq!(PatchMem::required_free_bytes(BlockLayout::Auto), compact);
+
+        // Too little free RAM in total is reported with both numbers
+        let mut ram = busy_ram();
+        let mut finder = FindRam::with_extra_blocks(&ram, &[(0x4000, 64)]);
+        let err = PatchMem::with_options(&snap, &mut ram, &mut finder, RestoreOptions::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains(&format!("64 bytes of free RAM, the restore blocks need at least {}", compact)), "{}", err);
+    }
+
+    #[test]
+    fn test_preferred_block9_address_is_honored_when_free() {
+        let snap = TestVsf::new().parse("patch_mem");
+        // (best-fit address, address with $9000 preferred) for the snapshot RAM with `busy` in use
+        let block9 = |busy: std::ops::Range<usize>| {
+            let mut ram = snap.mem.ram.clone();
+            ram[busy].fill(0xEA);
+            let mut finder = FindRam::new(&ram);
+            let default = PatchMem::new(&snap, &mut ram.clone(), &mut finder.clone()).unwrap().get_block9_addr();
+            let preferred = PatchMem::with_preferred_block9(&snap, &mut ram, &mut finder, RestoreOptions::default(), 0x9000)
+                .unwrap()
+                .get_block9_addr();
+            (default, preferred)
+        };
+
+        let (default, preferred) = block9(0..0);
+        assert_eq!(preferred, 0x9000);
+        assert_ne!(default, 0x9000);
+
+        // In use by the program: best fit, as without a preference
+        let (default, preferred) = block9(0x9000..0x9100);
+        assert_eq!(preferred, default);
+    }
+
+    #[test]
+    fn test_allowed_range_holds_every_restore_block() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = snap.mem.ram.clone();
+        let mut finder = FindRam::new(&ram);
+        let patch = PatchMem::with_allowed_ranges(&snap, &mut ram, &mut finder, RestoreOptions::default(), &[0xC000..=0xCFFF])
+            .unwrap();
+        for (address, size) in patch.block_ranges() {
+            assert!(address >= 0xC000 && address as u32 + size as u32 <= 0xD000, "${:04X}+{}", address, size);
+        }
+
+        // Too small for the blocks: the error names the range
+        let mut ram = snap.mem.ram.clone();
+        let mut finder = FindRam::new(&ram);
+        match PatchMem::with_allowed_ranges(&snap, &mut ram, &mut finder, RestoreOptions::default(), &[0xC000..=0xC0FF]) {
+            Err(PatchError::AllocationFailed(msg)) => assert!(msg.starts_with("restore blocks limited to $C000-$C0FF"), "{}", msg),
+            Err(e) => panic!("expected allocation error, got {}", e),
+            Ok(_) => panic!("256 bytes can't hold the restore blocks"),
+        }
+    }
+
+    #[test]
+    fn test_one_large_run_falls_back_to_compact_layout() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = busy_ram();
+
+        // 400 bytes: too few for blocks 1-10 in the standard layout
+        let extra = [(0x4000, 400)];
+        let standard = RestoreOptions { layout: BlockLayout::Standard, ..RestoreOptions::default() };
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+        assert!(matches!(
+            PatchMem::with_options(&snap, &mut ram, &mut finder, standard),
+            Err(PatchError::AllocationFailed(_))
+        ));
+
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+        let patch = PatchMem::with_options(&snap, &mut ram, &mut finder, RestoreOptions::default()).unwrap();
+        let ranges = patch.block_ranges();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0], (0x4000, 272));
+        // Stack page (with the restore code patched in), then $FFF0-$FFFF
+        assert_eq!(&ram[0x4000..0x4100], &ram[0x0100..0x0200]);
+        assert_eq!(&ram[0x4100..0x4110], &ram[0xFFF0..]);
+    }
+
+    #[test]
+    fn test_small_runs_fall_back_to_split_layout() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = busy_ram();
+
+        // Nine 32-byte runs and one for blocks 9 and 10; block 1 (48 bytes) fits nowhere else
+        let mut extra: Vec<(u16, u16)> = (0..9).map(|i| (0x4000 + i * 0x100, 32)).collect();
+        extra.push((0x6000, 0x120));
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+        let patch = PatchMem::with_options(&snap, &mut ram, &mut finder, RestoreOptions::default()).unwrap();
+
+        let ranges = patch.block_ranges();
+        assert_eq!(ranges.len(), 11);
+        assert!(ranges[..9].iter().all(|&(_, size)| size <= 32));
+    }
+
+    #[test]
+    fn test_very_low_stack_pointer_is_rejected() {
+        let mut snap = TestVsf::new().parse("patch_mem");
+        snap.cpu.sp = 0x04;
+        let mut ram = busy_ram();
+        let before = ram.clone();
+
+        let extra = [(0x4000, 0x0400)];
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+
+        match PatchMem::new(&snap, &mut ram, &mut finder) {
+            Err(PatchError::StackTooLow(msg)) => {
+                assert!(msg.contains("SP=$04"), "{msg}");
+                assert!(msg.contains("decompressor"), "{msg}");
+            }
+            Err(e) => panic!("expected stack error, got {e}"),
+            Ok(_) => panic!("expected stack error"),
+        }
+        // Nothing was patched into page 1
+        assert_eq!(ram[0x0100..0x0200], before[0x0100..0x0200]);
+    }
+
+    #[test]
+    fn test_disjoint_layout_is_accepted() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let mut ram = busy_ram();
+
+        let extra = [(0x4000, 0x0400)];
+        let mut finder = FindRam::with_extra_blocks(&ram, &extra);
+
+        assert!(PatchMem::new(&snap, &mut ram, &mut finder).is_ok());
+    }
+
+    /// P byte pushed for the RTI frame: last `LDA #imm` before the final `PHA` ahead of LDX/LDY/RTI
+    fn pushed_p(code: &[u8]) -> u8 {
+        let rti = code.len() - 1;
+        assert_eq!(code[rti], 0x40);
+        // ... LDA #p, PHA, LDX #x, LDY #y, RTI
+        assert_eq!(code[rti - 6], 0x48);
+        code[rti - 7]
+    }
+
+    #[test]
+    fn test_irq_off_sets_i_flag_in_rti_frame() {
+        let mut snap = TestVsf::new().parse("patch_mem");
+        snap.cpu.p = 0x20;
+        snap.cia1.ier = 0x01;
+
+        let normal = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+        assert_eq!(pushed_p(&normal), 0x20);
+        assert!(normal.windows(5).any(|w| w == [0xA9, 0x81, 0x8D, 0x0D, 0xDC]));
+
+        let options = RestoreOptions { irq_off: true, ..RestoreOptions::default() };
+        let irq_off = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, options).unwrap();
+        assert_eq!(pushed_p(&irq_off), 0x24);
+        assert!(!irq_off.windows(5).any(|w| w == [0xA9, 0x81, 0x8D, 0x0D, 0xDC]));
+    }
+
+    #[test]
+    fn test_defer_timers_clears_start_bits() {
+        let mut snap = TestVsf::new().parse("patch_mem");
+        snap.cia1.cra = 0x11;
+        snap.cia1.crb = 0x41;
+        snap.cia2.cra = 0x01;
+        snap.cia2.crb = 0x09;
+        let writes = |code: &[u8]| -> Vec<u8> {
+            [[0x0E, 0xDC], [0x0F, 0xDC], [0x0E, 0xDD], [0x0F, 0xDD]]
+                .iter()
+                .map(|reg| {
+                    let pos = code.windows(5).position(|w| w[0] == 0xA9 && w[2] == 0x8D && w[3..] == reg[..]).unwrap();
+                    code[pos + 1]
+                })
+                .collect()
+        };
+
+        let normal = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+        assert_eq!(writes(&normal), vec![0x11, 0x41, 0x01, 0x09]);
+
+        let options = RestoreOptions { defer_timers: true, ..RestoreOptions::default() };
+        let deferred = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, options).unwrap();
+        assert_eq!(writes(&deferred), vec![0x10, 0x40, 0x00, 0x08]);
+    }
+
+    #[test]
+    fn test_raster_irq_latch_is_only_rearmed_on_request() {
+        let mut snap = TestVsf::new().parse("patch_mem");
+        snap.vic.registers[0x11] = 0x9B;
+        snap.vic.registers[0x12] = 0x30;
+        snap.vic.registers[0x19] = 0x81;
+        snap.vic.registers[0x1A] = 0x01;
+        let rearm = [0xAD, 0x12, 0xD0, 0x8D, 0x12, 0xD0, 0xA9, 0x9B, 0x8D, 0x11, 0xD0, 0xA9, 0x30, 0x8D, 0x12, 0xD0];
+
+        // Default: the latch is acknowledged ($FF -> $D019) before $D01A is enabled and never re-armed
+        let plain = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+        let ack = plain.windows(5).position(|w| w == [0xA9, 0xFF, 0x8D, 0x19, 0xD0]).unwrap();
+        let enable = plain.windows(5).position(|w| w == [0xA9, 0x01, 0x8D, 0x1A, 0xD0]).unwrap();
+        assert!(ack < enable);
+        assert!(!plain.windows(3).any(|w| w == [0xAD, 0x12, 0xD0]));
+
+        let options = RestoreOptions { rearm_raster_irq: true, ..RestoreOptions::default() };
+        let rearmed = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, options).unwrap();
+        let enable = rearmed.windows(5).position(|w| w == [0xA9, 0x01, 0x8D, 0x1A, 0xD0]).unwrap();
+        assert_eq!(&rearmed[enable + 5..enable + 11], &[0xAD, 0x11, 0xD0, 0x8D, 0x11, 0xD0]);
+        assert_eq!(&rearmed[enable + 11..enable + 11 + rearm.len()], &rearm);
+
+        // Nothing pending: the option adds no code
+        snap.vic.registers[0x19] = 0x00;
+        let idle = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, options).unwrap();
+        assert_eq!(idle, plain);
+    }
+
+    #[test]
+    fn test_verify_compares_and_halts_with_red_border() {
+        let snap = TestVsf::new().parse("patch_mem");
+        let plain = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+        assert!(!plain.windows(3).any(|w| w == [0x8D, 0x20, 0xD0]));
+
+        let options = RestoreOptions { verify: Some((0xC123, 0x42)), ..RestoreOptions::default() };
+        let code = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, options).unwrap();
+        // LDA $C123, CMP #$42, BEQ ok, LDA #red, STA $D020, BNE *
+        let check = [0xAD, 0x23, 0xC1, 0xC9, 0x42, 0xF0, 0x07, 0xA9, VERIFY_FAIL_COLOR, 0x8D, 0x20, 0xD0, 0xD0, 0xFE];
+        let at = code.windows(check.len()).position(|w| w == check).expect("verify check missing");
+        // The match path continues with the CPU port and the RTI frame
+        assert_eq!(&code[at + check.len()..at + check.len() + 4], &[0xA9, snap.mem.cpu_port_data, 0x85, 0x01]);
+        assert_eq!(code.len(), plain.len() + check.len());
+    }
+
+    #[test]
+    fn test_rti_frame_status_matches_hardware() {
+        // (snapshot P, pushed byte): B dropped, bit 5 set, D/I/NVZC kept
+        let cases = [
+            (0x00, 0x20),
+            (0x20, 0x20),
+            (0x30, 0x20),
+            (0x08, 0x28),
+            (0x3C, 0x2C),
+            (0xFF, 0xEF),
+            (0xC3, 0xE3),
+        ];
+        let mut snap = TestVsf::new().parse("patch_mem");
+        for (p, expected) in cases {
+            snap.cpu.p = p;
+            let code = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+            assert_eq!(pushed_p(&code), expected, "P = ${:02X}", p);
+            // What RTI loads into the register (bits 4/5 don't exist there)
+            assert_eq!(pushed_p(&code) & 0xCF, p & 0xCF);
+        }
+    }
+
+    #[test]
+    fn test_cpu_port_is_set_from_snapshot_port_not_ram() {
+        let mut snap = TestVsf::new().parse("patch_mem");
+        snap.mem.cpu_port_dir = 0x2F;
+        snap.mem.cpu_port_data = 0x36;
+        let code = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+
+        // DDR: STX $00 with X = snapshot DDR (loaded by block 10)
+        assert!(code.windows(2).any(|w| w == [0x86, 0x00]));
+        // Data: the last $01 write is the snapshot value, after all I/O setup
+        let last = code.windows(4).rposition(|w| w[0] == 0xA9 && w[2..] == [0x85, 0x01]).unwrap();
+        assert_eq!(code[last + 1], 0x36);
+
+        // RAM under the port is not part of the restore
+        snap.mem.ram[0x00] = 0xAA;
+        snap.mem.ram[0x01] = 0x55;
+        let with_ram = PatchMem::generate_restore_code(&snap, 0x4000, 0x20, 0x00, RestoreOptions::default()).unwrap();
+        assert_eq!(code, with_ram);
+    }
 }
@@ -0,0 +1,220 @@
+//! User code run between the restore and the snapshot program (`--prepend`)
+//!
+//! The stub is a PRG loaded into free RAM of the snapshot (a run of identical
+//! bytes), e.g. a splash screen or a key wait. The restore ends with an RTI
+//! into a small entry placed right after the stub, which calls it with JSR.
+//! When the stub returns with RTS, the entry resumes the snapshot program
+//! the same way the restore would have: P, X and Y restored, RTI to the PC.
+//!
+//! Constraints:
+//! - The stub runs with interrupts disabled and the snapshot's memory
+//!   configuration; it must return with RTS and leave the stack balanced
+//! - Stub and entry stay in RAM; the program may overwrite them later
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::parse_vsf::C64Snapshot;
+use crate::patch_mem::{PatchMem, RestoreOptions};
+
+/// Free RAM as scanned by `FindRam`
+const FREE_START: usize = 0x0200;
+const FREE_END: usize = 0xFFF0;
+
+/// A user PRG that runs after the restore, before the snapshot program
+#[derive(Debug, Clone)]
+pub struct PrependStub {
+    load_address: u16,
+    code: Vec<u8>,
+}
+
+impl PrependStub {
+    /// Take a PRG image: 2-byte load address followed by the code
+    pub fn from_prg(prg: &[u8]) -> Result<Self, String> {
+        if prg.len() < 3 {
+            return Err("Prepend stub must be a PRG with a load address and at least one byte of code".to_string());
+        }
+        Ok(Self {
+            load_address: u16::from_le_bytes([prg[0], prg[1]]),
+            code: prg[2..].to_vec(),
+        })
+    }
+
+    /// Read a PRG file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let prg = fs::read(path)
+            .map_err(|e| format!("Failed to read prepend stub {}: {}", path.display(), e))?;
+        Self::from_prg(&prg)
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// Entry the restore returns to: JSR to the stub, then the resume code
+    pub fn entry(&self) -> u16 {
+        (self.load_address as usize + self.code.len()) as u16
+    }
+
+    /// JSR to the stub followed by `PatchMem::resume_code`
+    fn entry_code(&self, snap: &C64Snapshot, irq_off: bool) -> Vec<u8> {
+        let mut code = vec![0x20, (self.load_address & 0xFF) as u8, (self.load_address >> 8) as u8];
+        code.extend(PatchMem::resume_code(snap, irq_off));
+        code
+    }
+
+    /// Write stub and entry to `ram`; the range must be free RAM visible to the CPU
+    pub fn install(&self, snap: &C64Snapshot, ram: &mut [u8; 65536], irq_off: bool) -> Result<(), String> {
+        let entry_code = self.entry_code(snap, irq_off);
+        let start = self.load_address as usize;
+        let end = start + self.code.len() + entry_code.len();
+
+        if start < FREE_START || end > FREE_END {
+            return Err(format!(
+                "Prepend stub at ${:04X}-${:04X} must lie within ${:04X}-${:04X}",
+                start,
+                end - 1,
+                FREE_START,
+                FREE_END - 1
+            ));
+        }
+
+        if let Some(area) = hidden_by(snap.mem.cpu_port_data, start, end) {
+            return Err(format!(
+                "Prepend stub at ${:04X}-${:04X} overlaps {}, which the snapshot has banked in (${:02X} in $01)",
+                start,
+                end - 1,
+                area,
+                snap.mem.cpu_port_data
+            ));
+        }
+
+        let region = &ram[start..end];
+        if region.iter().any(|&b| b != region[0]) {
+            return Err(format!(
+                "Prepend stub at ${:04X}-${:04X} overlaps RAM the snapshot uses; \
+                 load it where the snapshot has free (uniformly filled) memory",
+                start,
+                end - 1
+            ));
+        }
+
+        let entry = start + self.code.len();
+        ram[start..entry].copy_from_slice(&self.code);
+        ram[entry..end].copy_from_slice(&entry_code);
+        Ok(())
+    }
+
+    /// Fail if a restore block (address, size) was placed over the stub
+    pub fn check_blocks(&self, blocks: &[(u16, u16)]) -> Result<(), String> {
+        let start = self.load_address as usize;
+        let end = self.entry() as usize;
+        for &(address, size) in blocks {
+            let (block_start, block_end) = (address as usize, address as usize + size as usize);
+            if block_start < end && start < block_end {
+                return Err(format!(
+                    "Restore block ${:04X}-${:04X} overlaps the prepend stub at ${:04X}; \
+                     load the stub elsewhere",
+                    block_start,
+                    block_end - 1,
+                    start
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ROM or I/O the CPU sees instead of RAM in `start..end`, for CPU port value `port`
+pub(crate) fn hidden_by(port: u8, start: usize, end: usize) -> Option<&'static str> {
+    let overlaps = |from: usize, to: usize| start < to && from < end;
+    let banks = port & 0x03;
+
+    if banks == 0x03 && overlaps(0xA000, 0xC000) {
+        Some("BASIC ROM")
+    } else if banks != 0 && overlaps(0xD000, 0xE000) {
+        Some(if port & 0x04 != 0 { "I/O" } else { "character ROM" })
+    } else if port & 0x02 != 0 && overlaps(0xE000, 0x10000) {
+        Some("KERNAL ROM")
+    } else {
+        None
+    }
+}
+
+/// Install the `Config::prepend` stub, if any, and return the restore options entering it
+///
+/// Call before scanning for free RAM, so restore blocks are placed around
+/// the stub; check them afterwards with `PrependStub::check_blocks`.
+pub fn install_configured(
+    config: &Config,
+    snap: &C64Snapshot,
+    ram: &mut [u8; 65536],
+) -> Result<(RestoreOptions, Option<PrependStub>), String> {
+    let mut options = config.restore_options();
+    let stub = match config.prepend {
+        Some(ref path) => PrependStub::load(path)?,
+        None => return Ok((options, None)),
+    };
+
+    stub.install(snap, ram, config.irq_off)?;
+    options.entry = Some(stub.entry());
+    Ok((options, Some(stub)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_ram::FindRam;
+    use crate::test_vsf::TestVsf;
+
+    /// INC $D020; RTS at $C000
+    const STUB: [u8; 6] = [0x00, 0xC0, 0xEE, 0x20, 0xD0, 0x60];
+
+    #[test]
+    fn test_restore_enters_stub_which_resumes_at_pc() {
+        let snap = TestVsf::new().parse("prepend");
+        let stub = PrependStub::from_prg(&STUB).unwrap();
+        let mut ram = snap.mem.ram.clone();
+        stub.install(&snap, &mut ram, false).unwrap();
+
+        let mut finder = FindRam::new(&ram);
+        let options = RestoreOptions { entry: Some(stub.entry()), ..RestoreOptions::default() };
+        let patch = PatchMem::with_options(&snap, &mut ram, &mut finder, options).unwrap();
+        stub.check_blocks(&patch.block_ranges()).unwrap();
+
+        // The restore's RTI frame targets the entry at $C004 with I set
+        let frame = [0xA9, 0xC0, 0x48, 0xA9, 0x04, 0x48, 0xA9, 0x24, 0x48];
+        assert!(ram[0x0100..0x0200].windows(frame.len()).any(|w| w == frame));
+
+        // Stub, then JSR $C000 and an RTI frame for the snapshot PC ($0810)
+        assert_eq!(&ram[0xC000..0xC004], &STUB[2..]);
+        assert_eq!(&ram[0xC004..0xC007], &[0x20, 0x00, 0xC0]);
+        assert_eq!(&ram[0xC007..0xC00D], &[0xA9, 0x08, 0x48, 0xA9, 0x10, 0x48]);
+        let end = 0xC007 + PatchMem::resume_code(&snap, false).len();
+        assert_eq!(ram[end - 1], 0x40);
+    }
+
+    #[test]
+    fn test_stub_over_used_ram_is_rejected() {
+        let mut vsf = TestVsf::new();
+        vsf.ram[0xC002] = 0x55;
+        let snap = vsf.parse("prepend_used");
+        let mut ram = snap.mem.ram.clone();
+
+        let err = PrependStub::from_prg(&STUB).unwrap().install(&snap, &mut ram, false).unwrap_err();
+        assert!(err.contains("$C000"), "{}", err);
+    }
+
+    #[test]
+    fn test_stub_under_kernal_is_rejected() {
+        let snap = TestVsf::new().parse("prepend_kernal");
+        let mut ram = snap.mem.ram.clone();
+
+        let err = PrependStub::from_prg(&[0x00, 0xE0, 0x60]).unwrap().install(&snap, &mut ram, false).unwrap_err();
+        assert!(err.contains("KERNAL"), "{}", err);
+    }
+}
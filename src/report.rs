@@ -0,0 +1,192 @@
+//! Machine-readable summary of a finished conversion
+//!
+//! Collected after the converter has written its output, while the work
+//! directory still holds the compressed components, and rendered as JSON for
+//! build pipelines (CLI `--report`).
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::convert_snapshot::json_escape;
+use crate::parse_vsf::{Cpu6510, ParseVSF, VideoStandard};
+
+/// Components left in the work directory by `extract_ram` + `compress_lzsa`:
+/// (name, file suffix after the snapshot's file stem)
+const COMPONENT_FILES: [(&str, &str); 7] = [
+    ("ram", "-ram.hi.lzsa"),
+    ("color", "-color.lzsa"),
+    ("zp", "-zp.lzsa"),
+    ("vic", "-vic.lzsa"),
+    ("sid", "-sid.lzsa"),
+    ("cia1", "-cia1"),
+    ("cia2", "-cia2"),
+];
+
+/// CRT file header length and CHIP packet header length
+const CRT_HEADER_LEN: usize = 0x40;
+const CHIP_HEADER_LEN: usize = 0x10;
+
+/// Result of one conversion
+pub struct ConversionReport {
+    pub input_path: String,
+    pub output_path: String,
+    /// Output format as shown to the user ("PRG", "EasyFlash CRT", ...)
+    pub format: String,
+    /// VSF machine name ("C64" or "C64SC")
+    pub machine: String,
+    /// Video standard of the snapshot (PAL unless the VIC-II model says otherwise)
+    pub video_standard: VideoStandard,
+    pub cpu: Cpu6510,
+    /// Component sizes in bytes, compressed except for the CIAs
+    pub components: Vec<(String, u64)>,
+    pub output_size: u64,
+    /// Number of 8K banks, for CRT output
+    pub banks: Option<usize>,
+    /// C64 names of the embedded include-dir files, as the conversion placed them
+    pub files: Vec<String>,
+}
+
+impl ConversionReport {
+    /// Gather the report for a conversion that has just finished
+    ///
+    /// `config` must be the converter's configuration, before its work
+    /// directory is cleaned up. `files` are the embedded files the conversion
+    /// reported (`CrtUsage::files`), empty for a PRG.
+    pub fn collect(
+        input_path: &str,
+        output_path: &str,
+        format: &str,
+        config: &Config,
+        files: &[String],
+    ) -> Result<Self, String> {
+        let parser = ParseVSF::import_streaming(input_path, config)
+            .map_err(|e| format!("Failed to read VSF file: {}", e))?;
+        let snap = parser.parse_import()
+            .map_err(|e| format!("Failed to parse VSF: {}", e))?;
+
+        let stem = Path::new(input_path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("");
+        let components = COMPONENT_FILES
+            .iter()
+            .filter_map(|&(name, suffix)| {
+                let path = config.work_path.join(format!("{}{}", stem, suffix));
+                fs::metadata(path).ok().map(|m| (name.to_string(), m.len()))
+            })
+            .collect();
+
+        let output = fs::read(output_path)
+            .map_err(|e| format!("Failed to read output file {}: {}", output_path, e))?;
+
+        Ok(Self {
+            input_path: input_path.to_string(),
+            output_path: output_path.to_string(),
+            format: format.to_string(),
+            machine: parser.machine_name().unwrap_or_default(),
+            video_standard: snap.vic.video_standard().unwrap_or(VideoStandard::Pal),
+            cpu: snap.cpu,
+            components,
+            output_size: output.len() as u64,
+            banks: crt_bank_count(&output),
+            files: files.to_vec(),
+        })
+    }
+
+    /// Render as a JSON object
+    pub fn to_json(&self) -> String {
+        let standard = self.video_standard.name();
+        let components: Vec<String> = self.components
+            .iter()
+            .map(|(name, size)| format!("    {{ \"name\": \"{}\", \"size\": {} }}", name, size))
+            .collect();
+        let files: Vec<String> = self.files
+            .iter()
+            .map(|name| format!("\"{}\"", json_escape(name)))
+            .collect();
+        let banks = self.banks.map_or("null".to_string(), |n| n.to_string());
+        let cpu = &self.cpu;
+
+        format!(
+            "{{\n  \"input\": \"{}\",\n  \"output\": \"{}\",\n  \"format\": \"{}\",\n  \"machine\": \"{}\",\n  \"video_standard\": \"{}\",\n  \"cpu\": {{ \"pc\": {}, \"a\": {}, \"x\": {}, \"y\": {}, \"sp\": {}, \"p\": {} }},\n  \"components\": [\n{}\n  ],\n  \"output_size\": {},\n  \"banks\": {},\n  \"files\": [{}]\n}}\n",
+            json_escape(&self.input_path),
+            json_escape(&self.output_path),
+            json_escape(&self.format),
+            json_escape(&self.machine),
+            standard,
+            cpu.pc, cpu.a, cpu.x, cpu.y, cpu.sp, cpu.p,
+            components.join(",\n"),
+            self.output_size,
+            banks,
+            files.join(", ")
+        )
+    }
+}
+
+/// Count the distinct banks in a CRT image; `None` if it is not a CRT
+fn crt_bank_count(image: &[u8]) -> Option<usize> {
+    if !image.starts_with(b"C64 CARTRIDGE") || image.len() < CRT_HEADER_LEN {
+        return None;
+    }
+
+    let mut banks = BTreeSet::new();
+    let mut pos = CRT_HEADER_LEN;
+    while pos + CHIP_HEADER_LEN <= image.len() && &image[pos..pos + 4] == b"CHIP" {
+        let len = u32::from_be_bytes([image[pos + 4], image[pos + 5], image[pos + 6], image[pos + 7]]) as usize;
+        banks.insert(u16::from_be_bytes([image[pos + 10], image[pos + 11]]));
+        if len < CHIP_HEADER_LEN {
+            break;
+        }
+        pos += len;
+    }
+    Some(banks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert_snapshot::ConvertSnapshot;
+    use crate::crt_builder::{CRTBuilder, CartridgeType};
+    use crate::test_vsf::TestVsf;
+
+    #[test]
+    fn test_prg_report_matches_output() {
+        let (dir, vsf) = TestVsf::new().write("report");
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+        let output = dir.join("report.prg");
+        let output = output.to_str().unwrap();
+
+        let config = Config::new(&work);
+        ConvertSnapshot::new(config.clone()).convert(&vsf, output).unwrap();
+        let report = ConversionReport::collect(&vsf, output, "PRG", &config, &[]).unwrap();
+        let json = report.to_json();
+
+        for key in [
+            "\"input\"", "\"output\"", "\"format\"", "\"machine\"", "\"video_standard\"",
+            "\"cpu\"", "\"sp\"", "\"components\"", "\"output_size\"", "\"banks\"", "\"files\"",
+        ] {
+            assert!(json.contains(key), "missing {} in {}", key, json);
+        }
+        assert_eq!(report.output_size, fs::metadata(output).unwrap().len());
+        assert!(json.contains(&format!("\"output_size\": {}", report.output_size)));
+        assert!(json.contains("\"machine\": \"C64SC\""));
+        assert!(json.contains("\"banks\": null"));
+        assert_eq!(report.components.len(), COMPONENT_FILES.len());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_crt_bank_count() {
+        let mut crt = CRTBuilder::new(CartridgeType::EasyFlash, 3, "Report").unwrap();
+        crt.set_bank_romh(0, &[0u8; 8192]).unwrap();
+        assert_eq!(crt_bank_count(&crt.generate_crt_data()), Some(3));
+        assert_eq!(crt_bank_count(&[0x01, 0x08]), None);
+    }
+}
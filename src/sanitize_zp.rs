@@ -0,0 +1,113 @@
+//! Detection and opt-in repair of damaged BASIC zero page routines
+//!
+//! CHRGET ($0073-$008A) is copied from BASIC ROM at reset and fetches every
+//! character BASIC interprets; IMAIN ($0302) is the warm-start vector BASIC
+//! jumps through on its way back to READY. A snapshot with either damaged
+//! restores fine but crashes as soon as the program returns to BASIC.
+//! Zero page a program took over for its own data is left alone: only a
+//! CHRGET that is still recognisable counts as damaged.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use crate::parse_vsf::C64Snapshot;
+use crate::prepend_stub::hidden_by;
+
+/// CHRGET as BASIC ROM copies it; `None` marks TXTPTR ($7A/$7B), which varies
+const CHRGET: [Option<u8>; 24] = [
+    Some(0xE6), Some(0x7A), Some(0xD0), Some(0x02), Some(0xE6), Some(0x7B), // INC TXTPTR
+    Some(0xAD), None, None,                                                  // LDA (TXTPTR)
+    Some(0xC9), Some(0x3A), Some(0xB0), Some(0x0A), Some(0xC9), Some(0x20),
+    Some(0xF0), Some(0xEF), Some(0x38), Some(0xE9), Some(0x30), Some(0x38),
+    Some(0xE9), Some(0xD0), Some(0x60),
+];
+const CHRGET_ADDR: usize = 0x73;
+const CHRGET_NAME: &str = "CHRGET at $0073";
+/// Fixed CHRGET bytes that must match before a mismatch counts as damage
+const CHRGET_MIN_MATCHES: usize = 11;
+
+/// BASIC warm-start vector and its ROM default
+const IMAIN: usize = 0x0302;
+const IMAIN_DEFAULT: u16 = 0xA483;
+const IMAIN_NAME: &str = "warm-start vector at $0302";
+
+/// BASIC zero page routines that look damaged, by name
+///
+/// Only checked while BASIC ROM is banked in and CHRGET is still mostly
+/// intact; otherwise the program doesn't use the BASIC environment.
+pub fn damaged_basic_routines(snap: &C64Snapshot) -> Vec<&'static str> {
+    let ram = &snap.mem.ram;
+    let port = snap.mem.cpu_port_data;
+    if port & 0x03 != 0x03 {
+        return Vec::new();
+    }
+
+    let fixed = CHRGET.iter().zip(&ram[CHRGET_ADDR..]).filter_map(|(want, &have)| want.map(|w| w == have));
+    let (matches, total) = fixed.fold((0, 0), |(m, t), ok| (m + ok as usize, t + 1));
+    if matches < CHRGET_MIN_MATCHES {
+        return Vec::new();
+    }
+
+    let mut damaged = Vec::new();
+    if matches < total {
+        damaged.push(CHRGET_NAME);
+    }
+
+    let target = u16::from_le_bytes([ram[IMAIN], ram[IMAIN + 1]]) as usize;
+    let at_brk = hidden_by(port, target, target + 1).is_none() && ram[target] == 0x00;
+    if target < 0x0200 || at_brk {
+        damaged.push(IMAIN_NAME);
+    }
+    damaged
+}
+
+/// Write the ROM values over damaged BASIC zero page routines (`--sanitize-zp`)
+///
+/// Returns what was repaired. TXTPTR inside CHRGET is kept.
+pub fn repair_basic_routines(snap: &mut C64Snapshot) -> Vec<&'static str> {
+    let damaged = damaged_basic_routines(snap);
+    let ram = &mut snap.mem.ram;
+
+    if damaged.contains(&CHRGET_NAME) {
+        for (offset, byte) in CHRGET.iter().enumerate() {
+            if let Some(byte) = *byte {
+                ram[CHRGET_ADDR + offset] = byte;
+            }
+        }
+    }
+    if damaged.contains(&IMAIN_NAME) {
+        ram[IMAIN..IMAIN + 2].copy_from_slice(&IMAIN_DEFAULT.to_le_bytes());
+    }
+    damaged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::TestVsf;
+
+    #[test]
+    fn test_corrupt_chrget_detected_and_repaired() {
+        let mut vsf = TestVsf::new();
+        for (offset, byte) in CHRGET.iter().enumerate() {
+            vsf.ram[CHRGET_ADDR + offset] = byte.unwrap_or(0x08);
+        }
+        vsf.ram[IMAIN..IMAIN + 2].copy_from_slice(&IMAIN_DEFAULT.to_le_bytes());
+        assert!(damaged_basic_routines(&vsf.parse("zp_intact")).is_empty());
+
+        // CMP #$3A clobbered, warm-start vector into zero page
+        vsf.ram[0x7C] = 0x00;
+        vsf.ram[IMAIN..IMAIN + 2].copy_from_slice(&[0x80, 0x00]);
+        let mut snap = vsf.parse("zp_corrupt");
+        assert_eq!(damaged_basic_routines(&snap), [CHRGET_NAME, IMAIN_NAME]);
+
+        assert_eq!(repair_basic_routines(&mut snap).len(), 2);
+        assert_eq!(snap.mem.ram[0x7C], 0xC9);
+        assert_eq!(&snap.mem.ram[0x7A..0x7C], &[0x08, 0x08]);
+        assert_eq!(&snap.mem.ram[IMAIN..IMAIN + 2], &[0x83, 0xA4]);
+        assert!(damaged_basic_routines(&snap).is_empty());
+
+        // Zero page taken over by the program: nothing to repair
+        assert!(damaged_basic_routines(&TestVsf::new().parse("zp_reused")).is_empty());
+    }
+}
@@ -0,0 +1,597 @@
+//! Read-only sanity checks on a parsed snapshot and on generated output
+//!
+//! Looks for signs that the snapshot was taken during or right after a VICE
+//! "Smart attach": an autostart command still waiting in the keyboard buffer,
+//! or RAM holding VICE's power-up pattern instead of a uniform fill. Neither
+//! stops the conversion, but both commonly produce a PRG/CRT that crashes.
+//! Files left open on a device can't be reconnected after the restore, and
+//! a cartridge mapped in at snapshot time is missing from the restored machine.
+//! A CPU caught at a BRK or in the KERNAL's BRK handler suggests the snapshot
+//! was taken from the monitor at a breakpoint rather than a running program.
+//! A damaged CHRGET or BASIC warm-start vector crashes a return to BASIC.
+//! Only the first SID of a stereo setup is restored, and a display switched
+//! off at snapshot time restores as a blank screen. A stack more than half
+//! full suggests page 1 is used for more than return addresses.
+//! Generated PRGs are checked for running into the I/O area while loading.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+
+use crate::config::Config;
+use crate::load_save_hook::{trampoline_stack_warning, LoadSaveHook};
+use crate::make_prg_asm::{ram_decompression_overlap, staged_ram_stream};
+use crate::parse_vsf::{C64Snapshot, ParseVSF};
+use crate::prepend_stub::hidden_by;
+use crate::sanitize_zp::damaged_basic_routines;
+
+/// Keyboard buffer length ($C6) and buffer ($0277-$0280)
+const KEYBOARD_COUNT: usize = 0xC6;
+const KEYBOARD_BUFFER: usize = 0x0277;
+const KEYBOARD_BUFFER_LEN: usize = 10;
+
+/// VICE power-up RAM: 64 bytes of $00 followed by 64 bytes of $FF
+const POWER_UP_RUN: usize = 64;
+/// Pattern chunks (128 bytes each) needed before we call it uncleared RAM
+const POWER_UP_MIN_CHUNKS: usize = 32;
+
+/// Open file count ($98) and the KERNAL file tables: logical file numbers
+/// (LAT $0259), device numbers (FAT $0263) and secondary addresses (SAT $026D)
+const OPEN_FILE_COUNT: usize = 0x98;
+const FILE_NUMBERS: usize = 0x0259;
+const FILE_DEVICES: usize = 0x0263;
+const FILE_TABLE_LEN: usize = 10;
+/// Keyboard and screen need no device to be reconnected
+const LOCAL_DEVICES: [u8; 2] = [0, 3];
+
+/// KERNAL BRK handler ($FE66), only reached through a BRK
+const KERNAL_BRK_HANDLER: std::ops::RangeInclusive<u16> = 0xFE66..=0xFE71;
+/// Bytes around the PC checked for code when the PC points at a BRK
+const CODE_WINDOW: usize = 8;
+/// B flag in a status byte pushed by BRK
+const BREAK_FLAG: u8 = 0x10;
+
+/// SP below this means more than half of page 1 is in use as stack
+const LOW_STACK_POINTER: u8 = 0x80;
+
+/// LOAD writes through to I/O at $D000-$DFFF, so a PRG must end below it
+const PRG_LOAD_LIMIT: usize = 0xD000;
+
+/// Describe why the snapshot looks like a Smart attach, if it does
+pub fn smart_attach_warning(snap: &C64Snapshot) -> Option<String> {
+    let mut reasons = Vec::new();
+
+    if let Some(command) = pending_autostart(&snap.mem.ram) {
+        reasons.push(format!("the keyboard buffer still holds \"{}\"", command));
+    }
+
+    let chunks = power_up_pattern_chunks(&snap.mem.ram);
+    if chunks >= POWER_UP_MIN_CHUNKS {
+        reasons.push(format!(
+            "{} KB of RAM still holds VICE's power-up $00/$FF pattern",
+            chunks * 2 * POWER_UP_RUN / 1024
+        ));
+    }
+
+    if reasons.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "Snapshot may have been taken during a Smart attach ({}). \
+         The result may crash; clear RAM (f 0000 ffff 00 + reset) and load without Smart attach.",
+        reasons.join(", ")
+    ))
+}
+
+/// Describe logical files that were open on a device when the snapshot was taken
+///
+/// The KERNAL tables come back with RAM, but the drive, tape or RS-232
+/// channel behind them doesn't, so the next CHRIN/CHROUT fails or hangs.
+pub fn open_files_warning(snap: &C64Snapshot) -> Option<String> {
+    let ram = &snap.mem.ram;
+    let count = (ram[OPEN_FILE_COUNT] as usize).min(FILE_TABLE_LEN);
+
+    let files: Vec<String> = (0..count)
+        .filter(|&i| !LOCAL_DEVICES.contains(&ram[FILE_DEVICES + i]))
+        .map(|i| format!("#{} on device {}", ram[FILE_NUMBERS + i], ram[FILE_DEVICES + i]))
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "Snapshot has open files ({}). Open channels can't be reconnected in a standalone PRG/CRT, \
+         so the program may fail or hang on its next read or write. Take the snapshot with no files open.",
+        files.join(", ")
+    ))
+}
+
+/// Describe the cartridge mode if a cartridge was mapped in when the snapshot was taken
+///
+/// The restore only brings back RAM, so a program running from or calling
+/// into cartridge ROM finds RAM there instead, in PRG and CRT output alike.
+pub fn cartridge_warning(snap: &C64Snapshot) -> Option<String> {
+    let mode = snap.mem.cartridge_mode()?;
+
+    Some(format!(
+        "Snapshot depended on a cartridge ({} mode, EXROM/GAME asserted). The restore runs without \
+         its ROM, so code or data in cartridge ROM will be missing. Detach the cartridge before \
+         taking the snapshot.",
+        mode
+    ))
+}
+
+/// Describe why the CPU state looks captured at a monitor breakpoint, if it does
+///
+/// Best effort: a BRK at the PC amid code (a breakpoint or a crash about to
+/// happen), the PC in the KERNAL BRK handler, or a BRK frame on top of the
+/// stack as the KERNAL IRQ entry leaves it (Y, X, A, P with B set, return
+/// address past a BRK). Restoring any of these ends in the BRK handler.
+pub fn monitor_state_warning(snap: &C64Snapshot) -> Option<String> {
+    let cpu = &snap.cpu;
+    let ram = &snap.mem.ram;
+    let port = snap.mem.cpu_port_data;
+    let pc = cpu.pc as usize;
+    let mut reasons = Vec::new();
+
+    if hidden_by(port, pc, pc + 1).is_none() && ram[pc] == 0x00 {
+        let window = &ram[pc.saturating_sub(CODE_WINDOW)..(pc + CODE_WINDOW).min(ram.len())];
+        if window.iter().any(|&b| b != 0x00) {
+            reasons.push(format!("the PC ${:04X} points at a BRK", cpu.pc));
+        }
+    }
+
+    if port & 0x02 != 0 && KERNAL_BRK_HANDLER.contains(&cpu.pc) {
+        reasons.push(format!("the PC ${:04X} is in the KERNAL BRK handler", cpu.pc));
+    }
+
+    let stack = |offset: usize| ram[0x0100 + (cpu.sp as usize + offset) % 0x100];
+    let status = stack(4);
+    let return_address = u16::from_le_bytes([stack(5), stack(6)]) as usize;
+    if status & BREAK_FLAG != 0 && return_address >= 2 && ram[return_address - 2] == 0x00 {
+        reasons.push(format!("the stack holds a BRK frame returning to ${:04X}", return_address));
+    }
+
+    if reasons.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "CPU state looks like it was captured at a breakpoint ({}). The restored program will \
+         run into the BRK handler instead of continuing; leave the monitor and let the program \
+         run before taking the snapshot.",
+        reasons.join(", ")
+    ))
+}
+
+/// Describe damaged BASIC zero page routines (see `sanitize_zp`)
+pub fn basic_routines_warning(snap: &C64Snapshot) -> Option<String> {
+    let damaged = damaged_basic_routines(snap);
+    if damaged.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "BASIC zero page looks damaged ({}). The program runs, but crashes if it ever returns \
+         to BASIC; --sanitize-zp restores the ROM values.",
+        damaged.join(", ")
+    ))
+}
+
+/// Warn if the display was off ($D011 DEN clear) when the snapshot was taken
+pub fn blanked_display_warning(snap: &C64Snapshot) -> Option<String> {
+    if snap.vic.display_enabled() {
+        return None;
+    }
+
+    Some(format!(
+        "Display was off ($D011 = ${:02X}) when the snapshot was taken. The restored program \
+         shows only the border color until it turns the display on; --force-display-on shows \
+         the screen RAM instead.",
+        snap.vic.registers[0x11]
+    ))
+}
+
+/// Warn if the stack pointer is low enough to suggest a non-standard use of page 1
+///
+/// The 6510 stack is always $0100-$01FF; no CPU port ($01) setting moves it,
+/// and page 1 is RAM in every configuration. The restore does assume the
+/// bytes below SP are free: its last code runs there (or at the top of page 1
+/// if it doesn't fit) and the final RTI frame is pushed there. Programs that
+/// keep data below SP, or use TXS/PLA to treat page 1 as a buffer, tend to run
+/// with a deep stack, which is what this checks for.
+pub fn stack_warning(snap: &C64Snapshot) -> Option<String> {
+    let sp = snap.cpu.sp;
+    if sp >= LOW_STACK_POINTER {
+        return None;
+    }
+
+    Some(format!(
+        "Stack pointer is unusually low (SP=${:02X}, {} bytes of page 1 in use). The stack is \
+         always at $0100-$01FF, and the restore writes its last code and RTI frame into page 1 \
+         below SP; a program that keeps data there or uses the stack page as a buffer may lose \
+         it or crash. Take the snapshot where the stack is shallower.",
+        sp,
+        0xFF - sp as usize
+    ))
+}
+
+/// Tell the user to attach the REU image if the snapshot used a REU
+///
+/// The expansion RAM can't be restored into the C64, so the converters write
+/// it next to the output (`convert_snapshot::write_reu_sidecar`).
+pub fn reu_warning(input_path: &str) -> Option<String> {
+    let reu = ParseVSF::read_reu(input_path).ok()??;
+
+    Some(format!(
+        "Snapshot used a {} KB REU. Its contents are written next to the output as a .reu file; \
+         attach it as the REU image (VICE: -reu -reusize {} -reuimage <file>) before starting the \
+         program, or it will find an empty REU.",
+        reu.ram.len() / 1024,
+        reu.ram.len() / 1024
+    ))
+}
+
+/// Warn if the snapshot has more than one SID module (stereo or triple SID)
+///
+/// Names the second SID's address when the snapshot stores it (`C64Snapshot::sid2_addr`).
+pub fn extra_sid_warning(parser: &ParseVSF, snap: &C64Snapshot) -> Option<String> {
+    let extra = parser.sid_module_count().checked_sub(1).filter(|&n| n > 0)?;
+    let mapped = match snap.sid2_addr {
+        Some(addr) => format!("the second at ${:04X}", addr),
+        None => "mapped by VICE's SidStereoAddressStart, usually $D420 or $DE00".to_string(),
+    };
+
+    Some(format!(
+        "Snapshot has {} SID modules (stereo SID). Only the first SID at $D400 is restored; \
+         the {} other SID{} ({}) start silent until the program writes to {}.",
+        extra + 1,
+        extra,
+        if extra == 1 { "" } else { "s" },
+        mapped,
+        if extra == 1 { "it" } else { "them" }
+    ))
+}
+
+/// Parse `input_path` and return all warnings; parse errors are left to the converter
+pub fn check_snapshot(input_path: &str, config: &Config) -> Vec<String> {
+    let parser = match ParseVSF::import_streaming(input_path, config) {
+        Ok(parser) => parser,
+        Err(_) => return Vec::new(),
+    };
+    let snap = match parser.parse_import() {
+        Ok(snap) => snap,
+        Err(_) => return Vec::new(),
+    };
+
+    smart_attach_warning(&snap)
+        .into_iter()
+        .chain(open_files_warning(&snap))
+        .chain(cartridge_warning(&snap))
+        .chain(monitor_state_warning(&snap))
+        .chain(basic_routines_warning(&snap))
+        .chain(blanked_display_warning(&snap))
+        .chain(stack_warning(&snap))
+        .chain(reu_warning(input_path))
+        .chain(extra_sid_warning(&parser, &snap))
+        .collect()
+}
+
+/// Warn if a manual LOAD/SAVE hook address overlaps the snapshot's live stack
+///
+/// Addresses that break the restore outright are rejected by the converter.
+pub fn check_hook_address(input_path: &str, config: &Config, addr: u16) -> Vec<String> {
+    let snap = match ParseVSF::import_streaming(input_path, config)
+        .ok()
+        .and_then(|parser| parser.parse_import().ok())
+    {
+        Some(snap) => snap,
+        None => return Vec::new(),
+    };
+
+    let len = match LoadSaveHook::new(snap.cpu.sp, true, Some(addr)).generate_trampoline_binary() {
+        Ok(code) => code.len(),
+        Err(_) => return Vec::new(),
+    };
+
+    trampoline_stack_warning(addr, snap.cpu.sp, len).into_iter().collect()
+}
+
+/// Describe the problem if a PRG would load into the I/O area
+///
+/// The loader moves the compressed RAM to the top of memory only after it
+/// runs, so the whole file has to fit below $D000 as loaded. Poorly
+/// compressing snapshots can exceed that and crash during LOAD.
+pub fn prg_size_warning(prg: &[u8]) -> Option<String> {
+    if prg.len() < 2 {
+        return None;
+    }
+
+    let load_address = u16::from_le_bytes([prg[0], prg[1]]) as usize;
+    let end = load_address + prg.len() - 2;
+    if end <= PRG_LOAD_LIMIT {
+        return None;
+    }
+
+    Some(format!(
+        "PRG loads to ${:04X}-${:04X}, past $CFFF: LOAD writes into the I/O area and the program \
+         crashes while loading. The snapshot RAM compresses poorly; clear unused memory before \
+         saving it, or use a CRT format.",
+        load_address,
+        end - 1
+    ))
+}
+
+/// Describe the problem if the loader's staged RAM would be overwritten while it decompresses
+///
+/// After loading, the compressed RAM is moved so it ends at $FFFF and
+/// decompressed upward from $0200 over the same memory; the output must not
+/// catch up with input not read yet. Names the staging range.
+pub fn prg_staging_warning(prg: &[u8]) -> Option<String> {
+    let (staged, stream) = staged_ram_stream(prg)?;
+    let overlap = ram_decompression_overlap(stream).ok().filter(|&overlap| overlap > 0)?;
+
+    Some(format!(
+        "PRG stages its compressed RAM at ${:04X}-$FFFF, and decompressing it to $0200 overwrites \
+         {} bytes of it before they are read: the program crashes after loading. Clear unused \
+         memory before saving the snapshot, or use a CRT format.",
+        staged, overlap
+    ))
+}
+
+/// Read a generated PRG and return all warnings; unreadable files are ignored
+pub fn check_prg(output_path: &str) -> Vec<String> {
+    match fs::read(output_path) {
+        Ok(prg) => prg_size_warning(&prg).into_iter().chain(prg_staging_warning(&prg)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Autostart types LOAD/RUN into the keyboard buffer; return it if still pending
+fn pending_autostart(ram: &[u8; 65536]) -> Option<String> {
+    let count = (ram[KEYBOARD_COUNT] as usize).min(KEYBOARD_BUFFER_LEN);
+    if count == 0 {
+        return None;
+    }
+
+    let text: String = ram[KEYBOARD_BUFFER..KEYBOARD_BUFFER + count]
+        .iter()
+        .map(|&b| if b == 0x0D { ' ' } else { b as char })
+        .collect();
+    let text = text.trim().to_string();
+
+    if text.starts_with("RUN") || text.starts_with("LOAD") {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// Count 128-byte aligned chunks matching VICE's power-up RAM pattern
+fn power_up_pattern_chunks(ram: &[u8; 65536]) -> usize {
+    ram.chunks_exact(2 * POWER_UP_RUN)
+        .filter(|chunk| {
+            chunk[..POWER_UP_RUN].iter().all(|&b| b == 0x00)
+                && chunk[POWER_UP_RUN..].iter().all(|&b| b == 0xFF)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::make_prg_asm::MakePRGAsm;
+    use crate::test_vsf::{temp_dir, TestVsf};
+
+    fn warnings_for(vsf: &TestVsf, tag: &str) -> Vec<String> {
+        let (dir, path) = vsf.write(tag);
+        let warnings = check_snapshot(&path, &Config::new(&dir));
+        let _ = fs::remove_dir_all(&dir);
+        warnings
+    }
+
+    #[test]
+    fn test_smart_attach_pattern_warns() {
+        // Autostart pending and uncleared power-up RAM at $C000-$CFFF
+        let mut vsf = TestVsf::new();
+        vsf.ram[KEYBOARD_COUNT] = 4;
+        vsf.ram[KEYBOARD_BUFFER..KEYBOARD_BUFFER + 4].copy_from_slice(b"RUN\r");
+        for chunk in vsf.ram[0xC000..0xD000].chunks_exact_mut(128) {
+            chunk[64..].fill(0xFF);
+        }
+
+        let warnings = warnings_for(&vsf, "smart_attach");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Smart attach"));
+        assert!(warnings[0].contains("\"RUN\""));
+        assert!(warnings[0].contains("4 KB"));
+    }
+
+    #[test]
+    fn test_open_file_on_drive_warns() {
+        // OPEN 2,8,2 still open, plus OPEN 1,3 (screen) which needs no device
+        let mut vsf = TestVsf::new();
+        vsf.ram[OPEN_FILE_COUNT] = 2;
+        vsf.ram[FILE_NUMBERS..FILE_NUMBERS + 2].copy_from_slice(&[1, 2]);
+        vsf.ram[FILE_DEVICES..FILE_DEVICES + 2].copy_from_slice(&[3, 8]);
+
+        let warnings = warnings_for(&vsf, "open_files");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("#2 on device 8"), "{}", warnings[0]);
+        assert!(!warnings[0].contains("#1"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_cartridge_lines_warn() {
+        // VICE stores the lines as asserted: EXROM off, GAME on is Ultimax
+        let mut vsf = TestVsf::new();
+        vsf.game = 1;
+
+        let warnings = warnings_for(&vsf, "cartridge");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("depended on a cartridge"), "{}", warnings[0]);
+        assert!(warnings[0].contains("Ultimax"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_monitor_breakpoint_warns() {
+        // Stopped at a BRK patched into a NOP slide
+        let mut vsf = TestVsf::new();
+        vsf.ram[0x0808..0x0818].fill(0xEA);
+        vsf.ram[0x0810] = 0x00;
+
+        let warnings = warnings_for(&vsf, "monitor_brk");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("breakpoint"), "{}", warnings[0]);
+        assert!(warnings[0].contains("$0810 points at a BRK"), "{}", warnings[0]);
+
+        // In the KERNAL BRK handler with Y, X, A, P (B set) and the return address stacked
+        let mut vsf = TestVsf::new();
+        vsf.pc = 0xFE66;
+        vsf.sp = 0xF0;
+        vsf.ram[0x0900] = 0x00;
+        vsf.ram[0x0901] = 0xEA;
+        vsf.ram[0x01F1..0x01F7].copy_from_slice(&[0x00, 0x00, 0x00, 0x30, 0x02, 0x09]);
+
+        let warnings = warnings_for(&vsf, "monitor_handler");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("KERNAL BRK handler"), "{}", warnings[0]);
+        assert!(warnings[0].contains("BRK frame returning to $0902"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_low_stack_pointer_warns() {
+        // Deep stack with the I/O mapped out ($01 = $34): page 1 is RAM either way
+        let mut vsf = TestVsf::new();
+        vsf.sp = 0x30;
+        vsf.cpu_port_data = 0x34;
+
+        let warnings = warnings_for(&vsf, "low_stack");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("SP=$30, 207 bytes"), "{}", warnings[0]);
+
+        let mut vsf = TestVsf::new();
+        vsf.sp = LOW_STACK_POINTER;
+        assert!(warnings_for(&vsf, "stack_half_full").is_empty());
+    }
+
+    #[test]
+    fn test_second_sid_module_is_ignored_with_warning() {
+        let mut vsf = TestVsf::new();
+        vsf.sid[0x18] = 0x0F;
+        let mut bytes = vsf.to_bytes();
+
+        // A second SID module with different registers
+        let mut payload = vec![1u8, 1, 0];
+        payload.extend_from_slice(&[0x55; 32]);
+        let mut name = [0u8; 16];
+        name[..3].copy_from_slice(b"SID");
+        bytes.extend_from_slice(&name);
+        bytes.extend_from_slice(&[1, 1]);
+        bytes.extend_from_slice(&(payload.len() as u32 + 22).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let dir = temp_dir("stereo_sid");
+        let path = dir.join("stereo.vsf");
+        fs::write(&path, &bytes).unwrap();
+        let path = path.to_str().unwrap();
+
+        let parser = ParseVSF::import(path, &Config::new(&dir)).unwrap();
+        assert_eq!(parser.sid_module_count(), 2);
+        let snap = parser.parse_import().unwrap();
+        assert_eq!(snap.sid.regs_25[0x18], 0x0F);
+        assert_eq!(snap.sid.regs_25[0x00], 0x00);
+
+        let warnings = check_snapshot(path, &Config::new(&dir));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("2 SID modules"), "{}", warnings[0]);
+        assert!(warnings[0].contains("$D400"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_cleared_snapshot_has_no_warning() {
+        assert!(warnings_for(&TestVsf::new(), "no_smart_attach").is_empty());
+    }
+
+    /// Assemble a PRG whose compressed RAM component is `ram_size` bytes
+    fn prg_with_ram_component(tag: &str, ram_size: usize) -> Vec<u8> {
+        // Incompressible filler: the assembler only embeds it
+        let mut seed = 0x1234_5678u32;
+        let ram: Vec<u8> = (0..ram_size)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        prg_with_ram_stream(tag, &ram)
+    }
+
+    /// Assemble a PRG with `ram` as its compressed RAM component
+    fn prg_with_ram_stream(tag: &str, ram: &[u8]) -> Vec<u8> {
+        let dir = temp_dir(tag);
+        let file = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, data).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+
+        let maker = MakePRGAsm::new(
+            &file("color.lzsa", &[0x00; 16]),
+            &file("vic.lzsa", &[0x00; 16]),
+            &file("sid.lzsa", &[0x00; 16]),
+            &file("cia1.bin", &[0x00; 20]),
+            &file("cia2.bin", &[0x00; 20]),
+            &file("zp.lzsa", &[0x00; 16]),
+            &file("ram.lzsa", ram),
+            0x4000,
+            [0; 8],
+            [0x1B, 0x00],
+            &Config::new(&dir),
+        )
+        .unwrap();
+
+        let output = dir.join("out.prg");
+        maker.generate_prg(output.to_str().unwrap()).unwrap();
+        let prg = fs::read(&output).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        prg
+    }
+
+    #[test]
+    fn test_oversized_prg_warns_with_end_address() {
+        let prg = prg_with_ram_component("prg_too_large", 0xD000 - 0x0801);
+        let end = 0x0801 + prg.len() - 2 - 1;
+        let warning = prg_size_warning(&prg).expect("PRG past $CFFF must warn");
+        assert!(warning.contains(&format!("${:04X}", end)), "{}", warning);
+    }
+
+    #[test]
+    fn test_small_prg_has_no_size_warning() {
+        assert!(prg_size_warning(&prg_with_ram_component("prg_small", 0x1000)).is_none());
+    }
+
+    #[test]
+    fn test_staging_overlap_warns_with_staging_range() {
+        // One literal, a match of 64007 + 1 bytes, then 100 tokens that expand
+        // 11 bytes in to 10 out: the output runs 89 bytes into the unread input
+        let mut ram = vec![0x1F, 0xAA, 0xFF, 238];
+        ram.extend_from_slice(&64007u16.to_le_bytes());
+        for _ in 0..100 {
+            ram.extend_from_slice(&[0xF0, 0x00, 1, 2, 3, 4, 5, 6, 7, 0xF9, 0xFF]);
+        }
+        ram.extend_from_slice(&[0x0F, 0x00, 238, 0x00, 0x00]);
+
+        let prg = prg_with_ram_stream("prg_staging_overlap", &ram);
+        let (staged, stream) = staged_ram_stream(&prg).expect("loader operands");
+        assert_eq!(stream, &ram[..]);
+        assert_eq!(staged as usize, 0x10000 - ram.len());
+
+        let warning = prg_staging_warning(&prg).expect("overlapping staging must warn");
+        assert!(warning.contains(&format!("${:04X}-$FFFF", staged)), "{}", warning);
+        assert!(warning.contains("89 bytes"), "{}", warning);
+        assert!(prg_size_warning(&prg).is_none());
+    }
+}
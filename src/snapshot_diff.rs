@@ -0,0 +1,104 @@
+//! RAM comparison between two snapshots
+//!
+//! Compares an original snapshot with one captured after the converted
+//! PRG/CRT has restored it in an emulator. Differing bytes are grouped into
+//! ranges; the stack page and the restore blocks, which the restore uses as
+//! scratch space, are left out.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use crate::find_ram::FindRam;
+use crate::parse_vsf::C64Snapshot;
+use crate::patch_mem::PatchMem;
+
+/// Differences closer than this many bytes are reported as one range
+const MERGE_GAP: usize = 16;
+
+/// Stack page, home of the $01xx restore code and trampolines
+const STACK_PAGE: (u16, u16) = (0x0100, 0x0100);
+
+/// Bytes that differ between two snapshots within `start..=end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffRange {
+    pub start: u16,
+    pub end: u16,
+    /// Differing bytes in the range (gaps up to `MERGE_GAP` are not counted)
+    pub count: usize,
+}
+
+/// Regions the restore of `snap` uses as scratch: $0100-$01FF and blocks 1-10
+///
+/// Block placement is recomputed the way the converters do it, so it matches
+/// a conversion with default options.
+pub fn restore_scratch_regions(snap: &C64Snapshot) -> Vec<(u16, u16)> {
+    let mut regions = vec![STACK_PAGE];
+    let mut ram = snap.mem.ram.clone();
+    let mut finder = FindRam::new(&ram);
+    if let Ok(patch) = PatchMem::new(snap, &mut ram, &mut finder) {
+        regions.extend(patch.block_ranges());
+    }
+    regions
+}
+
+/// Compare two RAM images, skipping the `ignore` regions (address, size)
+pub fn diff_ram(expected: &[u8; 65536], actual: &[u8; 65536], ignore: &[(u16, u16)]) -> Vec<DiffRange> {
+    let ignored = |addr: usize| {
+        ignore
+            .iter()
+            .any(|&(start, size)| addr >= start as usize && addr < start as usize + size as usize)
+    };
+
+    let mut ranges: Vec<DiffRange> = Vec::new();
+    for (addr, (want, got)) in expected.iter().zip(actual.iter()).enumerate() {
+        if want == got || ignored(addr) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if addr - last.end as usize <= MERGE_GAP => {
+                last.end = addr as u16;
+                last.count += 1;
+            }
+            _ => ranges.push(DiffRange { start: addr as u16, end: addr as u16, count: 1 }),
+        }
+    }
+    ranges
+}
+
+/// Compare the RAM of two snapshots, ignoring the restore scratch of `expected`
+pub fn diff_snapshots(expected: &C64Snapshot, actual: &C64Snapshot) -> Vec<DiffRange> {
+    diff_ram(&expected.mem.ram, &actual.mem.ram, &restore_scratch_regions(expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::TestVsf;
+
+    #[test]
+    fn test_diff_reports_only_the_changed_range() {
+        let original = TestVsf::new();
+        let mut restored = TestVsf::new();
+        restored.ram[0x4000..0x4010].fill(0xAA);
+        // Stack page scratch differs too, but is expected to
+        restored.ram[0x0100..0x0140].fill(0x55);
+
+        let diff = diff_snapshots(&original.parse("diff_a"), &restored.parse("diff_b"));
+        assert_eq!(diff, vec![DiffRange { start: 0x4000, end: 0x400F, count: 16 }]);
+    }
+
+    #[test]
+    fn test_nearby_differences_are_grouped() {
+        let expected = Box::new([0u8; 65536]);
+        let mut actual = Box::new([0u8; 65536]);
+        actual[0x2000] = 1;
+        actual[0x2008] = 1;
+        actual[0x3000] = 1;
+
+        let diff = diff_ram(&expected, &actual, &[]);
+        assert_eq!(diff, vec![
+            DiffRange { start: 0x2000, end: 0x2008, count: 2 },
+            DiffRange { start: 0x3000, end: 0x3000, count: 1 },
+        ]);
+    }
+}
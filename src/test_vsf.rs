@@ -0,0 +1,239 @@
+//! Synthetic VSF builder for unit tests
+//!
+//! Assembles a minimal C64SC snapshot in memory so parser and converter tests
+//! can run without binary fixtures in the repository.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::Config;
+use crate::parse_vsf::{C64Snapshot, ParseVSF};
+
+/// Offset of the color RAM inside a C64SC VIC-II 1.1 payload
+pub const VIC_COLOR_OFFSET: usize = 757;
+
+/// Snapshot contents used to build a test VSF
+pub struct TestVsf {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub p: u8,
+    pub cpu_port_data: u8,
+    pub cpu_port_dir: u8,
+    /// C64MEM cartridge lines (VICE: 1 = asserted)
+    pub exrom: u8,
+    pub game: u8,
+    pub ram: Box<[u8; 65536]>,
+    pub vic_regs: [u8; 47],
+    pub vic_color: Box<[u8; 1024]>,
+    pub cia1: [u8; 20],
+    pub cia2: [u8; 20],
+    pub sid: [u8; 25],
+    /// Optional "VICE Version" block: (major, minor, build, patch), SVN revision
+    pub vice_version: Option<([u8; 4], u32)>,
+    /// Optional "REU1764" module RAM; its length must be a REU size
+    pub reu: Option<Vec<u8>>,
+}
+
+impl TestVsf {
+    /// Cleared memory, stack near the top and a plausible CPU/VIC/CIA state
+    pub fn new() -> Self {
+        let mut vic_regs = [0u8; 47];
+        vic_regs[0x11] = 0x1B;
+        vic_regs[0x16] = 0xC8;
+        vic_regs[0x18] = 0x15;
+        vic_regs[0x20] = 0x0E;
+        vic_regs[0x21] = 0x06;
+
+        // ora, orb, ddra, ddrb, tac, tbc, tod(4), sdr, ier, cra, crb, tal, tbl
+        let mut cia1 = [0u8; 20];
+        cia1[0] = 0x7F;
+        cia1[1] = 0xFF;
+        cia1[2] = 0xFF;
+        let mut cia2 = [0u8; 20];
+        cia2[0] = 0x97;
+        cia2[2] = 0x3F;
+
+        Self {
+            a: 0x00,
+            x: 0x00,
+            y: 0x00,
+            sp: 0xF6,
+            pc: 0x0810,
+            p: 0x20,
+            cpu_port_data: 0x37,
+            cpu_port_dir: 0x2F,
+            exrom: 0,
+            game: 0,
+            ram: Box::new([0u8; 65536]),
+            vic_regs,
+            vic_color: Box::new([0x0E; 1024]),
+            cia1,
+            cia2,
+            sid: [0u8; 25],
+            vice_version: None,
+            reu: None,
+        }
+    }
+
+    /// Serialize to VSF 2.0 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"VICE Snapshot File\x1A");
+        out.extend_from_slice(&[2, 0]);
+        out.extend_from_slice(&padded(b"C64SC"));
+        if let Some((version, revision)) = self.vice_version {
+            out.extend_from_slice(b"VICE Version\x1A");
+            out.extend_from_slice(&version);
+            out.extend_from_slice(&revision.to_le_bytes());
+        }
+
+        let mut cpu = vec![0u8; 4];
+        cpu.extend_from_slice(&[self.a, self.x, self.y, self.sp]);
+        cpu.extend_from_slice(&self.pc.to_le_bytes());
+        cpu.push(self.p);
+        push_module(&mut out, "MAINCPU", 1, 1, &cpu);
+
+        let mut mem = vec![self.cpu_port_data, self.cpu_port_dir, self.exrom, self.game];
+        mem.extend_from_slice(&self.ram[..]);
+        push_module(&mut out, "C64MEM", 0, 0, &mem);
+
+        let mut vic = vec![0u8; VIC_COLOR_OFFSET + 1024];
+        vic[1..48].copy_from_slice(&self.vic_regs);
+        vic[VIC_COLOR_OFFSET..].copy_from_slice(&self.vic_color[..]);
+        push_module(&mut out, "VIC-II", 1, 1, &vic);
+
+        push_module(&mut out, "CIA1", 2, 2, &self.cia1);
+        push_module(&mut out, "CIA2", 2, 2, &self.cia2);
+
+        let mut sid = vec![1u8, 1, 0];
+        sid.extend_from_slice(&self.sid);
+        sid.extend_from_slice(&[0u8; 7]);
+        push_module(&mut out, "SID", 1, 1, &sid);
+
+        if let Some(ref ram) = self.reu {
+            let mut reu = ((ram.len() / 1024) as u32).to_le_bytes().to_vec();
+            reu.extend_from_slice(&[0u8; 16]);
+            reu.extend_from_slice(ram);
+            push_module(&mut out, "REU1764", 0, 0, &reu);
+        }
+
+        out
+    }
+
+    /// Write the snapshot into a fresh temp directory and return (dir, vsf path)
+    pub fn write(&self, tag: &str) -> (PathBuf, String) {
+        let dir = temp_dir(tag);
+        let path = dir.join(format!("{}.vsf", tag));
+        fs::write(&path, self.to_bytes()).expect("write test VSF");
+        (dir, path.to_str().unwrap().to_string())
+    }
+
+    /// Write the snapshot, import it and remove the temp directory again
+    pub fn import(&self, tag: &str) -> ParseVSF {
+        let (dir, path) = self.write(tag);
+        let parser = ParseVSF::import(&path, &Config::new(&dir)).expect("import test VSF");
+        let _ = fs::remove_dir_all(&dir);
+        parser
+    }
+
+    /// Write, import and parse the snapshot
+    pub fn parse(&self, tag: &str) -> C64Snapshot {
+        self.import(tag).parse_import().expect("parse test VSF")
+    }
+}
+
+/// Create a unique, empty temp directory for one test
+pub fn temp_dir(tag: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!(
+        "ViceSnapshotTest.{}.{}.{}",
+        std::process::id(),
+        tag,
+        n
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create test dir");
+    dir
+}
+
+/// RAM without any run of 32 identical bytes, so FindRam sees no free space
+pub fn busy_ram() -> Box<[u8; 65536]> {
+    let mut ram = Box::new([0u8; 65536]);
+    for (i, b) in ram.iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+    ram
+}
+
+/// Decompress a raw LZSA1 block (as produced by `ParseVSF::compress_lzsa`)
+pub fn decompress_lzsa1(src: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    let mut next = || {
+        let b = src[pos];
+        pos += 1;
+        b
+    };
+
+    loop {
+        let token = next();
+
+        let mut literals = ((token >> 4) & 0x07) as usize;
+        if literals == 7 {
+            literals = match next() {
+                249 => u16::from_le_bytes([next(), next()]) as usize,
+                250 => 256 + next() as usize,
+                b => 7 + b as usize,
+            };
+        }
+        for _ in 0..literals {
+            let b = next();
+            out.push(b);
+        }
+
+        let offset = if token & 0x80 != 0 {
+            i16::from_le_bytes([next(), next()])
+        } else {
+            i16::from_le_bytes([next(), 0xFF])
+        };
+
+        let mut length = (token & 0x0F) as usize + 3;
+        if length == 18 {
+            length = match next() {
+                238 => match u16::from_le_bytes([next(), next()]) {
+                    0 => return out,
+                    n => n as usize,
+                },
+                239 => 256 + next() as usize,
+                b => 18 + b as usize,
+            };
+        }
+        let start = (out.len() as isize + offset as isize) as usize;
+        for i in 0..length {
+            let b = out[start + i];
+            out.push(b);
+        }
+    }
+}
+
+fn padded(name: &[u8]) -> [u8; 16] {
+    let mut buf = [0u8; 16];
+    buf[..name.len()].copy_from_slice(name);
+    buf
+}
+
+fn push_module(out: &mut Vec<u8>, name: &str, major: u8, minor: u8, payload: &[u8]) {
+    out.extend_from_slice(&padded(name.as_bytes()));
+    out.push(major);
+    out.push(minor);
+    out.extend_from_slice(&((payload.len() + 22) as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
@@ -0,0 +1,471 @@
+//! Snapshot from a running VICE over its binary monitor (feature `vice-monitor`)
+//!
+//! Connects to the TCP port VICE opens with `-binarymonitor`, reads RAM,
+//! registers and the I/O chips, and builds a `C64Snapshot` without going
+//! through File > Save snapshot. The emulator is resumed afterwards.
+//!
+//! The monitor reads chips the way the CPU would see them, which loses some
+//! state a real snapshot keeps:
+//! - CIA timer latches are not readable; the current counters are used
+//! - CIA interrupt masks are not readable; CIA1 Timer A is assumed enabled
+//!   while it runs (the KERNAL IRQ), all other sources off
+//! - The VIC-II model is unknown, so the snapshot is treated as PAL
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::parse_vsf::{release_keyboard_rows, C64Mem, C64Snapshot, Cia6526, Cpu6510, Sid6581, VicII};
+
+/// Lets a monitor snapshot go through the regular file-based conversion
+pub use crate::vsf_export::write_vsf;
+
+const STX: u8 = 0x02;
+const API_VERSION: u8 = 0x02;
+/// STX, API version, body length (4), response type, error code, request id (4)
+const RESPONSE_HEADER_LEN: usize = 12;
+/// Request id VICE uses for events it sends on its own (stopped, resumed, ...)
+const EVENT_ID: u32 = 0xFFFF_FFFF;
+
+const CMD_MEMORY_GET: u8 = 0x01;
+const CMD_REGISTERS_GET: u8 = 0x31;
+const CMD_BANKS_AVAILABLE: u8 = 0x82;
+const CMD_REGISTERS_AVAILABLE: u8 = 0x83;
+const CMD_EXIT: u8 = 0xAA;
+
+/// Main CPU memory space
+const MAIN_MEMSPACE: u8 = 0x00;
+
+/// Give up on a silent monitor instead of hanging
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Binary monitor connection
+pub struct MonitorClient<S: Read + Write> {
+    stream: S,
+    next_id: u32,
+}
+
+impl MonitorClient<TcpStream> {
+    /// Connect to VICE started with `-binarymonitor` (default port 6502)
+    pub fn connect(addr: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(addr)
+            .map_err(|e| format!("Failed to connect to VICE monitor at {}: {}", addr, e))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))
+            .map_err(|e| format!("Failed to set monitor timeout: {}", e))?;
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: Read + Write> MonitorClient<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, next_id: 1 }
+    }
+
+    /// Send one command and return the body of its response
+    ///
+    /// Events and replies to other requests are skipped.
+    fn request(&mut self, command: u8, body: &[u8]) -> Result<Vec<u8>, String> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut packet = vec![STX, API_VERSION];
+        packet.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.push(command);
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)
+            .and_then(|()| self.stream.flush())
+            .map_err(|e| format!("VICE monitor write failed: {}", e))?;
+
+        loop {
+            let mut header = [0u8; RESPONSE_HEADER_LEN];
+            self.stream.read_exact(&mut header)
+                .map_err(|e| format!("VICE monitor read failed: {}", e))?;
+            if header[0] != STX {
+                return Err(format!("VICE monitor sent an invalid response (${:02X})", header[0]));
+            }
+
+            let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+            let mut response = vec![0u8; len];
+            self.stream.read_exact(&mut response)
+                .map_err(|e| format!("VICE monitor read failed: {}", e))?;
+
+            let response_id = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+            if response_id != id {
+                continue;
+            }
+            if header[7] != 0 {
+                return Err(format!(
+                    "VICE monitor command ${:02X} failed with error ${:02X}",
+                    command, header[7]
+                ));
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Memory banks as (id, name), e.g. "ram", "io"
+    pub fn banks(&mut self) -> Result<Vec<(u16, String)>, String> {
+        let body = self.request(CMD_BANKS_AVAILABLE, &[])?;
+        items(&body)?
+            .into_iter()
+            .map(|item| {
+                let id = item.get(..2).ok_or_else(short_response)?;
+                Ok((u16::from_le_bytes([id[0], id[1]]), item_name(item, 2)?))
+            })
+            .collect()
+    }
+
+    /// Main CPU registers as (name, value), e.g. ("PC", $0810), ("01", $37)
+    pub fn registers(&mut self) -> Result<Vec<(String, u16)>, String> {
+        let available = self.request(CMD_REGISTERS_AVAILABLE, &[MAIN_MEMSPACE])?;
+        let names = items(&available)?
+            .into_iter()
+            .map(|item| Ok((item[0], item_name(item, 2)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let values = self.request(CMD_REGISTERS_GET, &[MAIN_MEMSPACE])?;
+        items(&values)?
+            .into_iter()
+            .filter_map(|item| {
+                let name = names.iter().find(|(id, _)| *id == item[0])?.1.clone();
+                Some(match item.get(1..3) {
+                    Some(value) => Ok((name, u16::from_le_bytes([value[0], value[1]]))),
+                    None => Err(short_response()),
+                })
+            })
+            .collect()
+    }
+
+    /// Read `start..=end` from `bank` without side effects (no register clears)
+    pub fn read_memory(&mut self, bank: u16, start: u16, end: u16) -> Result<Vec<u8>, String> {
+        let mut body = vec![0x00];
+        body.extend_from_slice(&start.to_le_bytes());
+        body.extend_from_slice(&end.to_le_bytes());
+        body.push(MAIN_MEMSPACE);
+        body.extend_from_slice(&bank.to_le_bytes());
+
+        let response = self.request(CMD_MEMORY_GET, &body)?;
+        let expected = end as usize - start as usize + 1;
+        match response.get(2..) {
+            Some(data) if data.len() == expected => Ok(data.to_vec()),
+            _ => Err(format!(
+                "VICE monitor returned {} bytes for ${:04X}-${:04X}",
+                response.len().saturating_sub(2),
+                start,
+                end
+            )),
+        }
+    }
+
+    /// Let the emulator continue
+    pub fn resume(&mut self) -> Result<(), String> {
+        self.request(CMD_EXIT, &[]).map(|_| ())
+    }
+
+    /// Read the machine state into a snapshot
+    pub fn snapshot(&mut self) -> Result<C64Snapshot, String> {
+        let banks = self.banks()?;
+        let bank = |name: &str| {
+            banks.iter()
+                .find(|(_, bank)| bank == name)
+                .map(|&(id, _)| id)
+                .ok_or_else(|| format!("VICE monitor has no \"{}\" memory bank", name))
+        };
+        let ram_bank = bank("ram")?;
+        let io_bank = bank("io")?;
+
+        let registers = self.registers()?;
+        let reg = |name: &str| {
+            registers.iter()
+                .find(|(reg, _)| reg == name)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| format!("VICE monitor has no {} register", name))
+        };
+        let cpu = Cpu6510 {
+            a: reg("A")? as u8,
+            x: reg("X")? as u8,
+            y: reg("Y")? as u8,
+            sp: reg("SP")? as u8,
+            pc: reg("PC")?,
+            p: reg("FL")? as u8,
+        };
+        let cpu_port_dir = reg("00")? as u8;
+        let cpu_port_data = reg("01")? as u8;
+
+        // Memory get reports its length in 16 bits, so 64K takes two reads
+        let mut ram = Box::new([0u8; 65536]);
+        ram[..0x8000].copy_from_slice(&self.read_memory(ram_bank, 0x0000, 0x7FFF)?);
+        ram[0x8000..].copy_from_slice(&self.read_memory(ram_bank, 0x8000, 0xFFFF)?);
+
+        let mut registers = [0u8; 47];
+        registers.copy_from_slice(&self.read_memory(io_bank, 0xD000, 0xD02E)?);
+        let mut color_ram = Box::new([0u8; 1024]);
+        for (dst, src) in color_ram.iter_mut().zip(self.read_memory(io_bank, 0xD800, 0xDBFF)?) {
+            *dst = src & 0x0F;
+        }
+
+        let mut regs_25 = [0u8; 25];
+        regs_25.copy_from_slice(&self.read_memory(io_bank, 0xD400, 0xD418)?);
+
+        let cia1 = self.read_memory(io_bank, 0xDC00, 0xDC0F)?;
+        let cia1_ier = if cia1[0x0E] & 0x01 != 0 { 0x01 } else { 0x00 };
+        let cia2 = self.read_memory(io_bank, 0xDD00, 0xDD0F)?;
+
+        Ok(C64Snapshot {
+            cpu,
+            // The monitor doesn't expose the cartridge lines
+            mem: C64Mem { cpu_port_data, cpu_port_dir, exrom: false, game: false, ram },
+            vic: VicII { registers, color_ram, model: None },
+            cia1: release_keyboard_rows(cia_from_registers(&cia1, cia1_ier)),
+            cia2: cia_from_registers(&cia2, 0x00),
+            sid: Sid6581 { regs_25 },
+            // Nor where a second SID is mapped
+            sid2_addr: None,
+        })
+    }
+}
+
+/// Connect to `addr`, read a snapshot and resume the emulator
+pub fn snapshot_from_monitor(addr: &str) -> Result<C64Snapshot, String> {
+    let mut client = MonitorClient::connect(addr)?;
+    let snap = client.snapshot();
+    let resumed = client.resume();
+    let snap = snap?;
+    resumed?;
+    Ok(snap)
+}
+
+/// CIA state from its 16 registers as the CPU reads them
+fn cia_from_registers(r: &[u8], ier: u8) -> Cia6526 {
+    let word = |i: usize| u16::from_le_bytes([r[i], r[i + 1]]);
+    Cia6526 {
+        ddra: r[0x02],
+        ddrb: r[0x03],
+        ora: r[0x00],
+        orb: r[0x01],
+        tac: word(0x04),
+        tbc: word(0x06),
+        tal: word(0x04),
+        tbl: word(0x06),
+        tod_10ths: r[0x08],
+        tod_sec: r[0x09],
+        tod_min: r[0x0A],
+        tod_hr: r[0x0B],
+        cra: r[0x0E],
+        crb: r[0x0F],
+        ier,
+    }
+}
+
+/// Split a response of the form count (2), then (item size (1), item) per entry
+fn items(body: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let count = match body.get(..2) {
+        Some(count) => u16::from_le_bytes([count[0], count[1]]) as usize,
+        None => return Err(short_response()),
+    };
+
+    let mut items = Vec::with_capacity(count);
+    let mut pos = 2;
+    for _ in 0..count {
+        let size = *body.get(pos).ok_or_else(short_response)? as usize;
+        let item = body.get(pos + 1..pos + 1 + size).ok_or_else(short_response)?;
+        if item.is_empty() {
+            return Err(short_response());
+        }
+        items.push(item);
+        pos += 1 + size;
+    }
+    Ok(items)
+}
+
+/// Name stored as length (1) + text at `offset` of an item
+fn item_name(item: &[u8], offset: usize) -> Result<String, String> {
+    let len = *item.get(offset).ok_or_else(short_response)? as usize;
+    let name = item.get(offset + 1..offset + 1 + len).ok_or_else(short_response)?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+fn short_response() -> String {
+    "VICE monitor response too short".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse_vsf::ParseVSF;
+    use crate::test_vsf::temp_dir;
+    use std::collections::VecDeque;
+    use std::fs;
+
+    const RAM_BANK: u16 = 1;
+    const IO_BANK: u16 = 4;
+
+    /// Answers binary monitor requests from a fixed machine state
+    struct MockMonitor {
+        ram: Box<[u8; 65536]>,
+        io: Box<[u8; 0x1000]>,
+        /// (id, name, value)
+        registers: Vec<(u8, &'static str, u16)>,
+        request: Vec<u8>,
+        replies: VecDeque<u8>,
+    }
+
+    impl MockMonitor {
+        fn new() -> Self {
+            let mut mock = Self {
+                ram: Box::new([0u8; 65536]),
+                io: Box::new([0u8; 0x1000]),
+                registers: vec![
+                    (0x00, "A", 0x11), (0x01, "X", 0x22), (0x02, "Y", 0x33), (0x03, "PC", 0x0810),
+                    (0x04, "SP", 0xF6), (0x05, "00", 0x2F), (0x06, "01", 0x37), (0x07, "FL", 0x24),
+                    (0x35, "LIN", 0x0042),
+                ],
+                request: Vec::new(),
+                replies: VecDeque::new(),
+            };
+            // VICE announces the stop before answering anything
+            mock.reply(0x62, EVENT_ID, &[0x10, 0x08]);
+            mock
+        }
+
+        fn reply(&mut self, kind: u8, id: u32, body: &[u8]) {
+            self.replies.extend([STX, API_VERSION]);
+            self.replies.extend((body.len() as u32).to_le_bytes());
+            self.replies.extend([kind, 0x00]);
+            self.replies.extend(id.to_le_bytes());
+            self.replies.extend(body);
+        }
+
+        fn handle(&mut self, id: u32, command: u8, body: &[u8]) {
+            let mut out = Vec::new();
+            match command {
+                CMD_BANKS_AVAILABLE => {
+                    let banks: [(u16, &str); 3] = [(0, "default"), (RAM_BANK, "ram"), (IO_BANK, "io")];
+                    out.extend((banks.len() as u16).to_le_bytes());
+                    for (bank, name) in banks {
+                        out.push(3 + name.len() as u8);
+                        out.extend(bank.to_le_bytes());
+                        out.push(name.len() as u8);
+                        out.extend(name.as_bytes());
+                    }
+                }
+                CMD_REGISTERS_AVAILABLE => {
+                    out.extend((self.registers.len() as u16).to_le_bytes());
+                    for &(reg, name, _) in &self.registers {
+                        out.extend([3 + name.len() as u8, reg, 16, name.len() as u8]);
+                        out.extend(name.as_bytes());
+                    }
+                }
+                CMD_REGISTERS_GET => {
+                    out.extend((self.registers.len() as u16).to_le_bytes());
+                    for &(reg, _, value) in &self.registers {
+                        out.extend([3, reg]);
+                        out.extend(value.to_le_bytes());
+                    }
+                }
+                CMD_MEMORY_GET => {
+                    let start = u16::from_le_bytes([body[1], body[2]]) as usize;
+                    let end = u16::from_le_bytes([body[3], body[4]]) as usize;
+                    let bank = u16::from_le_bytes([body[6], body[7]]);
+                    out.extend(((end - start + 1) as u16).to_le_bytes());
+                    for addr in start..=end {
+                        out.push(match bank {
+                            IO_BANK => self.io[addr - 0xD000],
+                            _ => self.ram[addr],
+                        });
+                    }
+                }
+                _ => {}
+            }
+            self.reply(command, id, &out);
+        }
+    }
+
+    impl Read for MockMonitor {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.replies.len());
+            for (dst, src) in buf.iter_mut().zip(self.replies.drain(..n)) {
+                *dst = src;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for MockMonitor {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.request.extend_from_slice(buf);
+            while self.request.len() >= 11 {
+                let len = u32::from_le_bytes([self.request[2], self.request[3], self.request[4], self.request[5]]) as usize;
+                if self.request.len() < 11 + len {
+                    break;
+                }
+                let packet: Vec<u8> = self.request.drain(..11 + len).collect();
+                let id = u32::from_le_bytes([packet[6], packet[7], packet[8], packet[9]]);
+                self.handle(id, packet[10], &packet[11..]);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn mock_machine() -> MockMonitor {
+        let mut mock = MockMonitor::new();
+        mock.ram[0x0801..0x0806].copy_from_slice(b"HELLO");
+        mock.ram[0xFFFE] = 0x48;
+        mock.io[0x020] = 0x0E;
+        mock.io[0x800..0xC00].fill(0xF5);
+        mock.io[0x418] = 0x0F;
+        // CIA1: port A out, Timer A $4025 running
+        mock.io[0xC00..0xC10].copy_from_slice(&[
+            0x7F, 0xFF, 0xFF, 0x00, 0x25, 0x40, 0xFF, 0xFF, 0, 0, 0, 0x01, 0, 0x81, 0x11, 0x08,
+        ]);
+        mock
+    }
+
+    #[test]
+    fn test_snapshot_from_mocked_monitor() {
+        let mut client = MonitorClient::new(mock_machine());
+        let snap = client.snapshot().unwrap();
+        client.resume().unwrap();
+
+        let cpu = &snap.cpu;
+        assert_eq!((cpu.a, cpu.x, cpu.y, cpu.sp, cpu.pc, cpu.p), (0x11, 0x22, 0x33, 0xF6, 0x0810, 0x24));
+        assert_eq!((snap.mem.cpu_port_dir, snap.mem.cpu_port_data), (0x2F, 0x37));
+        assert_eq!(&snap.mem.ram[0x0801..0x0806], b"HELLO");
+        assert_eq!(snap.mem.ram[0xFFFE], 0x48);
+        assert_eq!(snap.vic.registers[0x20], 0x0E);
+        assert!(snap.vic.color_ram.iter().all(|&c| c == 0x05));
+        assert_eq!(snap.sid.regs_25[0x18], 0x0F);
+        assert_eq!((snap.cia1.tac, snap.cia1.tal, snap.cia1.cra), (0x4025, 0x4025, 0x11));
+        assert_eq!(snap.cia1.ier, 0x01);
+        assert_eq!(snap.cia2.ier, 0x00);
+    }
+
+    #[test]
+    fn test_written_vsf_parses_back() {
+        let snap = MonitorClient::new(mock_machine()).snapshot().unwrap();
+        let dir = temp_dir("vice_monitor");
+        let path = dir.join("monitor.vsf");
+        write_vsf(&snap, &path).unwrap();
+
+        let parsed = ParseVSF::import(path.to_str().unwrap(), &Config::new(&dir))
+            .unwrap()
+            .parse_import()
+            .unwrap();
+        assert_eq!(parsed.cpu.pc, snap.cpu.pc);
+        assert_eq!(&parsed.mem.ram[..], &snap.mem.ram[..]);
+        assert_eq!(parsed.vic.registers, snap.vic.registers);
+        assert_eq!((parsed.cia1.tal, parsed.cia1.ier), (snap.cia1.tal, snap.cia1.ier));
+        assert_eq!(parsed.sid.regs_25, snap.sid.regs_25);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
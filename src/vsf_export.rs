@@ -0,0 +1,176 @@
+//! VSF writer and parser round-trip check
+//!
+//! `export` writes a `C64Snapshot` back out as a C64SC VSF 2.0 file that
+//! `ParseVSF` reads unchanged. Comparing that file with the snapshot it was
+//! parsed from, field by field, shows module bytes the parser dropped
+//! (CLI `--roundtrip-check`). Only the fields the parser models are
+//! compared; drive, cartridge and other modules are not written.
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::path::Path;
+
+use crate::parse_vsf::{modeled_fields, C64Snapshot, Cia6526, ColorRamSource, ParseVSF, ParserConfig};
+
+/// Offset of the color RAM in the VIC-II 1.1 payload written by `export`
+const VSF_VIC_COLOR_OFFSET: usize = 757;
+
+/// `snap` as a C64SC VSF 2.0 file with the six modules the parser needs
+pub fn export(snap: &C64Snapshot) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"VICE Snapshot File\x1A");
+    out.extend_from_slice(&[2, 0]);
+    out.extend_from_slice(&padded(b"C64SC"));
+
+    let cpu = &snap.cpu;
+    let mut cpu_payload = vec![0u8; 4];
+    cpu_payload.extend_from_slice(&[cpu.a, cpu.x, cpu.y, cpu.sp]);
+    cpu_payload.extend_from_slice(&cpu.pc.to_le_bytes());
+    cpu_payload.push(cpu.p);
+    push_module(&mut out, "MAINCPU", 1, 1, &cpu_payload);
+
+    let mut mem = vec![snap.mem.cpu_port_data, snap.mem.cpu_port_dir, snap.mem.exrom as u8, snap.mem.game as u8];
+    mem.extend_from_slice(&snap.mem.ram[..]);
+    push_module(&mut out, "C64MEM", 0, 0, &mem);
+
+    let mut vic = vec![0u8; VSF_VIC_COLOR_OFFSET + 1024];
+    vic[0] = snap.vic.model.unwrap_or(0);
+    vic[1..48].copy_from_slice(&snap.vic.registers);
+    vic[VSF_VIC_COLOR_OFFSET..].copy_from_slice(&snap.vic.color_ram[..]);
+    push_module(&mut out, "VIC-II", 1, 1, &vic);
+
+    push_module(&mut out, "CIA1", 2, 2, &cia_payload(&snap.cia1));
+    push_module(&mut out, "CIA2", 2, 2, &cia_payload(&snap.cia2));
+
+    // sids, sound, engine, then the register file and the second SID's address
+    let mut sid = vec![1 + snap.sid2_addr.is_some() as u8, 1, 0];
+    sid.extend_from_slice(&snap.sid.regs_25);
+    sid.extend_from_slice(&[0u8; 7]);
+    if let Some(addr) = snap.sid2_addr {
+        sid.extend_from_slice(&addr.to_le_bytes());
+    }
+    push_module(&mut out, "SID", 1, 1, &sid);
+
+    out
+}
+
+/// Write `snap` with `export`
+pub fn write_vsf(snap: &C64Snapshot, path: &Path) -> Result<(), String> {
+    fs::write(path, export(snap)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Modeled fields of `original` that don't survive a parse and `export`
+///
+/// `cfg` is the parser configuration the snapshot is converted with; the
+/// color RAM is always taken from the VIC-II module so it can be compared.
+/// One message per differing field, empty when everything was reproduced.
+/// CIA1 port B differs by design when the parser releases held keyboard
+/// rows.
+pub fn roundtrip_differences(original: &ParseVSF, cfg: &ParserConfig) -> Result<Vec<String>, String> {
+    let cfg = ParserConfig { color_ram_source: ColorRamSource::VicModule, ..cfg.clone() };
+    let snap = original.parse_import_with(&cfg)?;
+    let exported = modeled_fields(&export(&snap), &ParserConfig::default_vice_like());
+
+    let mut differences = Vec::new();
+    for field in original.modeled_fields(&cfg) {
+        let label = format!("{} {}", field.module, field.field);
+        let Some(written) = exported.iter().find(|f| f.module == field.module && f.field == field.field) else {
+            differences.push(format!("{}: not written", label));
+            continue;
+        };
+
+        let differing: Vec<usize> = field
+            .bytes
+            .iter()
+            .zip(written.bytes.iter())
+            .enumerate()
+            .filter(|(_, (want, got))| want != got)
+            .map(|(i, _)| i)
+            .collect();
+        if let Some(&first) = differing.first() {
+            differences.push(format!(
+                "{}: {} of {} bytes differ (first at +{}, file offset {})",
+                label,
+                differing.len(),
+                field.bytes.len(),
+                first,
+                field.offset + first
+            ));
+        }
+    }
+    Ok(differences)
+}
+
+/// CIA module payload in the order `parse_cia` reads it
+fn cia_payload(cia: &Cia6526) -> Vec<u8> {
+    let mut payload = vec![cia.ora, cia.orb, cia.ddra, cia.ddrb];
+    payload.extend_from_slice(&cia.tac.to_le_bytes());
+    payload.extend_from_slice(&cia.tbc.to_le_bytes());
+    payload.extend_from_slice(&[cia.tod_10ths, cia.tod_sec, cia.tod_min, cia.tod_hr]);
+    payload.extend_from_slice(&[0x00, cia.ier, cia.cra, cia.crb]);
+    payload.extend_from_slice(&cia.tal.to_le_bytes());
+    payload.extend_from_slice(&cia.tbl.to_le_bytes());
+    payload
+}
+
+fn padded(name: &[u8]) -> [u8; 16] {
+    let mut buf = [0u8; 16];
+    buf[..name.len()].copy_from_slice(name);
+    buf
+}
+
+fn push_module(out: &mut Vec<u8>, name: &str, major: u8, minor: u8, payload: &[u8]) {
+    out.extend_from_slice(&padded(name.as_bytes()));
+    out.push(major);
+    out.push(minor);
+    out.extend_from_slice(&((payload.len() + 22) as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vsf::TestVsf;
+
+    #[test]
+    fn test_parse_and_export_reproduce_modeled_modules() {
+        let mut vsf = TestVsf::new();
+        vsf.a = 0x42;
+        vsf.pc = 0xC123;
+        for (i, byte) in vsf.ram.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        vsf.vic_color[0x100] = 0x05;
+        vsf.cia1[4..6].copy_from_slice(&[0x25, 0x40]);
+        vsf.cia2[13] = 0x81;
+        vsf.sid[24] = 0x0F;
+        let parser = vsf.import("vsf_export_roundtrip");
+
+        let cfg = ParserConfig::default_vice_like();
+        assert_eq!(roundtrip_differences(&parser, &cfg).unwrap(), Vec::<String>::new());
+
+        // Every field the parser reads came back byte for byte
+        let module_colors = ParserConfig { color_ram_source: ColorRamSource::VicModule, ..cfg.clone() };
+        let snap = parser.parse_import_with(&module_colors).unwrap();
+        let original = parser.modeled_fields(&cfg);
+        let exported = modeled_fields(&export(&snap), &cfg);
+        assert_eq!(original.len(), exported.len());
+        for (want, got) in original.iter().zip(exported.iter()) {
+            assert_eq!((&want.module, want.field, &want.bytes), (&got.module, got.field, &got.bytes));
+        }
+    }
+
+    #[test]
+    fn test_dropped_bytes_are_reported() {
+        // A cartridge line stored as 2 comes back as 1: the parser keeps a bool
+        let mut vsf = TestVsf::new();
+        vsf.exrom = 2;
+        let parser = vsf.import("vsf_export_dropped");
+
+        let differences = roundtrip_differences(&parser, &ParserConfig::default_vice_like()).unwrap();
+        assert_eq!(differences.len(), 1);
+        assert!(differences[0].starts_with("C64MEM EXROM/GAME: 1 of 2 bytes differ (first at +0"), "{}", differences[0]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Command-line behavior of the CLI binary
+//!
+// Copyright (c) 2025-2026 Tommy Olsen
+// Licensed under the MIT License.
+
+use std::fs;
+use std::process::Command;
+
+#[allow(dead_code)]
+#[path = "../src/test_vsf.rs"]
+mod test_vsf;
+
+use test_vsf::TestVsf;
+
+#[test]
+fn test_quiet_conversion_prints_nothing() {
+    let (dir, input) = TestVsf::new().write("cli_quiet");
+    let output = dir.join("out.prg");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--quiet")
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+
+    assert!(run.status.success(), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+    assert!(run.stdout.is_empty(), "stdout: {}", String::from_utf8_lossy(&run.stdout));
+    assert!(output.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_diff_prints_the_changed_range() {
+    let (dir, original) = TestVsf::new().write("cli_diff_a");
+    let mut changed = TestVsf::new();
+    changed.ram[0x4000..0x4010].fill(0xAA);
+    let (changed_dir, changed) = changed.write("cli_diff_b");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--diff")
+        .arg(&original)
+        .arg(&changed)
+        .output()
+        .expect("run CLI");
+
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(run.status.code(), Some(1), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+    assert!(stdout.contains("$4000-$400F"), "stdout: {}", stdout);
+    assert!(stdout.contains("1 range(s), 16 byte(s) differ"), "stdout: {}", stdout);
+
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&changed_dir);
+}
+
+#[test]
+fn test_strict_fails_on_ignored_option() {
+    let (dir, input) = TestVsf::new().write("cli_strict");
+    let output = dir.join("out.prg");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--strict", "--hook-addr", "$0334"])
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert_eq!(run.status.code(), Some(1), "stderr: {}", stderr);
+    assert!(stderr.contains("--hook-addr"), "stderr: {}", stderr);
+    assert!(!output.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_strict_snapshot_warning_keeps_the_old_output() {
+    // A low stack pointer is a snapshot check warning
+    let mut vsf = TestVsf::new();
+    vsf.sp = 0x40;
+    let (dir, input) = vsf.write("cli_strict_keep");
+    let output = dir.join("out.prg");
+    fs::write(&output, b"old").unwrap();
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--strict")
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+    assert_eq!(run.status.code(), Some(1), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(fs::read(&output).unwrap(), b"old");
+
+    // Batch mode fails the input and writes nothing for it
+    let out = dir.join("out");
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--strict", "--batch"])
+        .arg(&out)
+        .arg(&input)
+        .output()
+        .expect("run CLI");
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(run.status.code(), Some(1), "stdout: {}", stdout);
+    assert!(stdout.contains("FAILED") && stdout.contains("--strict"), "stdout: {}", stdout);
+    assert!(!out.join("cli_strict_keep.prg").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_both_writes_prg_and_crt() {
+    let (dir, input) = TestVsf::new().write("cli_both");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--quiet", "--both"])
+        .arg(&input)
+        .arg(dir.join("game"))
+        .output()
+        .expect("run CLI");
+
+    assert!(run.status.success(), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+    let prg = fs::read(dir.join("game.prg")).expect("PRG written");
+    assert_eq!(&prg[..2], &[0x01, 0x08]);
+    let crt = fs::read(dir.join("game.crt")).expect("CRT written");
+    assert!(crt.starts_with(b"C64 CARTRIDGE   "));
+    // Hardware type 32: EasyFlash
+    assert_eq!(&crt[0x16..0x18], &[0x00, 0x20]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_validate_reports_missing_module() {
+    let (dir, input) = TestVsf::new().write("cli_validate");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--validate")
+        .arg(&input)
+        .output()
+        .expect("run CLI");
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(run.status.code(), Some(0), "stdout: {}", stdout);
+    assert!(stdout.contains("Machine: C64SC"), "stdout: {}", stdout);
+    assert!(stdout.contains("Format:  2.0"), "stdout: {}", stdout);
+
+    // Drop the trailing SID module (22 byte header + 35 byte payload)
+    let mut bytes = TestVsf::new().to_bytes();
+    bytes.truncate(bytes.len() - 57);
+    let truncated = dir.join("truncated.vsf");
+    fs::write(&truncated, &bytes).unwrap();
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--validate")
+        .arg(&truncated)
+        .output()
+        .expect("run CLI");
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(run.status.code(), Some(1), "stdout: {}", stdout);
+    assert!(stdout.contains("SID      missing"), "stdout: {}", stdout);
+    assert!(stdout.contains("Invalid: SID missing"), "stdout: {}", stdout);
+    assert!(fs::read_dir(&dir).unwrap().count() == 2);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_support_bundle_collects_log_sources_and_report() {
+    let (dir, input) = TestVsf::new().write("cli_bundle");
+    let output = dir.join("out.prg");
+    let bundle = dir.join("bundle");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--support-bundle")
+        .arg(&bundle)
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+
+    assert!(run.status.success(), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+    for name in ["log.txt", "report.json", "version.json"] {
+        assert!(bundle.join(name).exists(), "missing {}", name);
+    }
+    let sources = fs::read_dir(bundle.join("asm")).expect("asm directory").count();
+    assert!(sources > 0);
+    // Private data stays out unless asked for, and paths are reduced to file names
+    assert!(!bundle.join("snapshot.vsf").exists());
+    let log = fs::read_to_string(bundle.join("log.txt")).unwrap();
+    assert!(log.contains("Result:  success"), "log: {}", log);
+    assert!(!log.contains(&*dir.to_string_lossy()), "log: {}", log);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_report_failure_is_a_warning() {
+    let (dir, input) = TestVsf::new().write("cli_report_fail");
+    let output = dir.join("out.prg");
+    let report = dir.join("missing").join("report.json");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .arg("--report")
+        .arg(&report)
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(run.status.success(), "stderr: {}", stderr);
+    assert!(stderr.contains("Warning: Failed to write report"), "stderr: {}", stderr);
+    assert!(!stderr.contains("Conversion failed"), "stderr: {}", stderr);
+    assert!(output.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_force_converts_unsupported_format_version() {
+    let dir = test_vsf::temp_dir("cli_force");
+    let input = dir.join("future.vsf");
+    let mut raw = TestVsf::new().to_bytes();
+    raw[20] = 9;
+    fs::write(&input, &raw).unwrap();
+    let output = dir.join("out.prg");
+
+    let run = |force: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"));
+        if force {
+            command.arg("--force");
+        }
+        command.arg(&input).arg(&output).output().expect("run CLI")
+    };
+
+    let refused = run(false);
+    assert_eq!(refused.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("Unsupported snapshot format version 2.9"));
+    assert!(!output.exists());
+
+    let forced = run(true);
+    let stderr = String::from_utf8_lossy(&forced.stderr);
+    assert!(forced.status.success(), "stderr: {}", stderr);
+    assert!(stderr.contains("--force: Unsupported snapshot format version 2.9"), "stderr: {}", stderr);
+    assert!(String::from_utf8_lossy(&forced.stdout).contains("may be unreliable"));
+    assert!(output.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_blank_name_writes_default_cartridge_name() {
+    let (dir, input) = TestVsf::new().write("cli_blank_name");
+    let output = dir.join("out.crt");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--name", "   "])
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(run.status.success(), "stderr: {}", stderr);
+    assert!(stderr.contains("--name is blank"), "stderr: {}", stderr);
+    // CRT header name: 32 bytes at $20, zero padded
+    let crt = fs::read(&output).unwrap();
+    assert_eq!(&crt[0x20..0x2E], b"VICE SNAPSHOT\0");
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_hash_matches_the_written_output() {
+    let (dir, input) = TestVsf::new().write("cli_hash");
+    let output = dir.join("out.prg");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--quiet", "--hash", "crc32", "--hash-sidecar"])
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .expect("run CLI");
+    assert!(run.status.success(), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+
+    // Bitwise CRC-32 (IEEE), independent of the table-driven one in the CLI
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in fs::read(&output).unwrap() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    let expected = format!("{:08x}", !crc);
+
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(stdout, format!("{}  {}\n", expected, output.display()));
+    let sidecar = fs::read_to_string(dir.join("out.prg.crc32")).unwrap();
+    assert_eq!(sidecar, format!("{}  out.prg\n", expected));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cache_skips_unchanged_inputs_in_a_batch() {
+    let (dir_a, input_a) = TestVsf::new().write("cli_cache_a");
+    let (dir_b, input_b) = TestVsf::new().write("cli_cache_b");
+    let out = dir_a.join("out");
+    let cache = dir_a.join("cache");
+
+    let batch = || {
+        let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+            .arg("--batch")
+            .arg(&out)
+            .arg("--cache")
+            .arg(&cache)
+            .arg(&input_a)
+            .arg(&input_b)
+            .output()
+            .expect("run CLI");
+        assert!(run.status.success(), "stderr: {}", String::from_utf8_lossy(&run.stderr));
+        String::from_utf8_lossy(&run.stdout).into_owned()
+    };
+
+    let first = batch();
+    assert!(first.contains("2 converted, 0 cached, 0 failed"), "stdout: {}", first);
+
+    // Only the changed snapshot is converted again
+    let mut changed = TestVsf::new();
+    changed.ram[0x4000..0x4010].fill(0xAA);
+    fs::write(&input_b, changed.to_bytes()).unwrap();
+    let second = batch();
+    assert!(second.contains(&format!("  CACHED  {} ->", input_a)), "stdout: {}", second);
+    assert!(second.contains(&format!("  OK      {} ->", input_b)), "stdout: {}", second);
+    assert!(second.contains("1 converted, 1 cached, 0 failed"), "stdout: {}", second);
+
+    // A deleted output is rebuilt even though its input didn't change
+    fs::remove_file(out.join("cli_cache_a.prg")).unwrap();
+    let third = batch();
+    assert!(third.contains(&format!("  OK      {} ->", input_a)), "stdout: {}", third);
+    assert!(third.contains("1 converted, 1 cached, 0 failed"), "stdout: {}", third);
+
+    let _ = fs::remove_dir_all(&dir_a);
+    let _ = fs::remove_dir_all(&dir_b);
+}
+
+#[test]
+fn test_batch_with_four_jobs_converts_every_input() {
+    let inputs: Vec<_> = (0..6u8)
+        .map(|i| {
+            let mut vsf = TestVsf::new();
+            vsf.vic_regs[0x20] = i;
+            vsf.write(&format!("cli_jobs{}", i))
+        })
+        .collect();
+    let out = inputs[0].0.join("out");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--jobs", "4", "--batch"])
+        .arg(&out)
+        .args(inputs.iter().map(|(_, input)| input))
+        .output()
+        .expect("run CLI");
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(run.status.success(), "stdout: {}\nstderr: {}", stdout, String::from_utf8_lossy(&run.stderr));
+    assert!(stdout.contains("6 converted, 0 failed"), "stdout: {}", stdout);
+
+    // Summary in input order, every PRG written
+    let mut last = 0;
+    for (i, (_, input)) in inputs.iter().enumerate() {
+        let line = stdout.find(&format!("  OK      {} ->", input)).unwrap();
+        assert!(line >= last, "stdout: {}", stdout);
+        last = line;
+        assert!(out.join(format!("cli_jobs{}.prg", i)).exists());
+    }
+
+    for (dir, _) in &inputs {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+#[test]
+fn test_batch_rejects_inputs_with_the_same_output() {
+    let (dir_a, input_a) = TestVsf::new().write("cli_same_stem");
+    let (dir_b, input_b) = TestVsf::new().write("cli_same_stem");
+    let out = dir_a.join("out");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_vice-snapshot-to-prg-converter-cli"))
+        .args(["--jobs", "2", "--batch"])
+        .arg(&out)
+        .arg(&input_a)
+        .arg(&input_b)
+        .output()
+        .expect("run CLI");
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert_eq!(run.status.code(), Some(1), "stderr: {}", stderr);
+    assert!(stderr.contains("cli_same_stem.prg"), "stderr: {}", stderr);
+    assert!(!out.join("cli_same_stem.prg").exists());
+
+    let _ = fs::remove_dir_all(&dir_a);
+    let _ = fs::remove_dir_all(&dir_b);
+}